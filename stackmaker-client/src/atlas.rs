@@ -0,0 +1,84 @@
+//! Packs the block sprites into a single texture, so drawing a screen full of blocks doesn't switch to a different texture for almost every block.
+//! Each sprite is surrounded by a copy of its outermost pixels, so drawing it scaled never shows a bit of the sprite next to it.
+
+use image::RgbaImage;
+use speedy2d::{dimen::Vec2, shape::Rectangle};
+
+/// larger textures may not be supported by the graphics card. if the sprites don't fit, they are drawn from their own textures.
+const MAX_SIZE: u32 = 4096;
+/// how many copies of the outermost pixels surround each sprite
+const PADDING: u32 = 1;
+
+/// returns the atlas and, in the same order as `images`, the area of each image in it.
+/// the areas are fractions of the atlas' size, as used by `Graphics2D::draw_rectangle_image_subset_tinted`.
+/// returns `None` if there are no images, one of them is empty, or they don't fit into `MAX_SIZE`.
+pub fn pack(images: &[&RgbaImage]) -> Option<(RgbaImage, Vec<Rectangle<f32>>)> {
+    if images.is_empty()
+        || images
+            .iter()
+            .any(|img| img.width() == 0 || img.height() == 0)
+    {
+        return None;
+    }
+    let padded = |img: &RgbaImage| (img.width() + 2 * PADDING, img.height() + 2 * PADDING);
+    // rows of images, tallest first, so that little space is wasted in each row
+    let area: u64 = images
+        .iter()
+        .map(|img| {
+            let (w, h) = padded(img);
+            w as u64 * h as u64
+        })
+        .sum();
+    let widest = images.iter().map(|img| padded(img).0).max()?;
+    let width = ((area as f64).sqrt().ceil() as u32)
+        .max(widest)
+        .next_power_of_two();
+    if width > MAX_SIZE {
+        return None;
+    }
+    let mut order: Vec<usize> = (0..images.len()).collect();
+    order.sort_by_key(|&i| std::cmp::Reverse(images[i].height()));
+    // the top left corner of every image, not including the padding
+    let mut positions = vec![(0, 0); images.len()];
+    let (mut x, mut y, mut row_height) = (0, 0, 0);
+    for i in order {
+        let (w, h) = padded(images[i]);
+        if x + w > width {
+            x = 0;
+            y += row_height;
+            row_height = 0;
+        }
+        positions[i] = (x + PADDING, y + PADDING);
+        x += w;
+        row_height = row_height.max(h);
+    }
+    let height = y + row_height;
+    if height > MAX_SIZE {
+        return None;
+    }
+    let mut pixels = vec![0u8; width as usize * height as usize * 4];
+    let mut areas = Vec::with_capacity(images.len());
+    for (img, &(left, top)) in images.iter().zip(&positions) {
+        let (w, h) = (img.width(), img.height());
+        let src = img.as_raw();
+        // every pixel of the padded area copies the closest pixel of the image
+        for dy in 0..h + 2 * PADDING {
+            let sy = dy.saturating_sub(PADDING).min(h - 1);
+            let row = (top - PADDING + dy) as usize * width as usize;
+            for dx in 0..w + 2 * PADDING {
+                let sx = dx.saturating_sub(PADDING).min(w - 1);
+                let from = (sy as usize * w as usize + sx as usize) * 4;
+                let to = (row + (left - PADDING + dx) as usize) * 4;
+                pixels[to..to + 4].copy_from_slice(&src[from..from + 4]);
+            }
+        }
+        areas.push(Rectangle::new(
+            Vec2::new(left as f32 / width as f32, top as f32 / height as f32),
+            Vec2::new(
+                (left + w) as f32 / width as f32,
+                (top + h) as f32 / height as f32,
+            ),
+        ));
+    }
+    Some((RgbaImage::from_raw(width, height, pixels)?, areas))
+}
@@ -0,0 +1,307 @@
+//! Default assets which are built into the client, so it is usable even if the assets directory is missing or incomplete.
+//! These are the files in `assets/menu/0/` and `assets/world/0/`. Assets found on disk always take precedence.
+
+use image::RgbaImage;
+
+/// (directory in the assets directory, file name, contents)
+const BUILTIN_ASSETS: &[(&str, &str, &[u8])] = &[
+    (
+        "menu",
+        "background.png",
+        include_bytes!("../assets/menu/0/background.png"),
+    ),
+    (
+        "menu",
+        "new_singleplayer_world_button.png",
+        include_bytes!("../assets/menu/0/new_singleplayer_world_button.png"),
+    ),
+    (
+        "world",
+        "block_delay_down.png",
+        include_bytes!("../assets/world/0/block_delay_down.png"),
+    ),
+    (
+        "world",
+        "block_delay_left.png",
+        include_bytes!("../assets/world/0/block_delay_left.png"),
+    ),
+    (
+        "world",
+        "block_delay_right.png",
+        include_bytes!("../assets/world/0/block_delay_right.png"),
+    ),
+    (
+        "world",
+        "block_delay_up.png",
+        include_bytes!("../assets/world/0/block_delay_up.png"),
+    ),
+    (
+        "world",
+        "block_splitter_right.png",
+        include_bytes!("../assets/world/0/block_splitter_right.png"),
+    ),
+    (
+        "world",
+        "block_storage_add_down.png",
+        include_bytes!("../assets/world/0/block_storage_add_down.png"),
+    ),
+    (
+        "world",
+        "block_storage_add_left.png",
+        include_bytes!("../assets/world/0/block_storage_add_left.png"),
+    ),
+    (
+        "world",
+        "block_storage_add_right.png",
+        include_bytes!("../assets/world/0/block_storage_add_right.png"),
+    ),
+    (
+        "world",
+        "block_storage_add_up.png",
+        include_bytes!("../assets/world/0/block_storage_add_up.png"),
+    ),
+    (
+        "world",
+        "block_storage_and_down.png",
+        include_bytes!("../assets/world/0/block_storage_and_down.png"),
+    ),
+    (
+        "world",
+        "block_storage_and_left.png",
+        include_bytes!("../assets/world/0/block_storage_and_left.png"),
+    ),
+    (
+        "world",
+        "block_storage_and_right.png",
+        include_bytes!("../assets/world/0/block_storage_and_right.png"),
+    ),
+    (
+        "world",
+        "block_storage_and_up.png",
+        include_bytes!("../assets/world/0/block_storage_and_up.png"),
+    ),
+    (
+        "world",
+        "block_storage_default_down.png",
+        include_bytes!("../assets/world/0/block_storage_default_down.png"),
+    ),
+    (
+        "world",
+        "block_storage_default_left.png",
+        include_bytes!("../assets/world/0/block_storage_default_left.png"),
+    ),
+    (
+        "world",
+        "block_storage_default_right.png",
+        include_bytes!("../assets/world/0/block_storage_default_right.png"),
+    ),
+    (
+        "world",
+        "block_storage_default_up.png",
+        include_bytes!("../assets/world/0/block_storage_default_up.png"),
+    ),
+    (
+        "world",
+        "block_storage_div_down.png",
+        include_bytes!("../assets/world/0/block_storage_div_down.png"),
+    ),
+    (
+        "world",
+        "block_storage_div_left.png",
+        include_bytes!("../assets/world/0/block_storage_div_left.png"),
+    ),
+    (
+        "world",
+        "block_storage_div_right.png",
+        include_bytes!("../assets/world/0/block_storage_div_right.png"),
+    ),
+    (
+        "world",
+        "block_storage_div_up.png",
+        include_bytes!("../assets/world/0/block_storage_div_up.png"),
+    ),
+    (
+        "world",
+        "block_storage_mod_down.png",
+        include_bytes!("../assets/world/0/block_storage_mod_down.png"),
+    ),
+    (
+        "world",
+        "block_storage_mod_left.png",
+        include_bytes!("../assets/world/0/block_storage_mod_left.png"),
+    ),
+    (
+        "world",
+        "block_storage_mod_right.png",
+        include_bytes!("../assets/world/0/block_storage_mod_right.png"),
+    ),
+    (
+        "world",
+        "block_storage_mod_up.png",
+        include_bytes!("../assets/world/0/block_storage_mod_up.png"),
+    ),
+    (
+        "world",
+        "block_storage_mul_down.png",
+        include_bytes!("../assets/world/0/block_storage_mul_down.png"),
+    ),
+    (
+        "world",
+        "block_storage_mul_left.png",
+        include_bytes!("../assets/world/0/block_storage_mul_left.png"),
+    ),
+    (
+        "world",
+        "block_storage_mul_right.png",
+        include_bytes!("../assets/world/0/block_storage_mul_right.png"),
+    ),
+    (
+        "world",
+        "block_storage_mul_up.png",
+        include_bytes!("../assets/world/0/block_storage_mul_up.png"),
+    ),
+    (
+        "world",
+        "block_storage_or_down.png",
+        include_bytes!("../assets/world/0/block_storage_or_down.png"),
+    ),
+    (
+        "world",
+        "block_storage_or_left.png",
+        include_bytes!("../assets/world/0/block_storage_or_left.png"),
+    ),
+    (
+        "world",
+        "block_storage_or_right.png",
+        include_bytes!("../assets/world/0/block_storage_or_right.png"),
+    ),
+    (
+        "world",
+        "block_storage_or_up.png",
+        include_bytes!("../assets/world/0/block_storage_or_up.png"),
+    ),
+    (
+        "world",
+        "block_storage_sto_down.png",
+        include_bytes!("../assets/world/0/block_storage_sto_down.png"),
+    ),
+    (
+        "world",
+        "block_storage_sto_left.png",
+        include_bytes!("../assets/world/0/block_storage_sto_left.png"),
+    ),
+    (
+        "world",
+        "block_storage_sto_right.png",
+        include_bytes!("../assets/world/0/block_storage_sto_right.png"),
+    ),
+    (
+        "world",
+        "block_storage_sto_up.png",
+        include_bytes!("../assets/world/0/block_storage_sto_up.png"),
+    ),
+    (
+        "world",
+        "block_storage_sub_down.png",
+        include_bytes!("../assets/world/0/block_storage_sub_down.png"),
+    ),
+    (
+        "world",
+        "block_storage_sub_left.png",
+        include_bytes!("../assets/world/0/block_storage_sub_left.png"),
+    ),
+    (
+        "world",
+        "block_storage_sub_right.png",
+        include_bytes!("../assets/world/0/block_storage_sub_right.png"),
+    ),
+    (
+        "world",
+        "block_storage_sub_up.png",
+        include_bytes!("../assets/world/0/block_storage_sub_up.png"),
+    ),
+    (
+        "world",
+        "block_storage_xor_down.png",
+        include_bytes!("../assets/world/0/block_storage_xor_down.png"),
+    ),
+    (
+        "world",
+        "block_storage_xor_left.png",
+        include_bytes!("../assets/world/0/block_storage_xor_left.png"),
+    ),
+    (
+        "world",
+        "block_storage_xor_right.png",
+        include_bytes!("../assets/world/0/block_storage_xor_right.png"),
+    ),
+    (
+        "world",
+        "block_storage_xor_up.png",
+        include_bytes!("../assets/world/0/block_storage_xor_up.png"),
+    ),
+    (
+        "world",
+        "menu_arrow_selected.png",
+        include_bytes!("../assets/world/0/menu_arrow_selected.png"),
+    ),
+    (
+        "world",
+        "menu_arrow_source.png",
+        include_bytes!("../assets/world/0/menu_arrow_source.png"),
+    ),
+    (
+        "world",
+        "menu_arrow_target.png",
+        include_bytes!("../assets/world/0/menu_arrow_target.png"),
+    ),
+    (
+        "world",
+        "menu_button_pause.png",
+        include_bytes!("../assets/world/0/menu_button_pause.png"),
+    ),
+    (
+        "world",
+        "menu_button_paused.png",
+        include_bytes!("../assets/world/0/menu_button_paused.png"),
+    ),
+    (
+        "world",
+        "menu_button_signalzero.png",
+        include_bytes!("../assets/world/0/menu_button_signalzero.png"),
+    ),
+    (
+        "world",
+        "menu_button_tick.png",
+        include_bytes!("../assets/world/0/menu_button_tick.png"),
+    ),
+    (
+        "world",
+        "signal_away.png",
+        include_bytes!("../assets/world/0/signal_away.png"),
+    ),
+    (
+        "world",
+        "signal_to.png",
+        include_bytes!("../assets/world/0/signal_to.png"),
+    ),
+    (
+        "world",
+        "signal_up.png",
+        include_bytes!("../assets/world/0/signal_up.png"),
+    ),
+];
+
+/// decodes the built-in version of `{kind}/*/{file_name}` (`kind` is `menu` or `world`), if there is one
+pub fn open(kind: &str, file_name: &str) -> Option<RgbaImage> {
+    let (_, _, bytes) = BUILTIN_ASSETS
+        .iter()
+        .find(|(k, name, _)| *k == kind && *name == file_name)?;
+    match image::load_from_memory(bytes) {
+        Ok(img) => Some(img.into_rgba8()),
+        Err(e) => {
+            eprintln!("[warn] Couldn't decode built-in asset {kind}/{file_name}: {e}");
+            None
+        }
+    }
+}
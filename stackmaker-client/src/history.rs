@@ -0,0 +1,458 @@
+//! Undo and redo for the edits the player makes to the world.
+//!
+//! Only edits made by the player are recorded, changes made by the running simulation (Move, Swap, Duplicate and Destroy blocks, for example) are not.
+//! Every edit is stored as the changes it made to single stacks, not as a copy of the world,
+//! and undoing it applies the inverse changes to the stacks.
+//! If the simulation changed a stack since, so that the inverse doesn't fit anymore
+//! (the block it would remove is gone, for example), that stack can't be reverted and its change is dropped.
+//! Pasting and filling change many stacks at once, but are a single edit.
+
+use std::collections::VecDeque;
+
+use stackmaker::world::{Block, World};
+
+/// how many edits are remembered. once there are more, the oldest ones can't be undone anymore.
+const MAX_EDITS: usize = 1000;
+
+#[derive(Default)]
+pub struct History {
+    undo: VecDeque<Edit>,
+    redo: Vec<Edit>,
+}
+
+struct Edit {
+    layer: usize,
+    /// (x, y, change), in the order they were made
+    changes: Vec<(i64, i64, Change)>,
+}
+
+/// A change to one stack. Indices count from the bottom of the stack.
+enum Change {
+    Insert(usize, Block),
+    Remove(usize, Block),
+    /// (index, old block, new block)
+    Replace(usize, Block, Block),
+    /// removes the block at the first index, then inserts it at the second one
+    Move(usize, usize),
+    Swap(usize, usize),
+    /// (old stack, new stack)
+    SetStack(Vec<Block>, Vec<Block>),
+}
+
+impl History {
+    /// puts a block on top of the stack
+    pub fn push(&mut self, world: &mut World, layer: usize, x: i64, y: i64, block: Block) {
+        let len = stack(world, layer, x, y).map_or(0, |stack| stack.len());
+        self.edit(world, layer, x, y, Change::Insert(len, block));
+    }
+    /// removes the topmost block of the stack, if there is one
+    pub fn pop(&mut self, world: &mut World, layer: usize, x: i64, y: i64) {
+        if let Some(len) = stack(world, layer, x, y).map(|stack| stack.len()) {
+            if len > 0 {
+                self.remove(world, layer, x, y, len - 1);
+            }
+        }
+    }
+    /// removes the block at `index` (from the bottom), if there is one
+    pub fn remove(&mut self, world: &mut World, layer: usize, x: i64, y: i64, index: usize) {
+        if let Some(block) = stack(world, layer, x, y).and_then(|stack| stack.get(index)) {
+            let change = Change::Remove(index, block.clone());
+            self.edit(world, layer, x, y, change);
+        }
+    }
+    /// replaces the block at `index` (from the bottom), if there is one
+    pub fn replace(
+        &mut self,
+        world: &mut World,
+        layer: usize,
+        x: i64,
+        y: i64,
+        index: usize,
+        block: Block,
+    ) {
+        if let Some(old) = stack(world, layer, x, y).and_then(|stack| stack.get(index)) {
+            if *old != block {
+                let change = Change::Replace(index, old.clone(), block);
+                self.edit(world, layer, x, y, change);
+            }
+        }
+    }
+    /// removes the block at `from`, then inserts it at `to` (both from the bottom)
+    pub fn move_block(
+        &mut self,
+        world: &mut World,
+        layer: usize,
+        x: i64,
+        y: i64,
+        from: usize,
+        to: usize,
+    ) {
+        if from != to {
+            self.edit(world, layer, x, y, Change::Move(from, to));
+        }
+    }
+    /// swaps the blocks at `a` and `b` (both from the bottom)
+    pub fn swap(&mut self, world: &mut World, layer: usize, x: i64, y: i64, a: usize, b: usize) {
+        if a != b {
+            self.edit(world, layer, x, y, Change::Swap(a, b));
+        }
+    }
+    /// removes all blocks from the stack
+    pub fn clear(&mut self, world: &mut World, layer: usize, x: i64, y: i64) {
+        if let Some(old) = stack(world, layer, x, y).filter(|stack| !stack.is_empty()) {
+            let change = Change::SetStack(old.clone(), vec![]);
+            self.edit(world, layer, x, y, change);
+        }
+    }
+    /// puts the stacks on top of the stacks at `(x + dx, y + dy)` for every `((dx, dy), stack)`.
+    /// this is a single edit, so it is undone all at once.
+    pub fn paste(
+        &mut self,
+        world: &mut World,
+        layer: usize,
+        x: i64,
+        y: i64,
+        stacks: &[((i64, i64), Vec<Block>)],
+    ) {
+        let changes = stacks
+            .iter()
+            .map(|((dx, dy), blocks)| {
+                let (x, y) = (x + dx, y + dy);
+                let old = stack(world, layer, x, y).cloned().unwrap_or_default();
+                let mut new = old.clone();
+                new.extend(blocks.iter().cloned());
+                (x, y, Change::SetStack(old, new))
+            })
+            .collect();
+        self.edit_all(world, layer, changes);
+    }
+    /// puts a copy of `block` on top of every stack in the area between (x1, y1) and (x2, y2), inclusive.
+    /// like `paste`, this is a single edit.
+    pub fn fill(
+        &mut self,
+        world: &mut World,
+        layer: usize,
+        (x1, y1, x2, y2): (i64, i64, i64, i64),
+        block: &Block,
+    ) {
+        let mut changes = vec![];
+        for y in y1..=y2 {
+            for x in x1..=x2 {
+                let len = stack(world, layer, x, y).map_or(0, |stack| stack.len());
+                changes.push((x, y, Change::Insert(len, block.clone())));
+            }
+        }
+        self.edit_all(world, layer, changes);
+    }
+    /// reverts the most recent edit. returns false if there was nothing to undo
+    /// or if the simulation changed the stack so that the edit can't be reverted anymore.
+    /// of an edit which changed multiple stacks, only the stacks which can't be reverted anymore are dropped.
+    pub fn undo(&mut self, world: &mut World) -> bool {
+        let Some(edit) = self.undo.pop_back() else {
+            return false;
+        };
+        // the stacks are reverted in the opposite order they were changed in
+        let mut changes: Vec<_> = edit
+            .changes
+            .into_iter()
+            .rev()
+            .filter_map(|(x, y, change)| {
+                let inverse = change.inverse();
+                apply(world, edit.layer, x, y, &inverse).then(|| (x, y, inverse.inverse()))
+            })
+            .collect();
+        if changes.is_empty() {
+            return false;
+        }
+        changes.reverse();
+        self.redo.push(Edit {
+            layer: edit.layer,
+            changes,
+        });
+        true
+    }
+    /// applies the most recently undone edit again. returns false if there was nothing to redo
+    /// or if the simulation changed the stack so that the edit can't be applied anymore.
+    pub fn redo(&mut self, world: &mut World) -> bool {
+        let Some(edit) = self.redo.pop() else {
+            return false;
+        };
+        let changes = apply_all(world, edit.layer, edit.changes);
+        if changes.is_empty() {
+            return false;
+        }
+        self.undo.push_back(Edit {
+            layer: edit.layer,
+            changes,
+        });
+        true
+    }
+    /// applies the change and, if it fit the stack, records it.
+    /// a new edit makes the undone edits impossible to redo.
+    fn edit(&mut self, world: &mut World, layer: usize, x: i64, y: i64, change: Change) {
+        self.edit_all(world, layer, vec![(x, y, change)]);
+    }
+    /// like `edit`, but for changes to multiple stacks, which are recorded as a single edit.
+    fn edit_all(&mut self, world: &mut World, layer: usize, changes: Vec<(i64, i64, Change)>) {
+        let changes = apply_all(world, layer, changes);
+        if changes.is_empty() {
+            return;
+        }
+        self.redo.clear();
+        if self.undo.len() >= MAX_EDITS {
+            self.undo.pop_front();
+        }
+        self.undo.push_back(Edit { layer, changes });
+    }
+}
+
+impl Change {
+    fn inverse(self) -> Self {
+        match self {
+            Self::Insert(index, block) => Self::Remove(index, block),
+            Self::Remove(index, block) => Self::Insert(index, block),
+            Self::Replace(index, old, new) => Self::Replace(index, new, old),
+            Self::Move(from, to) => Self::Move(to, from),
+            Self::Swap(a, b) => Self::Swap(a, b),
+            Self::SetStack(old, new) => Self::SetStack(new, old),
+        }
+    }
+    /// returns false, without changing the stack, if the change doesn't fit the stack.
+    /// blocks are only compared by their kind, because the simulation may have changed their values.
+    fn apply(&self, stack: &mut Vec<Block>) -> bool {
+        match self {
+            Self::Insert(index, block) => {
+                if *index > stack.len() {
+                    return false;
+                }
+                stack.insert(*index, block.clone());
+            }
+            Self::Remove(index, block) => {
+                if !stack.get(*index).is_some_and(|v| same_kind(v, block)) {
+                    return false;
+                }
+                stack.remove(*index);
+            }
+            Self::Replace(index, old, new) => match stack.get_mut(*index) {
+                Some(v) if same_kind(v, old) => *v = new.clone(),
+                _ => return false,
+            },
+            Self::Move(from, to) => {
+                if *from >= stack.len() || *to >= stack.len() {
+                    return false;
+                }
+                let block = stack.remove(*from);
+                stack.insert(*to, block);
+            }
+            Self::Swap(a, b) => {
+                if *a >= stack.len() || *b >= stack.len() {
+                    return false;
+                }
+                stack.swap(*a, *b);
+            }
+            Self::SetStack(old, new) => {
+                if stack.len() != old.len() || !stack.iter().zip(old).all(|(a, b)| same_kind(a, b))
+                {
+                    return false;
+                }
+                *stack = new.clone();
+            }
+        }
+        true
+    }
+}
+
+fn same_kind(a: &Block, b: &Block) -> bool {
+    std::mem::discriminant(a) == std::mem::discriminant(b)
+}
+
+fn stack(world: &World, layer: usize, x: i64, y: i64) -> Option<&Vec<Block>> {
+    let (chunk, inner) = world.layers[layer].get_where(x, y);
+    world.layers[layer]
+        .get(&chunk)
+        .map(|chunk| &chunk[inner as usize])
+}
+
+/// applies the changes in order, returning the ones which fit their stacks
+fn apply_all(
+    world: &mut World,
+    layer: usize,
+    changes: Vec<(i64, i64, Change)>,
+) -> Vec<(i64, i64, Change)> {
+    changes
+        .into_iter()
+        .filter(|(x, y, change)| apply(world, layer, *x, *y, change))
+        .collect()
+}
+
+/// only creates the chunk, and only marks the layer as dirty, if the change fits the stack
+fn apply(world: &mut World, layer: usize, x: i64, y: i64, change: &Change) -> bool {
+    let layer = &mut world.layers[layer];
+    let (chunk, inner) = layer.get_where(x, y);
+    let applied = match layer.get_loaded_mut(&chunk) {
+        Ok(Some(chunk)) => change.apply(&mut chunk[inner as usize]),
+        // a missing chunk is the same as empty stacks
+        Ok(None) => {
+            let mut stack = vec![];
+            change.apply(&mut stack)
+                && layer
+                    .try_get_mut(&chunk)
+                    .map(|chunk| chunk[inner as usize] = stack)
+                    .inspect_err(|e| eprintln!("[warn] Couldn't edit the stack at ({x}, {y}): {e}"))
+                    .is_ok()
+        }
+        Err(e) => {
+            eprintln!("[warn] Couldn't edit the stack at ({x}, {y}): {e}");
+            false
+        }
+    };
+    if applied {
+        layer.mark_dirty();
+    }
+    applied
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn top(world: &World, x: i64, y: i64) -> Option<Block> {
+        world.layers[0].top_block(x, y).cloned()
+    }
+
+    #[test]
+    fn pushes_and_pops_can_be_undone() {
+        let mut world = World::new_empty();
+        let mut history = History::default();
+        history.push(&mut world, 0, -1, 20, Block::Color(1));
+        history.push(&mut world, 0, -1, 20, Block::Color(2));
+        history.pop(&mut world, 0, -1, 20);
+        assert_eq!(stack(&world, 0, -1, 20), Some(&vec![Block::Color(1)]));
+        assert!(history.undo(&mut world));
+        assert_eq!(top(&world, -1, 20), Some(Block::Color(2)));
+        assert!(history.undo(&mut world));
+        assert!(history.undo(&mut world));
+        assert_eq!(top(&world, -1, 20), None);
+        assert!(!history.undo(&mut world));
+        assert!(history.redo(&mut world));
+        assert!(history.redo(&mut world));
+        assert_eq!(top(&world, -1, 20), Some(Block::Color(2)));
+        // a new edit can't be followed by the undone ones
+        history.push(&mut world, 0, 5, 5, Block::Char('a' as u32));
+        assert!(!history.redo(&mut world));
+        assert!(history.undo(&mut world));
+        assert_eq!(top(&world, 5, 5), None);
+        assert_eq!(stack(&world, 0, -1, 20).map(Vec::len), Some(2));
+        // popping an empty stack isn't an edit
+        history.pop(&mut world, 0, 100, 100);
+        assert!(history.undo(&mut world));
+        assert_eq!(stack(&world, 0, -1, 20).map(Vec::len), Some(1));
+    }
+
+    #[test]
+    fn edits_changed_by_the_simulation_are_dropped() {
+        let mut world = World::new_empty();
+        let mut history = History::default();
+        history.push(&mut world, 0, 0, 0, Block::Color(1));
+        history.push(&mut world, 0, 1, 0, Block::Color(2));
+        // the simulation changed the color, which doesn't matter, and removed the other block
+        world.layers[0].try_pop_block(0, 0).unwrap();
+        world.layers[0]
+            .try_push_block(0, 0, Block::Color(3))
+            .unwrap();
+        world.layers[0].try_pop_block(1, 0).unwrap();
+        assert!(!history.undo(&mut world));
+        assert!(history.undo(&mut world));
+        assert_eq!(top(&world, 0, 0), None);
+        assert!(!history.undo(&mut world));
+    }
+
+    #[test]
+    fn pastes_are_undone_at_once() {
+        let mut world = World::new_empty();
+        let mut history = History::default();
+        history.push(&mut world, 0, 0, 0, Block::Color(1));
+        let stacks = [
+            ((0, 0), vec![Block::Not(0), Block::Color(2)]),
+            ((16, -16), vec![Block::Color(3)]),
+        ];
+        history.paste(&mut world, 0, 0, 0, &stacks);
+        history.fill(&mut world, 0, (-1, -1, 1, 1), &Block::Color(4));
+        assert_eq!(stack(&world, 0, 0, 0).map(Vec::len), Some(4));
+        assert!(history.undo(&mut world));
+        assert_eq!(top(&world, 0, 0), Some(Block::Color(2)));
+        assert_eq!(top(&world, 1, 1), None);
+        assert!(history.undo(&mut world));
+        assert_eq!(stack(&world, 0, 0, 0), Some(&vec![Block::Color(1)]));
+        assert_eq!(top(&world, 16, -16), None);
+        assert!(history.redo(&mut world));
+        assert_eq!(top(&world, 16, -16), Some(Block::Color(3)));
+        assert_eq!(stack(&world, 0, 0, 0).map(Vec::len), Some(3));
+    }
+
+    #[test]
+    fn groups_stay_separate_when_their_first_edit_is_dropped() {
+        let mut world = World::new_empty();
+        let mut history = History::default();
+        history.paste(
+            &mut world,
+            0,
+            0,
+            0,
+            &[
+                ((0, 0), vec![Block::Color(1)]),
+                ((1, 0), vec![Block::Color(1)]),
+            ],
+        );
+        history.paste(
+            &mut world,
+            0,
+            0,
+            5,
+            &[
+                ((0, 0), vec![Block::Color(2)]),
+                ((1, 0), vec![Block::Color(2)]),
+            ],
+        );
+        // the first stack of the second paste can't be undone anymore
+        world.layers[0].try_pop_block(0, 5).unwrap();
+        assert!(history.undo(&mut world));
+        assert_eq!(top(&world, 1, 5), None);
+        assert!(history.undo(&mut world));
+        assert_eq!(top(&world, 0, 0), None);
+        assert!(history.redo(&mut world));
+        assert_eq!(top(&world, 0, 0), Some(Block::Color(1)));
+        assert_eq!(top(&world, 1, 0), Some(Block::Color(1)));
+        assert_eq!(top(&world, 1, 5), None);
+        assert!(history.undo(&mut world));
+        assert_eq!(top(&world, 1, 0), None);
+        assert!(history.redo(&mut world));
+        assert!(history.redo(&mut world));
+        assert_eq!(top(&world, 1, 5), Some(Block::Color(2)));
+        assert!(history.undo(&mut world));
+        assert_eq!(top(&world, 1, 5), None);
+        assert_eq!(top(&world, 0, 0), Some(Block::Color(1)));
+        assert_eq!(top(&world, 1, 0), Some(Block::Color(1)));
+    }
+
+    #[test]
+    fn large_fills_are_remembered_as_one_edit() {
+        let mut world = World::new_empty();
+        let mut history = History::default();
+        history.fill(&mut world, 0, (0, 0, 99, 99), &Block::Color(1));
+        history.push(&mut world, 0, 200, 0, Block::Color(2));
+        assert!(history.undo(&mut world));
+        assert!(history.undo(&mut world));
+        assert_eq!(top(&world, 0, 0), None);
+        assert_eq!(top(&world, 99, 99), None);
+        assert!(!history.undo(&mut world));
+        for i in 0..=MAX_EDITS as i64 {
+            history.push(&mut world, 0, i, 0, Block::Color(3));
+        }
+        for _ in 0..MAX_EDITS {
+            assert!(history.undo(&mut world));
+        }
+        assert!(!history.undo(&mut world));
+        assert_eq!(top(&world, 0, 0), Some(Block::Color(3)));
+    }
+}
@@ -1,39 +1,121 @@
 use std::{
     collections::HashMap,
     fs,
-    io::{BufReader, Read},
+    io::Read,
     path::{Path, PathBuf},
-    sync::Arc,
+    sync::{Arc, Mutex},
     thread::JoinHandle,
+    time::SystemTime,
 };
 
 use image::{imageops, RgbaImage};
-use speedy2d::window::UserEventSender;
+use speedy2d::window::{EventLoopSendError, UserEventSender};
 
-use crate::{Config, Event};
+use crate::{builtin_assets, Config, Event, Frames};
 
 pub struct ThreadedLoading {
     pub config: Arc<Config>,
     pub thread: JoinHandle<Result<UserEventSender<Event>, LoadError>>,
 }
+/// written to config.txt if there is no config file
+const DEFAULT_CONFIG: &str = "# lines starting with # are ignored, all others are `key value`.
+# paths are relative to the directory the client is started in.
+# where worlds are saved
+saves-dir saves
+# the directory containing the menu/ and world/ asset directories
+assets-dir assets
+# any .ttf or .otf font file
+main-font /usr/share/fonts/TTF/DejaVuSans.ttf
+# used for numbers and other text where all characters should have the same width
+mono-font /usr/share/fonts/TTF/DejaVuSansMono.ttf
+# reload assets when they change, useful while editing them
+hot-reload false
+";
 #[derive(Debug)]
 pub enum ConfigLoadError {
     NoConfig(std::io::Error),
+    /// there was no config file, so a template was written to its path. it has to be edited before starting again.
+    CreatedDefaultConfig,
     NoSavesDir,
     NoAssetsDir,
     NoMainFont,
     NoMonoFont,
 }
+/// (name, directory) of the assets which couldn't be found.
+/// the image loading functions can't capture the event sender, so they collect them here.
+static MISSING_ASSETS: Mutex<Vec<(String, PathBuf)>> = Mutex::new(Vec::new());
+/// logs the missing asset and remembers it, so it can be shown in the main menu
+fn missing_asset(name: &str, assets_path: &Path) {
+    eprintln!("No asset named '{name}' found in {assets_path:?}.");
+    let mut missing = MISSING_ASSETS.lock().unwrap();
+    // assets which are still missing after a reload are reported again
+    if !missing
+        .iter()
+        .any(|(n, p)| n == name && p.as_path() == assets_path)
+    {
+        missing.push((name.to_owned(), assets_path.to_owned()));
+    }
+}
+/// `changed` is `None` on the first loading pass, and the names of the files which were added, changed or removed on a reload.
+/// an asset whose files' names start with `prefix` only has to be loaded again if one of these files changed.
+fn needs_reload(prefix: &str, changed: Option<&[String]>) -> bool {
+    changed.map_or(true, |changed| {
+        changed.iter().any(|name| name.starts_with(prefix))
+    })
+}
+/// sending the loaded assets fails once the window was closed
+type Sent = Result<(), EventLoopSendError>;
 #[derive(Debug)]
 pub enum LoadError {
     MainFont(std::io::Error),
     MonoFont(std::io::Error),
     CouldNotReadSavesDirectory(std::io::Error),
-    /// String is path relative to assets dir
-    MissingAsset(String),
 }
 
 impl ThreadedLoading {
+    /// loads any format the `image` crate supports, guessing it from the file's contents.
+    /// errors are logged and the image is skipped.
+    pub fn open_image_file(p: &Path) -> Option<RgbaImage> {
+        let reader =
+            match image::io::Reader::open(p).and_then(|reader| reader.with_guessed_format()) {
+                Ok(v) => v,
+                Err(e) => {
+                    eprintln!("Error opening file {p:?}: {e}");
+                    return None;
+                }
+            };
+        if reader.format().is_none() {
+            eprintln!("Skipping image {p:?}: unknown or unsupported image format");
+            return None;
+        }
+        match reader.decode() {
+            Ok(image) => Some(image.into_rgba8()),
+            Err(e) => {
+                eprintln!("Skipping image {p:?}, it couldn't be decoded: {e}");
+                None
+            }
+        }
+    }
+    /// every file in `{assets_dir}/*/*/*` with its modification time, sorted by path
+    fn assets_modification_times<P: AsRef<Path>>(assets_dir: P) -> Vec<(PathBuf, SystemTime)> {
+        fn add_files(dir: &Path, depth: usize, out: &mut Vec<(PathBuf, SystemTime)>) {
+            if let Ok(entries) = fs::read_dir(dir) {
+                for entry in entries.flatten() {
+                    if let Ok(meta) = entry.metadata() {
+                        if meta.is_dir() && depth > 0 {
+                            add_files(&entry.path(), depth - 1, out);
+                        } else if let (true, Ok(modified)) = (meta.is_file(), meta.modified()) {
+                            out.push((entry.path(), modified));
+                        }
+                    }
+                }
+            }
+        }
+        let mut out = vec![];
+        add_files(assets_dir.as_ref(), 2, &mut out);
+        out.sort_unstable();
+        out
+    }
     fn assets_priority_table<P: AsRef<Path>>(
         dir: P,
     ) -> Result<HashMap<String, Vec<u32>>, std::io::Error> {
@@ -84,18 +166,60 @@ impl ThreadedLoading {
             None
         }
     }
+    /// Each config value is taken from the first of these which has it:
+    /// 1. the command-line arguments (`--saves-dir <path>`, ...)
+    /// 2. the config file, which is `config.txt` unless `--config <path>` is given
     pub fn new(event_sender: UserEventSender<Event>) -> Result<Self, ConfigLoadError> {
         let mut saves_dir = Err(ConfigLoadError::NoSavesDir);
         let mut assets_dir = Err(ConfigLoadError::NoAssetsDir);
         let mut main_font = Err(ConfigLoadError::NoMainFont);
         let mut mono_font = Err(ConfigLoadError::NoMonoFont);
-        for (i, line) in match fs::read_to_string("config.txt") {
+        let mut hot_reload = false;
+        let mut config_path = "config.txt".to_owned();
+        let mut cli_values = vec![];
+        let mut args = std::env::args().skip(1);
+        while let Some(arg) = args.next() {
+            // both `--key value` and `--key=value` work
+            let (key, val) = match arg.split_once('=') {
+                Some((key, val)) => (key.to_owned(), Some(val.to_owned())),
+                None => (arg, None),
+            };
+            match key.as_str() {
+                "--config" | "--saves-dir" | "--assets-dir" | "--main-font" | "--mono-font" => {
+                    if let Some(val) = val.or_else(|| args.next()) {
+                        if key == "--config" {
+                            config_path = val;
+                        } else {
+                            cli_values.push((key[2..].to_owned(), val));
+                        }
+                    } else {
+                        eprintln!("Ignoring argument '{key}' because it needs a value.");
+                    }
+                }
+                _ => eprintln!("Ignoring unknown argument '{key}'. Known arguments are --config, --saves-dir, --assets-dir, --main-font and --mono-font, each followed by a path."),
+            }
+        }
+        let all_given_as_arguments = ["saves-dir", "assets-dir", "main-font", "mono-font"]
+            .iter()
+            .all(|key| cli_values.iter().any(|(k, _)| k == key));
+        let config_file = match fs::read_to_string(&config_path) {
             Ok(v) => v,
+            // if everything was given as arguments, the file isn't needed
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound && all_given_as_arguments => {
+                String::new()
+            }
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => {
+                return Err(match fs::write(&config_path, DEFAULT_CONFIG) {
+                    Ok(()) => {
+                        eprintln!("[info] Created a default config file at {config_path}, please edit it (especially the font paths) and start again.");
+                        ConfigLoadError::CreatedDefaultConfig
+                    }
+                    Err(e) => ConfigLoadError::NoConfig(e),
+                });
+            }
             Err(e) => return Err(ConfigLoadError::NoConfig(e)),
-        }
-        .lines()
-        .enumerate()
-        {
+        };
+        for (i, line) in config_file.lines().enumerate() {
             if line.starts_with('#') {
                 continue;
             }
@@ -105,6 +229,7 @@ impl ThreadedLoading {
                     "assets-dir" => assets_dir = Ok(val.to_owned()),
                     "main-font" => main_font = Ok(val.to_owned()),
                     "mono-font" => mono_font = Ok(val.to_owned()),
+                    "hot-reload" => hot_reload = val == "true",
                     _ => eprintln!(
                         "Ignoring line {} in config file because key '{key}' is unknown.",
                         i + 1
@@ -117,11 +242,22 @@ impl ThreadedLoading {
                 );
             }
         }
+        // arguments override the config file
+        for (key, val) in cli_values {
+            match key.as_str() {
+                "saves-dir" => saves_dir = Ok(val),
+                "assets-dir" => assets_dir = Ok(val),
+                "main-font" => main_font = Ok(val),
+                "mono-font" => mono_font = Ok(val),
+                _ => unreachable!(),
+            }
+        }
         let config = Arc::new(Config {
             main_font: main_font?,
             mono_font: mono_font?,
             saves_dir: saves_dir?,
             assets_dir: assets_dir?,
+            hot_reload,
         });
         Ok(Self {
             config: Arc::clone(&config),
@@ -140,23 +276,6 @@ impl ThreadedLoading {
                     Err(e) => return Err(LoadError::MonoFont(e)),
                     Ok(v) => event_sender.send_event(Event::LoadFontMono(v)).unwrap(),
                 }
-                fn open_image_file(p: &PathBuf) -> Option<RgbaImage> {
-                    match fs::File::open(p) {
-                        Ok(file) => {
-                            match image::load(BufReader::new(file), image::ImageFormat::Png) {
-                                Ok(image) => Some(image.into_rgba8()),
-                                Err(e) => {
-                                    eprintln!("Error loading image {p:?}: {e}");
-                                    None
-                                }
-                            }
-                        }
-                        Err(e) => {
-                            eprintln!("Error opening file {p:?}: {e}");
-                            None
-                        }
-                    }
-                }
                 fn load_first_image_to_rgba(
                     name: &str,
                     assets_path: &PathBuf,
@@ -166,18 +285,42 @@ impl ThreadedLoading {
                         name,
                         &assets_path,
                         &assets_table,
-                        |_, p| open_image_file(&p),
+                        |_, p| ThreadedLoading::open_image_file(&p),
                     );
                     if o.is_none() {
-                        eprintln!("No asset named '{name}' found in {assets_path:?}.");
+                        let builtin = builtin_asset(name, assets_path);
+                        if builtin.is_none() {
+                            missing_asset(name, assets_path);
+                        }
+                        return builtin;
                     }
                     o
                 }
+                /// like `load_first_image_to_rgba`, but returns `None` if the image doesn't need to be reloaded.
+                /// "{name}.png" and, for example, "{name}_old.png" count as the same image, see `needs_reload`.
+                fn load_changed_image_to_rgba(
+                    name: &str,
+                    assets_path: &PathBuf,
+                    assets_table: &HashMap<String, Vec<u32>>,
+                    changed: Option<&[String]>,
+                ) -> Option<RgbaImage> {
+                    let stem = name.rsplit_once('.').map_or(name, |(stem, _)| stem);
+                    if !needs_reload(stem, changed) {
+                        return None;
+                    }
+                    load_first_image_to_rgba(name, assets_path, assets_table)
+                }
+                /// `assets_path` is `assets/menu` or `assets/world`, which decides where the built-in asset is taken from
+                fn builtin_asset(name: &str, assets_path: &Path) -> Option<RgbaImage> {
+                    let kind = assets_path.file_name()?.to_str()?;
+                    builtin_assets::open(kind, name)
+                }
                 /// inserts up/down/right/left between `name` and `ext`.
                 /// it then finds the highest priority directory with at least one of these images.
                 /// from there, it uses autorotate to create four images from however many were found.
+                /// if no directory contains any of the images, the built-in images are used instead.
                 /// returns `None` if
-                /// - no directory contained any image
+                /// - no directory contained any image, and there are no built-in ones
                 /// - the chosen directory's images couldn't be loaded, but exist on disk
                 fn load_four_images_rgba(
                     name: &str,
@@ -206,7 +349,7 @@ impl ThreadedLoading {
                             .map(|v| {
                                 if v.as_ref()?.0 == max {
                                     let path = v?.1;
-                                    open_image_file(&path)
+                                    ThreadedLoading::open_image_file(&path)
                                 } else {
                                     None
                                 }
@@ -218,8 +361,17 @@ impl ThreadedLoading {
                         let f1 = found.pop()?;
                         autorotate_rgba_images(f1, f2, f3, f4)
                     } else {
-                        eprintln!("No asset named '{name}{{up/down/right/left}}.png' could be found anywhere in {assets_path:?}. (need at least one of four)");
-                        None
+                        let [up, down, right, left] = ["up", "down", "right", "left"]
+                            .map(|dir| builtin_asset(&format!("{name}{dir}{ext}"), assets_path));
+                        let imgs = autorotate_rgba_images(up, down, right, left);
+                        if imgs.is_none() {
+                            // only one of the four is needed
+                            missing_asset(
+                                &format!("{name}{{up/down/right/left}}{ext}"),
+                                assets_path,
+                            );
+                        }
+                        imgs
                     }
                 }
                 /// given at least one of four images, this method will return four images by rotating the images it was given.
@@ -261,30 +413,93 @@ impl ThreadedLoading {
                     };
                     Some([up, down, right, left])
                 }
-                // load menu assets (assets/menu/*/*)
-                let assets_path_menu = Path::new(&config.assets_dir).join("menu");
-                let assets_table_menu = match Self::assets_priority_table(&assets_path_menu) {
-                    Ok(v) => v,
-                    Err(_) => return Err(LoadError::MissingAsset("menu".to_owned())),
-                };
-                if let Some(bg) = load_first_image_to_rgba(
-                    "background.png",
-                    &assets_path_menu,
-                    &assets_table_menu,
-                ) {
-                    event_sender
-                        .send_event(Event::SetMainMenuBackgroundImage(bg))
-                        .unwrap();
+                /// if "{name}_0.png" exists, this loads "{name}_0.png", "{name}_1.png", ... until one is missing.
+                /// otherwise, it loads "{name}.png" as the only frame.
+                fn load_frames_rgba(
+                    name: &str,
+                    assets_path: &PathBuf,
+                    assets_table: &HashMap<String, Vec<u32>>,
+                ) -> Option<Frames> {
+                    let frame_name = |i: usize| format!("{name}_{i}.png");
+                    if !assets_table.contains_key(&frame_name(0)) {
+                        return load_first_image_to_rgba(
+                            &format!("{name}.png"),
+                            assets_path,
+                            assets_table,
+                        )
+                        .map(|img| vec![img]);
+                    }
+                    let frames: Frames = (0..)
+                        .map(frame_name)
+                        .take_while(|name| assets_table.contains_key(name))
+                        .filter_map(|name| {
+                            load_first_image_to_rgba(&name, assets_path, assets_table)
+                        })
+                        .collect();
+                    Some(frames).filter(|frames| !frames.is_empty())
                 }
-                if let Some(btn) = load_first_image_to_rgba(
-                    "new_singleplayer_world_button.png",
-                    &assets_path_menu,
-                    &assets_table_menu,
-                ) {
-                    event_sender
-                        .send_event(Event::SetMainMenuSingleplayerNewWorldImage(btn))
-                        .unwrap();
+                /// like `load_frames_rgba`, but for the four directions of `load_four_images_rgba`.
+                /// frame `i` is "{name}{up/down/right/left}_{i}.png", and each frame is autorotated on its own.
+                fn load_four_frames_rgba(
+                    name: &str,
+                    assets_path: &PathBuf,
+                    assets_table: &HashMap<String, Vec<u32>>,
+                ) -> Option<[Frames; 4]> {
+                    let has_frame = |i: usize| {
+                        ["up", "down", "right", "left"]
+                            .iter()
+                            .any(|dir| assets_table.contains_key(&format!("{name}{dir}_{i}.png")))
+                    };
+                    if !has_frame(0) {
+                        return load_four_images_rgba(name, ".png", assets_path, assets_table)
+                            .map(|imgs| imgs.map(|img| vec![img]));
+                    }
+                    let mut frames: [Frames; 4] = Default::default();
+                    for i in (0..).take_while(|i| has_frame(*i)) {
+                        if let Some(imgs) = load_four_images_rgba(
+                            name,
+                            &format!("_{i}.png"),
+                            assets_path,
+                            assets_table,
+                        ) {
+                            for (frames, img) in frames.iter_mut().zip(imgs) {
+                                frames.push(img);
+                            }
+                        }
+                    }
+                    Some(frames).filter(|frames| !frames[0].is_empty())
                 }
+                // load menu assets (assets/menu/*/*)
+                // if the directory is missing, the built-in assets are used
+                let load_menu_assets = |changed: Option<&[String]>| -> Sent {
+                    let assets_path_menu = Path::new(&config.assets_dir).join("menu");
+                    let assets_table_menu = match Self::assets_priority_table(&assets_path_menu) {
+                        Ok(v) => v,
+                        Err(e) => {
+                            eprintln!("[warn] Couldn't read {assets_path_menu:?}, using the built-in assets: {e}");
+                            HashMap::new()
+                        }
+                    };
+                    if let Some(bg) = load_changed_image_to_rgba(
+                        "background.png",
+                        &assets_path_menu,
+                        &assets_table_menu,
+                        changed,
+                    ) {
+                        event_sender.send_event(Event::SetMainMenuBackgroundImage(bg))?;
+                    }
+                    if let Some(btn) = load_changed_image_to_rgba(
+                        "new_singleplayer_world_button.png",
+                        &assets_path_menu,
+                        &assets_table_menu,
+                        changed,
+                    ) {
+                        event_sender
+                            .send_event(Event::SetMainMenuSingleplayerNewWorldImage(btn))?;
+                    }
+                    Ok(())
+                };
+                load_menu_assets(None).unwrap();
                 // load worlds
                 for dir in match fs::read_dir(&config.saves_dir) {
                     Ok(v) => v,
@@ -294,9 +509,18 @@ impl ThreadedLoading {
                         if dir.metadata().is_ok_and(|meta| meta.is_dir()) {
                             let path = dir.path();
                             let name = path.file_name().unwrap().to_string_lossy().into_owned();
+                            let thumbnail = path.join(stackmaker::thumbnail::THUMBNAIL_FILE);
                             event_sender
-                                .send_event(Event::AddWorld(path, name))
+                                .send_event(Event::AddWorld(path.clone(), name))
                                 .unwrap();
+                            // saves without a thumbnail get a placeholder in the menu
+                            if thumbnail.is_file() {
+                                if let Some(img) = Self::open_image_file(&thumbnail) {
+                                    event_sender
+                                        .send_event(Event::SetWorldThumbnail(path, img))
+                                        .unwrap();
+                                }
+                            }
                             // match World::load_from_dir(&path) {
                             //     Err(e) => eprintln!("Couldn't load world from {dir:?}: {e:?}"),
                             //     Ok(None) => {
@@ -318,285 +542,471 @@ impl ThreadedLoading {
                     }
                 }
                 // load world assets (assets/world/*/*)
-                let assets_path_world = Path::new(&config.assets_dir).join("world");
-                let assets_table_world = match Self::assets_priority_table(&assets_path_world) {
-                    Ok(v) => v,
-                    Err(_) => return Err(LoadError::MissingAsset("world".to_owned())),
+                let load_world_assets = |changed: Option<&[String]>| -> Sent {
+                    let assets_path_world = Path::new(&config.assets_dir).join("world");
+                    let assets_table_world = match Self::assets_priority_table(&assets_path_world) {
+                        Ok(v) => v,
+                        Err(e) => {
+                            eprintln!("[warn] Couldn't read {assets_path_world:?}, using the built-in assets: {e}");
+                            HashMap::new()
+                        }
+                    };
+                    /// actual file names are "{name}{to/away/up/down/right/left}.png",
+                    /// or "{name}{to/away/up/down/right/left}_{frame}.png" for animations (see `load_frames_rgba`).
+                    /// value is up, down, right, left, to, away.
+                    /// nothing is loaded if none of these files changed, see `needs_reload`.
+                    fn load_six_images_and_send<F: FnOnce([Option<Frames>; 6]) -> Sent>(
+                        name: &str,
+                        f: F,
+                        assets_path: &PathBuf,
+                        assets_table: &HashMap<String, Vec<u32>>,
+                        changed: Option<&[String]>,
+                    ) -> Sent {
+                        if !needs_reload(name, changed) {
+                            return Ok(());
+                        }
+                        let to = load_frames_rgba(&format!("{name}to"), assets_path, assets_table);
+                        let away =
+                            load_frames_rgba(&format!("{name}away"), assets_path, assets_table);
+                        if let Some(imgs) = load_four_frames_rgba(name, &assets_path, &assets_table)
+                        {
+                            let [f1, f2, f3, f4] = imgs;
+                            f([Some(f1), Some(f2), Some(f3), Some(f4), to, away])
+                        } else if to.is_some() || away.is_some() {
+                            f([None, None, None, None, to, away])
+                        } else {
+                            Ok(())
+                        }
+                    }
+                    if let Some(img) = load_changed_image_to_rgba(
+                        "menu_arrow_selected.png",
+                        &assets_path_world,
+                        &assets_table_world,
+                        changed,
+                    ) {
+                        event_sender.send_event(Event::SetWorldMenuArrowSelected(img))?;
+                    }
+                    if let Some(img) = load_changed_image_to_rgba(
+                        "menu_arrow_source.png",
+                        &assets_path_world,
+                        &assets_table_world,
+                        changed,
+                    ) {
+                        event_sender.send_event(Event::SetWorldMenuArrowSource(img))?;
+                    }
+                    if let Some(img) = load_changed_image_to_rgba(
+                        "menu_arrow_target.png",
+                        &assets_path_world,
+                        &assets_table_world,
+                        changed,
+                    ) {
+                        event_sender.send_event(Event::SetWorldMenuArrowTarget(img))?;
+                    }
+                    if let Some(img) = load_changed_image_to_rgba(
+                        "menu_button_pause.png",
+                        &assets_path_world,
+                        &assets_table_world,
+                        changed,
+                    ) {
+                        event_sender.send_event(Event::SetWorldMenuButtonPause(img))?;
+                    }
+                    if let Some(img) = load_changed_image_to_rgba(
+                        "menu_button_paused.png",
+                        &assets_path_world,
+                        &assets_table_world,
+                        changed,
+                    ) {
+                        event_sender.send_event(Event::SetWorldMenuButtonPaused(img))?;
+                    }
+                    if let Some(img) = load_changed_image_to_rgba(
+                        "menu_button_tick.png",
+                        &assets_path_world,
+                        &assets_table_world,
+                        changed,
+                    ) {
+                        event_sender.send_event(Event::SetWorldMenuButtonTick(img))?;
+                    }
+                    if let Some(img) = load_changed_image_to_rgba(
+                        "menu_button_signalzero.png",
+                        &assets_path_world,
+                        &assets_table_world,
+                        changed,
+                    ) {
+                        event_sender.send_event(Event::SetWorldMenuButtonSignalzero(img))?;
+                    }
+                    if let Some(img) = load_changed_image_to_rgba(
+                        "menu_button_delete.png",
+                        &assets_path_world,
+                        &assets_table_world,
+                        changed,
+                    ) {
+                        event_sender.send_event(Event::SetWorldMenuButtonDelete(img))?;
+                    }
+                    if let Some(img) = load_changed_image_to_rgba(
+                        "menu_button_clear.png",
+                        &assets_path_world,
+                        &assets_table_world,
+                        changed,
+                    ) {
+                        event_sender.send_event(Event::SetWorldMenuButtonClear(img))?;
+                    }
+                    load_six_images_and_send(
+                        "signal_",
+                        |v| event_sender.send_event(Event::SetWorldSignal(v)),
+                        &assets_path_world,
+                        &assets_table_world,
+                        changed,
+                    )?;
+                    if let Some(img) = load_changed_image_to_rgba(
+                        "block_color.png",
+                        &assets_path_world,
+                        &assets_table_world,
+                        changed,
+                    ) {
+                        event_sender.send_event(Event::SetWorldBlockColor(img))?;
+                    }
+                    if let Some(img) = load_changed_image_to_rgba(
+                        "block_char.png",
+                        &assets_path_world,
+                        &assets_table_world,
+                        changed,
+                    ) {
+                        event_sender.send_event(Event::SetWorldBlockChar(img))?;
+                    }
+                    if let Some(img) = load_changed_image_to_rgba(
+                        "block_fan.png",
+                        &assets_path_world,
+                        &assets_table_world,
+                        changed,
+                    ) {
+                        event_sender.send_event(Event::SetWorldBlockFan(img))?;
+                    }
+                    load_six_images_and_send(
+                        "block_delay_",
+                        |v| event_sender.send_event(Event::SetWorldBlockDelay(v)),
+                        &assets_path_world,
+                        &assets_table_world,
+                        changed,
+                    )?;
+                    load_six_images_and_send(
+                        "block_delay_value_",
+                        |v| event_sender.send_event(Event::SetWorldBlockDelayValue(v)),
+                        &assets_path_world,
+                        &assets_table_world,
+                        changed,
+                    )?;
+                    load_six_images_and_send(
+                        "block_storage_sto_",
+                        |v| event_sender.send_event(Event::SetWorldBlockStorageSto(v)),
+                        &assets_path_world,
+                        &assets_table_world,
+                        changed,
+                    )?;
+                    load_six_images_and_send(
+                        "block_storage_or_",
+                        |v| event_sender.send_event(Event::SetWorldBlockStorageOr(v)),
+                        &assets_path_world,
+                        &assets_table_world,
+                        changed,
+                    )?;
+                    load_six_images_and_send(
+                        "block_storage_and_",
+                        |v| event_sender.send_event(Event::SetWorldBlockStorageAnd(v)),
+                        &assets_path_world,
+                        &assets_table_world,
+                        changed,
+                    )?;
+                    load_six_images_and_send(
+                        "block_storage_xor_",
+                        |v| event_sender.send_event(Event::SetWorldBlockStorageXor(v)),
+                        &assets_path_world,
+                        &assets_table_world,
+                        changed,
+                    )?;
+                    load_six_images_and_send(
+                        "block_storage_add_",
+                        |v| event_sender.send_event(Event::SetWorldBlockStorageAdd(v)),
+                        &assets_path_world,
+                        &assets_table_world,
+                        changed,
+                    )?;
+                    load_six_images_and_send(
+                        "block_storage_sub_",
+                        |v| event_sender.send_event(Event::SetWorldBlockStorageSub(v)),
+                        &assets_path_world,
+                        &assets_table_world,
+                        changed,
+                    )?;
+                    load_six_images_and_send(
+                        "block_storage_mul_",
+                        |v| event_sender.send_event(Event::SetWorldBlockStorageMul(v)),
+                        &assets_path_world,
+                        &assets_table_world,
+                        changed,
+                    )?;
+                    load_six_images_and_send(
+                        "block_storage_div_",
+                        |v| event_sender.send_event(Event::SetWorldBlockStorageDiv(v)),
+                        &assets_path_world,
+                        &assets_table_world,
+                        changed,
+                    )?;
+                    load_six_images_and_send(
+                        "block_storage_mod_",
+                        |v| event_sender.send_event(Event::SetWorldBlockStorageMod(v)),
+                        &assets_path_world,
+                        &assets_table_world,
+                        changed,
+                    )?;
+                    load_six_images_and_send(
+                        "block_storage_default_",
+                        |v| event_sender.send_event(Event::SetWorldBlockStorageDefault(v)),
+                        &assets_path_world,
+                        &assets_table_world,
+                        changed,
+                    )?;
+                    load_six_images_and_send(
+                        "block_gate_open_",
+                        |v| event_sender.send_event(Event::SetWorldBlockGateOpen(v)),
+                        &assets_path_world,
+                        &assets_table_world,
+                        changed,
+                    )?;
+                    load_six_images_and_send(
+                        "block_gate_closed_",
+                        |v| event_sender.send_event(Event::SetWorldBlockGateClosed(v)),
+                        &assets_path_world,
+                        &assets_table_world,
+                        changed,
+                    )?;
+                    load_six_images_and_send(
+                        "block_gate_inv_",
+                        |v| event_sender.send_event(Event::SetWorldBlockGateInv(v)),
+                        &assets_path_world,
+                        &assets_table_world,
+                        changed,
+                    )?;
+                    load_six_images_and_send(
+                        "block_gate_pass_",
+                        |v| event_sender.send_event(Event::SetWorldBlockGatePass(v)),
+                        &assets_path_world,
+                        &assets_table_world,
+                        changed,
+                    )?;
+                    load_six_images_and_send(
+                        "block_splitter_",
+                        |v| event_sender.send_event(Event::SetWorldBlockSplitter(v)),
+                        &assets_path_world,
+                        &assets_table_world,
+                        changed,
+                    )?;
+                    load_six_images_and_send(
+                        "block_move_",
+                        |v| event_sender.send_event(Event::SetWorldBlockMove(v)),
+                        &assets_path_world,
+                        &assets_table_world,
+                        changed,
+                    )?;
+                    load_six_images_and_send(
+                        "block_swap_",
+                        |v| event_sender.send_event(Event::SetWorldBlockSwap(v)),
+                        &assets_path_world,
+                        &assets_table_world,
+                        changed,
+                    )?;
+                    load_six_images_and_send(
+                        "block_swap_all_",
+                        |v| event_sender.send_event(Event::SetWorldBlockSwapAll(v)),
+                        &assets_path_world,
+                        &assets_table_world,
+                        changed,
+                    )?;
+                    load_six_images_and_send(
+                        "block_duplicate_",
+                        |v| event_sender.send_event(Event::SetWorldBlockDuplicate(v)),
+                        &assets_path_world,
+                        &assets_table_world,
+                        changed,
+                    )?;
+                    load_six_images_and_send(
+                        "block_destroy_",
+                        |v| event_sender.send_event(Event::SetWorldBlockDestroy(v)),
+                        &assets_path_world,
+                        &assets_table_world,
+                        changed,
+                    )?;
+                    load_six_images_and_send(
+                        "block_not_",
+                        |v| event_sender.send_event(Event::SetWorldBlockNot(v)),
+                        &assets_path_world,
+                        &assets_table_world,
+                        changed,
+                    )?;
+                    load_six_images_and_send(
+                        "block_probe_",
+                        |v| event_sender.send_event(Event::SetWorldBlockProbe(v)),
+                        &assets_path_world,
+                        &assets_table_world,
+                        changed,
+                    )?;
+                    if let Some(img) = load_changed_image_to_rgba(
+                        "block_clock.png",
+                        &assets_path_world,
+                        &assets_table_world,
+                        changed,
+                    ) {
+                        event_sender.send_event(Event::SetWorldBlockClock(img))?;
+                    }
+                    if let Some(img) = load_changed_image_to_rgba(
+                        "block_random.png",
+                        &assets_path_world,
+                        &assets_table_world,
+                        changed,
+                    ) {
+                        event_sender.send_event(Event::SetWorldBlockRandom(img))?;
+                    }
+                    load_six_images_and_send(
+                        "block_compare_lt_",
+                        |v| event_sender.send_event(Event::SetWorldBlockCompareLt(v)),
+                        &assets_path_world,
+                        &assets_table_world,
+                        changed,
+                    )?;
+                    load_six_images_and_send(
+                        "block_compare_le_",
+                        |v| event_sender.send_event(Event::SetWorldBlockCompareLe(v)),
+                        &assets_path_world,
+                        &assets_table_world,
+                        changed,
+                    )?;
+                    load_six_images_and_send(
+                        "block_compare_eq_",
+                        |v| event_sender.send_event(Event::SetWorldBlockCompareEq(v)),
+                        &assets_path_world,
+                        &assets_table_world,
+                        changed,
+                    )?;
+                    load_six_images_and_send(
+                        "block_compare_ge_",
+                        |v| event_sender.send_event(Event::SetWorldBlockCompareGe(v)),
+                        &assets_path_world,
+                        &assets_table_world,
+                        changed,
+                    )?;
+                    load_six_images_and_send(
+                        "block_compare_gt_",
+                        |v| event_sender.send_event(Event::SetWorldBlockCompareGt(v)),
+                        &assets_path_world,
+                        &assets_table_world,
+                        changed,
+                    )?;
+                    load_six_images_and_send(
+                        "block_compare_default_",
+                        |v| event_sender.send_event(Event::SetWorldBlockCompareDefault(v)),
+                        &assets_path_world,
+                        &assets_table_world,
+                        changed,
+                    )?;
+                    load_six_images_and_send(
+                        "block_height_",
+                        |v| event_sender.send_event(Event::SetWorldBlockHeight(v)),
+                        &assets_path_world,
+                        &assets_table_world,
+                        changed,
+                    )?;
+                    load_six_images_and_send(
+                        "block_storage_wadd_",
+                        |v| event_sender.send_event(Event::SetWorldBlockStorageWadd(v)),
+                        &assets_path_world,
+                        &assets_table_world,
+                        changed,
+                    )?;
+                    load_six_images_and_send(
+                        "block_storage_wsub_",
+                        |v| event_sender.send_event(Event::SetWorldBlockStorageWsub(v)),
+                        &assets_path_world,
+                        &assets_table_world,
+                        changed,
+                    )?;
+                    load_six_images_and_send(
+                        "block_storage_wmul_",
+                        |v| event_sender.send_event(Event::SetWorldBlockStorageWmul(v)),
+                        &assets_path_world,
+                        &assets_table_world,
+                        changed,
+                    )?;
+                    load_six_images_and_send(
+                        "block_storage_min_",
+                        |v| event_sender.send_event(Event::SetWorldBlockStorageMin(v)),
+                        &assets_path_world,
+                        &assets_table_world,
+                        changed,
+                    )?;
+                    load_six_images_and_send(
+                        "block_storage_max_",
+                        |v| event_sender.send_event(Event::SetWorldBlockStorageMax(v)),
+                        &assets_path_world,
+                        &assets_table_world,
+                        changed,
+                    )?;
+                    load_six_images_and_send(
+                        "block_counter_",
+                        |v| event_sender.send_event(Event::SetWorldBlockCounter(v)),
+                        &assets_path_world,
+                        &assets_table_world,
+                        changed,
+                    )?;
+                    Ok(())
                 };
-                /// actual file names are "{name}{to/away/up/down/right/left}.png".
-                /// value is up, down, right, left, to, away
-                fn load_six_images_and_send<F: FnOnce([Option<RgbaImage>; 6])>(
-                    name: &str,
-                    f: F,
-                    assets_path: &PathBuf,
-                    assets_table: &HashMap<String, Vec<u32>>,
-                ) {
-                    let to = load_first_image_to_rgba(
-                        &format!("{name}to.png"),
-                        assets_path,
-                        assets_table,
-                    );
-                    let away = load_first_image_to_rgba(
-                        &format!("{name}away.png"),
-                        assets_path,
-                        assets_table,
-                    );
-                    if let Some(imgs) =
-                        load_four_images_rgba(name, ".png", &assets_path, &assets_table)
-                    {
-                        let [f1, f2, f3, f4] = imgs;
-                        f([Some(f1), Some(f2), Some(f3), Some(f4), to, away]);
-                    } else if to.is_some() || away.is_some() {
-                        f([None, None, None, None, to, away])
+                load_world_assets(None).unwrap();
+                let send_missing_assets = || {
+                    let missing = MISSING_ASSETS
+                        .lock()
+                        .unwrap()
+                        .iter()
+                        .map(|(name, path)| format!("{name} (in {})", path.display()))
+                        .collect();
+                    event_sender.send_event(Event::SetMissingAssets(missing))
+                };
+                send_missing_assets().unwrap();
+                // reload the assets whose files changed.
+                // std has no file watching, so the modification times are polled.
+                if config.hot_reload {
+                    let mut last = Self::assets_modification_times(&config.assets_dir);
+                    loop {
+                        std::thread::sleep(std::time::Duration::from_secs(1));
+                        let now = Self::assets_modification_times(&config.assets_dir);
+                        // the names of the files which were added, changed or removed.
+                        // both lists are sorted by path, so they can be searched.
+                        let changed: Vec<String> = now
+                            .iter()
+                            .filter(|file| last.binary_search(file).is_err())
+                            .chain(last.iter().filter(|file| now.binary_search(file).is_err()))
+                            .filter_map(|(path, _)| Some(path.file_name()?.to_str()?.to_owned()))
+                            .collect();
+                        last = now;
+                        if changed.is_empty() {
+                            continue;
+                        }
+                        eprintln!("[info] Assets changed, reloading {changed:?}...");
+                        // these are reported again if they are still missing
+                        MISSING_ASSETS.lock().unwrap().retain(|(name, _)| {
+                            // "{prefix}{up/down/right/left}.png" or "{stem}.png"
+                            let prefix = name
+                                .split_once('{')
+                                .or_else(|| name.rsplit_once('.'))
+                                .map_or(name.as_str(), |(prefix, _)| prefix);
+                            !needs_reload(prefix, Some(&changed))
+                        });
+                        let reloaded = load_menu_assets(Some(&changed))
+                            .and_then(|()| load_world_assets(Some(&changed)))
+                            .and_then(|()| send_missing_assets());
+                        if reloaded.is_err() {
+                            eprintln!("[info] The window was closed, no longer reloading assets.");
+                            break;
+                        }
                     }
                 }
-                if let Some(img) = load_first_image_to_rgba(
-                    "menu_arrow_selected.png",
-                    &assets_path_world,
-                    &assets_table_world,
-                ) {
-                    event_sender
-                        .send_event(Event::SetWorldMenuArrowSelected(img))
-                        .unwrap();
-                }
-                if let Some(img) = load_first_image_to_rgba(
-                    "menu_arrow_source.png",
-                    &assets_path_world,
-                    &assets_table_world,
-                ) {
-                    event_sender
-                        .send_event(Event::SetWorldMenuArrowSource(img))
-                        .unwrap();
-                }
-                if let Some(img) = load_first_image_to_rgba(
-                    "menu_arrow_target.png",
-                    &assets_path_world,
-                    &assets_table_world,
-                ) {
-                    event_sender
-                        .send_event(Event::SetWorldMenuArrowTarget(img))
-                        .unwrap();
-                }
-                if let Some(img) = load_first_image_to_rgba(
-                    "menu_button_pause.png",
-                    &assets_path_world,
-                    &assets_table_world,
-                ) {
-                    event_sender
-                        .send_event(Event::SetWorldMenuButtonPause(img))
-                        .unwrap();
-                }
-                if let Some(img) = load_first_image_to_rgba(
-                    "menu_button_paused.png",
-                    &assets_path_world,
-                    &assets_table_world,
-                ) {
-                    event_sender
-                        .send_event(Event::SetWorldMenuButtonPaused(img))
-                        .unwrap();
-                }
-                if let Some(img) = load_first_image_to_rgba(
-                    "menu_button_tick.png",
-                    &assets_path_world,
-                    &assets_table_world,
-                ) {
-                    event_sender
-                        .send_event(Event::SetWorldMenuButtonTick(img))
-                        .unwrap();
-                }
-                if let Some(img) = load_first_image_to_rgba(
-                    "menu_button_signalzero.png",
-                    &assets_path_world,
-                    &assets_table_world,
-                ) {
-                    event_sender
-                        .send_event(Event::SetWorldMenuButtonSignalzero(img))
-                        .unwrap();
-                }
-                load_six_images_and_send(
-                    "signal_",
-                    |v| event_sender.send_event(Event::SetWorldSignal(v)).unwrap(),
-                    &assets_path_world,
-                    &assets_table_world,
-                );
-                if let Some(img) = load_first_image_to_rgba(
-                    "block_color.png",
-                    &assets_path_world,
-                    &assets_table_world,
-                ) {
-                    event_sender
-                        .send_event(Event::SetWorldBlockColor(img))
-                        .unwrap();
-                }
-                if let Some(img) = load_first_image_to_rgba(
-                    "block_char.png",
-                    &assets_path_world,
-                    &assets_table_world,
-                ) {
-                    event_sender
-                        .send_event(Event::SetWorldBlockChar(img))
-                        .unwrap();
-                }
-                load_six_images_and_send(
-                    "block_delay_",
-                    |v| {
-                        event_sender
-                            .send_event(Event::SetWorldBlockDelay(v))
-                            .unwrap()
-                    },
-                    &assets_path_world,
-                    &assets_table_world,
-                );
-                load_six_images_and_send(
-                    "block_storage_sto_",
-                    |v| {
-                        event_sender
-                            .send_event(Event::SetWorldBlockStorageSto(v))
-                            .unwrap()
-                    },
-                    &assets_path_world,
-                    &assets_table_world,
-                );
-                load_six_images_and_send(
-                    "block_storage_or_",
-                    |v| {
-                        event_sender
-                            .send_event(Event::SetWorldBlockStorageOr(v))
-                            .unwrap()
-                    },
-                    &assets_path_world,
-                    &assets_table_world,
-                );
-                load_six_images_and_send(
-                    "block_storage_and_",
-                    |v| {
-                        event_sender
-                            .send_event(Event::SetWorldBlockStorageAnd(v))
-                            .unwrap()
-                    },
-                    &assets_path_world,
-                    &assets_table_world,
-                );
-                load_six_images_and_send(
-                    "block_storage_xor_",
-                    |v| {
-                        event_sender
-                            .send_event(Event::SetWorldBlockStorageXor(v))
-                            .unwrap()
-                    },
-                    &assets_path_world,
-                    &assets_table_world,
-                );
-                load_six_images_and_send(
-                    "block_storage_add_",
-                    |v| {
-                        event_sender
-                            .send_event(Event::SetWorldBlockStorageAdd(v))
-                            .unwrap()
-                    },
-                    &assets_path_world,
-                    &assets_table_world,
-                );
-                load_six_images_and_send(
-                    "block_storage_sub_",
-                    |v| {
-                        event_sender
-                            .send_event(Event::SetWorldBlockStorageSub(v))
-                            .unwrap()
-                    },
-                    &assets_path_world,
-                    &assets_table_world,
-                );
-                load_six_images_and_send(
-                    "block_storage_mul_",
-                    |v| {
-                        event_sender
-                            .send_event(Event::SetWorldBlockStorageMul(v))
-                            .unwrap()
-                    },
-                    &assets_path_world,
-                    &assets_table_world,
-                );
-                load_six_images_and_send(
-                    "block_storage_div_",
-                    |v| {
-                        event_sender
-                            .send_event(Event::SetWorldBlockStorageDiv(v))
-                            .unwrap()
-                    },
-                    &assets_path_world,
-                    &assets_table_world,
-                );
-                load_six_images_and_send(
-                    "block_storage_mod_",
-                    |v| {
-                        event_sender
-                            .send_event(Event::SetWorldBlockStorageMod(v))
-                            .unwrap()
-                    },
-                    &assets_path_world,
-                    &assets_table_world,
-                );
-                load_six_images_and_send(
-                    "block_storage_default_",
-                    |v| {
-                        event_sender
-                            .send_event(Event::SetWorldBlockStorageDefault(v))
-                            .unwrap()
-                    },
-                    &assets_path_world,
-                    &assets_table_world,
-                );
-                load_six_images_and_send(
-                    "block_gate_open_",
-                    |v| {
-                        event_sender
-                            .send_event(Event::SetWorldBlockGateOpen(v))
-                            .unwrap()
-                    },
-                    &assets_path_world,
-                    &assets_table_world,
-                );
-                load_six_images_and_send(
-                    "block_gate_closed_",
-                    |v| {
-                        event_sender
-                            .send_event(Event::SetWorldBlockGateClosed(v))
-                            .unwrap()
-                    },
-                    &assets_path_world,
-                    &assets_table_world,
-                );
-                load_six_images_and_send(
-                    "block_splitter_",
-                    |v| {
-                        event_sender
-                            .send_event(Event::SetWorldBlockSplitter(v))
-                            .unwrap()
-                    },
-                    &assets_path_world,
-                    &assets_table_world,
-                );
-                load_six_images_and_send(
-                    "block_move_",
-                    |v| {
-                        event_sender
-                            .send_event(Event::SetWorldBlockMove(v))
-                            .unwrap()
-                    },
-                    &assets_path_world,
-                    &assets_table_world,
-                );
-                load_six_images_and_send(
-                    "block_swap_",
-                    |v| {
-                        event_sender
-                            .send_event(Event::SetWorldBlockSwap(v))
-                            .unwrap()
-                    },
-                    &assets_path_world,
-                    &assets_table_world,
-                );
                 Ok(event_sender)
             }),
         })
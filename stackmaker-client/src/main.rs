@@ -1,33 +1,66 @@
 use std::{
+    collections::{HashMap, HashSet, VecDeque},
     env::current_dir,
-    path::PathBuf,
+    fs,
+    path::{Path, PathBuf},
     rc::Rc,
-    sync::{Arc, Mutex},
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        Arc, Mutex,
+    },
     thread::JoinHandle,
     time::Instant,
 };
 
+use history::History;
 use image::RgbaImage;
 use loading::ThreadedLoading;
 use speedy2d::{
     color::Color,
     dimen::{IVec2, UVec2, Vec2},
-    font::{Font, FormattedTextBlock, TextLayout, TextOptions},
+    font::{Font, FormattedTextBlock, TextAlignment, TextLayout, TextOptions},
     image::{ImageDataType, ImageHandle, ImageSmoothingMode},
     shape::Rectangle,
     window::{
-        MouseButton, MouseScrollDistance, UserEventSender, WindowCreationOptions, WindowHandler,
-        WindowHelper,
+        KeyScancode, ModifiersState, MouseButton, MouseScrollDistance, UserEventSender,
+        VirtualKeyCode, WindowCreationOptions, WindowHandler, WindowHelper,
     },
     Graphics2D,
 };
 use stackmaker::{
-    runner::{self, Runner},
-    world::{Block, World},
+    runner::{self, ProbeRecord, Runner},
+    thumbnail,
+    world::{Block, Layer, View, World, LAYER_COUNT, LAYER_MASK},
 };
 
+mod atlas;
+mod builtin_assets;
+mod history;
 mod loading;
 
+/// in pixels. if the mouse moves further than this while a button is held down, it is a drag and not a click.
+const CLICK_DRAG_THRESHOLD: f32 = 5.0;
+/// the size of one block in images exported with P
+const EXPORT_PIXELS_PER_BLOCK: usize = 16;
+/// the largest width and height (in blocks) of an area which can be exported, so images stay at most 8192x8192 pixels
+const EXPORT_MAX_BLOCKS: i64 = 512;
+/// the most stacks which can be filled at once with F
+const FILL_MAX_CELLS: i64 = 10000;
+/// the tick rates which can be selected with , and . while in a world, from slowest to fastest
+const TPS_STEPS: [f32; 9] = [1.0, 2.0, 5.0, 10.0, 20.0, 30.0, 60.0, 120.0, 240.0];
+/// the grid is only drawn if blocks are at least this many pixels wide, otherwise it would just cover the blocks
+const GRID_MIN_PIXELS_PER_BLOCK: f32 = 12.0;
+/// numbers (like the size of a stack or a Delay block's delay) are only shown on blocks which are at least this many pixels wide
+const BLOCK_NUMBER_MIN_PIXELS: f32 = 24.0;
+/// once this many numbers were laid out for `Window::number_texts`, they are thrown away and laid out again when needed
+const MAX_NUMBER_TEXTS: usize = 4096;
+/// how many of the missing assets are listed in the main menu, the rest are only counted
+const MISSING_ASSETS_SHOWN: usize = 20;
+/// below this, blocks are drawn as squares of their average color instead of their sprites (see `Window::draw_blocks_zoomed_out`)
+const ZOOMED_OUT_PIXELS_PER_BLOCK: f32 = 3.0;
+/// how long each frame of an animated image is shown
+const ANIMATION_FRAME_MILLIS: u128 = 150;
+
 fn main() {
     let window = speedy2d::Window::new_with_user_events(
         "Stackmaker",
@@ -42,6 +75,7 @@ impl Window {
     pub fn new(user_event_sender: UserEventSender<Event>) -> Self {
         let loader = ThreadedLoading::new(user_event_sender).unwrap();
         Self {
+            config: Arc::clone(&loader.config),
             thread_loading: Some(loader),
             events: vec![],
             font_monospace: None,
@@ -49,24 +83,65 @@ impl Window {
             size: UVec2::ZERO,
             mouse_pos: Vec2::new(0.0, 0.0),
             mouse_down_l: false,
+            mouse_drag_distance: 0.0,
+            shift_down: false,
+            ctrl_down: false,
+            show_grid: false,
+            number_texts: HashMap::new(),
             mouse_down_m: false,
             mouse_down_r: false,
+            keys_down: HashSet::new(),
             redraw: true,
             state: WindowState::MainMenu(WSMainMenu::new()),
             saves: vec![],
+            save_thumbnails: HashMap::new(),
+            missing_assets: vec![],
+            missing_assets_dismissed: false,
             images: Default::default(),
+            atlas_dirty: false,
+        }
+    }
+}
+/// speedy2d has no event for the window being closed, but the window handler is dropped when the event loop exits,
+/// so a world which is open when the window is closed (without pressing Escape first) is saved here.
+impl Drop for Window {
+    fn drop(&mut self) {
+        // an autosave which is still being written could otherwise write the same files at the same time
+        if let WindowState::Singleplayer(_, runner) = &mut self.state {
+            runner.wait_for_autosave();
+        }
+        if let WindowState::Singleplayer(_, runner) = &self.state {
+            let dir = match &runner.world.save_dir {
+                Some(dir) => dir.clone(),
+                None => self.new_world_dir("New World").0,
+            };
+            eprintln!("[info] window closed, saving to {dir:?}");
+            if let Err(e) = runner.world.save_to_dir(&dir) {
+                eprintln!("[err] Couldn't save world to {dir:?}: {e}");
+            }
         }
     }
 }
 
 struct Window {
+    config: Arc<Config>,
     thread_loading: Option<ThreadedLoading>,
     events: Vec<Event>,
     size: UVec2,
     mouse_pos: Vec2,
     mouse_down_l: bool,
+    /// how far the mouse moved since the left button was pressed. clicks which moved further than `CLICK_DRAG_THRESHOLD` are camera drags.
+    mouse_drag_distance: f32,
+    shift_down: bool,
+    ctrl_down: bool,
+    /// if true, lines are drawn between blocks while zoomed in (toggled with G). this stays the same when a different world is loaded.
+    show_grid: bool,
+    /// (font size as bits, number) => text, for the numbers drawn on blocks (see `draw_number`)
+    number_texts: HashMap<(u32, u64), Rc<FormattedTextBlock>>,
     mouse_down_m: bool,
     mouse_down_r: bool,
+    /// keys which are currently held down
+    keys_down: HashSet<VirtualKeyCode>,
     /// if true, we need a full redraw (state changed, window resized, etc.)
     redraw: bool,
 
@@ -76,8 +151,15 @@ struct Window {
     state: WindowState,
 
     saves: Vec<(PathBuf, String)>,
+    /// the thumbnails of the saves, by path. saves without one don't have an entry.
+    save_thumbnails: HashMap<PathBuf, LoadableImage>,
+    /// shown in the main menu until dismissed, so a broken install doesn't just look like missing blocks
+    missing_assets: Vec<String>,
+    missing_assets_dismissed: bool,
 
     images: WindowImages,
+    /// if true, sprites were loaded since the atlas was last built
+    atlas_dirty: bool,
 }
 #[derive(Default)]
 struct WindowImages {
@@ -90,10 +172,15 @@ struct WindowImages {
     world_menu_button_paused: LoadableImage,
     world_menu_button_tick: LoadableImage,
     world_menu_button_signalzero: LoadableImage,
+    world_menu_button_delete: LoadableImage,
+    world_menu_button_clear: LoadableImage,
     world_signal: [LoadableImage; 6],
     world_block_color: LoadableImage,
     world_block_char: LoadableImage,
+    world_block_fan: LoadableImage,
     world_block_delay: [LoadableImage; 6],
+    /// drawn on top of a delay with `runner::DELAY_BY_VALUE` set
+    world_block_delay_value: [LoadableImage; 6],
     world_block_storage_sto: [LoadableImage; 6],
     world_block_storage_or: [LoadableImage; 6],
     world_block_storage_and: [LoadableImage; 6],
@@ -106,15 +193,161 @@ struct WindowImages {
     world_block_storage_default: [LoadableImage; 6],
     world_block_gate_open: [LoadableImage; 6],
     world_block_gate_closed: [LoadableImage; 6],
+    /// drawn on top of a gate which passes side-signals on, pointing in the direction they are passed on in
+    world_block_gate_pass: [LoadableImage; 6],
+    /// drawn on top of a gate with `runner::GATE_INVERTED` set
+    world_block_gate_inv: [LoadableImage; 6],
     world_block_splitter: [LoadableImage; 6],
     world_block_move: [LoadableImage; 6],
     world_block_swap: [LoadableImage; 6],
+    world_block_swap_all: [LoadableImage; 6],
+    world_block_duplicate: [LoadableImage; 6],
+    world_block_destroy: [LoadableImage; 6],
+    world_block_not: [LoadableImage; 6],
+    world_block_probe: [LoadableImage; 6],
+    world_block_clock: LoadableImage,
+    world_block_random: LoadableImage,
+    world_block_compare_lt: [LoadableImage; 6],
+    world_block_compare_le: [LoadableImage; 6],
+    world_block_compare_eq: [LoadableImage; 6],
+    world_block_compare_ge: [LoadableImage; 6],
+    world_block_compare_gt: [LoadableImage; 6],
+    world_block_compare_default: [LoadableImage; 6],
+    world_block_height: [LoadableImage; 6],
+    world_block_storage_wadd: [LoadableImage; 6],
+    world_block_storage_wsub: [LoadableImage; 6],
+    world_block_storage_wmul: [LoadableImage; 6],
+    world_block_storage_min: [LoadableImage; 6],
+    world_block_storage_max: [LoadableImage; 6],
+    world_block_counter: [LoadableImage; 6],
 }
+impl WindowImages {
+    /// the images which are drawn as blocks or signals in the world, and are packed into the atlas.
+    /// every field is listed here, so new images have to be sorted into one of the two groups.
+    fn block_sprites_mut(&mut self) -> Vec<&mut LoadableImage> {
+        let Self {
+            main_menu_background_image: _,
+            main_menu_singleplayer_new_world_image: _,
+            world_menu_arrow_selected: _,
+            world_menu_arrow_source: _,
+            world_menu_arrow_target: _,
+            world_menu_button_pause: _,
+            world_menu_button_paused: _,
+            world_menu_button_tick: _,
+            world_menu_button_signalzero: _,
+            world_menu_button_delete: _,
+            world_menu_button_clear: _,
+            world_block_color,
+            world_block_char,
+            world_block_fan,
+            world_block_random,
+            world_block_clock,
+            world_signal,
+            world_block_delay,
+            world_block_delay_value,
+            world_block_storage_sto,
+            world_block_storage_or,
+            world_block_storage_and,
+            world_block_storage_xor,
+            world_block_storage_add,
+            world_block_storage_sub,
+            world_block_storage_mul,
+            world_block_storage_div,
+            world_block_storage_mod,
+            world_block_storage_default,
+            world_block_gate_open,
+            world_block_gate_closed,
+            world_block_gate_pass,
+            world_block_gate_inv,
+            world_block_splitter,
+            world_block_move,
+            world_block_swap,
+            world_block_swap_all,
+            world_block_duplicate,
+            world_block_destroy,
+            world_block_not,
+            world_block_probe,
+            world_block_compare_lt,
+            world_block_compare_le,
+            world_block_compare_eq,
+            world_block_compare_ge,
+            world_block_compare_gt,
+            world_block_compare_default,
+            world_block_height,
+            world_block_storage_wadd,
+            world_block_storage_wsub,
+            world_block_storage_wmul,
+            world_block_storage_min,
+            world_block_storage_max,
+            world_block_counter,
+        } = self;
+        [
+            world_block_color,
+            world_block_char,
+            world_block_fan,
+            world_block_random,
+            world_block_clock,
+        ]
+        .into_iter()
+        .chain(
+            [
+                world_signal,
+                world_block_delay,
+                world_block_delay_value,
+                world_block_storage_sto,
+                world_block_storage_or,
+                world_block_storage_and,
+                world_block_storage_xor,
+                world_block_storage_add,
+                world_block_storage_sub,
+                world_block_storage_mul,
+                world_block_storage_div,
+                world_block_storage_mod,
+                world_block_storage_default,
+                world_block_gate_open,
+                world_block_gate_closed,
+                world_block_gate_pass,
+                world_block_gate_inv,
+                world_block_splitter,
+                world_block_move,
+                world_block_swap,
+                world_block_swap_all,
+                world_block_duplicate,
+                world_block_destroy,
+                world_block_not,
+                world_block_probe,
+                world_block_compare_lt,
+                world_block_compare_le,
+                world_block_compare_eq,
+                world_block_compare_ge,
+                world_block_compare_gt,
+                world_block_compare_default,
+                world_block_height,
+                world_block_storage_wadd,
+                world_block_storage_wsub,
+                world_block_storage_wmul,
+                world_block_storage_min,
+                world_block_storage_max,
+                world_block_counter,
+            ]
+            .into_iter()
+            .flatten(),
+        )
+        .collect()
+    }
+}
+
+/// the frames of an animated image, or just one for images which aren't animated. never empty.
+pub type Frames = Vec<RgbaImage>;
 
 pub enum Event {
     LoadFontMain(Vec<u8>),
     LoadFontMono(Vec<u8>),
     AddWorld(PathBuf, String),
+    /// the thumbnail of the save with this path
+    SetWorldThumbnail(PathBuf, RgbaImage),
+    /// the assets which couldn't be found while (re)loading them, replaces the previous list
+    SetMissingAssets(Vec<String>),
     SetMainMenuBackgroundImage(RgbaImage),
     SetMainMenuSingleplayerNewWorldImage(RgbaImage),
     SetWorldMenuArrowSelected(RgbaImage),
@@ -124,31 +357,58 @@ pub enum Event {
     SetWorldMenuButtonPaused(RgbaImage),
     SetWorldMenuButtonTick(RgbaImage),
     SetWorldMenuButtonSignalzero(RgbaImage),
-    SetWorldSignal([Option<RgbaImage>; 6]),
+    SetWorldMenuButtonDelete(RgbaImage),
+    SetWorldMenuButtonClear(RgbaImage),
+    SetWorldSignal([Option<Frames>; 6]),
     SetWorldBlockColor(RgbaImage),
     SetWorldBlockChar(RgbaImage),
-    SetWorldBlockDelay([Option<RgbaImage>; 6]),
-    SetWorldBlockStorageSto([Option<RgbaImage>; 6]),
-    SetWorldBlockStorageOr([Option<RgbaImage>; 6]),
-    SetWorldBlockStorageAnd([Option<RgbaImage>; 6]),
-    SetWorldBlockStorageXor([Option<RgbaImage>; 6]),
-    SetWorldBlockStorageAdd([Option<RgbaImage>; 6]),
-    SetWorldBlockStorageSub([Option<RgbaImage>; 6]),
-    SetWorldBlockStorageMul([Option<RgbaImage>; 6]),
-    SetWorldBlockStorageDiv([Option<RgbaImage>; 6]),
-    SetWorldBlockStorageMod([Option<RgbaImage>; 6]),
-    SetWorldBlockStorageDefault([Option<RgbaImage>; 6]),
-    SetWorldBlockGateOpen([Option<RgbaImage>; 6]),
-    SetWorldBlockGateClosed([Option<RgbaImage>; 6]),
-    SetWorldBlockSplitter([Option<RgbaImage>; 6]),
-    SetWorldBlockMove([Option<RgbaImage>; 6]),
-    SetWorldBlockSwap([Option<RgbaImage>; 6]),
+    SetWorldBlockFan(RgbaImage),
+    SetWorldBlockDelay([Option<Frames>; 6]),
+    SetWorldBlockDelayValue([Option<Frames>; 6]),
+    SetWorldBlockStorageSto([Option<Frames>; 6]),
+    SetWorldBlockStorageOr([Option<Frames>; 6]),
+    SetWorldBlockStorageAnd([Option<Frames>; 6]),
+    SetWorldBlockStorageXor([Option<Frames>; 6]),
+    SetWorldBlockStorageAdd([Option<Frames>; 6]),
+    SetWorldBlockStorageSub([Option<Frames>; 6]),
+    SetWorldBlockStorageMul([Option<Frames>; 6]),
+    SetWorldBlockStorageDiv([Option<Frames>; 6]),
+    SetWorldBlockStorageMod([Option<Frames>; 6]),
+    SetWorldBlockStorageDefault([Option<Frames>; 6]),
+    SetWorldBlockGateOpen([Option<Frames>; 6]),
+    SetWorldBlockGateClosed([Option<Frames>; 6]),
+    SetWorldBlockGatePass([Option<Frames>; 6]),
+    SetWorldBlockGateInv([Option<Frames>; 6]),
+    SetWorldBlockSplitter([Option<Frames>; 6]),
+    SetWorldBlockMove([Option<Frames>; 6]),
+    SetWorldBlockSwap([Option<Frames>; 6]),
+    SetWorldBlockSwapAll([Option<Frames>; 6]),
+    SetWorldBlockDuplicate([Option<Frames>; 6]),
+    SetWorldBlockDestroy([Option<Frames>; 6]),
+    SetWorldBlockNot([Option<Frames>; 6]),
+    SetWorldBlockProbe([Option<Frames>; 6]),
+    SetWorldBlockClock(RgbaImage),
+    SetWorldBlockRandom(RgbaImage),
+    SetWorldBlockCompareLt([Option<Frames>; 6]),
+    SetWorldBlockCompareLe([Option<Frames>; 6]),
+    SetWorldBlockCompareEq([Option<Frames>; 6]),
+    SetWorldBlockCompareGe([Option<Frames>; 6]),
+    SetWorldBlockCompareGt([Option<Frames>; 6]),
+    SetWorldBlockCompareDefault([Option<Frames>; 6]),
+    SetWorldBlockHeight([Option<Frames>; 6]),
+    SetWorldBlockStorageWadd([Option<Frames>; 6]),
+    SetWorldBlockStorageWsub([Option<Frames>; 6]),
+    SetWorldBlockStorageWmul([Option<Frames>; 6]),
+    SetWorldBlockStorageMin([Option<Frames>; 6]),
+    SetWorldBlockStorageMax([Option<Frames>; 6]),
+    SetWorldBlockCounter([Option<Frames>; 6]),
 }
 
 enum WindowState {
     Nothing,
     MainMenu(WSMainMenu),
-    LoadingWorld(Arc<Mutex<f32>>, Option<JoinHandle<Option<Runner>>>),
+    /// the thread returns a description of the problem if the world couldn't be loaded
+    LoadingWorld(Arc<Mutex<f32>>, Option<JoinHandle<Result<Runner, String>>>),
     Singleplayer(WSInGame, Runner),
 }
 impl WindowState {
@@ -215,6 +475,18 @@ impl WindowHandler<Event> for Window {
                             _ => (),
                         }
                     }
+                    Event::SetWorldThumbnail(path, img) => {
+                        let mut thumbnail = LoadableImage::default();
+                        Self::load_img(&mut thumbnail, img, graphics);
+                        self.save_thumbnails.insert(path, thumbnail);
+                    }
+                    Event::SetMissingAssets(missing) => {
+                        // after a hot reload, the panel only comes back if something changed
+                        if missing != self.missing_assets {
+                            self.missing_assets = missing;
+                            self.missing_assets_dismissed = false;
+                        }
+                    }
                     Event::SetMainMenuBackgroundImage(img) => {
                         Self::load_img(&mut self.images.main_menu_background_image, img, graphics);
                     }
@@ -250,18 +522,30 @@ impl WindowHandler<Event> for Window {
                             graphics,
                         );
                     }
+                    Event::SetWorldMenuButtonDelete(img) => {
+                        Self::load_img(&mut self.images.world_menu_button_delete, img, graphics);
+                    }
+                    Event::SetWorldMenuButtonClear(img) => {
+                        Self::load_img(&mut self.images.world_menu_button_clear, img, graphics);
+                    }
                     Event::SetWorldSignal(img) => {
                         Self::load_imgs(&mut self.images.world_signal, img, graphics);
                     }
                     Event::SetWorldBlockColor(img) => {
-                        Self::load_img(&mut self.images.world_block_color, img, graphics);
+                        Self::load_sprite(&mut self.images.world_block_color, img, graphics);
                     }
                     Event::SetWorldBlockChar(img) => {
-                        Self::load_img(&mut self.images.world_block_char, img, graphics);
+                        Self::load_sprite(&mut self.images.world_block_char, img, graphics);
+                    }
+                    Event::SetWorldBlockFan(img) => {
+                        Self::load_sprite(&mut self.images.world_block_fan, img, graphics);
                     }
                     Event::SetWorldBlockDelay(img) => {
                         Self::load_imgs(&mut self.images.world_block_delay, img, graphics);
                     }
+                    Event::SetWorldBlockDelayValue(img) => {
+                        Self::load_imgs(&mut self.images.world_block_delay_value, img, graphics);
+                    }
                     Event::SetWorldBlockStorageSto(img) => {
                         Self::load_imgs(&mut self.images.world_block_storage_sto, img, graphics);
                     }
@@ -302,6 +586,12 @@ impl WindowHandler<Event> for Window {
                     Event::SetWorldBlockGateClosed(img) => {
                         Self::load_imgs(&mut self.images.world_block_gate_closed, img, graphics);
                     }
+                    Event::SetWorldBlockGatePass(img) => {
+                        Self::load_imgs(&mut self.images.world_block_gate_pass, img, graphics);
+                    }
+                    Event::SetWorldBlockGateInv(img) => {
+                        Self::load_imgs(&mut self.images.world_block_gate_inv, img, graphics);
+                    }
                     Event::SetWorldBlockSplitter(img) => {
                         Self::load_imgs(&mut self.images.world_block_splitter, img, graphics);
                     }
@@ -311,8 +601,78 @@ impl WindowHandler<Event> for Window {
                     Event::SetWorldBlockSwap(img) => {
                         Self::load_imgs(&mut self.images.world_block_swap, img, graphics);
                     }
+                    Event::SetWorldBlockSwapAll(img) => {
+                        Self::load_imgs(&mut self.images.world_block_swap_all, img, graphics);
+                    }
+                    Event::SetWorldBlockDuplicate(img) => {
+                        Self::load_imgs(&mut self.images.world_block_duplicate, img, graphics);
+                    }
+                    Event::SetWorldBlockDestroy(img) => {
+                        Self::load_imgs(&mut self.images.world_block_destroy, img, graphics);
+                    }
+                    Event::SetWorldBlockNot(img) => {
+                        Self::load_imgs(&mut self.images.world_block_not, img, graphics);
+                    }
+                    Event::SetWorldBlockProbe(img) => {
+                        Self::load_imgs(&mut self.images.world_block_probe, img, graphics);
+                    }
+                    Event::SetWorldBlockClock(img) => {
+                        Self::load_sprite(&mut self.images.world_block_clock, img, graphics);
+                    }
+                    Event::SetWorldBlockRandom(img) => {
+                        Self::load_sprite(&mut self.images.world_block_random, img, graphics);
+                    }
+                    Event::SetWorldBlockCompareLt(img) => {
+                        Self::load_imgs(&mut self.images.world_block_compare_lt, img, graphics);
+                    }
+                    Event::SetWorldBlockCompareLe(img) => {
+                        Self::load_imgs(&mut self.images.world_block_compare_le, img, graphics);
+                    }
+                    Event::SetWorldBlockCompareEq(img) => {
+                        Self::load_imgs(&mut self.images.world_block_compare_eq, img, graphics);
+                    }
+                    Event::SetWorldBlockCompareGe(img) => {
+                        Self::load_imgs(&mut self.images.world_block_compare_ge, img, graphics);
+                    }
+                    Event::SetWorldBlockCompareGt(img) => {
+                        Self::load_imgs(&mut self.images.world_block_compare_gt, img, graphics);
+                    }
+                    Event::SetWorldBlockCompareDefault(img) => {
+                        Self::load_imgs(
+                            &mut self.images.world_block_compare_default,
+                            img,
+                            graphics,
+                        );
+                    }
+                    Event::SetWorldBlockHeight(img) => {
+                        Self::load_imgs(&mut self.images.world_block_height, img, graphics);
+                    }
+                    Event::SetWorldBlockStorageWadd(img) => {
+                        Self::load_imgs(&mut self.images.world_block_storage_wadd, img, graphics);
+                    }
+                    Event::SetWorldBlockStorageWsub(img) => {
+                        Self::load_imgs(&mut self.images.world_block_storage_wsub, img, graphics);
+                    }
+                    Event::SetWorldBlockStorageWmul(img) => {
+                        Self::load_imgs(&mut self.images.world_block_storage_wmul, img, graphics);
+                    }
+                    Event::SetWorldBlockStorageMin(img) => {
+                        Self::load_imgs(&mut self.images.world_block_storage_min, img, graphics);
+                    }
+                    Event::SetWorldBlockStorageMax(img) => {
+                        Self::load_imgs(&mut self.images.world_block_storage_max, img, graphics);
+                    }
+                    Event::SetWorldBlockCounter(img) => {
+                        Self::load_imgs(&mut self.images.world_block_counter, img, graphics);
+                    }
                 }
             }
+            self.atlas_dirty = true;
+        }
+        // the atlas is only used to draw worlds, so it isn't rebuilt for every image while loading
+        if self.atlas_dirty && matches!(self.state, WindowState::Singleplayer(..)) {
+            self.atlas_dirty = false;
+            self.rebuild_atlas(graphics);
         }
         // draw
         let mut state = self.state.take();
@@ -408,9 +768,53 @@ impl WindowHandler<Event> for Window {
                     .skip(state.world_scroll)
                 {
                     let new_height = height + state.desired_world_height;
+                    // delete button
+                    if let Some(font) = &self.font_main {
+                        let delete_area = Rectangle::new(
+                            Vec2::new(area.bottom_right().x - state.desired_world_height, height),
+                            Vec2::new(area.bottom_right().x, new_height),
+                        );
+                        let confirm = state.delete_confirm == Some(i);
+                        let layout = font.layout_text(
+                            if confirm { "delete? X" } else { "X" },
+                            state.world_display_font_scale,
+                            TextOptions::default(),
+                        );
+                        graphics.draw_text(
+                            Vec2::new(delete_area.bottom_right().x - layout.width(), height),
+                            if confirm {
+                                Color::RED
+                            } else if delete_area.contains(self.mouse_pos) {
+                                Color::WHITE
+                            } else {
+                                Color::GRAY
+                            },
+                            &layout,
+                        );
+                    }
+                    // thumbnail, or a placeholder if there is none
+                    let thumbnail_area = Rectangle::new(
+                        Vec2::new(area.top_left().x, height),
+                        Vec2::new(area.top_left().x + state.desired_world_height, new_height),
+                    );
+                    if let Some(thumbnail) = self.save_thumbnails.get_mut(&self.saves[i].0) {
+                        thumbnail.draw_image_aspect_ratio_tinted(
+                            graphics,
+                            helper,
+                            thumbnail_area,
+                            Color::WHITE,
+                            false,
+                        );
+                    } else {
+                        graphics
+                            .draw_rectangle(thumbnail_area, Color::from_rgba(0.3, 0.3, 0.3, 0.5));
+                    }
                     if let Some(text) = text {
                         graphics.draw_text(
-                            Vec2::new(area.top_left().x, height),
+                            Vec2::new(
+                                area.top_left().x + state.desired_world_height * 1.25,
+                                height,
+                            ),
                             if mouse_in_box
                                 && self.mouse_pos.y >= height
                                 && self.mouse_pos.y < new_height
@@ -470,15 +874,149 @@ impl WindowHandler<Event> for Window {
                         Color::from_gray(state.singleplayer_new_world_button_brightness),
                         false,
                     );
+                // new world name prompt
+                if let (Some(name), Some(font)) = (&state.name_prompt, &self.font_main) {
+                    let area = Self::rel_to_abs_rect(
+                        self.size,
+                        &Rectangle::new(Vec2::new(0.25, 0.4), Vec2::new(0.75, 0.6)),
+                    );
+                    graphics.draw_rectangle(area.clone(), Color::from_rgba(0.1, 0.1, 0.1, 0.95));
+                    let scale = state.world_display_font_scale;
+                    let title =
+                        font.layout_text("Name of the new world:", scale, TextOptions::default());
+                    let padding = self.size.y as f32 * 0.02;
+                    graphics.draw_text(
+                        area.top_left() + Vec2::new(padding, padding),
+                        Color::LIGHT_GRAY,
+                        &title,
+                    );
+                    let text = font.layout_text(&format!("{name}_"), scale, TextOptions::default());
+                    graphics.draw_text(
+                        area.top_left() + Vec2::new(padding, 2.0 * padding + title.height()),
+                        Color::WHITE,
+                        &text,
+                    );
+                }
+                // missing assets, below the load error
+                if let (true, None, Some(font)) = (
+                    self.missing_assets_shown(),
+                    &state.load_error,
+                    &self.font_main,
+                ) {
+                    let area = Self::rel_to_abs_rect(
+                        self.size,
+                        &Rectangle::new(Vec2::new(0.15, 0.15), Vec2::new(0.85, 0.85)),
+                    );
+                    graphics.draw_rectangle(area.clone(), Color::from_rgba(0.15, 0.1, 0.05, 0.95));
+                    let padding = self.size.y as f32 * 0.02;
+                    let mut text = format!(
+                        "{} assets couldn't be found, the things using them won't be drawn:",
+                        self.missing_assets.len()
+                    );
+                    for name in self.missing_assets.iter().take(MISSING_ASSETS_SHOWN) {
+                        text.push_str("\n");
+                        text.push_str(name);
+                    }
+                    if self.missing_assets.len() > MISSING_ASSETS_SHOWN {
+                        text.push_str(&format!(
+                            "\n... and {} more (see the terminal output)",
+                            self.missing_assets.len() - MISSING_ASSETS_SHOWN
+                        ));
+                    }
+                    let text = font.layout_text(
+                        &text,
+                        state.world_display_font_scale * 0.6,
+                        TextOptions::new()
+                            .with_wrap_to_width(area.width() - 2.0 * padding, TextAlignment::Left),
+                    );
+                    let button =
+                        Self::rel_to_abs_rect(self.size, &state.missing_assets_dismiss_button);
+                    // long lists are cut off above the button
+                    graphics.set_clip(Some(Rectangle::new(
+                        area.top_left().into_i32(),
+                        Vec2::new(area.bottom_right().x, button.top_left().y - padding).into_i32(),
+                    )));
+                    graphics.draw_text(
+                        area.top_left() + Vec2::new(padding, padding),
+                        Color::WHITE,
+                        &text,
+                    );
+                    graphics.set_clip(None);
+                    graphics.draw_rectangle(
+                        button.clone(),
+                        if button.contains(self.mouse_pos) {
+                            Color::from_gray(0.5)
+                        } else {
+                            Color::from_gray(0.3)
+                        },
+                    );
+                    let ok = font.layout_text(
+                        "OK",
+                        state.world_display_font_scale,
+                        TextOptions::default(),
+                    );
+                    graphics.draw_text(
+                        button.top_left()
+                            + (button.size() - Vec2::new(ok.width(), ok.height())) / 2.0,
+                        Color::WHITE,
+                        &ok,
+                    );
+                }
+                // load error
+                if let (Some(error), Some(font)) = (&state.load_error, &self.font_main) {
+                    let area = Self::rel_to_abs_rect(
+                        self.size,
+                        &Rectangle::new(Vec2::new(0.2, 0.35), Vec2::new(0.8, 0.65)),
+                    );
+                    graphics.draw_rectangle(area.clone(), Color::from_rgba(0.2, 0.05, 0.05, 0.95));
+                    let padding = self.size.y as f32 * 0.02;
+                    let text = font.layout_text(
+                        error,
+                        state.world_display_font_scale,
+                        TextOptions::new()
+                            .with_wrap_to_width(area.width() - 2.0 * padding, TextAlignment::Left),
+                    );
+                    graphics.draw_text(
+                        area.top_left() + Vec2::new(padding, padding),
+                        Color::WHITE,
+                        &text,
+                    );
+                    let button = Self::rel_to_abs_rect(self.size, &state.load_error_dismiss_button);
+                    graphics.draw_rectangle(
+                        button.clone(),
+                        if button.contains(self.mouse_pos) {
+                            Color::from_gray(0.5)
+                        } else {
+                            Color::from_gray(0.3)
+                        },
+                    );
+                    let ok = font.layout_text(
+                        "OK",
+                        state.world_display_font_scale,
+                        TextOptions::default(),
+                    );
+                    graphics.draw_text(
+                        button.top_left()
+                            + (button.size() - Vec2::new(ok.width(), ok.height())) / 2.0,
+                        Color::WHITE,
+                        &ok,
+                    );
+                }
                 //
             }
             WindowState::LoadingWorld(prog, handle) => {
                 helper.request_redraw();
                 if handle.as_ref().unwrap().is_finished() {
-                    if let Some(runner) = handle.take().unwrap().join().unwrap() {
-                        self.state = WindowState::Singleplayer(WSInGame::default(), runner);
-                    } else {
-                        self.state = WindowState::MainMenu(WSMainMenu::new())
+                    match handle.take().unwrap().join().unwrap() {
+                        Ok(runner) => {
+                            let state = WSInGame::with_view(runner.world.view);
+                            self.state = WindowState::Singleplayer(state, runner)
+                        }
+                        Err(e) => {
+                            let mut menu = WSMainMenu::new();
+                            menu.load_error = Some(e);
+                            self.state = WindowState::MainMenu(menu);
+                        }
                     }
                     self.redraw = true;
                 } else {
@@ -504,55 +1042,120 @@ impl WindowHandler<Event> for Window {
                 }
             }
             WindowState::Singleplayer(state, runner) => {
+                // kept up to date so that every save (autosave, Escape, closing the window) stores the current view
+                runner.world.view = Some(state.view());
                 if state.run {
-                    runner.tick();
+                    let now = Instant::now();
+                    if let Some(last_frame) = state.last_frame {
+                        state.tick_time += (now - last_frame).as_secs_f32();
+                    }
+                    state.last_frame = Some(now);
+                    let tick_duration = 1.0 / state.tps;
+                    // if we fall behind by more than a quarter of a second, skip the missed ticks instead of trying to catch up
+                    let max_ticks = (state.tps * 0.25).ceil().max(1.0);
+                    let ticks = (state.tick_time / tick_duration).floor();
+                    if ticks > max_ticks {
+                        state.tick_time = 0.0;
+                    } else {
+                        state.tick_time -= ticks * tick_duration;
+                    }
+                    let ticks = ticks.min(max_ticks) as u32;
+                    runner.tick_n(ticks as _);
+                    state.tps_measurement.1 += ticks;
+                } else {
+                    state.tick_time = 0.0;
+                    state.last_frame = None;
                 }
-                graphics.clear_screen(Color::BLACK);
+                let measured_for = state.tps_measurement.0.elapsed().as_secs_f32();
+                if measured_for >= 1.0 {
+                    state.tps_measurement = (
+                        Instant::now(),
+                        0,
+                        state.tps_measurement.1 as f32 / measured_for,
+                    );
+                }
+                // move the camera using the keyboard
+                let now = Instant::now();
+                let frame_time = state
+                    .last_draw
+                    .map(|last_draw| (now - last_draw).as_secs_f32())
+                    .unwrap_or(0.0);
+                let mut camera_moving = false;
+                if state.open_menu.is_none() {
+                    let held = |keys: &[VirtualKeyCode]| {
+                        keys.iter().any(|key| self.keys_down.contains(key)) as u8 as f32
+                    };
+                    let movement = Vec2::new(
+                        held(&[VirtualKeyCode::D, VirtualKeyCode::Right])
+                            - held(&[VirtualKeyCode::A, VirtualKeyCode::Left]),
+                        held(&[VirtualKeyCode::S, VirtualKeyCode::Down])
+                            - held(&[VirtualKeyCode::W, VirtualKeyCode::Up]),
+                    );
+                    let zoom = held(&[VirtualKeyCode::Equals, VirtualKeyCode::NumpadAdd])
+                        - held(&[VirtualKeyCode::Minus, VirtualKeyCode::NumpadSubtract]);
+                    if movement != Vec2::ZERO || zoom != 0.0 {
+                        camera_moving = true;
+                        // move at the same speed on screen, no matter the zoom
+                        state.position += movement * (800.0 * frame_time / state.pixels_per_block);
+                        state.zoom += zoom * 2.0 * frame_time;
+                    }
+                }
+                // only remember the time while moving, so the first frame after a pause doesn't jump
+                state.last_draw = if camera_moving { Some(now) } else { None };
+                if let Some(region) = state.export_pending.take() {
+                    let name = runner
+                        .world
+                        .save_dir
+                        .as_ref()
+                        .and_then(|dir| dir.file_name())
+                        .map_or("world".into(), |name| name.to_string_lossy());
+                    let time = std::time::SystemTime::now()
+                        .duration_since(std::time::UNIX_EPOCH)
+                        .map_or(0, |t| t.as_secs());
+                    let dir = PathBuf::from("screenshots");
+                    let path = dir.join(format!("{name}_layer{}_{time}.png", state.layer));
+                    match fs::create_dir_all(&dir)
+                        .map_err(|e| e.to_string())
+                        .and_then(|()| {
+                            self.export_region(
+                                graphics,
+                                &runner.world.layers[state.layer],
+                                region,
+                                &path,
+                            )
+                        }) {
+                        Ok(()) => eprintln!("[info] exported {region:?} to {path:?}"),
+                        Err(e) => eprintln!("[warn] Couldn't export {region:?} to {path:?}: {e}"),
+                    }
+                }
+                // tint the background on layers other than 0, so it's obvious which layer we're on
+                graphics.clear_screen(if state.layer == 0 {
+                    Color::BLACK
+                } else {
+                    Color::from_int_rgb(0, 8, 24)
+                });
                 // draw the blocks
                 state.pixels_per_block = 2.0f32.powf(state.zoom);
                 let pixels_per_block = state.pixels_per_block;
-                let top_left_x = state.position.x - self.size.x as f32 / pixels_per_block / 2.0;
-                let top_left_y = state.position.y - self.size.y as f32 / pixels_per_block / 2.0;
-                let px_x_start = (top_left_x.floor() - top_left_x) * pixels_per_block;
-                let mut px_x = px_x_start;
-                let mut px_y = (top_left_y.floor() - top_left_y) * pixels_per_block;
-                let block_x_start = top_left_x.floor() as _;
-                let mut block_x = block_x_start;
-                let mut block_y = top_left_y.floor() as _;
-                let width = self.size.x as f32;
-                let height = self.size.y as f32;
-                loop {
-                    if px_y >= height {
-                        break;
-                    }
-                    if px_x >= width {
-                        px_x = px_x_start;
-                        block_x = block_x_start;
-                        px_y += pixels_per_block;
-                        block_y += 1;
+                let size = Vec2::new(self.size.x as f32, self.size.y as f32);
+                let top_left = state.position - size / pixels_per_block / 2.0;
+                self.draw_blocks(
+                    graphics,
+                    &runner.world.layers[state.layer],
+                    top_left,
+                    pixels_per_block,
+                    size,
+                    true,
+                );
+                if self.show_grid && pixels_per_block >= GRID_MIN_PIXELS_PER_BLOCK {
+                    Self::draw_grid(graphics, top_left, pixels_per_block, size);
+                }
+                // overlay the signal indicator, tinted by the signal's value (see `signal_color`)
+                for (value, dir_layer, chunk, pos) in &runner.world.signals_queue[0] {
+                    if (*dir_layer & LAYER_MASK) as usize != state.layer {
                         continue;
                     }
-                    let area = Rectangle::new(
-                        Vec2::new(px_x, px_y),
-                        Vec2::new(px_x + pixels_per_block, px_y + pixels_per_block),
-                    );
-                    // TODO: load chunk once for all 256 (or at least 16) blocks
-                    let (chunk, block) =
-                        runner.world.layers[state.layer].get_where(block_x, block_y);
-                    if let Some(chunk) = runner.world.layers[state.layer].get(&chunk) {
-                        if let Some(topmost_block) = chunk[block as usize].last() {
-                            self.draw_block(graphics, area, topmost_block);
-                        }
-                    }
-                    px_x += pixels_per_block;
-                    block_x += 1;
-                }
-                // overlay the signal indicator
-                for (_, dir_layer, chunk, pos) in &runner.world.signals_queue[0] {
-                    let chunk_y = i64::from_ne_bytes((*chunk >> 32).to_ne_bytes());
-                    let chunk_x = i64::from_ne_bytes((*chunk & 0xFFFFFFFF).to_ne_bytes());
-                    let x = chunk_x * 16 + (*pos as i64) % 16;
-                    let y = chunk_y * 16 + (*pos as i64) / 16;
+                    let (x, y) = Layer::where_to_coords(*chunk, *pos);
                     let x =
                         (x as f32 - state.position.x) * pixels_per_block + self.size.x as f32 / 2.0;
                     let y =
@@ -566,15 +1169,33 @@ impl WindowHandler<Event> for Window {
                         && signal_area.top_left().x <= self.size.x as f32
                         && signal_area.top_left().y <= self.size.y as f32
                     {
-                        if let Some(handle) =
-                            Self::index_by_dir(*dir_layer & 0b11100000, &self.images.world_signal)
-                                .handle()
-                        {
-                            graphics.draw_rectangle_image(signal_area, handle);
-                        }
+                        Self::index_by_dir(*dir_layer & 0b11100000, &self.images.world_signal)
+                            .draw_tinted(graphics, signal_area, Self::signal_color(*value));
                     }
                 }
+                // the area which is being selected for exporting or copying
+                if let Some(((x1, y1), (x2, y2))) = state.selection {
+                    let corner = |x: i64, y: i64| {
+                        Vec2::new(
+                            (x as f32 - state.position.x) * pixels_per_block + size.x / 2.0,
+                            (y as f32 - state.position.y) * pixels_per_block + size.y / 2.0,
+                        )
+                    };
+                    graphics.draw_rectangle(
+                        Rectangle::new(
+                            corner(x1.min(x2), y1.min(y2)),
+                            corner(x1.max(x2) + 1, y1.max(y2) + 1),
+                        ),
+                        if matches!(state.selecting, Some(Selecting::Export)) {
+                            Color::from_rgba(1.0, 1.0, 1.0, 0.25)
+                        } else {
+                            Color::from_rgba(0.3, 0.6, 1.0, 0.25)
+                        },
+                    );
+                }
                 // draw the menu, if there is one
+                // true if the menu is still opening/closing or its arrows are still moving
+                let mut animating = false;
                 'draw_menu: {
                     if let Some((_pos, menu)) = &mut state.open_menu {
                         match menu {
@@ -582,10 +1203,12 @@ impl WindowHandler<Event> for Window {
                                 changing,
                                 block,
                                 scroll_l,
+                                scroll_r,
                                 current,
                                 target,
                             } => {
                                 let (left, right) = if let Some((closing, since_when)) = changing {
+                                    animating = true;
                                     let prog = since_when.elapsed().as_secs_f32() * 3.0;
                                     if prog >= 0.3 {
                                         if *closing {
@@ -632,8 +1255,9 @@ impl WindowHandler<Event> for Window {
                                     // get blocks info
                                     let (chunk, inchunk) = runner.world.layers[state.layer]
                                         .get_where(block.0, block.1);
-                                    let chunk = runner.world.layers[state.layer].get_mut(&chunk);
-                                    let blocks = &mut chunk[inchunk as usize];
+                                    let blocks = runner.world.layers[state.layer]
+                                        .get(&chunk)
+                                        .map_or(&[][..], |chunk| &chunk[inchunk as usize][..]);
                                     // draw blocks
                                     if scroll_l.is_sign_negative() {
                                         *scroll_l = 0.0;
@@ -660,6 +1284,7 @@ impl WindowHandler<Event> for Window {
                                     // draw arrows
                                     // arrow 1: selected/source
                                     current.1 = 0.6 * current.1 + 0.4 * current.0 as f32;
+                                    animating |= (current.1 - current.0 as f32).abs() > 0.001;
                                     let arrow_y = area.top_left().y
                                         + pixels_per_block * (current.1 - *scroll_l);
                                     let arrow_area = Rectangle::new(
@@ -689,13 +1314,15 @@ impl WindowHandler<Event> for Window {
                                     graphics.set_clip(None);
                                     if let Some((target_block, is_move, target_arr_height)) = target
                                     {
-                                        *target_arr_height = 0.6 * *target_arr_height
-                                            + 0.4
-                                                * if *is_move {
-                                                    *target_block as f32 - 0.5
-                                                } else {
-                                                    *target_block as f32
-                                                };
+                                        let target_height = if *is_move {
+                                            *target_block as f32 - 0.5
+                                        } else {
+                                            *target_block as f32
+                                        };
+                                        *target_arr_height =
+                                            0.6 * *target_arr_height + 0.4 * target_height;
+                                        animating |=
+                                            (*target_arr_height - target_height).abs() > 0.001;
                                         let arrow_y = area.top_left().y
                                             + pixels_per_block * (*target_arr_height - *scroll_l);
                                         let arrow_area = Rectangle::new(
@@ -771,6 +1398,75 @@ impl WindowHandler<Event> for Window {
                                             if mi { Color::WHITE } else { Color::LIGHT_GRAY },
                                             false,
                                         );
+                                    // button 4: delete the selected block
+                                    let ba = button_area(3.0);
+                                    let mi = ba.contains(self.mouse_pos);
+                                    self.images
+                                        .world_menu_button_delete
+                                        .draw_image_aspect_ratio_tinted(
+                                            graphics,
+                                            helper,
+                                            ba,
+                                            if mi { Color::WHITE } else { Color::LIGHT_GRAY },
+                                            false,
+                                        );
+                                    // button 5: clear the stack, red while waiting for the confirming click
+                                    let ba = button_area(4.0);
+                                    let mi = ba.contains(self.mouse_pos);
+                                    self.images
+                                        .world_menu_button_clear
+                                        .draw_image_aspect_ratio_tinted(
+                                            graphics,
+                                            helper,
+                                            ba,
+                                            match (state.clear_confirm, mi) {
+                                                (true, _) => Color::RED,
+                                                (false, true) => Color::WHITE,
+                                                (false, false) => Color::LIGHT_GRAY,
+                                            },
+                                            false,
+                                        );
+                                    // value of the selected block, click or type digits to edit it
+                                    let value_text = match &state.value_edit {
+                                        Some((_, text)) => Some(format!("{text}_")),
+                                        None => {
+                                            blocks.iter().rev().nth(current.0).cloned().and_then(
+                                                |mut block| {
+                                                    WSInGame::block_value_mut(&mut block)
+                                                        .map(|v| v.to_string())
+                                                },
+                                            )
+                                        }
+                                    };
+                                    if let (Some(text), Some(font)) =
+                                        (value_text, &self.font_monospace)
+                                    {
+                                        let ba = button_area(5.0);
+                                        graphics.draw_rectangle(
+                                            ba.clone(),
+                                            Color::from_rgba(0.2, 0.2, 0.2, 0.8),
+                                        );
+                                        let layout =
+                                            font.layout_text(&text, 1.0, TextOptions::default());
+                                        let scale = (ba.height() * 0.5 / layout.height())
+                                            .min(ba.width() * 0.9 / layout.width());
+                                        let layout =
+                                            font.layout_text(&text, scale, TextOptions::default());
+                                        graphics.draw_text(
+                                            Vec2::new(
+                                                ba.top_left().x
+                                                    + (ba.width() - layout.width()) / 2.0,
+                                                ba.top_left().y
+                                                    + (ba.height() - layout.height()) / 2.0,
+                                            ),
+                                            if state.value_edit.is_some() {
+                                                Color::YELLOW
+                                            } else {
+                                                Color::WHITE
+                                            },
+                                            &layout,
+                                        );
+                                    }
                                 }
                                 // right
                                 {
@@ -789,11 +1485,93 @@ impl WindowHandler<Event> for Window {
                                         Color::from_rgba(0.2, 0.2, 0.2, 0.8),
                                     );
                                     let pixels_per_block = area.height() / 9.0 / 2.0;
-                                    for (i, block) in state.blocks_for_menu.iter().enumerate() {
-                                        let x =
-                                            area.top_left().x + pixels_per_block * (i % 6) as f32;
-                                        let y =
-                                            area.top_left().y + pixels_per_block * (i / 6) as f32;
+                                    let top_left = *area.top_left();
+                                    // row 0: tabs
+                                    let tab_width =
+                                        area.width() / PaletteCategory::ALL.len() as f32;
+                                    for (i, category) in
+                                        PaletteCategory::ALL.into_iter().enumerate()
+                                    {
+                                        let tab = Rectangle::new(
+                                            top_left + Vec2::new(tab_width * i as f32, 0.0),
+                                            top_left
+                                                + Vec2::new(
+                                                    tab_width * (i + 1) as f32,
+                                                    pixels_per_block,
+                                                ),
+                                        );
+                                        if category == state.palette_category {
+                                            graphics.draw_rectangle(
+                                                tab.clone(),
+                                                Color::from_rgba(0.4, 0.4, 0.4, 0.8),
+                                            );
+                                        }
+                                        if let Some(font) = &self.font_monospace {
+                                            Self::draw_text_in(
+                                                graphics,
+                                                font,
+                                                category.name(),
+                                                &tab,
+                                                Color::WHITE,
+                                            );
+                                        }
+                                    }
+                                    // row 1: filter
+                                    if let Some(font) = &self.font_monospace {
+                                        let filter_area = Rectangle::new(
+                                            top_left + Vec2::new(0.0, pixels_per_block),
+                                            top_left
+                                                + Vec2::new(area.width(), 2.0 * pixels_per_block),
+                                        );
+                                        let (text, color) = if state.palette_filter_edit {
+                                            (format!("{}_", state.palette_filter), Color::YELLOW)
+                                        } else if state.palette_filter.is_empty() {
+                                            ("click to search".to_owned(), Color::GRAY)
+                                        } else {
+                                            (state.palette_filter.clone(), Color::WHITE)
+                                        };
+                                        Self::draw_text_in(
+                                            graphics,
+                                            font,
+                                            &text,
+                                            &filter_area,
+                                            color,
+                                        );
+                                    }
+                                    // rows 2 to 17: the blocks. the last row can be scrolled up to the bottom of the panel.
+                                    let palette_top = top_left.y + 2.0 * pixels_per_block;
+                                    let shown = state
+                                        .blocks_for_menu
+                                        .iter()
+                                        .filter(|b| {
+                                            state.palette_category.shows(b, &state.palette_filter)
+                                        })
+                                        .count();
+                                    let rows = (shown + 5) / 6;
+                                    *scroll_r = scroll_r.min(rows as f32 - 16.0).max(0.0);
+                                    graphics.set_clip(Some(Rectangle::new(
+                                        IVec2::new(
+                                            area.top_left().x.ceil() as _,
+                                            palette_top.ceil() as _,
+                                        ),
+                                        IVec2::new(
+                                            area.bottom_right().x.floor() as _,
+                                            area.bottom_right().y.floor() as _,
+                                        ),
+                                    )));
+                                    for (i, block) in state
+                                        .blocks_for_menu
+                                        .iter()
+                                        .filter(|b| {
+                                            state.palette_category.shows(b, &state.palette_filter)
+                                        })
+                                        .enumerate()
+                                        .skip(6 * scroll_r.floor() as usize)
+                                        .take(6 * 17)
+                                    {
+                                        let x = top_left.x + pixels_per_block * (i % 6) as f32;
+                                        let y = palette_top
+                                            + pixels_per_block * ((i / 6) as f32 - *scroll_r);
                                         self.draw_block(
                                             graphics,
                                             Rectangle::new(
@@ -806,12 +1584,178 @@ impl WindowHandler<Event> for Window {
                                             block,
                                         );
                                     }
+                                    graphics.set_clip(None);
                                 }
                             }
                         }
                     }
                 }
-                helper.request_redraw();
+                // layer and tps indicators
+                if let Some(font) = &self.font_monospace {
+                    let layout = font.layout_text(
+                        &if let Some(selecting) = state.selecting {
+                            format!(
+                                "Layer {} (drag to select the area to {}, Esc to cancel)",
+                                state.layer,
+                                match selecting {
+                                    Selecting::Export => "export",
+                                    Selecting::Copy => "copy",
+                                }
+                            )
+                        } else if let Some(selection) = state.selection {
+                            let (x1, y1, x2, y2) = WSInGame::selection_rect(selection);
+                            format!(
+                                "Layer {} ({} stacks selected: Ctrl+C to copy, F to fill with the brush, Esc to cancel)",
+                                state.layer,
+                                (x2 - x1 + 1).saturating_mul(y2 - y1 + 1)
+                            )
+                        } else if state.brush_mode {
+                            format!("Layer {} (brush)", state.layer)
+                        } else {
+                            format!("Layer {}", state.layer)
+                        },
+                        20.0 * (self.size.y as f32 / 1080.0).sqrt(),
+                        TextOptions::default(),
+                    );
+                    graphics.draw_text(Vec2::new(8.0, 8.0), Color::WHITE, &layout);
+                    let text = if state.run {
+                        format!(
+                            "{:.1} / {} TPS, {} signals",
+                            state.tps_measurement.2,
+                            state.tps,
+                            runner.pending_signals()
+                        )
+                    } else {
+                        format!(
+                            "paused / {} TPS, {} signals",
+                            state.tps,
+                            runner.pending_signals()
+                        )
+                    };
+                    let layout = font.layout_text(
+                        &text,
+                        20.0 * (self.size.y as f32 / 1080.0).sqrt(),
+                        TextOptions::default(),
+                    );
+                    graphics.draw_text(
+                        Vec2::new(self.size.x as f32 - layout.width() - 8.0, 8.0),
+                        Color::WHITE,
+                        &layout,
+                    );
+                    if state.show_hud {
+                        let (x, y) = state.block_at(self.mouse_pos, self.size);
+                        let text = format!(
+                            "cursor {x}, {y} | camera {:.1}, {:.1} | zoom {:.2} ({:.1} px/block) | layer {}",
+                            state.position.x,
+                            state.position.y,
+                            state.zoom,
+                            state.pixels_per_block,
+                            state.layer
+                        );
+                        let scale = 20.0 * (self.size.y as f32 / 1080.0).sqrt();
+                        let layout = match &state.hud_text {
+                            Some((old_text, old_scale, layout))
+                                if *old_text == text && *old_scale == scale =>
+                            {
+                                layout.clone()
+                            }
+                            _ => {
+                                let layout = font.layout_text(&text, scale, TextOptions::default());
+                                state.hud_text = Some((text, scale, layout.clone()));
+                                layout
+                            }
+                        };
+                        graphics.draw_text(
+                            Vec2::new(8.0, self.size.y as f32 - layout.height() - 8.0),
+                            Color::WHITE,
+                            &layout,
+                        );
+                    }
+                }
+                // tooltip for the hovered block
+                let (w, h) = (self.size.x as f32, self.size.y as f32);
+                let in_menu_height = self.mouse_pos.y >= h * 0.05 && self.mouse_pos.y <= h * 0.95;
+                let hovered = match &state.open_menu {
+                    Some((
+                        _,
+                        WSInGameMenu::BlockStackChanger {
+                            block,
+                            scroll_l,
+                            scroll_r,
+                            ..
+                        },
+                    )) => {
+                        if in_menu_height && self.mouse_pos.x >= 0.0 && self.mouse_pos.x <= h * 0.1
+                        {
+                            // the stack, topmost block first
+                            let index = ((self.mouse_pos.y / h - 0.05) * 10.0 + scroll_l) as usize;
+                            let (chunk, inchunk) =
+                                runner.world.layers[state.layer].get_where(block.0, block.1);
+                            runner.world.layers[state.layer]
+                                .get(&chunk)
+                                .and_then(|chunk| chunk[inchunk as usize].iter().rev().nth(index))
+                                .map(|b| (b, Some(*block)))
+                        } else if let Some(PalettePart::Block(i)) =
+                            Self::palette_part_at(self.mouse_pos, self.size, *scroll_r)
+                        {
+                            state
+                                .blocks_for_menu
+                                .iter()
+                                .filter(|b| state.palette_category.shows(b, &state.palette_filter))
+                                .nth(i)
+                                .map(|b| (b, None))
+                        } else {
+                            None
+                        }
+                    }
+                    None => {
+                        let block_pos = Vec2::new(
+                            state.position.x
+                                + (self.mouse_pos.x - w / 2.0) / state.pixels_per_block,
+                            state.position.y
+                                + (self.mouse_pos.y - h / 2.0) / state.pixels_per_block,
+                        );
+                        let (x, y) = (block_pos.x.floor() as _, block_pos.y.floor() as _);
+                        runner.world.layers[state.layer]
+                            .top_block(x, y)
+                            .map(|b| (b, Some((x, y))))
+                    }
+                };
+                if let (Some((block, pos)), Some(font)) = (hovered, &self.font_monospace) {
+                    let mut text = Self::block_tooltip(block);
+                    if let (Block::Probe(_), Some((x, y))) = (block, pos) {
+                        text.push_str(&Self::probe_values(runner.probe_log(), state.layer, x, y));
+                    }
+                    let layout =
+                        font.layout_text(&text, 16.0 * (h / 1080.0).sqrt(), TextOptions::default());
+                    let padding = 4.0;
+                    // below and to the right of the cursor, but always on screen
+                    let pos = Vec2::new(
+                        (self.mouse_pos.x + 16.0).min(w - layout.width() - 2.0 * padding),
+                        (self.mouse_pos.y + 16.0).min(h - layout.height() - 2.0 * padding),
+                    );
+                    graphics.draw_rectangle(
+                        Rectangle::new(
+                            pos,
+                            pos + Vec2::new(
+                                layout.width() + 2.0 * padding,
+                                layout.height() + 2.0 * padding,
+                            ),
+                        ),
+                        Color::from_rgba(0.0, 0.0, 0.0, 0.8),
+                    );
+                    graphics.draw_text(pos + Vec2::new(padding, padding), Color::WHITE, &layout);
+                }
+                // a paused world only changes because of input, and input requests redraws itself
+                if state.run
+                    || redraw
+                    || self.redraw
+                    || animating
+                    || camera_moving
+                    || ANIMATION_LOADED.load(Ordering::Relaxed)
+                {
+                    helper.request_redraw();
+                }
             }
         }
         self.state.setnew(state);
@@ -819,13 +1763,22 @@ impl WindowHandler<Event> for Window {
     }
     fn on_mouse_button_down(&mut self, helper: &mut WindowHelper<Event>, button: MouseButton) {
         match button {
-            MouseButton::Left => self.mouse_down_l = true,
+            MouseButton::Left => {
+                self.mouse_down_l = true;
+                self.mouse_drag_distance = 0.0;
+            }
             MouseButton::Middle => self.mouse_down_m = true,
             MouseButton::Right => self.mouse_down_r = true,
             MouseButton::Other(..) => {}
         }
         match &mut self.state {
             WindowState::Nothing | WindowState::MainMenu(..) | WindowState::LoadingWorld(..) => {}
+            WindowState::Singleplayer(state, _)
+                if state.selecting.is_some() && matches!(button, MouseButton::Left) =>
+            {
+                let block = state.block_at(self.mouse_pos, self.size);
+                state.selection = Some((block, block));
+            }
             WindowState::Singleplayer(state, _) => match &mut state.open_menu {
                 None => {}
                 Some((
@@ -834,6 +1787,7 @@ impl WindowHandler<Event> for Window {
                         changing,
                         block,
                         scroll_l: scroll,
+                        scroll_r: _,
                         current,
                         target,
                     },
@@ -849,6 +1803,7 @@ impl WindowHandler<Event> for Window {
                 }
             },
         }
+        helper.request_redraw();
     }
     fn on_mouse_button_up(&mut self, helper: &mut WindowHelper<Event>, button: MouseButton) {
         match button {
@@ -861,33 +1816,80 @@ impl WindowHandler<Event> for Window {
         match button {
             MouseButton::Left => match &mut state {
                 WindowState::Nothing => {}
+                WindowState::MainMenu(state) if state.load_error.is_some() => {
+                    if Self::rel_to_abs_rect(self.size, &state.load_error_dismiss_button)
+                        .contains(self.mouse_pos)
+                    {
+                        state.load_error = None;
+                    }
+                }
+                WindowState::MainMenu(state) if self.missing_assets_shown() => {
+                    if Self::rel_to_abs_rect(self.size, &state.missing_assets_dismiss_button)
+                        .contains(self.mouse_pos)
+                    {
+                        self.missing_assets_dismissed = true;
+                    }
+                }
+                // the name prompt only reacts to the keyboard
+                WindowState::MainMenu(state) if state.name_prompt.is_some() => {}
                 WindowState::MainMenu(state) => {
                     let singleplayer_world_box =
                         Self::rel_to_abs_rect(self.size, &state.singleplayer_world_box);
+                    let delete_confirm = state.delete_confirm.take();
                     if singleplayer_world_box.contains(self.mouse_pos) {
                         let height = (self.mouse_pos.y - singleplayer_world_box.top_left().y)
                             / state.desired_world_height;
                         let index = state.world_scroll + height.floor() as usize;
-                        if let Some(save) = self.saves.get(index) {
+                        if self.mouse_pos.x
+                            >= singleplayer_world_box.bottom_right().x - state.desired_world_height
+                        {
+                            // delete button. worlds can only be deleted from the main menu, so the world can't be loaded right now.
+                            if index < self.saves.len() {
+                                if delete_confirm == Some(index) {
+                                    let path = self.saves[index].0.clone();
+                                    eprintln!("Deleting save {path:?}");
+                                    match fs::remove_dir_all(&path) {
+                                        Ok(()) => {
+                                            self.saves.remove(index);
+                                            self.save_thumbnails.remove(&path);
+                                            if index < state.worlds_texts.len() {
+                                                state.worlds_texts.remove(index);
+                                            }
+                                            state.world_scroll = state
+                                                .world_scroll
+                                                .min(self.saves.len().saturating_sub(1));
+                                        }
+                                        Err(e) => {
+                                            eprintln!("[warn] Couldn't delete save {path:?}: {e}")
+                                        }
+                                    }
+                                } else {
+                                    state.delete_confirm = Some(index);
+                                }
+                            }
+                        } else if let Some(save) = self.saves.get(index) {
                             eprintln!("Loading save {save:?}");
                             let prog = Arc::new(Mutex::new(0.0));
                             let path = save.0.clone();
                             self.state = WindowState::LoadingWorld(
                                 Arc::clone(&prog),
                                 Some(std::thread::spawn(move || {
-                                    match World::load_from_dir(path, Some(prog)) {
+                                    match World::load_from_dir(&path, Some(prog)) {
                                         Ok(Some(world)) => {
                                             let mut runner = Runner::new(world);
                                             runner.autosave = (100, 1000);
-                                            Some(runner)
+                                            Ok(runner)
                                         }
                                         Ok(None) => {
-                                            eprintln!("[err] couldn't load world!");
-                                            None
+                                            let e = format!("Couldn't load world from {path:?}: the save data is invalid or from an unsupported version.");
+                                            eprintln!("[err] {e}");
+                                            Err(e)
                                         }
                                         Err(e) => {
-                                            eprintln!("[err] couldn't load world: {e}");
-                                            None
+                                            let e =
+                                                format!("Couldn't load world from {path:?}: {e}");
+                                            eprintln!("[err] {e}");
+                                            Err(e)
                                         }
                                     }
                                 })),
@@ -898,107 +1900,44 @@ impl WindowHandler<Event> for Window {
                         let singleplayer_new_world_button =
                             Self::rel_to_abs_rect(self.size, &state.singleplayer_new_world_button);
                         if singleplayer_new_world_button.contains(self.mouse_pos) {
-                            eprintln!("Setting up empty world...");
-                            let world = World::new_empty();
-                            // eprintln!("Adding some blocks for testing...");
-                            // {
-                            //     let chunk = world.layers[0].get_mut(&0);
-                            //     let dirs = [
-                            //         runner::DIR_UP,
-                            //         runner::DIR_RIGHT,
-                            //         runner::DIR_DOWN,
-                            //         runner::DIR_LEFT,
-                            //         runner::DIR_UP_L,
-                            //         runner::DIR_DOWN_L,
-                            //     ];
-                            //     for ch in (b'A'..=b'Z').rev() {
-                            //         chunk[0].push(Block::Char(ch as _));
-                            //     }
-                            //     for (i, dir) in dirs.iter().enumerate() {
-                            //         chunk[16 * 0 + 4 + i].push(Block::Delay(0, *dir));
-                            //     }
-                            //     for (i, dir) in dirs.iter().enumerate() {
-                            //         chunk[16 * 0 + 10 + i].push(Block::Splitter(*dir));
-                            //     }
-                            //     for mode in 0..=9u8 {
-                            //         for (i, dir) in dirs.iter().enumerate() {
-                            //             chunk[16 * (1 + mode as usize) + 4 + i]
-                            //                 .push(Block::Storage(0, mode, *dir));
-                            //         }
-                            //     }
-                            //     for (i, dir) in dirs.iter().enumerate() {
-                            //         chunk[16 * 14 + 4 + i].push(Block::Gate(false, *dir));
-                            //     }
-                            //     for (i, dir) in dirs.iter().enumerate() {
-                            //         chunk[16 * 14 + 10 + i].push(Block::Gate(true, *dir));
-                            //     }
-                            //     for (i, dir) in dirs.iter().enumerate() {
-                            //         chunk[16 * 15 + 4 + i].push(Block::Move(*dir));
-                            //     }
-                            //     for (i, dir) in dirs.iter().enumerate() {
-                            //         chunk[16 * 15 + 10 + i].push(Block::Swap(*dir));
-                            //     }
-                            // }
-                            // // TOP LEFT
-                            // {
-                            //     let (chunk, pos) = world.layers[0].get_where(-1, -1);
-                            //     let chunk = world.layers[0].get_mut(&chunk);
-                            //     for (i, blocks) in chunk.iter_mut().enumerate() {
-                            //         let (x, y) =
-                            //             (15 - (i as u32 & 0xF), 15 - ((i as u32 & 0xF0) >> 4));
-                            //         blocks.push(Block::Color(
-                            //             0xFF000000 | x << 16 | x << 20 | y << 0 | y << 4,
-                            //         ));
-                            //     }
-                            // }
-                            // // TOP RIGHT
-                            // {
-                            //     let (chunk, pos) = world.layers[0].get_where(0, -1);
-                            //     let chunk = world.layers[0].get_mut(&chunk);
-                            //     for (i, blocks) in chunk.iter_mut().enumerate() {
-                            //         let (x, y) = (i as u32 & 0xF, 15 - ((i as u32 & 0xF0) >> 4));
-                            //         blocks.push(Block::Color(
-                            //             0xFF000000 | x << 8 | x << 12 | y << 0 | y << 4,
-                            //         ));
-                            //     }
-                            // }
-                            // // BOTTOM LEFT
-                            // {
-                            //     let (chunk, pos) = world.layers[0].get_where(-1, 0);
-                            //     let chunk = world.layers[0].get_mut(&chunk);
-                            //     for (i, blocks) in chunk.iter_mut().enumerate() {
-                            //         let (x, y) = (15 - (i as u32 & 0xF), (i as u32 & 0xF0) >> 4);
-                            //         blocks.push(Block::Color(
-                            //             0xFF000000 | x << 16 | x << 20 | y << 8 | y << 12,
-                            //         ));
-                            //     }
-                            // }
-                            // // BOTTOM 2 RIGHT
-                            // {
-                            //     let (chunk, _) = world.layers[0].get_where(16, 0);
-                            //     let chunk = world.layers[0].get_mut(&chunk);
-                            //     chunk[1].push(Block::Color(0xFFFFFFFF));
-                            //     chunk[16 + 1].push(Block::Splitter(runner::DIR_UP));
-                            //     chunk[32 + 1].push(Block::Delay(0, runner::DIR_DOWN));
-                            //     chunk[48 + 1].push(Block::Splitter(runner::DIR_RIGHT));
-                            //     chunk[16 + 2].push(Block::Storage(0xFF000000, 4, runner::DIR_LEFT));
-                            //     chunk[32 + 2].push(Block::Storage(16, 0, runner::DIR_UP));
-                            //     chunk[48 + 2].push(Block::Splitter(runner::DIR_UP));
-                            //     chunk[64 + 2].push(Block::Splitter(runner::DIR_RIGHT));
-                            //     chunk[16 + 3].push(Block::Splitter(runner::DIR_LEFT));
-                            //     chunk[32 + 3].push(Block::Storage(4, 0, runner::DIR_UP));
-                            //     chunk[48 + 3].push(Block::Delay(0, runner::DIR_UP));
-                            //     chunk[64 + 3].push(Block::Splitter(runner::DIR_UP));
-                            // }
-                            let mut runner = Runner::new(world);
-                            runner.autosave = (500, 0);
-                            self.state = WindowState::Singleplayer(WSInGame::default(), runner);
-                            self.redraw = true;
+                            // ask for a name first, the world is created once it was entered
+                            state.name_prompt = Some(String::new());
                         }
                     }
                 }
                 WindowState::LoadingWorld(..) => {}
+                WindowState::Singleplayer(state, _) if state.selecting.is_some() => {
+                    match (state.selecting, state.selection) {
+                        (Some(Selecting::Export), Some(selection)) => {
+                            state.selecting = None;
+                            state.selection = None;
+                            state.export_pending = Some(WSInGame::selection_rect(selection));
+                        }
+                        // the selection stays visible until it is copied
+                        (Some(Selecting::Copy), Some(_)) => state.selecting = None,
+                        _ => {}
+                    }
+                }
                 WindowState::Singleplayer(state, runner) => match &mut state.open_menu {
+                    None if self.mouse_drag_distance < CLICK_DRAG_THRESHOLD => {
+                        let block_pos = Vec2::new(
+                            state.position.x
+                                + (self.mouse_pos.x - self.size.x as f32 / 2.0)
+                                    / state.pixels_per_block,
+                            state.position.y
+                                + (self.mouse_pos.y - self.size.y as f32 / 2.0)
+                                    / state.pixels_per_block,
+                        );
+                        let (x, y) = (block_pos.x.floor() as i64, block_pos.y.floor() as i64);
+                        if self.shift_down {
+                            // eraser
+                            state.history.pop(&mut runner.world, state.layer, x, y);
+                        } else if let (true, Some(brush)) = (state.brush_mode, &state.brush) {
+                            state
+                                .history
+                                .push(&mut runner.world, state.layer, x, y, brush.clone());
+                        }
+                    }
                     None => {}
                     Some((
                         _,
@@ -1006,29 +1945,43 @@ impl WindowHandler<Event> for Window {
                             changing,
                             block,
                             scroll_l: scroll,
+                            scroll_r,
                             current,
                             target,
                         },
                     )) => {
+                        let clear_confirm = std::mem::take(&mut state.clear_confirm);
+                        state.palette_filter_edit = false;
                         if let Some((which, is_move, _)) = target {
-                            let (chunk, inchunk) =
-                                runner.world.layers[state.layer].get_where(block.0, block.1);
-                            let blocks = &mut runner.world.layers[state.layer].get_mut(&chunk)
-                                [inchunk as usize];
+                            let (x, y) = *block;
+                            let len = WSInGame::stack_len(&runner.world.layers[state.layer], x, y);
+                            // current.0 and which count from the top of the stack
                             if *is_move {
-                                if current.0 < blocks.len() && *which <= blocks.len() {
-                                    let block = blocks.remove(blocks.len() - 1 - current.0);
-                                    let which = if *which > current.0 {
-                                        blocks.len() + 1 - *which
+                                if current.0 < len && *which <= len {
+                                    let to = if *which > current.0 {
+                                        len - *which
                                     } else {
-                                        blocks.len() - *which
+                                        len - 1 - *which
                                     };
-                                    blocks.insert(which, block);
+                                    state.history.move_block(
+                                        &mut runner.world,
+                                        state.layer,
+                                        x,
+                                        y,
+                                        len - 1 - current.0,
+                                        to,
+                                    );
                                 }
                             } else {
-                                if current.0 < blocks.len() && *which < blocks.len() {
-                                    let len = blocks.len();
-                                    blocks.swap(len - 1 - current.0, len - 1 - *which);
+                                if current.0 < len && *which < len {
+                                    state.history.swap(
+                                        &mut runner.world,
+                                        state.layer,
+                                        x,
+                                        y,
+                                        len - 1 - current.0,
+                                        len - 1 - *which,
+                                    );
                                 }
                             }
                             *target = None;
@@ -1042,42 +1995,88 @@ impl WindowHandler<Event> for Window {
                                 ((self.mouse_pos.y / self.size.y as f32) - 0.05) * 10.0;
                             match which_button as usize {
                                 0 => state.run = !state.run,
-                                1 => runner.tick(),
+                                1 => {
+                                    runner.tick();
+                                }
                                 2 => {
                                     // send zero-signal from above
-                                    let (chunk, inchunk) = runner.world.layers[state.layer]
-                                        .get_where(block.0, block.1);
-                                    runner.world.signals_queue[0].push((
+                                    runner.inject_signal(
+                                        block.0,
+                                        block.1,
+                                        state.layer as u8,
+                                        stackmaker::runner::DIR_DOWN_L,
+                                        0,
                                         0,
-                                        stackmaker::runner::DIR_DOWN_L | state.layer as u8,
-                                        chunk,
-                                        inchunk,
-                                    ));
+                                    );
+                                }
+                                3 | 4 => {
+                                    let (x, y) = *block;
+                                    let len = WSInGame::stack_len(
+                                        &runner.world.layers[state.layer],
+                                        x,
+                                        y,
+                                    );
+                                    if which_button as usize == 3 {
+                                        // current.0 counts from the top of the stack
+                                        if current.0 < len {
+                                            state.history.remove(
+                                                &mut runner.world,
+                                                state.layer,
+                                                x,
+                                                y,
+                                                len - 1 - current.0,
+                                            );
+                                        }
+                                    } else if clear_confirm {
+                                        state.history.clear(&mut runner.world, state.layer, x, y);
+                                    } else {
+                                        state.clear_confirm = true;
+                                    }
+                                    let max = WSInGame::stack_len(
+                                        &runner.world.layers[state.layer],
+                                        x,
+                                        y,
+                                    )
+                                    .saturating_sub(1);
+                                    current.0 = current.0.min(max);
+                                    *scroll = scroll.min(max as f32);
                                 }
+                                5 => state.value_edit = Some((current.0, String::new())),
                                 _ => {}
                             }
-                        } else if self.mouse_pos.y >= self.size.y as f32 * 0.05
-                            && self.mouse_pos.y <= self.size.y as f32 * 0.95
-                            && self.mouse_pos.x >= self.size.x as f32 - self.size.y as f32 * 0.3
-                            && self.mouse_pos.x <= self.size.x as f32
+                        } else if let Some(part) =
+                            Self::palette_part_at(self.mouse_pos, self.size, *scroll_r)
                         {
-                            let i = 6
-                                * (((self.mouse_pos.y / self.size.y as f32) - 0.05) * 20.0)
-                                    as usize
-                                + (((self.mouse_pos.x - self.size.x as f32
-                                    + self.size.y as f32 * 0.3)
-                                    * 6.0
-                                    / (self.size.y as f32 * 0.3))
-                                    as usize)
-                                    .max(0)
-                                    .min(5);
-                            if let Some(add_block) = state.blocks_for_menu.get(i) {
-                                let (chunk, pos) =
-                                    runner.world.layers[state.layer].get_where(block.0, block.1);
-                                runner.world.layers[state.layer].get_mut(&chunk)[pos as usize]
-                                    .push(add_block.clone());
-                            }
-                        }
+                            match part {
+                                PalettePart::Tab(category) => {
+                                    state.palette_category = category;
+                                    *scroll_r = 0.0;
+                                }
+                                PalettePart::Filter => {
+                                    state.value_edit = None;
+                                    state.palette_filter_edit = true;
+                                }
+                                PalettePart::Block(i) => {
+                                    if let Some(add_block) = state
+                                        .blocks_for_menu
+                                        .iter()
+                                        .filter(|b| {
+                                            state.palette_category.shows(b, &state.palette_filter)
+                                        })
+                                        .nth(i)
+                                    {
+                                        state.history.push(
+                                            &mut runner.world,
+                                            state.layer,
+                                            block.0,
+                                            block.1,
+                                            add_block.clone(),
+                                        );
+                                        state.brush = Some(add_block.clone());
+                                    }
+                                }
+                            }
+                        }
                     }
                 },
             },
@@ -1102,6 +2101,7 @@ impl WindowHandler<Event> for Window {
                                 changing,
                                 block: _,
                                 scroll_l: _,
+                                scroll_r: _,
                                 current: _,
                                 target: _,
                             },
@@ -1111,12 +2111,16 @@ impl WindowHandler<Event> for Window {
                             }
                         }
                         None => {
+                            state.clear_confirm = false;
+                            state.value_edit = None;
+                            state.palette_filter_edit = false;
                             state.open_menu = Some((
                                 self.mouse_pos,
                                 WSInGameMenu::BlockStackChanger {
                                     changing: Some((false, Instant::now())),
                                     block: (block_pos.x.floor() as _, block_pos.y.floor() as _),
                                     scroll_l: 0.0,
+                                    scroll_r: 0.0,
                                     current: (0, -0.0),
                                     target: None,
                                 },
@@ -1143,7 +2147,17 @@ impl WindowHandler<Event> for Window {
         let mut state = self.state.take();
         match &mut state {
             WindowState::Nothing => {}
-            WindowState::MainMenu(..) => {}
+            WindowState::MainMenu(state) => {
+                if Self::rel_to_abs_rect(self.size, &state.singleplayer_world_box)
+                    .contains(self.mouse_pos)
+                {
+                    // scroll by whole rows, scrolling down moves further down the list
+                    let rows = dist.round() as isize;
+                    state.world_scroll = (state.world_scroll as isize - rows)
+                        .clamp(0, self.saves.len().saturating_sub(1) as isize)
+                        as usize;
+                }
+            }
             WindowState::LoadingWorld(..) => {}
             WindowState::Singleplayer(state, _) => match &mut state.open_menu {
                 Some((
@@ -1152,6 +2166,7 @@ impl WindowHandler<Event> for Window {
                         changing,
                         block,
                         scroll_l: scroll,
+                        scroll_r,
                         current,
                         target,
                     },
@@ -1166,11 +2181,28 @@ impl WindowHandler<Event> for Window {
                         && self.mouse_pos.x <= self.size.y as f32 * 0.3
                     {
                         *scroll -= dist * 0.25;
+                    } else if rel_mouse.y >= 0.05
+                        && rel_mouse.y <= 0.95
+                        && self.mouse_pos.x >= self.size.x as f32 - self.size.y as f32 * 0.3
+                    {
+                        // the range is limited when the palette is drawn
+                        *scroll_r -= dist;
                     } else {
                         state.zoom += dist * 0.25;
                     }
                 }
-                None => state.zoom += dist,
+                None => {
+                    // keep the point under the cursor where it is on screen
+                    let mouse_centered = Vec2::new(
+                        self.mouse_pos.x - self.size.x as f32 / 2.0,
+                        self.mouse_pos.y - self.size.y as f32 / 2.0,
+                    );
+                    let old_pixels_per_block = 2.0f32.powf(state.zoom);
+                    state.zoom += dist;
+                    let new_pixels_per_block = 2.0f32.powf(state.zoom);
+                    state.position += mouse_centered / old_pixels_per_block
+                        - mouse_centered / new_pixels_per_block;
+                }
             },
         }
         self.state.setnew(state);
@@ -1193,6 +2225,247 @@ impl WindowHandler<Event> for Window {
         self.redraw = true;
         helper.request_redraw();
     }
+    fn on_key_down(
+        &mut self,
+        helper: &mut WindowHelper<Event>,
+        virtual_key_code: Option<VirtualKeyCode>,
+        _scancode: KeyScancode,
+    ) {
+        if let Some(key) = virtual_key_code {
+            let mut state = self.state.take();
+            match &mut state {
+                WindowState::MainMenu(state) if state.load_error.is_some() => {
+                    if matches!(
+                        key,
+                        VirtualKeyCode::Escape
+                            | VirtualKeyCode::Return
+                            | VirtualKeyCode::NumpadEnter
+                    ) {
+                        state.load_error = None;
+                    }
+                }
+                WindowState::MainMenu(_) if self.missing_assets_shown() => {
+                    if matches!(
+                        key,
+                        VirtualKeyCode::Escape
+                            | VirtualKeyCode::Return
+                            | VirtualKeyCode::NumpadEnter
+                    ) {
+                        self.missing_assets_dismissed = true;
+                    }
+                }
+                WindowState::MainMenu(state) if state.name_prompt.is_some() => match key {
+                    VirtualKeyCode::Backspace => {
+                        state.name_prompt.as_mut().unwrap().pop();
+                    }
+                    VirtualKeyCode::Return | VirtualKeyCode::NumpadEnter => {
+                        let name = state.name_prompt.take().unwrap();
+                        self.create_new_world(&name);
+                    }
+                    VirtualKeyCode::Escape => state.name_prompt = None,
+                    _ => {}
+                },
+                WindowState::Singleplayer(state, _) if state.palette_filter_edit => match key {
+                    VirtualKeyCode::Backspace => {
+                        state.palette_filter.pop();
+                    }
+                    VirtualKeyCode::Return | VirtualKeyCode::NumpadEnter => {
+                        state.palette_filter_edit = false
+                    }
+                    VirtualKeyCode::Escape => {
+                        state.palette_filter_edit = false;
+                        state.palette_filter.clear();
+                    }
+                    _ => {}
+                },
+                WindowState::Singleplayer(state, runner) if state.value_edit.is_some() => match key
+                {
+                    VirtualKeyCode::Backspace => {
+                        state.value_edit.as_mut().unwrap().1.pop();
+                    }
+                    VirtualKeyCode::Return | VirtualKeyCode::NumpadEnter => {
+                        state.apply_value_edit(&mut runner.world)
+                    }
+                    VirtualKeyCode::Escape => state.value_edit = None,
+                    _ => {}
+                },
+                WindowState::Singleplayer(state, runner)
+                    if self.ctrl_down && matches!(key, VirtualKeyCode::Z | VirtualKeyCode::Y) =>
+                {
+                    let redo = key == VirtualKeyCode::Y || self.shift_down;
+                    state.undo_redo(&mut runner.world, redo);
+                }
+                // keys would fight with scrolling in the menu
+                WindowState::Singleplayer(state, _) if state.open_menu.is_some() => {}
+                WindowState::Singleplayer(state, _)
+                    if (state.selecting.is_some() || state.selection.is_some())
+                        && key == VirtualKeyCode::Escape =>
+                {
+                    state.selecting = None;
+                    state.selection = None;
+                }
+                WindowState::Singleplayer(state, runner)
+                    if self.ctrl_down && key == VirtualKeyCode::C =>
+                {
+                    state.copy_selection(&runner.world);
+                }
+                WindowState::Singleplayer(state, runner)
+                    if self.ctrl_down && key == VirtualKeyCode::V =>
+                {
+                    let (x, y) = state.block_at(self.mouse_pos, self.size);
+                    if state.clipboard.is_empty() {
+                        eprintln!("[info] nothing to paste, copy an area using M and Ctrl+C first");
+                    } else {
+                        let layer = state.layer;
+                        state
+                            .history
+                            .paste(&mut runner.world, layer, x, y, &state.clipboard);
+                    }
+                }
+                WindowState::Singleplayer(state, runner)
+                    if state.selecting.is_none()
+                        && state.selection.is_some()
+                        && key == VirtualKeyCode::F =>
+                {
+                    state.fill_selection(&mut runner.world);
+                }
+                WindowState::Singleplayer(state, _) if key == VirtualKeyCode::P => {
+                    state.selecting = Some(Selecting::Export);
+                    state.selection = None;
+                }
+                WindowState::Singleplayer(state, _) if key == VirtualKeyCode::M => {
+                    state.selecting = Some(Selecting::Copy);
+                    state.selection = None;
+                }
+                WindowState::Singleplayer(_, runner) if key == VirtualKeyCode::Escape => {
+                    // save, then return to the main menu. if saving fails, stay in the world so nothing is lost.
+                    let (dir, new_save) = if let Some(dir) = &runner.world.save_dir {
+                        (dir.clone(), None)
+                    } else {
+                        let (dir, name) = self.new_world_dir("New World");
+                        (dir.clone(), Some((dir, name)))
+                    };
+                    eprintln!("[info] saving to {dir:?}");
+                    runner.wait_for_autosave();
+                    match runner.world.save_to_dir(&dir) {
+                        Ok(()) => {
+                            self.reload_thumbnail(&dir);
+                            if let Some(save) = new_save {
+                                runner.world.save_dir = Some(dir);
+                                self.saves.push(save);
+                            }
+                            self.keys_down.clear();
+                            self.state = WindowState::MainMenu(WSMainMenu::new());
+                            self.redraw = true;
+                        }
+                        Err(e) => eprintln!("[warn] Couldn't save world to {dir:?}: {e}"),
+                    }
+                }
+                WindowState::Singleplayer(state, _) if key == VirtualKeyCode::B => {
+                    state.brush_mode = !state.brush_mode;
+                }
+                WindowState::Singleplayer(state, _)
+                    if matches!(key, VirtualKeyCode::Comma | VirtualKeyCode::Period) =>
+                {
+                    // select the next slower or faster step, even if the current tps is between two steps
+                    state.tps = if key == VirtualKeyCode::Period {
+                        TPS_STEPS
+                            .into_iter()
+                            .find(|tps| *tps > state.tps)
+                            .unwrap_or(TPS_STEPS[TPS_STEPS.len() - 1])
+                    } else {
+                        TPS_STEPS
+                            .into_iter()
+                            .rev()
+                            .find(|tps| *tps < state.tps)
+                            .unwrap_or(TPS_STEPS[0])
+                    };
+                }
+                WindowState::Singleplayer(..) if key == VirtualKeyCode::G => {
+                    self.show_grid = !self.show_grid;
+                }
+                WindowState::Singleplayer(state, _) if key == VirtualKeyCode::F3 => {
+                    state.show_hud = !state.show_hud;
+                }
+                // layer 0 is the upmost layer
+                WindowState::Singleplayer(state, _)
+                    if matches!(key, VirtualKeyCode::PageUp | VirtualKeyCode::LBracket) =>
+                {
+                    state.layer = state.layer.saturating_sub(1);
+                }
+                WindowState::Singleplayer(state, runner)
+                    if matches!(key, VirtualKeyCode::PageDown | VirtualKeyCode::RBracket) =>
+                {
+                    state.layer = (state.layer + 1).min(runner.world.layers.len() - 1);
+                }
+                _ => {
+                    self.keys_down.insert(key);
+                }
+            }
+            self.state.setnew(state);
+        }
+        helper.request_redraw();
+    }
+    fn on_keyboard_modifiers_changed(
+        &mut self,
+        _helper: &mut WindowHelper<Event>,
+        state: ModifiersState,
+    ) {
+        self.shift_down = state.shift();
+        self.ctrl_down = state.ctrl();
+    }
+    fn on_keyboard_char(&mut self, helper: &mut WindowHelper<Event>, unicode_codepoint: char) {
+        // backspace and enter are handled in on_key_down
+        match &mut self.state {
+            WindowState::MainMenu(state) => {
+                if let Some(name) = &mut state.name_prompt {
+                    if !unicode_codepoint.is_control() {
+                        name.push(unicode_codepoint);
+                        helper.request_redraw();
+                    }
+                }
+            }
+            WindowState::Singleplayer(state, _) => {
+                if let Some((
+                    _,
+                    WSInGameMenu::BlockStackChanger {
+                        current, scroll_r, ..
+                    },
+                )) = &mut state.open_menu
+                {
+                    if state.palette_filter_edit {
+                        if !unicode_codepoint.is_control() {
+                            // type names are lowercase
+                            state
+                                .palette_filter
+                                .extend(unicode_codepoint.to_lowercase());
+                            *scroll_r = 0.0;
+                            helper.request_redraw();
+                        }
+                    } else if unicode_codepoint.is_ascii_digit() {
+                        state
+                            .value_edit
+                            .get_or_insert_with(|| (current.0, String::new()))
+                            .1
+                            .push(unicode_codepoint);
+                        helper.request_redraw();
+                    }
+                }
+            }
+            _ => {}
+        }
+    }
+    fn on_key_up(
+        &mut self,
+        helper: &mut WindowHelper<Event>,
+        virtual_key_code: Option<VirtualKeyCode>,
+        _scancode: KeyScancode,
+    ) {
+        if let Some(key) = virtual_key_code {
+            self.keys_down.remove(&key);
+        }
+        helper.request_redraw();
+    }
     fn on_mouse_move(
         &mut self,
         helper: &mut speedy2d::window::WindowHelper<Event>,
@@ -1208,6 +2481,7 @@ impl WindowHandler<Event> for Window {
                             changing,
                             block,
                             scroll_l: scroll,
+                            scroll_r: _,
                             current,
                             target,
                         },
@@ -1239,7 +2513,13 @@ impl WindowHandler<Event> for Window {
                     }
                     None => {}
                 };
-                if self.mouse_down_l {
+                if self.mouse_down_l && state.selecting.is_some() && state.selection.is_some() {
+                    let block = state.block_at(position, self.size);
+                    if let Some((_, corner)) = &mut state.selection {
+                        *corner = block;
+                    }
+                } else if self.mouse_down_l {
+                    self.mouse_drag_distance += (position - self.mouse_pos).magnitude();
                     state.position -= (position - self.mouse_pos) / state.pixels_per_block;
                 }
             }
@@ -1254,6 +2534,8 @@ pub struct Config {
     mono_font: String,
     saves_dir: String,
     assets_dir: String,
+    /// if true, assets are reloaded when a file in `assets_dir` changes
+    hot_reload: bool,
 }
 
 struct WSMainMenu {
@@ -1265,6 +2547,14 @@ struct WSMainMenu {
     world_display_font_scale: f32,
     world_scroll: usize,
     worlds_texts: Vec<Option<Rc<FormattedTextBlock>>>,
+    /// the index of the save whose delete button was clicked once. clicking it again deletes the save.
+    delete_confirm: Option<usize>,
+    /// if this is `Some`, the user is typing the name of a new world
+    name_prompt: Option<String>,
+    /// shown on top of the menu until it is dismissed
+    load_error: Option<String>,
+    load_error_dismiss_button: Rectangle,
+    missing_assets_dismiss_button: Rectangle,
 }
 impl WSMainMenu {
     fn new() -> Self {
@@ -1277,6 +2567,14 @@ impl WSMainMenu {
             world_display_font_scale: 0.0,
             worlds_texts: vec![],
             world_scroll: 0,
+            delete_confirm: None,
+            name_prompt: None,
+            load_error: None,
+            load_error_dismiss_button: Rectangle::new(Vec2::new(0.45, 0.56), Vec2::new(0.55, 0.62)),
+            missing_assets_dismiss_button: Rectangle::new(
+                Vec2::new(0.45, 0.76),
+                Vec2::new(0.55, 0.82),
+            ),
         }
     }
 }
@@ -1288,7 +2586,47 @@ struct WSInGame {
     /// updated on each draw
     pixels_per_block: f32,
     open_menu: Option<(Vec2, WSInGameMenu)>,
+    /// every block in the palette, the selected category and filter decide which ones are shown
     blocks_for_menu: Vec<Block>,
+    palette_category: PaletteCategory,
+    /// only blocks whose `type_name` contains this are shown in the palette
+    palette_filter: String,
+    /// while this is true, typed characters are added to `palette_filter`
+    palette_filter_edit: bool,
+    /// how many ticks per second to run while `run` is true, independent of the framerate.
+    /// changed using , and . (see `TPS_STEPS`).
+    tps: f32,
+    /// seconds which passed since the last tick, used to decide how many ticks to run in a frame
+    tick_time: f32,
+    /// when the last frame was drawn while running
+    last_frame: Option<Instant>,
+    /// (start of the current measurement, ticks since then, ticks per second of the last measurement)
+    tps_measurement: (Instant, u32, f32),
+    /// when the last frame was drawn while the camera was moving, used to move the camera while keys are held down
+    last_draw: Option<Instant>,
+    /// while this is true, left-clicking a cell pushes `brush` onto its stack (toggled with B)
+    brush_mode: bool,
+    /// the block which was last added from the palette
+    brush: Option<Block>,
+    /// set when the clear button in the stack menu was clicked once, clicking it again clears the stack
+    clear_confirm: bool,
+    /// (index from the top of the stack, text) while a new value for a block in the stack menu is being typed
+    value_edit: Option<(usize, String)>,
+    /// the player's edits, for undo (Ctrl+Z) and redo (Ctrl+Y or Ctrl+Shift+Z)
+    history: History,
+    /// while this is set, dragging with the left mouse button selects an area instead of moving the camera.
+    /// P selects an area to export as an image, M one to copy. Esc cancels.
+    selecting: Option<Selecting>,
+    /// the two corners (in blocks) of the area which is being selected, or which was selected for copying (Ctrl+C)
+    selection: Option<((i64, i64), (i64, i64))>,
+    /// the stacks copied using Ctrl+C, relative to the top left corner of the selection. Ctrl+V pastes them at the cursor.
+    clipboard: Vec<((i64, i64), Vec<Block>)>,
+    /// (x1, y1, x2, y2), an area which will be exported before the next frame is drawn
+    export_pending: Option<(i64, i64, i64, i64)>,
+    /// if true, the cursor's block, the camera and the zoom are shown in the bottom left corner (toggled with F3)
+    show_hud: bool,
+    /// the hud's last text and font size, so it is only laid out again when one of them changes
+    hud_text: Option<(String, f32, Rc<FormattedTextBlock>)>,
 }
 impl Default for WSInGame {
     fn default() -> Self {
@@ -1299,6 +2637,25 @@ impl Default for WSInGame {
             zoom: 5.0,
             pixels_per_block: 1.0,
             open_menu: None,
+            tps: 20.0,
+            tick_time: 0.0,
+            last_frame: None,
+            tps_measurement: (Instant::now(), 0, 0.0),
+            last_draw: None,
+            brush_mode: false,
+            brush: None,
+            clear_confirm: false,
+            value_edit: None,
+            history: History::default(),
+            selecting: None,
+            selection: None,
+            clipboard: vec![],
+            export_pending: None,
+            show_hud: false,
+            hud_text: None,
+            palette_category: PaletteCategory::All,
+            palette_filter: String::new(),
+            palette_filter_edit: false,
             blocks_for_menu: vec![
                 Block::Delay(0, runner::DIR_LEFT),
                 Block::Delay(0, runner::DIR_UP),
@@ -1306,12 +2663,25 @@ impl Default for WSInGame {
                 Block::Delay(0, runner::DIR_RIGHT),
                 Block::Delay(0, runner::DIR_UP_L),
                 Block::Delay(0, runner::DIR_DOWN_L),
+                Block::Delay(0, runner::DIR_LEFT | runner::DELAY_BY_VALUE),
+                Block::Delay(0, runner::DIR_UP | runner::DELAY_BY_VALUE),
+                Block::Delay(0, runner::DIR_DOWN | runner::DELAY_BY_VALUE),
+                Block::Delay(0, runner::DIR_RIGHT | runner::DELAY_BY_VALUE),
+                Block::Delay(0, runner::DIR_UP_L | runner::DELAY_BY_VALUE),
+                Block::Delay(0, runner::DIR_DOWN_L | runner::DELAY_BY_VALUE),
                 Block::Splitter(runner::DIR_LEFT),
                 Block::Splitter(runner::DIR_UP),
                 Block::Splitter(runner::DIR_DOWN),
                 Block::Splitter(runner::DIR_RIGHT),
                 Block::Splitter(runner::DIR_UP_L),
                 Block::Splitter(runner::DIR_DOWN_L),
+                // bits: left, up, down, right, up a layer, down a layer (see `runner::FAN_DIRECTIONS`)
+                Block::Fan(0b001001),
+                Block::Fan(0b000110),
+                Block::Fan(0b001111),
+                Block::Fan(0b000111),
+                Block::Fan(0b001110),
+                Block::Fan(0b111111),
                 Block::Color(0xFFFF0000),
                 Block::Color(0xFF00FF00),
                 Block::Color(0xFF0000FF),
@@ -1384,6 +2754,30 @@ impl Default for WSInGame {
                 Block::Storage(0, 9, runner::DIR_RIGHT),
                 Block::Storage(0, 9, runner::DIR_UP_L),
                 Block::Storage(0, 9, runner::DIR_DOWN_L),
+                Block::Storage(0, 10, runner::DIR_LEFT),
+                Block::Storage(0, 10, runner::DIR_UP),
+                Block::Storage(0, 10, runner::DIR_DOWN),
+                Block::Storage(0, 10, runner::DIR_RIGHT),
+                Block::Storage(0, 10, runner::DIR_UP_L),
+                Block::Storage(0, 10, runner::DIR_DOWN_L),
+                Block::Storage(0, 14, runner::DIR_LEFT),
+                Block::Storage(0, 14, runner::DIR_UP),
+                Block::Storage(0, 14, runner::DIR_DOWN),
+                Block::Storage(0, 14, runner::DIR_RIGHT),
+                Block::Storage(0, 14, runner::DIR_UP_L),
+                Block::Storage(0, 14, runner::DIR_DOWN_L),
+                Block::Storage(0, 15, runner::DIR_LEFT),
+                Block::Storage(0, 15, runner::DIR_UP),
+                Block::Storage(0, 15, runner::DIR_DOWN),
+                Block::Storage(0, 15, runner::DIR_RIGHT),
+                Block::Storage(0, 15, runner::DIR_UP_L),
+                Block::Storage(0, 15, runner::DIR_DOWN_L),
+                Block::Storage(0, 16, runner::DIR_LEFT),
+                Block::Storage(0, 16, runner::DIR_UP),
+                Block::Storage(0, 16, runner::DIR_DOWN),
+                Block::Storage(0, 16, runner::DIR_RIGHT),
+                Block::Storage(0, 16, runner::DIR_UP_L),
+                Block::Storage(0, 16, runner::DIR_DOWN_L),
                 Block::Gate(false, runner::DIR_LEFT),
                 Block::Gate(false, runner::DIR_UP),
                 Block::Gate(false, runner::DIR_DOWN),
@@ -1396,6 +2790,36 @@ impl Default for WSInGame {
                 Block::Gate(true, runner::DIR_RIGHT),
                 Block::Gate(true, runner::DIR_UP_L),
                 Block::Gate(true, runner::DIR_DOWN_L),
+                Block::Gate(
+                    false,
+                    runner::gate_control_out(runner::DIR_LEFT, runner::DIR_DOWN_L),
+                ),
+                Block::Gate(
+                    false,
+                    runner::gate_control_out(runner::DIR_UP, runner::DIR_DOWN_L),
+                ),
+                Block::Gate(
+                    false,
+                    runner::gate_control_out(runner::DIR_DOWN, runner::DIR_DOWN_L),
+                ),
+                Block::Gate(
+                    false,
+                    runner::gate_control_out(runner::DIR_RIGHT, runner::DIR_DOWN_L),
+                ),
+                Block::Gate(
+                    false,
+                    runner::gate_control_out(runner::DIR_UP_L, runner::DIR_DOWN),
+                ),
+                Block::Gate(
+                    false,
+                    runner::gate_control_out(runner::DIR_DOWN_L, runner::DIR_DOWN),
+                ),
+                Block::Gate(true, runner::DIR_LEFT | runner::GATE_INVERTED),
+                Block::Gate(true, runner::DIR_UP | runner::GATE_INVERTED),
+                Block::Gate(true, runner::DIR_DOWN | runner::GATE_INVERTED),
+                Block::Gate(true, runner::DIR_RIGHT | runner::GATE_INVERTED),
+                Block::Gate(true, runner::DIR_UP_L | runner::GATE_INVERTED),
+                Block::Gate(true, runner::DIR_DOWN_L | runner::GATE_INVERTED),
                 Block::Move(runner::DIR_LEFT),
                 Block::Move(runner::DIR_UP),
                 Block::Move(runner::DIR_DOWN),
@@ -1408,16 +2832,243 @@ impl Default for WSInGame {
                 Block::Swap(runner::DIR_RIGHT),
                 Block::Swap(runner::DIR_UP_L),
                 Block::Swap(runner::DIR_DOWN_L),
+                Block::SwapAll(runner::DIR_LEFT),
+                Block::SwapAll(runner::DIR_UP),
+                Block::SwapAll(runner::DIR_DOWN),
+                Block::SwapAll(runner::DIR_RIGHT),
+                Block::SwapAll(runner::DIR_UP_L),
+                Block::SwapAll(runner::DIR_DOWN_L),
+                Block::Duplicate(runner::DIR_LEFT),
+                Block::Duplicate(runner::DIR_UP),
+                Block::Duplicate(runner::DIR_DOWN),
+                Block::Duplicate(runner::DIR_RIGHT),
+                Block::Duplicate(runner::DIR_UP_L),
+                Block::Duplicate(runner::DIR_DOWN_L),
+                Block::Destroy(runner::DIR_LEFT),
+                Block::Destroy(runner::DIR_UP),
+                Block::Destroy(runner::DIR_DOWN),
+                Block::Destroy(runner::DIR_RIGHT),
+                Block::Destroy(runner::DIR_UP_L),
+                Block::Destroy(runner::DIR_DOWN_L),
+                Block::Not(runner::DIR_LEFT),
+                Block::Not(runner::DIR_UP),
+                Block::Not(runner::DIR_DOWN),
+                Block::Not(runner::DIR_RIGHT),
+                Block::Not(runner::DIR_UP_L),
+                Block::Not(runner::DIR_DOWN_L),
+                Block::Probe(runner::DIR_LEFT),
+                Block::Probe(runner::DIR_UP),
+                Block::Probe(runner::DIR_DOWN),
+                Block::Probe(runner::DIR_RIGHT),
+                Block::Probe(runner::DIR_UP_L),
+                Block::Probe(runner::DIR_DOWN_L),
+                Block::Clock(20, 0),
+                Block::Random(0),
+                Block::Random(1),
+                Block::Random(2),
+                Block::Random(3),
+                Block::Random(4),
+                Block::Random(5),
+                Block::Compare(0, 0, runner::DIR_LEFT),
+                Block::Compare(0, 0, runner::DIR_UP),
+                Block::Compare(0, 0, runner::DIR_DOWN),
+                Block::Compare(0, 0, runner::DIR_RIGHT),
+                Block::Compare(0, 0, runner::DIR_UP_L),
+                Block::Compare(0, 0, runner::DIR_DOWN_L),
+                Block::Compare(0, 1, runner::DIR_LEFT),
+                Block::Compare(0, 1, runner::DIR_UP),
+                Block::Compare(0, 1, runner::DIR_DOWN),
+                Block::Compare(0, 1, runner::DIR_RIGHT),
+                Block::Compare(0, 1, runner::DIR_UP_L),
+                Block::Compare(0, 1, runner::DIR_DOWN_L),
+                Block::Compare(0, 2, runner::DIR_LEFT),
+                Block::Compare(0, 2, runner::DIR_UP),
+                Block::Compare(0, 2, runner::DIR_DOWN),
+                Block::Compare(0, 2, runner::DIR_RIGHT),
+                Block::Compare(0, 2, runner::DIR_UP_L),
+                Block::Compare(0, 2, runner::DIR_DOWN_L),
+                Block::Compare(0, 3, runner::DIR_LEFT),
+                Block::Compare(0, 3, runner::DIR_UP),
+                Block::Compare(0, 3, runner::DIR_DOWN),
+                Block::Compare(0, 3, runner::DIR_RIGHT),
+                Block::Compare(0, 3, runner::DIR_UP_L),
+                Block::Compare(0, 3, runner::DIR_DOWN_L),
+                Block::Compare(0, 4, runner::DIR_LEFT),
+                Block::Compare(0, 4, runner::DIR_UP),
+                Block::Compare(0, 4, runner::DIR_DOWN),
+                Block::Compare(0, 4, runner::DIR_RIGHT),
+                Block::Compare(0, 4, runner::DIR_UP_L),
+                Block::Compare(0, 4, runner::DIR_DOWN_L),
+                Block::Height(runner::DIR_LEFT),
+                Block::Height(runner::DIR_UP),
+                Block::Height(runner::DIR_DOWN),
+                Block::Height(runner::DIR_RIGHT),
+                Block::Height(runner::DIR_UP_L),
+                Block::Height(runner::DIR_DOWN_L),
+                Block::Counter(0, runner::DIR_LEFT),
+                Block::Counter(0, runner::DIR_UP),
+                Block::Counter(0, runner::DIR_DOWN),
+                Block::Counter(0, runner::DIR_RIGHT),
+                Block::Counter(0, runner::DIR_UP_L),
+                Block::Counter(0, runner::DIR_DOWN_L),
             ],
         }
     }
 }
+impl WSInGame {
+    /// continues from where the world was last viewed, if it was saved with a view
+    fn with_view(view: Option<View>) -> Self {
+        let mut state = Self::default();
+        if let Some(view) = view {
+            state.position = Vec2::new(view.position.0, view.position.1);
+            state.zoom = view.zoom;
+            state.layer = view.layer.min(LAYER_COUNT - 1);
+        }
+        state
+    }
+    fn view(&self) -> View {
+        View {
+            position: (self.position.x, self.position.y),
+            zoom: self.zoom,
+            layer: self.layer,
+        }
+    }
+    /// the block under a position on the screen
+    fn block_at(&self, screen_pos: Vec2, screen_size: UVec2) -> (i64, i64) {
+        let x =
+            self.position.x + (screen_pos.x - screen_size.x as f32 / 2.0) / self.pixels_per_block;
+        let y =
+            self.position.y + (screen_pos.y - screen_size.y as f32 / 2.0) / self.pixels_per_block;
+        (x.floor() as i64, y.floor() as i64)
+    }
+    /// copies the stacks in the selection to the clipboard and removes the selection.
+    /// if there are no blocks in it, the clipboard is kept.
+    fn copy_selection(&mut self, world: &World) {
+        let Some(((x1, y1), (x2, y2))) = self.selection.take() else {
+            eprintln!("[info] nothing to copy, select an area using M first");
+            return;
+        };
+        let (x1, y1, x2, y2) = Self::selection_rect(((x1, y1), (x2, y2)));
+        let blocks = match world.layers[self.layer].iter_blocks() {
+            Ok(blocks) => blocks,
+            Err(e) => return eprintln!("[warn] Couldn't copy the selection: {e}"),
+        };
+        let mut stacks: Vec<_> = blocks
+            .filter(|(x, y, _)| (x1..=x2).contains(x) && (y1..=y2).contains(y))
+            .map(|(x, y, stack)| ((x - x1, y - y1), stack.clone()))
+            .collect();
+        if stacks.is_empty() {
+            eprintln!("[info] nothing to copy, the selection is empty");
+            return;
+        }
+        stacks.sort_unstable_by_key(|(pos, _)| *pos);
+        eprintln!("[info] copied {} stacks", stacks.len());
+        self.clipboard = stacks;
+    }
+    /// puts a copy of the brush onto every stack in the selection and removes the selection.
+    /// nothing happens if there is no brush or the selection is larger than `FILL_MAX_CELLS`.
+    fn fill_selection(&mut self, world: &mut World) {
+        let (Some(selection), Some(brush)) = (self.selection, &self.brush) else {
+            eprintln!("[info] nothing to fill with, add a block from the palette first");
+            return;
+        };
+        let rect @ (x1, y1, x2, y2) = Self::selection_rect(selection);
+        let cells = (x2 - x1 + 1).saturating_mul(y2 - y1 + 1);
+        if cells > FILL_MAX_CELLS {
+            eprintln!("[info] the selection is too large to fill ({cells} stacks, at most {FILL_MAX_CELLS})");
+            return;
+        }
+        self.history.fill(world, self.layer, rect, brush);
+        self.selection = None;
+    }
+    /// (min x, min y, max x, max y) of the area between two corners
+    fn selection_rect(((x1, y1), (x2, y2)): ((i64, i64), (i64, i64))) -> (i64, i64, i64, i64) {
+        (x1.min(x2), y1.min(y2), x1.max(x2), y1.max(y2))
+    }
+    /// writes the typed value into the block it was typed for. non-numeric or empty input is ignored.
+    fn apply_value_edit(&mut self, world: &mut World) {
+        if let (Some((index, text)), Some((_, WSInGameMenu::BlockStackChanger { block, .. }))) =
+            (self.value_edit.take(), &self.open_menu)
+        {
+            // values which don't fit are clamped to u32::MAX
+            let value = match text.parse::<u64>() {
+                Ok(v) => v.min(u32::MAX as u64) as u32,
+                Err(e) if matches!(e.kind(), std::num::IntErrorKind::PosOverflow) => u32::MAX,
+                Err(_) => return,
+            };
+            let (x, y) = *block;
+            let len = Self::stack_len(&world.layers[self.layer], x, y);
+            if index >= len {
+                return;
+            }
+            let (chunk, inchunk) = world.layers[self.layer].get_where(x, y);
+            let Some(chunk) = world.layers[self.layer].get(&chunk) else {
+                return;
+            };
+            let mut new_block = chunk[inchunk as usize][len - 1 - index].clone();
+            if let Some(v) = Self::block_value_mut(&mut new_block) {
+                *v = value;
+                self.history
+                    .replace(world, self.layer, x, y, len - 1 - index, new_block);
+            }
+        }
+    }
+    /// undoes or redoes one of the player's edits. the stack menu's selection is kept within the stack.
+    fn undo_redo(&mut self, world: &mut World, redo: bool) {
+        let done = if redo {
+            self.history.redo(world)
+        } else {
+            self.history.undo(world)
+        };
+        if !done {
+            eprintln!(
+                "[info] nothing to {}, or the stack was changed by the simulation since",
+                if redo { "redo" } else { "undo" }
+            );
+        }
+        if let Some((
+            _,
+            WSInGameMenu::BlockStackChanger {
+                block,
+                scroll_l,
+                current,
+                ..
+            },
+        )) = &mut self.open_menu
+        {
+            let max =
+                Self::stack_len(&world.layers[self.layer], block.0, block.1).saturating_sub(1);
+            current.0 = current.0.min(max);
+            *scroll_l = scroll_l.min(max as f32);
+        }
+    }
+    /// how many blocks are on the stack at the given position
+    fn stack_len(layer: &Layer, x: i64, y: i64) -> usize {
+        let (chunk, inchunk) = layer.get_where(x, y);
+        layer
+            .get(&chunk)
+            .map_or(0, |chunk| chunk[inchunk as usize].len())
+    }
+    /// the number which can be edited in the stack menu, if the block has one
+    fn block_value_mut(block: &mut Block) -> Option<&mut u32> {
+        match block {
+            Block::Delay(ticks, _) => Some(ticks),
+            Block::Storage(value, _, _) => Some(value),
+            Block::Clock(period, _) => Some(period),
+            Block::Compare(threshold, _, _) => Some(threshold),
+            Block::Counter(count, _) => Some(count),
+            _ => None,
+        }
+    }
+}
 enum WSInGameMenu {
     BlockStackChanger {
         /// false => opening, true => closing
         changing: Option<(bool, Instant)>,
         block: (i64, i64),
         scroll_l: f32,
+        /// how many rows of the palette on the right are scrolled past
+        scroll_r: f32,
         /// if target.is_none(), this is which block we are editing.
         /// if target.is_some(), this is the origin of the move/swap operation.
         current: (usize, f32),
@@ -1426,19 +3077,570 @@ enum WSInGameMenu {
     },
 }
 
+/// what the area which is being selected will be used for, see `WSInGame::selecting`
+#[derive(Clone, Copy)]
+enum Selecting {
+    Export,
+    Copy,
+}
+
+#[derive(Clone, Copy, PartialEq, Eq)]
+enum PaletteCategory {
+    All,
+    /// blocks which show something
+    Output,
+    /// blocks which process signals
+    Logic,
+    /// blocks which change or inspect the world
+    World,
+}
+impl PaletteCategory {
+    /// in the order of the tabs
+    const ALL: [Self; 4] = [Self::All, Self::Output, Self::Logic, Self::World];
+    fn name(self) -> &'static str {
+        match self {
+            Self::All => "All",
+            Self::Output => "Output",
+            Self::Logic => "Logic",
+            Self::World => "World",
+        }
+    }
+    /// if the block is in this category and its `type_name` contains `filter`
+    fn shows(self, block: &Block, filter: &str) -> bool {
+        let in_category = match self {
+            Self::All => true,
+            Self::Output => matches!(block, Block::Color(..) | Block::Char(..)),
+            Self::Logic => matches!(
+                block,
+                Block::Delay(..)
+                    | Block::Storage(..)
+                    | Block::Gate(..)
+                    | Block::Splitter(..)
+                    | Block::Fan(..)
+                    | Block::Clock(..)
+                    | Block::Compare(..)
+                    | Block::Not(..)
+                    | Block::Probe(..)
+                    | Block::Random(..)
+                    | Block::Counter(..)
+            ),
+            Self::World => matches!(
+                block,
+                Block::Height(..)
+                    | Block::Move(..)
+                    | Block::Swap(..)
+                    | Block::SwapAll(..)
+                    | Block::Duplicate(..)
+                    | Block::Destroy(..)
+            ),
+        };
+        in_category && block.type_name().contains(filter)
+    }
+}
+/// the parts of the palette in the stack menu
+enum PalettePart {
+    Tab(PaletteCategory),
+    Filter,
+    /// the index in the list of shown blocks
+    Block(usize),
+}
+
 impl Window {
+    /// the part of the palette (the right panel of the stack menu) under a position on the screen.
+    /// the first row holds the tabs, the second one the filter, and the blocks come after that, 6 per row.
+    fn palette_part_at(mouse_pos: Vec2, size: UVec2, scroll_r: f32) -> Option<PalettePart> {
+        let (w, h) = (size.x as f32, size.y as f32);
+        if mouse_pos.y < h * 0.05 || mouse_pos.y > h * 0.95 || mouse_pos.x < w - h * 0.3 {
+            return None;
+        }
+        let row = (mouse_pos.y / h - 0.05) * 20.0;
+        // 0.0 to 1.0 from the left to the right edge of the panel
+        let column = ((mouse_pos.x - w + h * 0.3) / (h * 0.3)).min(0.999);
+        Some(if row < 1.0 {
+            PalettePart::Tab(PaletteCategory::ALL[(column * 4.0) as usize])
+        } else if row < 2.0 {
+            PalettePart::Filter
+        } else {
+            PalettePart::Block(6 * (row - 2.0 + scroll_r) as usize + (column * 6.0) as usize)
+        })
+    }
+    /// draws the text as large as it fits into the area, centered
+    fn draw_text_in(
+        graphics: &mut Graphics2D,
+        font: &Font,
+        text: &str,
+        area: &Rectangle,
+        color: Color,
+    ) {
+        let layout = font.layout_text(text, 1.0, TextOptions::default());
+        let scale =
+            (area.height() * 0.5 / layout.height()).min(area.width() * 0.9 / layout.width());
+        let layout = font.layout_text(text, scale, TextOptions::default());
+        graphics.draw_text(
+            *area.top_left() + (area.size() - Vec2::new(layout.width(), layout.height())) / 2.0,
+            color,
+            &layout,
+        );
+    }
+    /// a path in the saves directory which doesn't exist yet, and the name of the save.
+    /// `name` is sanitized so it can be used as a directory name, and a number is added if a save with that name already exists.
+    fn new_world_dir(&self, name: &str) -> (PathBuf, String) {
+        let name: String = name
+            .chars()
+            .filter(|c| c.is_alphanumeric() || matches!(c, ' ' | '-' | '_'))
+            .collect();
+        let name = name.trim();
+        let name = if name.is_empty() { "New World" } else { name };
+        let saves_dir = PathBuf::from(&self.config.saves_dir);
+        let mut unique_name = name.to_owned();
+        let mut i = 1;
+        while saves_dir.join(&unique_name).exists() {
+            i += 1;
+            unique_name = format!("{name} {i}");
+        }
+        (saves_dir.join(&unique_name), unique_name)
+    }
+    /// reads the thumbnail of the save in `dir` again, after the world was saved
+    fn reload_thumbnail(&mut self, dir: &Path) {
+        let path = dir.join(stackmaker::thumbnail::THUMBNAIL_FILE);
+        match path
+            .is_file()
+            .then(|| ThreadedLoading::open_image_file(&path))
+        {
+            Some(Some(img)) => self
+                .events
+                .push(Event::SetWorldThumbnail(dir.to_path_buf(), img)),
+            _ => {
+                self.save_thumbnails.remove(dir);
+            }
+        }
+    }
+    /// creates an empty world with its own save directory and switches to it
+    fn create_new_world(&mut self, name: &str) {
+        eprintln!("Setting up empty world...");
+        let mut world = World::new_empty();
+        // give the world its own directory right away, so it can be autosaved
+        let (dir, name) = self.new_world_dir(name);
+        match fs::create_dir(&dir).and_then(|()| world.save_to_dir(&dir)) {
+            Ok(()) => {
+                world.save_dir = Some(dir.clone());
+                self.events.push(Event::AddWorld(dir, name));
+            }
+            Err(e) => {
+                eprintln!("[warn] Couldn't create world directory {dir:?}: {e}")
+            }
+        }
+        // eprintln!("Adding some blocks for testing...");
+        // {
+        //     let chunk = world.layers[0].get_mut(&0);
+        //     let dirs = [
+        //         runner::DIR_UP,
+        //         runner::DIR_RIGHT,
+        //         runner::DIR_DOWN,
+        //         runner::DIR_LEFT,
+        //         runner::DIR_UP_L,
+        //         runner::DIR_DOWN_L,
+        //     ];
+        //     for ch in (b'A'..=b'Z').rev() {
+        //         chunk[0].push(Block::Char(ch as _));
+        //     }
+        //     for (i, dir) in dirs.iter().enumerate() {
+        //         chunk[16 * 0 + 4 + i].push(Block::Delay(0, *dir));
+        //     }
+        //     for (i, dir) in dirs.iter().enumerate() {
+        //         chunk[16 * 0 + 10 + i].push(Block::Splitter(*dir));
+        //     }
+        //     for mode in 0..=9u8 {
+        //         for (i, dir) in dirs.iter().enumerate() {
+        //             chunk[16 * (1 + mode as usize) + 4 + i]
+        //                 .push(Block::Storage(0, mode, *dir));
+        //         }
+        //     }
+        //     for (i, dir) in dirs.iter().enumerate() {
+        //         chunk[16 * 14 + 4 + i].push(Block::Gate(false, *dir));
+        //     }
+        //     for (i, dir) in dirs.iter().enumerate() {
+        //         chunk[16 * 14 + 10 + i].push(Block::Gate(true, *dir));
+        //     }
+        //     for (i, dir) in dirs.iter().enumerate() {
+        //         chunk[16 * 15 + 4 + i].push(Block::Move(*dir));
+        //     }
+        //     for (i, dir) in dirs.iter().enumerate() {
+        //         chunk[16 * 15 + 10 + i].push(Block::Swap(*dir));
+        //     }
+        // }
+        // // TOP LEFT
+        // {
+        //     let (chunk, pos) = world.layers[0].get_where(-1, -1);
+        //     let chunk = world.layers[0].get_mut(&chunk);
+        //     for (i, blocks) in chunk.iter_mut().enumerate() {
+        //         let (x, y) =
+        //             (15 - (i as u32 & 0xF), 15 - ((i as u32 & 0xF0) >> 4));
+        //         blocks.push(Block::Color(
+        //             0xFF000000 | x << 16 | x << 20 | y << 0 | y << 4,
+        //         ));
+        //     }
+        // }
+        // // TOP RIGHT
+        // {
+        //     let (chunk, pos) = world.layers[0].get_where(0, -1);
+        //     let chunk = world.layers[0].get_mut(&chunk);
+        //     for (i, blocks) in chunk.iter_mut().enumerate() {
+        //         let (x, y) = (i as u32 & 0xF, 15 - ((i as u32 & 0xF0) >> 4));
+        //         blocks.push(Block::Color(
+        //             0xFF000000 | x << 8 | x << 12 | y << 0 | y << 4,
+        //         ));
+        //     }
+        // }
+        // // BOTTOM LEFT
+        // {
+        //     let (chunk, pos) = world.layers[0].get_where(-1, 0);
+        //     let chunk = world.layers[0].get_mut(&chunk);
+        //     for (i, blocks) in chunk.iter_mut().enumerate() {
+        //         let (x, y) = (15 - (i as u32 & 0xF), (i as u32 & 0xF0) >> 4);
+        //         blocks.push(Block::Color(
+        //             0xFF000000 | x << 16 | x << 20 | y << 8 | y << 12,
+        //         ));
+        //     }
+        // }
+        // // BOTTOM 2 RIGHT
+        // {
+        //     let (chunk, _) = world.layers[0].get_where(16, 0);
+        //     let chunk = world.layers[0].get_mut(&chunk);
+        //     chunk[1].push(Block::Color(0xFFFFFFFF));
+        //     chunk[16 + 1].push(Block::Splitter(runner::DIR_UP));
+        //     chunk[32 + 1].push(Block::Delay(0, runner::DIR_DOWN));
+        //     chunk[48 + 1].push(Block::Splitter(runner::DIR_RIGHT));
+        //     chunk[16 + 2].push(Block::Storage(0xFF000000, 4, runner::DIR_LEFT));
+        //     chunk[32 + 2].push(Block::Storage(16, 0, runner::DIR_UP));
+        //     chunk[48 + 2].push(Block::Splitter(runner::DIR_UP));
+        //     chunk[64 + 2].push(Block::Splitter(runner::DIR_RIGHT));
+        //     chunk[16 + 3].push(Block::Splitter(runner::DIR_LEFT));
+        //     chunk[32 + 3].push(Block::Storage(4, 0, runner::DIR_UP));
+        //     chunk[48 + 3].push(Block::Delay(0, runner::DIR_UP));
+        //     chunk[64 + 3].push(Block::Splitter(runner::DIR_UP));
+        // }
+        let mut runner = Runner::new(world);
+        runner.autosave = (500, 1000);
+        self.state = WindowState::Singleplayer(WSInGame::default(), runner);
+        self.redraw = true;
+    }
+    /// the tint of a signal's indicator. `0` is white, other values get a hue depending on how many bits they need:
+    /// `1` is red, and larger values go through yellow, green, cyan and blue up to purple for `u32::MAX`.
+    /// signals with similar values look similar, and the color of a value never changes.
+    fn signal_color(value: u32) -> Color {
+        if value == 0 {
+            return Color::WHITE;
+        }
+        let bits = (u32::BITS - value.leading_zeros()) as f32;
+        // 0.0 (red) to 5.0 (purple), in sixths of the color wheel
+        let hue = (bits - 1.0) / (u32::BITS as f32 - 1.0) * 5.0;
+        let rising = hue.fract();
+        let falling = 1.0 - rising;
+        match hue as u32 {
+            0 => Color::from_rgb(1.0, rising, 0.0),
+            1 => Color::from_rgb(falling, 1.0, 0.0),
+            2 => Color::from_rgb(0.0, 1.0, rising),
+            3 => Color::from_rgb(0.0, falling, 1.0),
+            _ => Color::from_rgb((hue - 4.0).min(1.0), 0.0, 1.0),
+        }
+    }
+    /// draws a line on every block boundary, using the same positions as `draw_blocks`
+    fn draw_grid(graphics: &mut Graphics2D, top_left: Vec2, pixels_per_block: f32, size: Vec2) {
+        let color = Color::from_rgba(1.0, 1.0, 1.0, 0.15);
+        let mut px_x = (top_left.x.floor() - top_left.x) * pixels_per_block;
+        while px_x < size.x {
+            graphics.draw_line(Vec2::new(px_x, 0.0), Vec2::new(px_x, size.y), 1.0, color);
+            px_x += pixels_per_block;
+        }
+        let mut px_y = (top_left.y.floor() - top_left.y) * pixels_per_block;
+        while px_y < size.y {
+            graphics.draw_line(Vec2::new(0.0, px_y), Vec2::new(size.x, px_y), 1.0, color);
+            px_y += pixels_per_block;
+        }
+    }
+    /// draws the topmost block of every stack in the area of the given size (in pixels), starting at `top_left` (in blocks)
+    fn draw_blocks(
+        &mut self,
+        graphics: &mut Graphics2D,
+        layer: &Layer,
+        top_left: Vec2,
+        pixels_per_block: f32,
+        size: Vec2,
+        stack_sizes: bool,
+    ) {
+        if pixels_per_block < ZOOMED_OUT_PIXELS_PER_BLOCK {
+            Self::draw_blocks_zoomed_out(graphics, layer, top_left, pixels_per_block, size);
+            return;
+        }
+        let (top_left_x, top_left_y) = (top_left.x, top_left.y);
+        let (width, height) = (size.x, size.y);
+        // screen positions of each visible column/row of blocks
+        let mut columns_px = vec![];
+        let mut px_x = (top_left_x.floor() - top_left_x) * pixels_per_block;
+        while px_x < width {
+            columns_px.push(px_x);
+            px_x += pixels_per_block;
+        }
+        let mut rows_px = vec![];
+        let mut px_y = (top_left_y.floor() - top_left_y) * pixels_per_block;
+        while px_y < height {
+            rows_px.push(px_y);
+            px_y += pixels_per_block;
+        }
+        let block_x_start = top_left_x.floor() as i64;
+        let block_y_start = top_left_y.floor() as i64;
+        let block_x_end = block_x_start + columns_px.len() as i64;
+        let block_y_end = block_y_start + rows_px.len() as i64;
+        // look up every visible chunk once, then draw all of its visible stacks
+        for chunk_y in block_y_start.div_euclid(16)..=(block_y_end - 1).div_euclid(16) {
+            for chunk_x in block_x_start.div_euclid(16)..=(block_x_end - 1).div_euclid(16) {
+                let (chunk, _) = layer.get_where(chunk_x * 16, chunk_y * 16);
+                let chunk = if let Some(chunk) = layer.get(&chunk) {
+                    chunk
+                } else {
+                    continue;
+                };
+                for block_y in
+                    (chunk_y * 16).max(block_y_start)..(chunk_y * 16 + 16).min(block_y_end)
+                {
+                    let px_y = rows_px[(block_y - block_y_start) as usize];
+                    for block_x in
+                        (chunk_x * 16).max(block_x_start)..(chunk_x * 16 + 16).min(block_x_end)
+                    {
+                        let inner = (block_y - chunk_y * 16) << 4 | (block_x - chunk_x * 16);
+                        if let Some(topmost_block) = chunk[inner as usize].last() {
+                            let px_x = columns_px[(block_x - block_x_start) as usize];
+                            let area = Rectangle::new(
+                                Vec2::new(px_x, px_y),
+                                Vec2::new(px_x + pixels_per_block, px_y + pixels_per_block),
+                            );
+                            self.draw_block(graphics, area.clone(), topmost_block);
+                            let len = chunk[inner as usize].len();
+                            if stack_sizes && len > 1 {
+                                self.draw_number(graphics, &area, len as u64, false);
+                            }
+                        }
+                    }
+                }
+            }
+        }
+    }
+    /// used by `draw_blocks` when blocks are smaller than `ZOOMED_OUT_PIXELS_PER_BLOCK`, because sprites that small flicker while the camera moves.
+    /// the blocks are grouped into squares which are at least a pixel wide, and each square is drawn in the average of its topmost blocks' colors (see `thumbnail::block_color`),
+    /// more transparent the more of its stacks are empty. the squares are aligned to the chunks, so they stay the same while the camera moves.
+    fn draw_blocks_zoomed_out(
+        graphics: &mut Graphics2D,
+        layer: &Layer,
+        top_left: Vec2,
+        pixels_per_block: f32,
+        size: Vec2,
+    ) {
+        // a power of two, so the squares divide the chunks evenly
+        let square = ((1.0 / pixels_per_block).ceil() as u32)
+            .next_power_of_two()
+            .min(16) as i64;
+        let chunk_x1 = (top_left.x.floor() as i64).div_euclid(16);
+        let chunk_y1 = (top_left.y.floor() as i64).div_euclid(16);
+        let chunk_x2 = ((top_left.x + size.x / pixels_per_block).ceil() as i64).div_euclid(16);
+        let chunk_y2 = ((top_left.y + size.y / pixels_per_block).ceil() as i64).div_euclid(16);
+        let draw_chunk = |graphics: &mut Graphics2D, chunk: &[Vec<Block>; 256], x: i64, y: i64| {
+            for square_y in (0..16).step_by(square as usize) {
+                for square_x in (0..16).step_by(square as usize) {
+                    let (mut sum, mut filled) = ([0u32; 3], 0u32);
+                    for inner_y in square_y..square_y + square {
+                        for inner_x in square_x..square_x + square {
+                            if let Some(block) = chunk[(inner_y << 4 | inner_x) as usize].last() {
+                                let color = thumbnail::block_color(block);
+                                for (sum, c) in sum.iter_mut().zip(color) {
+                                    *sum += c as u32;
+                                }
+                                filled += 1;
+                            }
+                        }
+                    }
+                    if filled == 0 {
+                        continue;
+                    }
+                    let [r, g, b] = sum.map(|sum| (sum / filled) as u8);
+                    let coverage = (filled * 255 / (square * square) as u32) as u8;
+                    let pos = Vec2::new(
+                        ((x + square_x) as f32 - top_left.x) * pixels_per_block,
+                        ((y + square_y) as f32 - top_left.y) * pixels_per_block,
+                    );
+                    let width = square as f32 * pixels_per_block;
+                    graphics.draw_rectangle(
+                        Rectangle::new(pos, pos + Vec2::new(width, width)),
+                        Color::from_int_rgba(r, g, b, coverage),
+                    );
+                }
+            }
+        };
+        let visible_chunks = (chunk_x2 - chunk_x1 + 1).saturating_mul(chunk_y2 - chunk_y1 + 1);
+        if visible_chunks > layer.loaded_chunks().len() as i64 {
+            // far out, most of the visible chunks don't exist
+            for (chunk_key, chunk) in layer.loaded_chunks() {
+                let (x, y) = Layer::where_to_coords(chunk_key, 0);
+                if (chunk_x1..=chunk_x2).contains(&x.div_euclid(16))
+                    && (chunk_y1..=chunk_y2).contains(&y.div_euclid(16))
+                {
+                    draw_chunk(graphics, chunk, x, y);
+                }
+            }
+        } else {
+            for chunk_y in chunk_y1..=chunk_y2 {
+                for chunk_x in chunk_x1..=chunk_x2 {
+                    let (chunk_key, _) = layer.get_where(chunk_x * 16, chunk_y * 16);
+                    if let Some(chunk) = layer.get(&chunk_key) {
+                        draw_chunk(graphics, chunk, chunk_x * 16, chunk_y * 16);
+                    }
+                }
+            }
+        }
+    }
+    /// shows a small number on a dark background in the top left (block values) or bottom right (stack sizes) corner of a block.
+    /// nothing is drawn if the block is smaller than `BLOCK_NUMBER_MIN_PIXELS`.
+    fn draw_number(
+        &mut self,
+        graphics: &mut Graphics2D,
+        area: &Rectangle,
+        number: u64,
+        top_left_corner: bool,
+    ) {
+        let font = match &self.font_monospace {
+            Some(font) if area.width() >= BLOCK_NUMBER_MIN_PIXELS => font,
+            _ => return,
+        };
+        let font_size = area.width() * 0.3;
+        if self.number_texts.len() >= MAX_NUMBER_TEXTS {
+            self.number_texts.clear();
+        }
+        let text = self
+            .number_texts
+            .entry((font_size.to_bits(), number))
+            .or_insert_with(|| {
+                font.layout_text(&number.to_string(), font_size, TextOptions::default())
+            });
+        let size = text.size() + Vec2::new(2.0, 0.0);
+        let top_left = if top_left_corner {
+            *area.top_left()
+        } else {
+            *area.bottom_right() - size
+        };
+        graphics.draw_rectangle(
+            Rectangle::new(top_left, top_left + size),
+            Color::from_rgba(0.0, 0.0, 0.0, 0.6),
+        );
+        graphics.draw_text(top_left + Vec2::new(1.0, 0.0), Color::WHITE, text);
+    }
+    /// renders the topmost blocks in the area from (x1, y1) to (x2, y2) (inclusive) to a png file, `EXPORT_PIXELS_PER_BLOCK` pixels per block.
+    /// speedy2d can only draw to the screen, so the image is drawn and captured one window-sized tile at a time.
+    /// this has to happen before the frame is drawn, because it overwrites the screen.
+    fn export_region(
+        &mut self,
+        graphics: &mut Graphics2D,
+        layer: &Layer,
+        (x1, y1, x2, y2): (i64, i64, i64, i64),
+        path: &Path,
+    ) -> Result<(), String> {
+        let blocks = (x2 - x1 + 1, y2 - y1 + 1);
+        if blocks.0 > EXPORT_MAX_BLOCKS || blocks.1 > EXPORT_MAX_BLOCKS {
+            return Err(format!(
+                "the area is {}x{} blocks, but at most {EXPORT_MAX_BLOCKS}x{EXPORT_MAX_BLOCKS} can be exported",
+                blocks.0, blocks.1
+            ));
+        }
+        if self.size.x == 0 || self.size.y == 0 {
+            return Err("the window has no size".to_owned());
+        }
+        let pixels_per_block = EXPORT_PIXELS_PER_BLOCK as f32;
+        let (width, height) = (
+            blocks.0 as usize * EXPORT_PIXELS_PER_BLOCK,
+            blocks.1 as usize * EXPORT_PIXELS_PER_BLOCK,
+        );
+        let mut data = vec![0; width * height * 4];
+        for tile_y in (0..height).step_by(self.size.y as usize) {
+            for tile_x in (0..width).step_by(self.size.x as usize) {
+                graphics.clear_screen(Color::BLACK);
+                let top_left = Vec2::new(
+                    x1 as f32 + tile_x as f32 / pixels_per_block,
+                    y1 as f32 + tile_y as f32 / pixels_per_block,
+                );
+                let tile_width = (self.size.x as usize).min(width - tile_x);
+                let tile_height = (self.size.y as usize).min(height - tile_y);
+                self.draw_blocks(
+                    graphics,
+                    layer,
+                    top_left,
+                    pixels_per_block,
+                    Vec2::new(tile_width as f32, tile_height as f32),
+                    false,
+                );
+                let captured = graphics.capture(ImageDataType::RGBA);
+                let captured_width = captured.size().x as usize;
+                let tile_width = tile_width.min(captured_width);
+                let tile_height = tile_height.min(captured.size().y as usize);
+                for row in 0..tile_height {
+                    let src = row * captured_width * 4;
+                    let dst = ((tile_y + row) * width + tile_x) * 4;
+                    data[dst..dst + tile_width * 4]
+                        .copy_from_slice(&captured.data()[src..src + tile_width * 4]);
+                }
+            }
+        }
+        RgbaImage::from_raw(width as _, height as _, data)
+            .unwrap()
+            .save(path)
+            .map_err(|e| e.to_string())
+    }
+    /// the block's type and its most important values
+    fn block_tooltip(block: &Block) -> String {
+        let name = block.type_name();
+        match block {
+            Block::Color(c) => format!("{name} #{c:08X}"),
+            Block::Char(c) => match char::from_u32(*c) {
+                Some(ch) => format!("{name} {ch:?}"),
+                None => format!("{name} {c:#X}"),
+            },
+            Block::Delay(ticks, _) => format!("{name} {ticks} ticks"),
+            Block::Storage(value, mode, _) => format!("{name} {value} (mode {mode})"),
+            Block::Clock(period, phase) => format!("{name} every {period} ticks (+{phase})"),
+            Block::Compare(threshold, _, _) => format!("{name} {threshold}"),
+            Block::Counter(count, _) => format!("{name} {count}"),
+            Block::Fan(dirs) => {
+                let names = ["left", "up", "down", "right", "up a layer", "down a layer"];
+                let dirs: Vec<_> = (0..names.len())
+                    .filter(|i| dirs & (1 << i) != 0)
+                    .map(|i| names[i])
+                    .collect();
+                format!("{name} {}", dirs.join(", "))
+            }
+            _ => name.to_owned(),
+        }
+    }
+    /// the most recent values which passed through the probe at (x, y), newest first, to append to its tooltip
+    fn probe_values(log: &VecDeque<ProbeRecord>, layer: usize, x: i64, y: i64) -> String {
+        let values: Vec<_> = log
+            .iter()
+            .rev()
+            .filter(|r| r.layer == layer && r.x == x && r.y == y)
+            .take(5)
+            .map(|r| format!("{} (tick {})", r.value, r.tick))
+            .collect();
+        if values.is_empty() {
+            " (nothing received)".to_owned()
+        } else {
+            format!(": {}", values.join(", "))
+        }
+    }
     fn draw_block(&mut self, graphics: &mut Graphics2D, area: Rectangle<f32>, block: &Block) {
         match block {
             Block::Color(c) => {
                 graphics.draw_rectangle(area.clone(), Color::from_hex_argb(*c));
-                if let Some(handle) = self.images.world_block_color.handle() {
-                    graphics.draw_rectangle_image(area, handle);
-                }
+                self.images.world_block_color.draw(graphics, area);
             }
             Block::Char(c) => {
-                if let Some(handle) = self.images.world_block_char.handle() {
-                    graphics.draw_rectangle_image(area.clone(), handle);
-                }
+                self.images.world_block_char.draw(graphics, area.clone());
                 if let Some(font) = &self.font_monospace {
                     if let Some(c) = char::from_u32(*c) {
                         let layout = font.layout_text(&c.to_string(), 1.0, TextOptions::default());
@@ -1453,15 +3655,21 @@ impl Window {
                     }
                 }
             }
-            Block::Delay(_, dir) => {
-                if let Some(handle) =
-                    Self::index_by_dir(*dir, &self.images.world_block_delay).handle()
-                {
-                    graphics.draw_rectangle_image(area.clone(), handle);
+            Block::Delay(how_long, dir) => {
+                // the layer bits are flags
+                let direction = *dir & 0b11100000;
+                Self::index_by_dir(direction, &self.images.world_block_delay)
+                    .draw(graphics, area.clone());
+                if *dir & runner::DELAY_BY_VALUE != 0 {
+                    // the stored amount isn't used
+                    Self::index_by_dir(direction, &self.images.world_block_delay_value)
+                        .draw(graphics, area.clone());
+                } else {
+                    self.draw_number(graphics, &area, *how_long as u64, true);
                 }
             }
             Block::Storage(_, mode, dir) => {
-                if let Some(handle) = Self::index_by_dir(
+                Self::index_by_dir(
                     *dir,
                     match mode {
                         0 => &self.images.world_block_storage_sto,
@@ -1473,48 +3681,117 @@ impl Window {
                         6 => &self.images.world_block_storage_mul,
                         7 => &self.images.world_block_storage_div,
                         8 => &self.images.world_block_storage_mod,
+                        9 => &self.images.world_block_storage_min,
+                        10 => &self.images.world_block_storage_max,
+                        14 => &self.images.world_block_storage_wadd,
+                        15 => &self.images.world_block_storage_wsub,
+                        16 => &self.images.world_block_storage_wmul,
                         _ => &self.images.world_block_storage_default,
                     },
                 )
-                .handle()
-                {
-                    graphics.draw_rectangle_image(area.clone(), handle);
-                }
+                .draw(graphics, area.clone());
             }
             Block::Gate(open, dir) => {
-                if let Some(handle) = Self::index_by_dir(
-                    *dir,
+                // the layer bits are flags
+                let direction = *dir & 0b11100000;
+                Self::index_by_dir(
+                    direction,
                     if *open {
                         &self.images.world_block_gate_open
                     } else {
                         &self.images.world_block_gate_closed
                     },
                 )
-                .handle()
-                {
-                    graphics.draw_rectangle_image(area.clone(), handle);
+                .draw(graphics, area.clone());
+                if *dir & runner::GATE_INVERTED != 0 {
+                    Self::index_by_dir(direction, &self.images.world_block_gate_inv)
+                        .draw(graphics, area.clone());
+                }
+                if let Some(control) = runner::gate_control_dir(*dir) {
+                    Self::index_by_dir(control, &self.images.world_block_gate_pass)
+                        .draw(graphics, area.clone());
                 }
             }
             Block::Splitter(dir) => {
-                if let Some(handle) =
-                    Self::index_by_dir(*dir, &self.images.world_block_splitter).handle()
-                {
-                    graphics.draw_rectangle_image(area.clone(), handle);
+                Self::index_by_dir(*dir, &self.images.world_block_splitter)
+                    .draw(graphics, area.clone());
+            }
+            Block::Fan(dirs) => {
+                self.images.world_block_fan.draw(graphics, area.clone());
+                // a line from the center towards every direction on this layer which the fan outputs to
+                let center = (*area.top_left() + *area.bottom_right()) / 2.0;
+                for (i, dir) in runner::FAN_DIRECTIONS.into_iter().enumerate() {
+                    let offset = match dir {
+                        runner::DIR_LEFT => Vec2::new(-0.4, 0.0),
+                        runner::DIR_RIGHT => Vec2::new(0.4, 0.0),
+                        runner::DIR_UP => Vec2::new(0.0, -0.4),
+                        runner::DIR_DOWN => Vec2::new(0.0, 0.4),
+                        _ => continue,
+                    };
+                    if dirs & (1 << i) != 0 {
+                        graphics.draw_line(
+                            center,
+                            center + offset * area.width(),
+                            (area.width() * 0.08).max(1.0),
+                            Color::WHITE,
+                        );
+                    }
                 }
             }
             Block::Move(dir) => {
-                if let Some(handle) =
-                    Self::index_by_dir(*dir, &self.images.world_block_move).handle()
-                {
-                    graphics.draw_rectangle_image(area.clone(), handle);
-                }
+                Self::index_by_dir(*dir, &self.images.world_block_move)
+                    .draw(graphics, area.clone());
             }
             Block::Swap(dir) => {
-                if let Some(handle) =
-                    Self::index_by_dir(*dir, &self.images.world_block_swap).handle()
-                {
-                    graphics.draw_rectangle_image(area.clone(), handle);
-                }
+                Self::index_by_dir(*dir, &self.images.world_block_swap)
+                    .draw(graphics, area.clone());
+            }
+            Block::SwapAll(dir) => {
+                Self::index_by_dir(*dir, &self.images.world_block_swap_all)
+                    .draw(graphics, area.clone());
+            }
+            Block::Duplicate(dir) => {
+                Self::index_by_dir(*dir, &self.images.world_block_duplicate)
+                    .draw(graphics, area.clone());
+            }
+            Block::Destroy(dir) => {
+                Self::index_by_dir(*dir, &self.images.world_block_destroy)
+                    .draw(graphics, area.clone());
+            }
+            Block::Not(dir) => {
+                Self::index_by_dir(*dir, &self.images.world_block_not).draw(graphics, area.clone());
+            }
+            Block::Probe(dir) => {
+                Self::index_by_dir(*dir, &self.images.world_block_probe)
+                    .draw(graphics, area.clone());
+            }
+            Block::Clock(..) => {
+                self.images.world_block_clock.draw(graphics, area.clone());
+            }
+            Block::Random(_) => {
+                self.images.world_block_random.draw(graphics, area.clone());
+            }
+            Block::Compare(_, op, dir) => {
+                Self::index_by_dir(
+                    *dir,
+                    match op {
+                        0 => &self.images.world_block_compare_lt,
+                        1 => &self.images.world_block_compare_le,
+                        2 => &self.images.world_block_compare_eq,
+                        3 => &self.images.world_block_compare_ge,
+                        4 => &self.images.world_block_compare_gt,
+                        _ => &self.images.world_block_compare_default,
+                    },
+                )
+                .draw(graphics, area.clone());
+            }
+            Block::Height(dir) => {
+                Self::index_by_dir(*dir, &self.images.world_block_height)
+                    .draw(graphics, area.clone());
+            }
+            Block::Counter(_, dir) => {
+                Self::index_by_dir(*dir, &self.images.world_block_counter)
+                    .draw(graphics, area.clone());
             }
         }
     }
@@ -1529,6 +3806,10 @@ impl Window {
             _ => panic!("dir was not (just) a direction!"),
         }]
     }
+    /// the missing assets panel is shown in the main menu and blocks its other inputs
+    fn missing_assets_shown(&self) -> bool {
+        !self.missing_assets_dismissed && !self.missing_assets.is_empty()
+    }
     fn rel_to_abs_rect(size: UVec2, rect: &Rectangle<f32>) -> Rectangle<f32> {
         Rectangle::new(
             Vec2::new(
@@ -1542,45 +3823,139 @@ impl Window {
         )
     }
     fn load_img(dest: &mut LoadableImage, img: RgbaImage, graphics: &mut Graphics2D) {
-        if let Ok(handle) = graphics.create_image_from_raw_pixels(
-            ImageDataType::RGBA,
-            ImageSmoothingMode::NearestNeighbor,
-            UVec2::new(img.width(), img.height()),
-            &img,
-        ) {
-            dest.load(handle);
+        Self::load_frames(dest, vec![img], graphics, false);
+    }
+    /// like `load_img`, but the image is also packed into the atlas
+    fn load_sprite(dest: &mut LoadableImage, img: RgbaImage, graphics: &mut Graphics2D) {
+        Self::load_frames(dest, vec![img], graphics, true);
+    }
+    /// if there are no frames or one of them can't be created, the image isn't changed.
+    /// `sprite` keeps the pixels, so the image can be packed into the atlas.
+    fn load_frames(
+        dest: &mut LoadableImage,
+        frames: Frames,
+        graphics: &mut Graphics2D,
+        sprite: bool,
+    ) {
+        let handles: Result<Vec<_>, _> = frames
+            .iter()
+            .map(|img| {
+                graphics.create_image_from_raw_pixels(
+                    ImageDataType::RGBA,
+                    ImageSmoothingMode::NearestNeighbor,
+                    UVec2::new(img.width(), img.height()),
+                    img,
+                )
+            })
+            .collect();
+        match handles {
+            Ok(handles) if !handles.is_empty() => {
+                dest.load(handles, if sprite { frames } else { vec![] })
+            }
+            _ => {}
         }
     }
+    /// these are block sprites, so they are packed into the atlas
     fn load_imgs<const L: usize>(
         dest: &mut [LoadableImage; L],
-        img: [Option<RgbaImage>; L],
+        img: [Option<Frames>; L],
         graphics: &mut Graphics2D,
     ) {
-        for (i, img) in img.into_iter().enumerate() {
-            if let Some(img) = img {
-                Self::load_img(&mut dest[i], img, graphics);
+        for (i, frames) in img.into_iter().enumerate() {
+            if let Some(frames) = frames {
+                Self::load_frames(&mut dest[i], frames, graphics, true);
+            }
+        }
+    }
+    /// packs the block sprites into one texture (see `atlas`), which makes drawing many blocks faster.
+    /// if they don't fit, each sprite is drawn from its own texture.
+    fn rebuild_atlas(&mut self, graphics: &mut Graphics2D) {
+        let mut sprites = self.images.block_sprites_mut();
+        let packed = {
+            let images: Vec<&RgbaImage> = sprites.iter().flat_map(|s| s.pixels.iter()).collect();
+            atlas::pack(&images)
+        };
+        for sprite in sprites.iter_mut() {
+            sprite.in_atlas = None;
+        }
+        let (atlas, areas) = match packed {
+            Some(v) => v,
+            None => return,
+        };
+        let handle = match graphics.create_image_from_raw_pixels(
+            ImageDataType::RGBA,
+            ImageSmoothingMode::NearestNeighbor,
+            UVec2::new(atlas.width(), atlas.height()),
+            &atlas,
+        ) {
+            Ok(v) => v,
+            Err(e) => {
+                eprintln!("[warn] Couldn't create the atlas, drawing sprites separately: {e:?}");
+                return;
+            }
+        };
+        // the areas are in the same order as the pixels were collected
+        let mut areas = areas.into_iter();
+        for sprite in sprites {
+            if !sprite.pixels.is_empty() {
+                let frames = areas.by_ref().take(sprite.pixels.len()).collect();
+                sprite.in_atlas = Some((handle.clone(), frames));
             }
         }
     }
 }
+/// set once an image with more than one frame was loaded. from then on, worlds are redrawn even while paused, so the animations keep playing.
+static ANIMATION_LOADED: AtomicBool = AtomicBool::new(false);
+/// which of an animation's frames to show right now. all animations use the same clock, so they stay in sync with each other.
+fn current_frame(frames: usize) -> usize {
+    let time = std::time::SystemTime::now()
+        .duration_since(std::time::UNIX_EPOCH)
+        .map_or(0, |t| t.as_millis());
+    (time / ANIMATION_FRAME_MILLIS) as usize % frames
+}
 #[derive(Default)]
-struct LoadableImage(Option<(ImageHandle, Option<Instant>)>);
+struct LoadableImage {
+    /// (the frames, when it was loaded for fading it in)
+    image: Option<(Vec<ImageHandle>, Option<Instant>)>,
+    /// the frames' pixels. only kept for block sprites, which are packed into the atlas (see `Window::rebuild_atlas`).
+    pixels: Frames,
+    /// the atlas and the area of each frame in it, if this image was packed into it
+    in_atlas: Option<(ImageHandle, Vec<Rectangle<f32>>)>,
+}
 impl LoadableImage {
-    fn load(&mut self, handle: ImageHandle) {
-        self.0 = Some((handle, Some(Instant::now())));
+    /// `handles` must not be empty. `pixels` are the frames' pixels if this is a block sprite, otherwise empty.
+    fn load(&mut self, handles: Vec<ImageHandle>, pixels: Frames) {
+        if handles.len() > 1 {
+            ANIMATION_LOADED.store(true, Ordering::Relaxed);
+        }
+        *self = Self {
+            image: Some((handles, Some(Instant::now()))),
+            pixels,
+            in_atlas: None,
+        };
     }
     fn loaded(&self) -> bool {
-        self.0.is_some()
+        self.image.is_some()
     }
+    /// the current frame's own texture
     fn handle(&self) -> Option<&ImageHandle> {
-        if let Some((v, _)) = &self.0 {
-            Some(v)
-        } else {
-            None
-        }
+        let (frames, _) = self.image.as_ref()?;
+        frames.get(current_frame(frames.len()))
     }
     fn clear(&mut self) {
-        self.0 = None;
+        *self = Self::default();
+    }
+    /// draws the current frame, from the atlas if possible
+    fn draw(&self, graphics: &mut Graphics2D, area: Rectangle<f32>) {
+        self.draw_tinted(graphics, area, Color::WHITE);
+    }
+    fn draw_tinted(&self, graphics: &mut Graphics2D, area: Rectangle<f32>, tint: Color) {
+        if let Some((atlas, areas)) = &self.in_atlas {
+            let subset = areas[current_frame(areas.len())].clone();
+            graphics.draw_rectangle_image_subset_tinted(area, tint, subset, atlas);
+        } else if let Some(handle) = self.handle() {
+            graphics.draw_rectangle_image_tinted(area, tint, handle);
+        }
     }
     fn draw_image_aspect_ratio_tinted(
         &mut self,
@@ -1590,7 +3965,12 @@ impl LoadableImage {
         tint: Color,
         crop: bool,
     ) {
-        if let Some((image, since_when)) = &mut self.0 {
+        let image = if let Some(image) = self.handle() {
+            image.clone()
+        } else {
+            return;
+        };
+        if let Some((_, since_when)) = &mut self.image {
             let tint = if let Some(t) = since_when {
                 helper.request_redraw();
                 let t = t.elapsed().as_secs_f32();
@@ -1613,7 +3993,7 @@ impl LoadableImage {
                     let cut = 0.5 - 0.5 * area_aspect_ratio / img_aspect_ratio;
                     Rectangle::new(Vec2::new(cut, 0.0), Vec2::new(1.0 - cut, 1.0))
                 };
-                graphics.draw_rectangle_image_subset_tinted(pos, tint, subset, image);
+                graphics.draw_rectangle_image_subset_tinted(pos, tint, subset, &image);
             } else {
                 let area = if area_aspect_ratio > img_aspect_ratio {
                     let w_crop = pos.width() * 0.5 * (1.0 - (img_aspect_ratio / area_aspect_ratio));
@@ -1629,7 +4009,7 @@ impl LoadableImage {
                         Vec2::new(pos.bottom_right().x, pos.bottom_right().y - h_crop),
                     )
                 };
-                graphics.draw_rectangle_image_tinted(area, tint, image);
+                graphics.draw_rectangle_image_tinted(area, tint, &image);
             }
         }
     }
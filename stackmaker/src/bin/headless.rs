@@ -0,0 +1,145 @@
+//! Advances a saved world by some number of ticks without opening a window.
+//!
+//! usage: `headless <world dir> <ticks> [--dry-run] [--stats] [--max-chunks=<n>]`
+//!
+//! The world is ticked using `Runner::tick`, just like in the client, so the results are the same.
+//! With `--dry-run`, the world is loaded and ticked, but not saved.
+//! With `--stats`, the number of blocks of every type is printed after ticking (see `World::stats`).
+//! With `--max-chunks`, at most about `n` chunks are kept in memory while ticking, the others are moved to
+//! a directory in the system's temp directory (see the `eviction` module). `--stats` reads them from there at the end.
+
+use std::time::Instant;
+
+use stackmaker::{eviction::ChunkLimit, runner::Runner, world::World};
+
+const USAGE: &str = "usage: headless <world dir> <ticks> [--dry-run] [--stats] [--max-chunks=<n>]";
+
+fn main() {
+    let mut dir = None;
+    let mut ticks = None;
+    let mut dry_run = false;
+    let mut print_stats = false;
+    let mut max_chunks = None;
+    for arg in std::env::args().skip(1) {
+        if arg == "--dry-run" {
+            dry_run = true;
+        } else if arg == "--stats" {
+            print_stats = true;
+        } else if let Some(n) = arg.strip_prefix("--max-chunks=") {
+            match n.parse::<usize>() {
+                Ok(v) => max_chunks = Some(v),
+                Err(_) => usage_error(&format!("chunk count {n:?} is not a number")),
+            }
+        } else if arg == "--help" || arg == "-h" {
+            eprintln!("{USAGE}");
+            return;
+        } else if arg.starts_with("--") {
+            usage_error(&format!("unknown option {arg:?}"));
+        } else if dir.is_none() {
+            dir = Some(arg);
+        } else if ticks.is_none() {
+            match arg.parse::<u64>() {
+                Ok(v) => ticks = Some(v),
+                Err(_) => usage_error(&format!("tick count {arg:?} is not a number")),
+            }
+        } else {
+            usage_error(&format!("unexpected argument {arg:?}"));
+        }
+    }
+    let (dir, ticks) = match (dir, ticks) {
+        (Some(dir), Some(ticks)) => (dir, ticks),
+        _ => usage_error("missing arguments"),
+    };
+
+    let start = Instant::now();
+    let world = match World::load_from_dir(&dir, None) {
+        Ok(Some(world)) => world,
+        Ok(None) => exit_with(&format!(
+            "Couldn't load world from {dir:?}: the save data is invalid or from an unsupported version."
+        )),
+        Err(e) => exit_with(&format!("Couldn't load world from {dir:?}: {e}")),
+    };
+    eprintln!(
+        "[info] loaded {dir:?} in {:.3}s",
+        start.elapsed().as_secs_f64()
+    );
+
+    let mut runner = Runner::new(world);
+    runner.chunk_limit = max_chunks.map(|n| {
+        let evicted_dir =
+            std::env::temp_dir().join(format!("stackmaker-evicted-{}", std::process::id()));
+        ChunkLimit::new(n, evicted_dir)
+    });
+    let mut signals = 0u64;
+    let start = Instant::now();
+    for _ in 0..ticks {
+        signals += runner.world.signals_queue[0].len() as u64;
+        runner.tick();
+    }
+    let elapsed = start.elapsed().as_secs_f64();
+    eprintln!(
+        "[info] {ticks} ticks in {elapsed:.3}s ({:.1} ticks/s), {signals} signals ({:.1} signals/s)",
+        ticks as f64 / elapsed,
+        signals as f64 / elapsed,
+    );
+
+    if runner.chunk_limit.is_some() && !print_stats {
+        let loaded: usize = runner
+            .world
+            .layers
+            .iter()
+            .map(|l| l.loaded_chunks().len())
+            .sum();
+        eprintln!(
+            "[info] {loaded} chunks loaded, {} signals pending",
+            runner.pending_signals()
+        );
+    } else {
+        let stats = match runner.world.stats() {
+            Ok(stats) => stats,
+            Err(e) => exit_with(&format!("Couldn't read the evicted chunks: {e}")),
+        };
+        eprintln!(
+            "[info] {} blocks in {} chunks, {} signals pending",
+            stats.total_blocks(),
+            stats.chunks,
+            stats.pending_signals
+        );
+        if print_stats {
+            let mut blocks: Vec<_> = stats.blocks.into_iter().collect();
+            blocks.sort_unstable();
+            for (name, count) in blocks {
+                println!("{name}: {count}");
+            }
+        }
+    }
+
+    if dry_run {
+        eprintln!("[info] dry run, not saving");
+    } else {
+        let start = Instant::now();
+        if let Err(e) = runner.world.save_to_dir(&dir) {
+            exit_with(&format!("Couldn't save world to {dir:?}: {e}"));
+        }
+        eprintln!(
+            "[info] saved {dir:?} in {:.3}s",
+            start.elapsed().as_secs_f64()
+        );
+    }
+    if let Some(limit) = runner.chunk_limit.take() {
+        // dropping the world deletes the evicted chunks' files, then the directory is empty
+        drop(runner);
+        _ = std::fs::remove_dir(limit.dir());
+    }
+}
+
+fn exit_with(e: &str) -> ! {
+    eprintln!("[err] {e}");
+    std::process::exit(1);
+}
+
+fn usage_error(e: &str) -> ! {
+    eprintln!("[err] {e}");
+    eprintln!("{USAGE}");
+    std::process::exit(2);
+}
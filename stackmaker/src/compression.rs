@@ -0,0 +1,574 @@
+//! A small, dependency-free gzip (DEFLATE) implementation used for compressed save files.
+//! The encoder uses LZ77 with the fixed huffman codes, the decoder understands all block types.
+//!
+//! There is no zstd: a zstd implementation is many times the size of this one, and stackmaker doesn't have any dependencies.
+//! Most of what makes save files large are the same few bytes repeated (empty stacks, directions), which gzip removes well enough.
+//! Another format can be added as a new `Compression` variant, saves using it would be rejected by older versions.
+
+/// How the payload of a save file is stored. The discriminant is the header byte written to the file.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Compression {
+    None = 0,
+    Gzip = 1,
+}
+
+impl Compression {
+    pub fn from_byte(b: u8) -> Option<Self> {
+        Some(match b {
+            0 => Self::None,
+            1 => Self::Gzip,
+            _ => return None,
+        })
+    }
+    pub fn compress(self, data: Vec<u8>) -> Vec<u8> {
+        match self {
+            Self::None => data,
+            Self::Gzip => gzip(&data),
+        }
+    }
+    /// returns `None` if the data is not valid for this format
+    pub fn decompress(self, data: &[u8]) -> Option<Vec<u8>> {
+        match self {
+            Self::None => Some(data.to_vec()),
+            Self::Gzip => gunzip(data),
+        }
+    }
+}
+
+/// CRC-32 (IEEE), as used by gzip and png.
+pub fn crc32(data: &[u8]) -> u32 {
+    let mut crc = Crc32::new();
+    crc.update(data);
+    crc.finish()
+}
+
+/// Computes a CRC-32 like `crc32`, but for data which arrives in multiple parts.
+#[derive(Clone, Copy, Debug)]
+pub struct Crc32(u32);
+impl Crc32 {
+    pub fn new() -> Self {
+        Self(0xFFFFFFFF)
+    }
+    pub fn update(&mut self, data: &[u8]) {
+        for b in data {
+            self.0 = CRC32_TABLE[((self.0 ^ *b as u32) & 0xFF) as usize] ^ (self.0 >> 8);
+        }
+    }
+    /// the CRC-32 of all the data passed to `update`
+    pub fn finish(self) -> u32 {
+        self.0 ^ 0xFFFFFFFF
+    }
+}
+impl Default for Crc32 {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+const CRC32_TABLE: [u32; 256] = {
+    let mut table = [0u32; 256];
+    let mut i = 0;
+    while i < 256 {
+        let mut c = i as u32;
+        let mut k = 0;
+        while k < 8 {
+            c = if c & 1 == 1 {
+                0xEDB88320 ^ (c >> 1)
+            } else {
+                c >> 1
+            };
+            k += 1;
+        }
+        table[i] = c;
+        i += 1;
+    }
+    table
+};
+
+const LEN_BASE: [u16; 29] = [
+    3, 4, 5, 6, 7, 8, 9, 10, 11, 13, 15, 17, 19, 23, 27, 31, 35, 43, 51, 59, 67, 83, 99, 115, 131,
+    163, 195, 227, 258,
+];
+const LEN_EXTRA: [u8; 29] = [
+    0, 0, 0, 0, 0, 0, 0, 0, 1, 1, 1, 1, 2, 2, 2, 2, 3, 3, 3, 3, 4, 4, 4, 4, 5, 5, 5, 5, 0,
+];
+const DIST_BASE: [u16; 30] = [
+    1, 2, 3, 4, 5, 7, 9, 13, 17, 25, 33, 49, 65, 97, 129, 193, 257, 385, 513, 769, 1025, 1537,
+    2049, 3073, 4097, 6145, 8193, 12289, 16385, 24577,
+];
+const DIST_EXTRA: [u8; 30] = [
+    0, 0, 0, 0, 1, 1, 2, 2, 3, 3, 4, 4, 5, 5, 6, 6, 7, 7, 8, 8, 9, 9, 10, 10, 11, 11, 12, 12, 13,
+    13,
+];
+
+// ENCODING
+
+struct BitWriter {
+    out: Vec<u8>,
+    bits: u32,
+    count: u8,
+}
+impl BitWriter {
+    /// writes the lowest `count` bits of `value`, least significant bit first
+    fn write(&mut self, value: u32, count: u8) {
+        for i in 0..count {
+            self.bits |= ((value >> i) & 1) << self.count;
+            self.count += 1;
+            if self.count == 8 {
+                self.out.push(self.bits as u8);
+                self.bits = 0;
+                self.count = 0;
+            }
+        }
+    }
+    /// huffman codes are stored most significant bit first
+    fn write_code(&mut self, code: u32, len: u8) {
+        for i in (0..len).rev() {
+            self.write((code >> i) & 1, 1);
+        }
+    }
+    fn finish(mut self) -> Vec<u8> {
+        if self.count > 0 {
+            self.out.push(self.bits as u8);
+        }
+        self.out
+    }
+}
+
+fn write_fixed_literal(w: &mut BitWriter, lit: u16) {
+    match lit {
+        0..=143 => w.write_code(0x30 + lit as u32, 8),
+        144..=255 => w.write_code(0x190 + (lit as u32 - 144), 9),
+        256..=279 => w.write_code(lit as u32 - 256, 7),
+        _ => w.write_code(0xC0 + (lit as u32 - 280), 8),
+    }
+}
+
+fn write_match(w: &mut BitWriter, len: usize, dist: usize) {
+    let li = LEN_BASE.iter().rposition(|b| *b as usize <= len).unwrap();
+    write_fixed_literal(w, 257 + li as u16);
+    w.write((len - LEN_BASE[li] as usize) as u32, LEN_EXTRA[li]);
+    let di = DIST_BASE.iter().rposition(|b| *b as usize <= dist).unwrap();
+    w.write_code(di as u32, 5);
+    w.write((dist - DIST_BASE[di] as usize) as u32, DIST_EXTRA[di]);
+}
+
+/// raw DEFLATE stream consisting of a single block using the fixed huffman codes
+fn deflate(data: &[u8]) -> Vec<u8> {
+    const WINDOW: usize = 32768;
+    const MAX_CHAIN: usize = 64;
+    const HASH_SIZE: usize = 1 << 15;
+    let hash = |i: usize| {
+        ((data[i] as usize) << 10 ^ (data[i + 1] as usize) << 5 ^ data[i + 2] as usize)
+            & (HASH_SIZE - 1)
+    };
+    let mut w = BitWriter {
+        out: Vec::with_capacity(data.len() / 4),
+        bits: 0,
+        count: 0,
+    };
+    // final block, fixed huffman codes
+    w.write(1, 1);
+    w.write(1, 2);
+    let mut head = vec![usize::MAX; HASH_SIZE];
+    let mut prev = vec![usize::MAX; data.len()];
+    let insert = |i: usize, head: &mut [usize], prev: &mut [usize]| {
+        if i + 2 < data.len() {
+            let h = hash(i);
+            prev[i] = head[h];
+            head[h] = i;
+        }
+    };
+    let mut i = 0;
+    while i < data.len() {
+        let mut best = (0, 0);
+        if i + 2 < data.len() {
+            let mut candidate = head[hash(i)];
+            let mut tries = 0;
+            while candidate != usize::MAX && i - candidate <= WINDOW && tries < MAX_CHAIN {
+                let len = data[candidate..]
+                    .iter()
+                    .zip(&data[i..])
+                    .take(258)
+                    .take_while(|(a, b)| a == b)
+                    .count();
+                if len > best.0 {
+                    best = (len, i - candidate);
+                    if len == 258 {
+                        break;
+                    }
+                }
+                candidate = prev[candidate];
+                tries += 1;
+            }
+        }
+        if best.0 >= 3 {
+            write_match(&mut w, best.0, best.1);
+            for j in i..i + best.0 {
+                insert(j, &mut head, &mut prev);
+            }
+            i += best.0;
+        } else {
+            write_fixed_literal(&mut w, data[i] as u16);
+            insert(i, &mut head, &mut prev);
+            i += 1;
+        }
+    }
+    write_fixed_literal(&mut w, 256);
+    w.finish()
+}
+
+pub fn gzip(data: &[u8]) -> Vec<u8> {
+    // magic, deflate, no flags, no mtime, no extra flags, unknown os
+    let mut out = vec![0x1F, 0x8B, 8, 0, 0, 0, 0, 0, 0, 0xFF];
+    out.extend(deflate(data));
+    out.extend_from_slice(&crc32(data).to_le_bytes());
+    out.extend_from_slice(&(data.len() as u32).to_le_bytes());
+    out
+}
+
+/// a zlib stream, as used by png
+pub fn zlib(data: &[u8]) -> Vec<u8> {
+    // deflate with a 32K window, no preset dictionary, and a header check value
+    let mut out = vec![0x78, 0x01];
+    out.extend(deflate(data));
+    out.extend_from_slice(&adler32(data).to_be_bytes());
+    out
+}
+
+pub(crate) fn adler32(data: &[u8]) -> u32 {
+    let (mut a, mut b) = (1u32, 0u32);
+    for chunk in data.chunks(5552) {
+        for v in chunk {
+            a += *v as u32;
+            b += a;
+        }
+        a %= 65521;
+        b %= 65521;
+    }
+    b << 16 | a
+}
+
+// DECODING
+
+struct BitReader<'a> {
+    data: &'a [u8],
+    pos: usize,
+    bit: u8,
+}
+impl BitReader<'_> {
+    fn bit(&mut self) -> Option<u32> {
+        let byte = *self.data.get(self.pos)?;
+        let v = (byte >> self.bit) & 1;
+        self.bit += 1;
+        if self.bit == 8 {
+            self.bit = 0;
+            self.pos += 1;
+        }
+        Some(v as u32)
+    }
+    fn bits(&mut self, count: u8) -> Option<u32> {
+        let mut v = 0;
+        for i in 0..count {
+            v |= self.bit()? << i;
+        }
+        Some(v)
+    }
+    fn align(&mut self) {
+        if self.bit != 0 {
+            self.bit = 0;
+            self.pos += 1;
+        }
+    }
+}
+
+/// canonical huffman code, decoded one bit at a time
+struct Huffman {
+    /// how many codes have each length
+    counts: [u16; 16],
+    /// symbols ordered by code
+    symbols: Vec<u16>,
+}
+impl Huffman {
+    fn new(lengths: &[u8]) -> Self {
+        let mut counts = [0u16; 16];
+        for l in lengths {
+            counts[*l as usize] += 1;
+        }
+        counts[0] = 0;
+        let mut offsets = [0u16; 16];
+        for i in 1..16 {
+            offsets[i] = offsets[i - 1] + counts[i - 1];
+        }
+        let mut symbols = vec![0; lengths.len()];
+        for (sym, l) in lengths.iter().enumerate() {
+            if *l != 0 {
+                symbols[offsets[*l as usize] as usize] = sym as u16;
+                offsets[*l as usize] += 1;
+            }
+        }
+        Self { counts, symbols }
+    }
+    fn decode(&self, r: &mut BitReader) -> Option<u16> {
+        let (mut code, mut first, mut index) = (0i32, 0i32, 0i32);
+        for len in 1..16 {
+            code |= r.bit()? as i32;
+            let count = self.counts[len] as i32;
+            if code - first < count {
+                return self.symbols.get((index + code - first) as usize).copied();
+            }
+            index += count;
+            first = (first + count) << 1;
+            code <<= 1;
+        }
+        None
+    }
+}
+
+fn inflate_block(
+    r: &mut BitReader,
+    out: &mut Vec<u8>,
+    lit: &Huffman,
+    dist: &Huffman,
+) -> Option<()> {
+    loop {
+        let sym = lit.decode(r)? as usize;
+        match sym {
+            0..=255 => out.push(sym as u8),
+            256 => return Some(()),
+            _ => {
+                let li = sym - 257;
+                let len = *LEN_BASE.get(li)? as usize + r.bits(LEN_EXTRA[li])? as usize;
+                let di = dist.decode(r)? as usize;
+                let d = *DIST_BASE.get(di)? as usize + r.bits(DIST_EXTRA[di])? as usize;
+                if d > out.len() {
+                    return None;
+                }
+                let start = out.len() - d;
+                for i in 0..len {
+                    out.push(out[start + i]);
+                }
+            }
+        }
+    }
+}
+
+/// decodes a raw DEFLATE stream, returning the data and the number of bytes consumed
+pub(crate) fn inflate(data: &[u8]) -> Option<(Vec<u8>, usize)> {
+    let mut r = BitReader {
+        data,
+        pos: 0,
+        bit: 0,
+    };
+    let mut out = vec![];
+    loop {
+        let last = r.bit()? == 1;
+        match r.bits(2)? {
+            0 => {
+                r.align();
+                let len = u16::from_le_bytes([*data.get(r.pos)?, *data.get(r.pos + 1)?]);
+                r.pos += 4;
+                out.extend_from_slice(data.get(r.pos..r.pos + len as usize)?);
+                r.pos += len as usize;
+            }
+            1 => {
+                let mut lengths = [0u8; 288];
+                lengths[..144].fill(8);
+                lengths[144..256].fill(9);
+                lengths[256..280].fill(7);
+                lengths[280..].fill(8);
+                inflate_block(
+                    &mut r,
+                    &mut out,
+                    &Huffman::new(&lengths),
+                    &Huffman::new(&[5; 30]),
+                )?;
+            }
+            2 => {
+                const ORDER: [usize; 19] = [
+                    16, 17, 18, 0, 8, 7, 9, 6, 10, 5, 11, 4, 12, 3, 13, 2, 14, 1, 15,
+                ];
+                let hlit = r.bits(5)? as usize + 257;
+                let hdist = r.bits(5)? as usize + 1;
+                let hclen = r.bits(4)? as usize + 4;
+                let mut code_lengths = [0u8; 19];
+                for i in ORDER.iter().take(hclen) {
+                    code_lengths[*i] = r.bits(3)? as u8;
+                }
+                let code_lengths = Huffman::new(&code_lengths);
+                let mut lengths = Vec::with_capacity(hlit + hdist);
+                while lengths.len() < hlit + hdist {
+                    match code_lengths.decode(&mut r)? {
+                        l @ 0..=15 => lengths.push(l as u8),
+                        16 => {
+                            let prev = *lengths.last()?;
+                            for _ in 0..3 + r.bits(2)? {
+                                lengths.push(prev);
+                            }
+                        }
+                        17 => lengths.extend(std::iter::repeat_n(0, 3 + r.bits(3)? as usize)),
+                        _ => lengths.extend(std::iter::repeat_n(0, 11 + r.bits(7)? as usize)),
+                    }
+                }
+                if lengths.len() != hlit + hdist {
+                    return None;
+                }
+                inflate_block(
+                    &mut r,
+                    &mut out,
+                    &Huffman::new(&lengths[..hlit]),
+                    &Huffman::new(&lengths[hlit..]),
+                )?;
+            }
+            _ => return None,
+        }
+        if last {
+            r.align();
+            return Some((out, r.pos));
+        }
+    }
+}
+
+/// returns `None` if `data` isn't a valid gzip file or its checksum doesn't match
+pub fn gunzip(data: &[u8]) -> Option<Vec<u8>> {
+    if data.len() < 18 || data[0..3] != [0x1F, 0x8B, 8] {
+        return None;
+    }
+    let flags = data[3];
+    let mut pos = 10;
+    if flags & 0b100 != 0 {
+        let len = u16::from_le_bytes([*data.get(pos)?, *data.get(pos + 1)?]);
+        pos += 2 + len as usize;
+    }
+    for flag in [0b1000, 0b10000] {
+        if flags & flag != 0 {
+            // zero-terminated file name / comment
+            pos += data.get(pos..)?.iter().position(|b| *b == 0)? + 1;
+        }
+    }
+    if flags & 0b10 != 0 {
+        pos += 2;
+    }
+    let (out, used) = inflate(data.get(pos..)?)?;
+    let trailer = data.get(pos + used..pos + used + 8)?;
+    let crc = u32::from_le_bytes(trailer[0..4].try_into().ok()?);
+    let len = u32::from_le_bytes(trailer[4..8].try_into().ok()?);
+    if crc != crc32(&out) || len != out.len() as u32 {
+        return None;
+    }
+    Some(out)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// `b"stored blocks are copied as they are"`, compressed by python's zlib at level 0
+    const STORED: [u8; 41] = [
+        0x01, 0x24, 0x00, 0xdb, 0xff, 0x73, 0x74, 0x6f, 0x72, 0x65, 0x64, 0x20, 0x62, 0x6c, 0x6f,
+        0x63, 0x6b, 0x73, 0x20, 0x61, 0x72, 0x65, 0x20, 0x63, 0x6f, 0x70, 0x69, 0x65, 0x64, 0x20,
+        0x61, 0x73, 0x20, 0x74, 0x68, 0x65, 0x79, 0x20, 0x61, 0x72, 0x65,
+    ];
+    /// `dynamic_text()`, compressed by python's zlib at level 9, which uses a dynamic huffman block
+    const DYNAMIC: [u8; 112] = [
+        0x7d, 0xd1, 0xd7, 0x0d, 0x80, 0x30, 0x0c, 0x04, 0xd0, 0x55, 0x3c, 0x01, 0x22, 0xa1, 0x04,
+        0xc6, 0xa1, 0x84, 0x0e, 0xa1, 0x84, 0x3a, 0x3d, 0xfc, 0xe2, 0x48, 0xf7, 0xed, 0x27, 0x4b,
+        0x77, 0x67, 0x1b, 0x4d, 0x3e, 0x2d, 0x7b, 0x5b, 0xf4, 0x94, 0xaf, 0xe6, 0x9c, 0xa8, 0x32,
+        0x97, 0xde, 0xa8, 0xdb, 0xc7, 0x99, 0xcc, 0xa1, 0xd7, 0xef, 0x3a, 0x64, 0xcf, 0x4d, 0xa5,
+        0xa9, 0x37, 0x8f, 0xec, 0xc7, 0x05, 0xe4, 0x8a, 0x73, 0x09, 0xb9, 0xe0, 0x3c, 0x80, 0x3c,
+        0xe1, 0x3c, 0x84, 0x5c, 0x72, 0x1e, 0x41, 0x9e, 0x72, 0x1e, 0x43, 0x1e, 0x70, 0xae, 0x70,
+        0x54, 0xa7, 0xc9, 0x04, 0xfa, 0x90, 0xf3, 0x14, 0xbf, 0x77, 0xaa, 0x14, 0x78, 0xd8, 0xc8,
+        0xf1, 0x78, 0x59, 0xf1, 0x6b, 0xf3, 0x05,
+    ];
+    /// `b"hello hello hello gzip"`, compressed by python's `gzip.compress`
+    const GZIP: [u8; 32] = [
+        0x1f, 0x8b, 0x08, 0x00, 0x00, 0x00, 0x00, 0x00, 0x02, 0x03, 0xcb, 0x48, 0xcd, 0xc9, 0xc9,
+        0x57, 0xc8, 0x40, 0x22, 0xd3, 0xab, 0x32, 0x0b, 0x00, 0x47, 0x3a, 0x59, 0x1c, 0x16, 0x00,
+        0x00, 0x00,
+    ];
+
+    fn dynamic_text() -> Vec<u8> {
+        (0..12)
+            .map(|i| {
+                format!(
+                    "the {i} quick brown foxes jump over {} lazy dogs. ",
+                    i * 7 % 13
+                )
+            })
+            .collect::<String>()
+            .into_bytes()
+    }
+
+    /// bytes without any repetitions a match could use
+    fn noise(len: usize) -> Vec<u8> {
+        let mut state = 0x2545F4914F6CDD1Du64;
+        (0..len)
+            .map(|_| {
+                state ^= state << 13;
+                state ^= state >> 7;
+                state ^= state << 17;
+                (state >> 24) as u8
+            })
+            .collect()
+    }
+
+    fn roundtrip(data: &[u8]) -> usize {
+        let compressed = deflate(data);
+        assert_eq!(
+            inflate(&compressed),
+            Some((data.to_vec(), compressed.len()))
+        );
+        assert_eq!(gunzip(&gzip(data)).as_deref(), Some(data));
+        compressed.len()
+    }
+
+    #[test]
+    fn deflate_roundtrips() {
+        roundtrip(&[]);
+        roundtrip(&[42]);
+        roundtrip(&[1, 2]);
+        roundtrip(&dynamic_text());
+        // runs longer than the longest match (258) are split into multiple matches
+        assert!(roundtrip(&[7; 1000]) < 20);
+        assert!(roundtrip(&[b"ab".repeat(300), vec![0; 259], b"ab".repeat(300)].concat()) < 40);
+        // the oldest byte a match can reference is 32768 bytes back
+        let mut data = noise(32768);
+        data.extend_from_within(0..300);
+        let without_match = roundtrip(&data[..32768]);
+        assert!(roundtrip(&data) < without_match + 10);
+        // but not 32769
+        let mut data = noise(32769);
+        data.extend_from_within(0..300);
+        roundtrip(&data);
+    }
+
+    #[test]
+    fn inflates_blocks_from_other_encoders() {
+        let stored = b"stored blocks are copied as they are";
+        assert_eq!(inflate(&STORED), Some((stored.to_vec(), STORED.len())));
+        assert_eq!(inflate(&DYNAMIC), Some((dynamic_text(), DYNAMIC.len())));
+        assert_eq!(
+            gunzip(&GZIP).as_deref(),
+            Some(&b"hello hello hello gzip"[..])
+        );
+        // our encoder only writes fixed huffman blocks
+        assert_eq!((deflate(stored)[0] >> 1) & 0b11, 1);
+    }
+
+    #[test]
+    fn bad_checksums_are_rejected() {
+        let data = dynamic_text();
+        let good = gzip(&data);
+        let crc_start = good.len() - 8;
+        for i in [crc_start, crc_start + 3, crc_start + 4] {
+            let mut bad = good.clone();
+            bad[i] ^= 1;
+            assert_eq!(gunzip(&bad), None, "changed byte {i}");
+        }
+        // truncated
+        assert_eq!(gunzip(&good[..good.len() - 1]), None);
+        assert_eq!(gunzip(&good[..good.len() / 2]), None);
+        assert_eq!(Compression::Gzip.decompress(&good), Some(data));
+        assert_eq!(crc32(b"123456789"), 0xCBF43926);
+    }
+}
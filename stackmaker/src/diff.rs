@@ -0,0 +1,104 @@
+//! Finding the stacks which differ between two worlds, for example a world and its `World::snapshot`,
+//! to see exactly where a simulation went a different way.
+
+use std::collections::HashSet;
+
+use crate::world::{Block, Layer, World};
+
+/// A position at which two worlds' stacks differ.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct WorldDiffEntry {
+    pub layer: usize,
+    pub x: i64,
+    pub y: i64,
+    /// The stack in the world `World::diff` was called on
+    pub this: Vec<Block>,
+    /// The stack in the world which was passed to `World::diff`
+    pub other: Vec<Block>,
+}
+
+impl World {
+    /// Every position at which the stacks of the two worlds aren't equal, sorted by (layer, x, y).
+    /// A missing chunk is the same as a chunk of empty stacks. Only blocks are compared, not the signals or options.
+    /// Evicted chunks are read from their files, see `Layer::read_chunk`.
+    pub fn diff(&self, other: &World) -> std::io::Result<Vec<WorldDiffEntry>> {
+        let mut out = vec![];
+        for (layer, (a, b)) in self.layers.iter().zip(other.layers.iter()).enumerate() {
+            // chunks which only exist in one of the worlds are compared to empty stacks
+            let keys: HashSet<u64> = a.chunk_keys().chain(b.chunk_keys()).collect();
+            for key in keys {
+                let (chunk_a, chunk_b) = (a.read_chunk(&key)?, b.read_chunk(&key)?);
+                for inner in 0..256 {
+                    let stack_a = chunk_a.as_ref().map_or(&[][..], |chunk| &chunk[inner]);
+                    let stack_b = chunk_b.as_ref().map_or(&[][..], |chunk| &chunk[inner]);
+                    if stack_a != stack_b {
+                        let (x, y) = Layer::where_to_coords(key, inner as u8);
+                        out.push(WorldDiffEntry {
+                            layer,
+                            x,
+                            y,
+                            this: stack_a.to_vec(),
+                            other: stack_b.to_vec(),
+                        });
+                    }
+                }
+            }
+        }
+        out.sort_unstable_by_key(|entry| (entry.layer, entry.x, entry.y));
+        Ok(out)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::runner::DIR_UP;
+
+    #[test]
+    fn diff_finds_the_changed_stack() {
+        let mut world = World::new_empty();
+        world.layers[0]
+            .try_push_block(3, 4, Block::Color(1))
+            .unwrap();
+        world.layers[6]
+            .try_push_block(-30, 40, Block::Not(DIR_UP))
+            .unwrap();
+        world.layers[6]
+            .try_push_block(-30, 40, Block::Color(2))
+            .unwrap();
+        let snapshot = world.snapshot();
+        assert_eq!(world.diff(&snapshot).unwrap(), []);
+        world.layers[6].try_pop_block(-30, 40).unwrap();
+        world.layers[6]
+            .try_push_block(-30, 40, Block::Color(3))
+            .unwrap();
+        let entry = WorldDiffEntry {
+            layer: 6,
+            x: -30,
+            y: 40,
+            this: vec![Block::Not(DIR_UP), Block::Color(3)],
+            other: vec![Block::Not(DIR_UP), Block::Color(2)],
+        };
+        assert_eq!(world.diff(&snapshot).unwrap(), std::slice::from_ref(&entry));
+        let reversed = snapshot.diff(&world).unwrap();
+        assert_eq!(reversed.len(), 1);
+        assert_eq!(
+            (&reversed[0].this, &reversed[0].other),
+            (&entry.other, &entry.this)
+        );
+        // a chunk which only exists in one of the worlds
+        world.layers[1]
+            .try_push_block(100, 100, Block::Color(4))
+            .unwrap();
+        let diff = snapshot.diff(&world).unwrap();
+        assert_eq!(diff.len(), 2);
+        assert_eq!((diff[0].layer, diff[0].x, diff[0].y), (1, 100, 100));
+        assert_eq!((diff[0].this.len(), diff[0].other.len()), (0, 1));
+        // empty chunks are the same as missing ones
+        world.layers[1].try_pop_block(100, 100).unwrap();
+        world.layers[0]
+            .try_get_mut(&crate::world::chunk_key(-9, -9))
+            .unwrap();
+        assert_eq!(world.diff(&snapshot).unwrap(), [entry]);
+    }
+}
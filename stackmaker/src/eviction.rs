@@ -0,0 +1,288 @@
+//! Keeps only the recently used chunks in memory, so huge worlds can be simulated without holding all of their chunks at once.
+//! The other chunks are written to their own files and read back as soon as a signal reaches them, see `Runner::chunk_limit`.
+
+use std::{
+    collections::HashMap,
+    fs,
+    path::{Path, PathBuf},
+};
+
+use crate::world::{load_chunk, Block, World, LAYER_MASK};
+
+/// The file of an evicted chunk. It contains the same bytes as the chunk's slot in a layer file.
+/// Snapshots of a layer share the file with the original, it is deleted once none of them need it anymore.
+pub(crate) struct EvictedChunk {
+    pub(crate) path: PathBuf,
+}
+impl EvictedChunk {
+    pub(crate) fn write(path: PathBuf, bytes: &[u8]) -> std::io::Result<Self> {
+        fs::write(&path, bytes)?;
+        Ok(Self { path })
+    }
+    pub(crate) fn read(&self) -> std::io::Result<Vec<u8>> {
+        fs::read(&self.path)
+    }
+    pub(crate) fn read_chunk(&self) -> std::io::Result<[Vec<Block>; 256]> {
+        load_chunk(&mut self.read()?.into_iter())
+            .map(|(_, chunk)| chunk)
+            .ok_or_else(|| {
+                std::io::Error::new(
+                    std::io::ErrorKind::InvalidData,
+                    format!("{:?} doesn't contain a chunk", self.path),
+                )
+            })
+    }
+}
+impl Drop for EvictedChunk {
+    fn drop(&mut self) {
+        _ = fs::remove_file(&self.path);
+    }
+}
+
+/// How many chunks a `Runner` keeps in memory.
+pub struct ChunkLimit {
+    /// if more chunks than this are loaded after a tick, the least recently used ones are evicted until 7/8 of this many are left.
+    /// chunks which can't be evicted (because of their clocks) count towards the limit, but stay loaded.
+    pub max_chunks: usize,
+    /// where the evicted chunks are written to. their files are deleted once they aren't needed anymore.
+    dir: PathBuf,
+    /// (layer, chunk) => the last tick in which a signal was sent to the chunk
+    last_used: HashMap<(u8, u64), u64>,
+    /// numbers the files, so a chunk evicted again never overwrites a file an autosave might still be reading
+    next_file: u64,
+}
+
+impl ChunkLimit {
+    /// `dir` is created when the first chunk is evicted. it should only be used by this limit.
+    pub fn new(max_chunks: usize, dir: impl Into<PathBuf>) -> Self {
+        Self {
+            max_chunks,
+            dir: dir.into(),
+            last_used: HashMap::new(),
+            next_file: 0,
+        }
+    }
+    pub fn dir(&self) -> &Path {
+        &self.dir
+    }
+    /// records which chunks the next tick's signals are sent to, then evicts chunks if too many are loaded.
+    /// `tick` is the runner's `tick_counter`.
+    pub(crate) fn after_tick(&mut self, world: &mut World, tick: u64) {
+        for &(_, dir_layer, chunk, _) in &world.signals_queue[0] {
+            let layer = dir_layer & LAYER_MASK;
+            let l = &world.layers[layer as usize];
+            // signals sent to empty space don't create a chunk, so they don't need to be tracked
+            if l.chunks.contains_key(&chunk) || l.evicted.contains_key(&chunk) {
+                self.last_used.insert((layer, chunk), tick);
+            }
+        }
+        let loaded: usize = world.layers.iter().map(|layer| layer.chunks.len()).sum();
+        if loaded <= self.max_chunks {
+            return;
+        }
+        // forget chunks which were removed since
+        self.last_used.retain(|&(layer, chunk), _| {
+            let layer = &world.layers[layer as usize];
+            layer.chunks.contains_key(&chunk) || layer.evicted.contains_key(&chunk)
+        });
+        // (last used, layer, chunk), so the oldest come first and equally old ones are evicted in the same order every time
+        let mut candidates = vec![];
+        for (i, layer) in world.layers.iter().enumerate() {
+            for key in layer.chunks.keys() {
+                if layer.clocks.range((*key, 0)..=(*key, 255)).next().is_some() {
+                    continue;
+                }
+                // chunks created without a signal (by a client, for example) count as used now
+                let last_used = *self.last_used.entry((i as u8, *key)).or_insert(tick);
+                candidates.push((last_used, i as u8, *key));
+            }
+        }
+        candidates.sort_unstable();
+        let target = self.max_chunks - self.max_chunks / 8;
+        if let Err(e) = fs::create_dir_all(&self.dir) {
+            eprintln!(
+                "[warn] Couldn't create {:?} for evicted chunks: {e}",
+                self.dir
+            );
+            return;
+        }
+        for (_, layer, chunk) in candidates.into_iter().take(loaded.saturating_sub(target)) {
+            let path = self
+                .dir
+                .join(format!("{layer}_{chunk:016x}_{}", self.next_file));
+            self.next_file += 1;
+            if let Err(e) = world.layers[layer as usize].evict(chunk, path) {
+                eprintln!("[warn] Couldn't evict a chunk to {:?}: {e}", self.dir);
+                return;
+            }
+            self.last_used.remove(&(layer, chunk));
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        runner::{Runner, DIR_DOWN, DIR_DOWN_L, DIR_LEFT, DIR_RIGHT, DIR_UP, DIR_UP_L},
+        thumbnail,
+    };
+
+    const DIRS: [u8; 6] = [DIR_LEFT, DIR_RIGHT, DIR_UP, DIR_DOWN, DIR_UP_L, DIR_DOWN_L];
+
+    /// xorshift, so both runners get the same blocks and signals
+    struct Rng(u64);
+    impl Rng {
+        fn below(&mut self, n: u64) -> u64 {
+            self.0 ^= self.0 << 13;
+            self.0 ^= self.0 >> 7;
+            self.0 ^= self.0 << 17;
+            self.0 % n
+        }
+    }
+
+    /// a world spread over about 100 chunks on three layers
+    fn busy_world() -> World {
+        let mut rng = Rng(12345);
+        let mut world = World::new_empty();
+        for _ in 0..4000 {
+            let (x, y) = (rng.below(160) as i64 - 80, rng.below(160) as i64 - 80);
+            let layer = rng.below(3) as usize;
+            let dir = DIRS[rng.below(6) as usize];
+            let block = match rng.below(8) {
+                0 => Block::Delay(rng.below(4) as u32, dir),
+                1 => Block::Splitter(dir),
+                2 => Block::Counter(0, dir),
+                3 if rng.below(20) == 0 => Block::Clock(5, 0),
+                3 => Block::Not(dir),
+                4 => Block::Move(dir),
+                5 => Block::Swap(dir),
+                6 => Block::Duplicate(dir),
+                _ => Block::Destroy(dir),
+            };
+            world.layers[layer].try_push_block(x, y, block).unwrap();
+        }
+        world
+    }
+
+    fn temp_dir(name: &str) -> PathBuf {
+        let dir =
+            std::env::temp_dir().join(format!("stackmaker-test-{name}-{}", std::process::id()));
+        _ = fs::remove_dir_all(&dir);
+        dir
+    }
+
+    fn blocks(world: &World) -> Vec<(usize, i64, i64, Vec<Block>)> {
+        let mut blocks: Vec<_> = world
+            .iter_blocks()
+            .unwrap()
+            .map(|(layer, x, y, stack)| (layer, x, y, stack.clone()))
+            .collect();
+        blocks.sort_by_key(|&(layer, x, y, _)| (layer, x, y));
+        blocks
+    }
+
+    fn loaded(world: &World) -> usize {
+        world.layers.iter().map(|layer| layer.chunks.len()).sum()
+    }
+
+    #[test]
+    fn evicting_doesnt_change_the_outcome() {
+        let dir = temp_dir("evict");
+        let mut unlimited = Runner::new(busy_world());
+        let mut limited = Runner::new(busy_world());
+        limited.chunk_limit = Some(ChunkLimit::new(8, &dir));
+        let mut rng = Rng(99);
+        let mut evicted = 0;
+        for _ in 0..300 {
+            for _ in 0..3 {
+                let (x, y) = (rng.below(160) as i64 - 80, rng.below(160) as i64 - 80);
+                let layer = rng.below(3) as u8;
+                let dir = DIRS[rng.below(6) as usize];
+                let value = rng.below(3) as u32;
+                unlimited.inject_signal(x, y, layer, dir, value, 0);
+                limited.inject_signal(x, y, layer, dir, value, 0);
+            }
+            unlimited.tick();
+            limited.tick();
+            evicted = evicted.max(limited.world.layers.iter().map(|l| l.evicted.len()).sum());
+        }
+        assert!(evicted > 50, "only {evicted} chunks were evicted");
+        assert_eq!(unlimited.world.signals_queue, limited.world.signals_queue);
+        limited.world.reload_all_chunks().unwrap();
+        assert!(loaded(&limited.world) > 8);
+        assert_eq!(blocks(&unlimited.world), blocks(&limited.world));
+        drop(limited);
+        _ = fs::remove_dir_all(&dir);
+    }
+
+    #[test]
+    fn unreadable_chunks_stay_evicted() {
+        let dir = temp_dir("unreadable");
+        let mut world = World::new_empty();
+        world.layers[0]
+            .try_push_block(0, 0, Block::Color(1))
+            .unwrap();
+        world.layers[0]
+            .try_push_block(100, 0, Block::Color(2))
+            .unwrap();
+        let mut runner = Runner::new(world);
+        runner.chunk_limit = Some(ChunkLimit::new(0, &dir));
+        runner.tick();
+        assert_eq!(loaded(&runner.world), 0);
+        let (chunk, _) = runner.world.layers[0].get_where(0, 0);
+        let path = runner.world.layers[0].evicted[&chunk].path.clone();
+        fs::remove_file(&path).unwrap();
+        runner.inject_signal(0, 0, 0, DIR_RIGHT, 5, 0);
+        runner.inject_signal(100, 0, 0, DIR_RIGHT, 6, 0);
+        runner.tick();
+        assert!(runner.world.layers[0].evicted.contains_key(&chunk));
+        assert!(runner.world.layers[0].get_loaded(&chunk).is_err());
+        assert!(runner.world.reload_all_chunks().is_err());
+        // editing it fails instead of losing the blocks which are still in its file
+        let layer = &mut runner.world.layers[0];
+        assert!(layer.try_push_block(0, 0, Block::Color(3)).is_err());
+        assert!(layer.try_pop_block(0, 0).is_err());
+        assert!(layer.try_get_mut(&chunk).is_err());
+        assert!(layer.evicted.contains_key(&chunk));
+        // the chunk whose file is still there was changed as usual (and evicted again)
+        let (other, inner) = runner.world.layers[0].get_where(100, 0);
+        let other = runner.world.layers[0].get_loaded(&other).unwrap().unwrap();
+        assert_eq!(other[inner as usize], [Block::Color(6)]);
+        drop(runner);
+        _ = fs::remove_dir_all(&dir);
+    }
+
+    #[test]
+    fn evicted_chunks_are_read_without_loading_them() {
+        let dir = temp_dir("read");
+        fs::create_dir_all(&dir).unwrap();
+        let original = busy_world();
+        let mut world = busy_world();
+        for (i, layer) in world.layers.iter_mut().enumerate() {
+            for key in layer.chunk_keys().collect::<Vec<_>>() {
+                layer
+                    .evict(key, dir.join(format!("{i}_{key:016x}")))
+                    .unwrap();
+            }
+        }
+        assert_eq!(loaded(&world), 0);
+        assert!(world.iter_blocks().is_err());
+        assert_eq!(world.to_json().unwrap(), original.to_json().unwrap());
+        assert_eq!(world.diff(&original).unwrap(), []);
+        assert_eq!(world.extents().unwrap(), original.extents().unwrap());
+        let (stats, expected) = (world.stats().unwrap(), original.stats().unwrap());
+        assert_eq!(stats.blocks, expected.blocks);
+        assert_eq!(stats.chunks, expected.chunks);
+        assert_eq!(
+            thumbnail::render(&world.layers[0]).unwrap(),
+            thumbnail::render(&original.layers[0]).unwrap()
+        );
+        let mut merged = World::new_empty();
+        merged.try_merge(&world, 0, 0, 0).unwrap();
+        assert_eq!(blocks(&merged), blocks(&original));
+        assert_eq!(loaded(&world), 0);
+        drop(world);
+        _ = fs::remove_dir_all(&dir);
+    }
+}
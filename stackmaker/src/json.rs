@@ -0,0 +1,663 @@
+//! A human-readable alternative to the binary save format, mostly for debugging and sharing small worlds.
+//! Only the subset of JSON needed for worlds is supported: numbers must be integers.
+
+use std::collections::VecDeque;
+
+use crate::world::{
+    chunk_key, chunk_pos, create_empty_chunk, Block, ChunkMap, Layer, World, LAYER_COUNT,
+    LAYER_MASK,
+};
+
+#[derive(Clone, Debug, PartialEq)]
+pub enum Json {
+    Null,
+    Bool(bool),
+    Int(i128),
+    Str(String),
+    Array(Vec<Json>),
+    Object(Vec<(String, Json)>),
+}
+
+#[derive(Debug)]
+pub struct JsonError(pub String);
+
+impl std::fmt::Display for JsonError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "invalid world json: {}", self.0)
+    }
+}
+impl std::error::Error for JsonError {}
+
+impl World {
+    /// Layers only contain non-empty chunks and non-empty stacks, stacks are listed bottom to top.
+    /// Evicted chunks are read from their files, see `Layer::read_chunk`.
+    pub fn to_json(&self) -> std::io::Result<String> {
+        let mut layers = Vec::with_capacity(self.layers.len());
+        for layer in self.layers.iter() {
+            let mut keys: Vec<u64> = layer.chunk_keys().collect();
+            keys.sort_unstable_by_key(|key| chunk_pos(*key));
+            let mut chunks = vec![];
+            for key in keys {
+                let Some(chunk) = layer.read_chunk(&key)? else {
+                    continue;
+                };
+                if chunk.iter().all(|blocks| blocks.is_empty()) {
+                    continue;
+                }
+                let (x, y) = chunk_pos(key);
+                let stacks = chunk
+                    .iter()
+                    .enumerate()
+                    .filter(|(_, blocks)| !blocks.is_empty())
+                    .map(|(pos, blocks)| {
+                        obj(vec![
+                            ("pos", Json::Int(pos as _)),
+                            (
+                                "blocks",
+                                Json::Array(blocks.iter().map(block_to_json).collect()),
+                            ),
+                        ])
+                    })
+                    .collect();
+                chunks.push(obj(vec![
+                    ("x", Json::Int(x as _)),
+                    ("y", Json::Int(y as _)),
+                    ("stacks", Json::Array(stacks)),
+                ]));
+            }
+            layers.push(Json::Array(chunks));
+        }
+        let signals = self
+            .signals_queue
+            .iter()
+            .map(|signals| {
+                Json::Array(
+                    signals
+                        .iter()
+                        .map(|(signal, dir_layer, chunk, pos)| {
+                            let (x, y) = chunk_pos(*chunk);
+                            obj(vec![
+                                ("value", Json::Int(*signal as _)),
+                                ("dir", Json::Int((dir_layer & 0b11100000) as _)),
+                                ("layer", Json::Int((dir_layer & LAYER_MASK) as _)),
+                                ("chunk_x", Json::Int(x as _)),
+                                ("chunk_y", Json::Int(y as _)),
+                                ("pos", Json::Int(*pos as _)),
+                            ])
+                        })
+                        .collect(),
+                )
+            })
+            .collect();
+        let mut out = String::new();
+        obj(vec![
+            ("layers", Json::Array(layers)),
+            ("signals", Json::Array(signals)),
+            ("wrap_layers", Json::Bool(self.wrap_layers)),
+        ])
+        .write(&mut out, 0);
+        Ok(out)
+    }
+    pub fn from_json(src: &str) -> Result<Self, JsonError> {
+        let json = Json::parse(src)?;
+        let mut world = Self::new_empty();
+        // optional, so files from before it was added can still be loaded
+        if let Ok(wrap_layers) = json.get("wrap_layers") {
+            world.wrap_layers = wrap_layers.as_bool()?;
+        }
+        let layers = json.get("layers")?.as_array()?;
+        if layers.len() > world.layers.len() {
+            return Err(JsonError(format!("too many layers ({})", layers.len())));
+        }
+        for (layer, layer_json) in world.layers.iter_mut().zip(layers) {
+            let mut chunks = ChunkMap::default();
+            for chunk_json in layer_json.as_array()? {
+                let mut chunk = create_empty_chunk();
+                for stack in chunk_json.get("stacks")?.as_array()? {
+                    let pos: u8 = stack.get("pos")?.as_int()?;
+                    chunk[pos as usize] = stack
+                        .get("blocks")?
+                        .as_array()?
+                        .iter()
+                        .map(block_from_json)
+                        .collect::<Result<_, _>>()?;
+                }
+                let key = chunk_key(
+                    chunk_json.get("x")?.as_int()?,
+                    chunk_json.get("y")?.as_int()?,
+                );
+                chunks.insert(key, chunk);
+            }
+            *layer = Layer::loaded(chunks);
+            // unlike a layer loaded from save files, it hasn't been saved yet
+            layer.mark_dirty();
+        }
+        world.signals_queue = json
+            .get("signals")?
+            .as_array()?
+            .iter()
+            .map(|signals| {
+                signals
+                    .as_array()?
+                    .iter()
+                    .map(|signal| {
+                        let dir: u8 = signal.get("dir")?.as_int()?;
+                        let layer: u8 = signal.get("layer")?.as_int()?;
+                        if dir & LAYER_MASK != 0 || layer as usize >= LAYER_COUNT {
+                            return Err(JsonError(format!(
+                                "invalid signal dir {dir} / layer {layer}"
+                            )));
+                        }
+                        Ok((
+                            signal.get("value")?.as_int()?,
+                            dir | layer,
+                            chunk_key(
+                                signal.get("chunk_x")?.as_int()?,
+                                signal.get("chunk_y")?.as_int()?,
+                            ),
+                            signal.get("pos")?.as_int()?,
+                        ))
+                    })
+                    .collect()
+            })
+            .collect::<Result<VecDeque<_>, _>>()?;
+        Ok(world)
+    }
+}
+
+fn obj(fields: Vec<(&str, Json)>) -> Json {
+    Json::Object(fields.into_iter().map(|(k, v)| (k.to_owned(), v)).collect())
+}
+
+fn block_to_json(block: &Block) -> Json {
+    let t = |name: &str| ("type", Json::Str(name.to_owned()));
+    let int = |name, v: u32| (name, Json::Int(v as _));
+    obj(match block {
+        Block::Delay(ticks, dir) => vec![t("delay"), int("ticks", *ticks), int("dir", *dir as _)],
+        Block::Splitter(dir) => vec![t("splitter"), int("dir", *dir as _)],
+        Block::Fan(dirs) => vec![t("fan"), int("dirs", *dirs as _)],
+        Block::Color(c) => vec![t("color"), int("value", *c)],
+        Block::Char(c) => vec![t("char"), int("value", *c)],
+        Block::Storage(value, mode, dir) => vec![
+            t("storage"),
+            int("value", *value),
+            int("mode", *mode as _),
+            int("dir", *dir as _),
+        ],
+        Block::Gate(open, dir) => vec![
+            t("gate"),
+            ("open", Json::Bool(*open)),
+            int("dir", *dir as _),
+        ],
+        Block::Clock(period, phase) => {
+            vec![t("clock"), int("period", *period), int("phase", *phase)]
+        }
+        Block::Compare(threshold, op, dir) => vec![
+            t("compare"),
+            int("threshold", *threshold),
+            int("op", *op as _),
+            int("dir", *dir as _),
+        ],
+        Block::Not(dir) => vec![t("not"), int("dir", *dir as _)],
+        Block::Probe(dir) => vec![t("probe"), int("dir", *dir as _)],
+        Block::Random(state) => vec![t("random"), ("state", Json::Int(*state as _))],
+        Block::Counter(count, dir) => {
+            vec![t("counter"), int("count", *count), int("dir", *dir as _)]
+        }
+        Block::Height(dir) => vec![t("height"), int("dir", *dir as _)],
+        Block::Move(dir) => vec![t("move"), int("dir", *dir as _)],
+        Block::Swap(dir) => vec![t("swap"), int("dir", *dir as _)],
+        Block::SwapAll(dir) => vec![t("swap_all"), int("dir", *dir as _)],
+        Block::Duplicate(dir) => vec![t("duplicate"), int("dir", *dir as _)],
+        Block::Destroy(dir) => vec![t("destroy"), int("dir", *dir as _)],
+    })
+}
+
+fn block_from_json(json: &Json) -> Result<Block, JsonError> {
+    Ok(match json.get("type")?.as_str()? {
+        "delay" => Block::Delay(json.get_int("ticks")?, json.get_int("dir")?),
+        "splitter" => Block::Splitter(json.get_int("dir")?),
+        "fan" => Block::Fan(json.get_int("dirs")?),
+        "color" => Block::Color(json.get_int("value")?),
+        "char" => Block::Char(json.get_int("value")?),
+        "storage" => Block::Storage(
+            json.get_int("value")?,
+            json.get_int("mode")?,
+            json.get_int("dir")?,
+        ),
+        "gate" => Block::Gate(json.get("open")?.as_bool()?, json.get_int("dir")?),
+        "clock" => Block::Clock(json.get_int("period")?, json.get_int("phase")?),
+        "compare" => Block::Compare(
+            json.get_int("threshold")?,
+            json.get_int("op")?,
+            json.get_int("dir")?,
+        ),
+        "not" => Block::Not(json.get_int("dir")?),
+        "probe" => Block::Probe(json.get_int("dir")?),
+        "random" => Block::Random(json.get_int("state")?),
+        "counter" => Block::Counter(json.get_int("count")?, json.get_int("dir")?),
+        "height" => Block::Height(json.get_int("dir")?),
+        "move" => Block::Move(json.get_int("dir")?),
+        "swap" => Block::Swap(json.get_int("dir")?),
+        "swap_all" => Block::SwapAll(json.get_int("dir")?),
+        "duplicate" => Block::Duplicate(json.get_int("dir")?),
+        "destroy" => Block::Destroy(json.get_int("dir")?),
+        other => return Err(JsonError(format!("unknown block type '{other}'"))),
+    })
+}
+
+impl Json {
+    pub fn get(&self, key: &str) -> Result<&Self, JsonError> {
+        if let Self::Object(fields) = self {
+            if let Some((_, v)) = fields.iter().find(|(k, _)| k == key) {
+                return Ok(v);
+            }
+        }
+        Err(JsonError(format!("missing field '{key}'")))
+    }
+    pub fn get_int<T: TryFrom<i128>>(&self, key: &str) -> Result<T, JsonError> {
+        self.get(key)?.as_int()
+    }
+    pub fn as_int<T: TryFrom<i128>>(&self) -> Result<T, JsonError> {
+        if let Self::Int(v) = self {
+            if let Ok(v) = T::try_from(*v) {
+                return Ok(v);
+            }
+        }
+        Err(JsonError(format!(
+            "expected an integer in range, found {self:?}"
+        )))
+    }
+    pub fn as_bool(&self) -> Result<bool, JsonError> {
+        if let Self::Bool(v) = self {
+            Ok(*v)
+        } else {
+            Err(JsonError(format!("expected a bool, found {self:?}")))
+        }
+    }
+    pub fn as_str(&self) -> Result<&str, JsonError> {
+        if let Self::Str(v) = self {
+            Ok(v)
+        } else {
+            Err(JsonError(format!("expected a string, found {self:?}")))
+        }
+    }
+    pub fn as_array(&self) -> Result<&Vec<Self>, JsonError> {
+        if let Self::Array(v) = self {
+            Ok(v)
+        } else {
+            Err(JsonError(format!("expected an array, found {self:?}")))
+        }
+    }
+
+    /// arrays and objects which don't contain other arrays or objects are kept on one line
+    pub fn write(&self, out: &mut String, indent: usize) {
+        fn nested<'a>(mut items: impl Iterator<Item = &'a Json>) -> bool {
+            items.any(|v| matches!(v, Json::Array(_) | Json::Object(_)))
+        }
+        match self {
+            Self::Null => out.push_str("null"),
+            Self::Bool(v) => out.push_str(if *v { "true" } else { "false" }),
+            Self::Int(v) => out.push_str(&v.to_string()),
+            Self::Str(v) => write_str(out, v),
+            Self::Array(items) => {
+                let multiline = nested(items.iter());
+                out.push('[');
+                for (i, item) in items.iter().enumerate() {
+                    if i > 0 {
+                        out.push(',');
+                    }
+                    newline(out, multiline, indent + 1);
+                    item.write(out, indent + 1);
+                }
+                if !items.is_empty() {
+                    newline(out, multiline, indent);
+                }
+                out.push(']');
+            }
+            Self::Object(fields) => {
+                let multiline = nested(fields.iter().map(|(_, v)| v));
+                out.push('{');
+                for (i, (key, value)) in fields.iter().enumerate() {
+                    if i > 0 {
+                        out.push(',');
+                    }
+                    newline(out, multiline, indent + 1);
+                    write_str(out, key);
+                    out.push(':');
+                    value.write(out, indent + 1);
+                }
+                if !fields.is_empty() {
+                    newline(out, multiline, indent);
+                }
+                out.push('}');
+            }
+        }
+    }
+
+    pub fn parse(src: &str) -> Result<Self, JsonError> {
+        let mut parser = Parser {
+            src: src.as_bytes(),
+            pos: 0,
+            depth: 0,
+        };
+        let v = parser.value()?;
+        parser.whitespace();
+        if parser.pos < parser.src.len() {
+            return Err(parser.err("trailing characters"));
+        }
+        Ok(v)
+    }
+}
+
+fn newline(out: &mut String, multiline: bool, indent: usize) {
+    if multiline {
+        out.push('\n');
+        for _ in 0..indent {
+            out.push_str("  ");
+        }
+    }
+}
+
+fn write_str(out: &mut String, s: &str) {
+    out.push('"');
+    for c in s.chars() {
+        match c {
+            '"' => out.push_str("\\\""),
+            '\\' => out.push_str("\\\\"),
+            '\n' => out.push_str("\\n"),
+            c if (c as u32) < 0x20 => out.push_str(&format!("\\u{:04x}", c as u32)),
+            c => out.push(c),
+        }
+    }
+    out.push('"');
+}
+
+/// How deeply arrays and objects can be nested in `Json::parse`. Worlds only need a few levels,
+/// but without a limit, a file starting with thousands of `[` would overflow the stack.
+pub const MAX_DEPTH: usize = 128;
+
+struct Parser<'a> {
+    src: &'a [u8],
+    pos: usize,
+    /// how many arrays and objects contain the current value
+    depth: usize,
+}
+impl Parser<'_> {
+    fn err(&self, msg: &str) -> JsonError {
+        JsonError(format!("{msg} at byte {}", self.pos))
+    }
+    fn whitespace(&mut self) {
+        while self
+            .src
+            .get(self.pos)
+            .is_some_and(|c| c.is_ascii_whitespace())
+        {
+            self.pos += 1;
+        }
+    }
+    /// skips whitespace, then consumes `c` if it is the next character
+    fn eat(&mut self, c: u8) -> bool {
+        self.whitespace();
+        if self.src.get(self.pos) == Some(&c) {
+            self.pos += 1;
+            true
+        } else {
+            false
+        }
+    }
+    /// consumes the `[` or `{` starting an array or object
+    fn enter(&mut self) -> Result<(), JsonError> {
+        if self.depth >= MAX_DEPTH {
+            return Err(self.err("nested too deeply"));
+        }
+        self.depth += 1;
+        self.pos += 1;
+        Ok(())
+    }
+    fn keyword(&mut self, word: &str, v: Json) -> Result<Json, JsonError> {
+        if self.src[self.pos..].starts_with(word.as_bytes()) {
+            self.pos += word.len();
+            Ok(v)
+        } else {
+            Err(self.err("unexpected character"))
+        }
+    }
+    fn value(&mut self) -> Result<Json, JsonError> {
+        self.whitespace();
+        match self.src.get(self.pos) {
+            None => Err(self.err("unexpected end of input")),
+            Some(b'n') => self.keyword("null", Json::Null),
+            Some(b't') => self.keyword("true", Json::Bool(true)),
+            Some(b'f') => self.keyword("false", Json::Bool(false)),
+            Some(b'"') => Ok(Json::Str(self.string()?)),
+            Some(b'[') => {
+                self.enter()?;
+                let mut items = vec![];
+                if !self.eat(b']') {
+                    loop {
+                        items.push(self.value()?);
+                        if self.eat(b']') {
+                            break;
+                        }
+                        if !self.eat(b',') {
+                            return Err(self.err("expected ',' or ']'"));
+                        }
+                    }
+                }
+                self.depth -= 1;
+                Ok(Json::Array(items))
+            }
+            Some(b'{') => {
+                self.enter()?;
+                let mut fields = vec![];
+                if !self.eat(b'}') {
+                    loop {
+                        self.whitespace();
+                        let key = self.string()?;
+                        if !self.eat(b':') {
+                            return Err(self.err("expected ':'"));
+                        }
+                        fields.push((key, self.value()?));
+                        if self.eat(b'}') {
+                            break;
+                        }
+                        if !self.eat(b',') {
+                            return Err(self.err("expected ',' or '}'"));
+                        }
+                    }
+                }
+                self.depth -= 1;
+                Ok(Json::Object(fields))
+            }
+            Some(_) => {
+                let start = self.pos;
+                if self.src[self.pos] == b'-' {
+                    self.pos += 1;
+                }
+                while self.src.get(self.pos).is_some_and(|c| c.is_ascii_digit()) {
+                    self.pos += 1;
+                }
+                std::str::from_utf8(&self.src[start..self.pos])
+                    .ok()
+                    .and_then(|v| v.parse().ok())
+                    .map(Json::Int)
+                    .ok_or_else(|| self.err("expected an integer"))
+            }
+        }
+    }
+    fn string(&mut self) -> Result<String, JsonError> {
+        if self.src.get(self.pos) != Some(&b'"') {
+            return Err(self.err("expected a string"));
+        }
+        self.pos += 1;
+        let mut out = vec![];
+        loop {
+            match self.src.get(self.pos) {
+                None => return Err(self.err("unterminated string")),
+                Some(b'"') => break,
+                Some(b'\\') => {
+                    self.pos += 1;
+                    let c = match self.src.get(self.pos) {
+                        Some(b'n') => '\n',
+                        Some(b't') => '\t',
+                        Some(b'r') => '\r',
+                        Some(b'b') => '\u{8}',
+                        Some(b'f') => '\u{c}',
+                        Some(b'u') => {
+                            let hex = self
+                                .src
+                                .get(self.pos + 1..self.pos + 5)
+                                .and_then(|v| std::str::from_utf8(v).ok())
+                                .and_then(|v| u32::from_str_radix(v, 16).ok())
+                                .and_then(char::from_u32)
+                                .ok_or_else(|| self.err("invalid unicode escape"))?;
+                            self.pos += 4;
+                            hex
+                        }
+                        Some(c) => *c as char,
+                        None => return Err(self.err("unterminated string")),
+                    };
+                    out.extend_from_slice(c.encode_utf8(&mut [0; 4]).as_bytes());
+                }
+                Some(c) => out.push(*c),
+            }
+            self.pos += 1;
+        }
+        self.pos += 1;
+        String::from_utf8(out).map_err(|_| self.err("invalid utf-8"))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::runner::{DIR_DOWN, DIR_LEFT, DIR_RIGHT, DIR_UP, DIR_UP_L, GATE_INVERTED};
+
+    fn roundtrip(v: &Json) -> Json {
+        let mut out = String::new();
+        v.write(&mut out, 0);
+        Json::parse(&out).unwrap_or_else(|e| panic!("{e} in {out}"))
+    }
+
+    #[test]
+    fn values_roundtrip() {
+        let v = Json::Object(vec![
+            ("null".to_owned(), Json::Null),
+            (
+                "bools".to_owned(),
+                Json::Array(vec![Json::Bool(true), Json::Bool(false)]),
+            ),
+            (
+                "ints".to_owned(),
+                Json::Array(vec![Json::Int(0), Json::Int(-12), Json::Int(u64::MAX as _)]),
+            ),
+            (
+                "str".to_owned(),
+                Json::Str("\"quoted\"\\ line\nbreak \t tab \u{1} ünïcödé".to_owned()),
+            ),
+            ("empty".to_owned(), Json::Object(vec![])),
+            (
+                "nested".to_owned(),
+                Json::Array(vec![Json::Array(vec![]), Json::Array(vec![Json::Null])]),
+            ),
+        ]);
+        assert_eq!(roundtrip(&v), v);
+        assert_eq!(
+            Json::parse(" { \"a\" : [ 1 , \"\\u0041\\/\" ] } ").unwrap(),
+            Json::Object(vec![(
+                "a".to_owned(),
+                Json::Array(vec![Json::Int(1), Json::Str("A/".to_owned())])
+            )])
+        );
+    }
+
+    #[test]
+    fn malformed_input_is_rejected() {
+        for src in [
+            "",
+            "[",
+            "[1,]",
+            "[1 2]",
+            "{\"a\" 1}",
+            "{\"a\":1,}",
+            "{1:2}",
+            "\"unterminated",
+            "\"\\u12\"",
+            "nul",
+            "1.5",
+            "-",
+            "[] []",
+            "\"\\",
+        ] {
+            assert!(Json::parse(src).is_err(), "{src:?} was accepted");
+        }
+    }
+
+    #[test]
+    fn nesting_is_limited() {
+        let nested = |depth| "[".repeat(depth) + &"]".repeat(depth);
+        assert!(Json::parse(&nested(MAX_DEPTH)).is_ok());
+        assert!(Json::parse(&nested(MAX_DEPTH + 1)).is_err());
+        // would overflow the stack without the limit
+        assert!(Json::parse(&"[{\"a\":".repeat(1_000_000)).is_err());
+        assert!(World::from_json(&"[".repeat(1_000_000)).is_err());
+    }
+
+    #[test]
+    fn worlds_roundtrip() {
+        let every_block = [
+            Block::Delay(3, DIR_RIGHT | 1),
+            Block::Splitter(DIR_LEFT),
+            Block::Fan(DIR_UP),
+            Block::Clock(10, 4),
+            Block::Color(0xFF00FF00),
+            Block::Char('ß' as u32),
+            Block::Storage(u32::MAX, 16, DIR_UP_L),
+            Block::Gate(true, DIR_RIGHT | GATE_INVERTED),
+            Block::Gate(false, DIR_LEFT),
+            Block::Compare(7, 2, DIR_UP),
+            Block::Not(DIR_DOWN),
+            Block::Probe(DIR_RIGHT),
+            Block::Random(u64::MAX),
+            Block::Counter(2, DIR_LEFT),
+            Block::Height(DIR_UP),
+            Block::Move(DIR_DOWN),
+            Block::Swap(DIR_RIGHT),
+            Block::SwapAll(DIR_LEFT),
+            Block::Duplicate(DIR_UP),
+            Block::Destroy(DIR_DOWN),
+        ];
+        let mut world = World::new_empty();
+        for (i, block) in every_block.iter().enumerate() {
+            let i = i as i64;
+            world.layers[i as usize % LAYER_COUNT]
+                .try_push_block(i * 5 - 40, -i * 9, block.clone())
+                .unwrap();
+            world.layers[3]
+                .try_push_block(-1, -1, block.clone())
+                .unwrap();
+        }
+        world
+            .signals_mut(2)
+            .push((u32::MAX, DIR_LEFT | 31, chunk_key(-1, i32::MAX), 255));
+        world
+            .signals_mut(2)
+            .push((0, DIR_UP, chunk_key(i32::MIN, 0), 0));
+        world.wrap_layers = true;
+        let loaded = World::from_json(&world.to_json().unwrap()).unwrap();
+        let blocks = |world: &World| {
+            let mut blocks: Vec<_> = world
+                .iter_blocks()
+                .unwrap()
+                .map(|(layer, x, y, stack)| (layer, x, y, stack.clone()))
+                .collect();
+            blocks.sort_by_key(|&(layer, x, y, _)| (layer, x, y));
+            blocks
+        };
+        assert_eq!(blocks(&loaded), blocks(&world));
+        assert_eq!(blocks(&world).len(), every_block.len() + 1);
+        assert_eq!(loaded.signals_queue, world.signals_queue);
+        assert!(loaded.wrap_layers);
+    }
+}
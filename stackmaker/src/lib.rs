@@ -1,2 +1,9 @@
+pub mod compression;
+pub mod diff;
+pub mod eviction;
+pub mod json;
 pub mod runner;
+pub mod schematic;
+pub mod stats;
+pub mod thumbnail;
 pub mod world;
@@ -1,15 +1,98 @@
-use crate::world::{Block, World};
+//! Runs the simulation: signals travel between blocks and are processed once per tick.
+//!
+//! Signal directions are stored in the same byte as the layer: the top 3 bits are the direction, the low 5 bits (`LAYER_MASK`) the layer.
+//! There are six directions, and inverting all 3 bits (`dir_rev`) gives the opposite direction:
+//!
+//! | bits  | direction                      |
+//! |-------|--------------------------------|
+//! | `001` | up one layer (`DIR_UP_L`)      |
+//! | `110` | down one layer (`DIR_DOWN_L`)  |
+//! | `100` | left (`DIR_LEFT`)              |
+//! | `011` | right (`DIR_RIGHT`)            |
+//! | `010` | up (`DIR_UP`)                  |
+//! | `101` | down (`DIR_DOWN`)              |
+//!
+//! `000` and `111` are not valid directions.
+//! Blocks store their direction in the same way, leaving the layer bits for other uses.
+
+#[cfg(feature = "rayon")]
+use std::collections::HashMap;
+use std::{collections::VecDeque, path::PathBuf};
+
+#[cfg(feature = "rayon")]
+use crate::world::create_empty_chunk;
+use crate::{
+    compression::Compression,
+    eviction::ChunkLimit,
+    world::{Block, Layer, World, LAYER_COUNT, LAYER_MASK},
+};
+
+/// The default `Runner::max_value_delay`. Like every delay, it is also limited by `world::MAX_DELAY`.
+pub const DEFAULT_MAX_VALUE_DELAY: u32 = 1024;
+
+/// How many records `Runner::probe_log` keeps. Once it is full, the oldest record is dropped for every new one.
+pub const PROBE_LOG_LEN: usize = 1024;
 
 pub struct Runner {
     pub world: World,
     /// 0 = don't autosave, otherwise save once for every n ticks.
-    /// first field saves to /tmp,
-    /// second field saves to the actual save on disk.
+    /// autosaves are written on a background thread, see `wait_for_autosave`.
+    /// first field saves to the system's temp directory (see `temp_save_dir`),
+    /// second field saves to the actual save on disk (`save_path`).
     pub autosave: (u64, u64),
+    /// where the second autosave field saves to, nothing is saved there if this is `None`.
+    /// `new` sets it to `world.save_dir`. if it is another directory, the whole world is written to it every time.
+    pub save_path: Option<PathBuf>,
     autosave_elapsed: (u64, u64),
+    /// the autosave which is still being written in the background, if there is one
+    autosave_job: Option<AutosaveJob>,
+    /// how many ticks this runner has performed
+    pub tick_counter: u64,
+    /// if true, `tick` returns the chunks it modified
+    pub track_changes: bool,
+    /// the longest delay of a Delay block with `DELAY_BY_VALUE` set, larger signal values wait this long instead.
+    /// without a limit, a single large signal would create billions of empty signal buffers.
+    pub max_value_delay: u32,
+    /// if set, chunks which weren't used for a while are moved from memory to disk, see the `eviction` module.
+    pub chunk_limit: Option<ChunkLimit>,
+    /// how many threads `tick_parallel` uses at most. 0 uses as many as the system can run in parallel.
+    #[cfg(feature = "rayon")]
+    pub threads: usize,
+    probe_log: VecDeque<ProbeRecord>,
 }
 
-pub enum Changes {}
+impl Drop for Runner {
+    /// the process could otherwise exit while an autosave is only partially written
+    fn drop(&mut self) {
+        self.wait_for_autosave();
+    }
+}
+
+/// An autosave which is being written on its own thread, see `Runner::autosave`.
+struct AutosaveJob {
+    /// fails if the save to `save_path` failed
+    handle: std::thread::JoinHandle<Result<(), std::io::Error>>,
+    /// the layers which were dirty when the autosave started, as a bitmask
+    layers: u32,
+}
+
+/// A signal which passed through a `Block::Probe`.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct ProbeRecord {
+    /// the `tick_counter` of the tick in which the signal reached the probe
+    pub tick: u64,
+    pub layer: usize,
+    pub x: i64,
+    pub y: i64,
+    pub value: u32,
+}
+
+/// What a tick changed, returned by `Runner::tick` if `track_changes` is enabled.
+#[derive(Clone, Debug, Default)]
+pub struct Changes {
+    /// (layer, chunk) for every chunk in which a stack was modified. Sorted, without duplicates.
+    pub modified: Vec<(usize, u64)>,
+}
 
 impl Runner {
     pub fn new(mut world: World) -> Self {
@@ -18,208 +101,1046 @@ impl Runner {
             world.signals_queue.push_back(vec![]);
         }
         Self {
+            save_path: world.save_dir.clone(),
             world,
             autosave: (0, 0),
             autosave_elapsed: (0, 0),
+            autosave_job: None,
+            tick_counter: 0,
+            track_changes: false,
+            max_value_delay: DEFAULT_MAX_VALUE_DELAY,
+            chunk_limit: None,
+            #[cfg(feature = "rayon")]
+            threads: 0,
+            probe_log: VecDeque::new(),
+        }
+    }
+    /// the signals which passed through probes recently, oldest first. at most `PROBE_LOG_LEN` are kept.
+    pub fn probe_log(&self) -> &VecDeque<ProbeRecord> {
+        &self.probe_log
+    }
+    pub fn clear_probe_log(&mut self) {
+        self.probe_log.clear();
+    }
+    /// where the first autosave field saves to
+    pub fn temp_save_dir() -> PathBuf {
+        std::env::temp_dir().join("stackmaker-temp-save")
+    }
+    /// how many signals are waiting to be processed, in this tick and all future ones
+    pub fn pending_signals(&self) -> usize {
+        self.world.signals_queue.iter().map(|v| v.len()).sum()
+    }
+    /// true if no signals are waiting to be processed, so ticking won't change anything
+    pub fn is_idle(&self) -> bool {
+        self.world.signals_queue.iter().all(|v| v.is_empty())
+    }
+    /// queues a signal with the given value which arrives at the block at (x, y) on `layer`
+    /// in `delay + 1` ticks, as if it was moving in direction `dir` (one of the `DIR_` constants).
+    /// returns false (and queues nothing) if there is no such layer.
+    pub fn inject_signal(
+        &mut self,
+        x: i64,
+        y: i64,
+        layer: u8,
+        dir: u8,
+        value: u32,
+        delay: usize,
+    ) -> bool {
+        let Some(target) = self.world.layers.get(layer as usize) else {
+            return false;
+        };
+        let (chunk, inner) = target.get_where(x, y);
+        self.world
+            .signals_mut(delay)
+            .push((value, (dir & 0b11100000) | layer, chunk, inner));
+        true
+    }
+    /// ticks until `is_idle` or until `max_ticks` ticks were performed, whichever comes first,
+    /// so that contraptions that never settle can't loop forever.
+    /// returns how many ticks were performed.
+    /// like `is_idle`, this only looks at queued signals, so it doesn't wait for a clock's next output.
+    pub fn step_until_stable(&mut self, max_ticks: u64) -> u64 {
+        let mut ticks = 0;
+        while ticks < max_ticks && !self.is_idle() {
+            self.tick();
+            ticks += 1;
+        }
+        ticks
+    }
+    /// performs `n` ticks, exactly like calling `tick` `n` times.
+    /// changes are not reported, even if `track_changes` is enabled.
+    pub fn tick_n(&mut self, n: u64) {
+        for _ in 0..n {
+            self.tick();
+        }
+    }
+    /// performs one tick. returns `None` unless `track_changes` is enabled.
+    /// the signals of this tick are processed in the order they were pushed to the queue,
+    /// and signals emitted while processing them are appended to the next tick's signals in the same order.
+    pub fn tick(&mut self) -> Option<Changes> {
+        let mut signals = self.start_tick();
+        let modified = self.process_signals(&mut signals);
+        self.end_tick(signals, modified)
+    }
+    /// processes and removes all of the signals, returning the modified chunks if `track_changes` is enabled
+    fn process_signals(&mut self, signals: &mut Vec<(u32, u8, u64, u8)>) -> Vec<(usize, u64)> {
+        let track_changes = self.track_changes;
+        let wrap_layers = self.world.wrap_layers;
+        let max_value_delay = self.max_value_delay;
+        let mut modified = vec![];
+        let mut dirty = 0u32;
+        let mut changed = |layer: u8, chunk: u64| {
+            dirty |= 1 << (layer & LAYER_MASK);
+            if track_changes {
+                modified.push(((layer & LAYER_MASK) as usize, chunk));
+            }
+        };
+        let tick = self.tick_counter;
+        let probe_log = &mut self.probe_log;
+        let mut probed = |signal| log_probe(probe_log, tick, signal);
+        for signal in signals.drain(..) {
+            process_signal(
+                &mut self.world,
+                signal,
+                wrap_layers,
+                max_value_delay,
+                &mut changed,
+                &mut probed,
+            );
         }
+        mark_dirty(&self.world, dirty);
+        modified
     }
-    pub fn tick(&mut self) {
+    /// the parts of a tick which happen before the signals are processed. returns this tick's signals.
+    fn start_tick(&mut self) -> Vec<(u32, u8, u64, u8)> {
+        self.tick_counter += 1;
+        let mut save_temp = false;
         if self.autosave.0 > 0 {
             self.autosave_elapsed.0 += 1;
-            if self.autosave_elapsed.0 >= self.autosave.0 {
-                // autosave to /tmp
-                eprintln!("[info] autosaving to /tmp/stackmaker-temp-save");
-                match self.world.save_to_dir("/tmp/stackmaker-temp-save") {
-                    Ok(()) => {
-                        self.autosave_elapsed.0 = 0;
-                    }
-                    Err(e) => {
-                        eprintln!("[warn] Couldn't save world to /tmp/stackmaker-temp-save: {e}");
-                    }
-                }
-            }
+            save_temp = self.autosave_elapsed.0 >= self.autosave.0;
         }
+        let mut save_file = false;
         if self.autosave.1 > 0 {
             self.autosave_elapsed.1 += 1;
-            if self.autosave_elapsed.1 >= self.autosave.1 {
-                // autosave to file
-                if let Some(dir) = &self.world.save_dir {
-                    eprintln!("[info] saving to {dir:?}");
-                    match self.world.save_to_dir(dir) {
-                        Ok(()) => {
-                            self.autosave_elapsed.1 = 0;
-                        }
-                        Err(e) => {
-                            eprintln!("[warn] Couldn't save world to {dir:?}: {e}");
-                        }
-                    }
+            save_file = self.autosave_elapsed.1 >= self.autosave.1 && self.save_path.is_some();
+        }
+        // if the previous autosave is still being written, try again on the next tick
+        if (save_temp || save_file) && !self.autosave_busy() {
+            // if saving fails, try again after another interval
+            if save_temp {
+                self.autosave_elapsed.0 = 0;
+            }
+            if save_file {
+                self.autosave_elapsed.1 = 0;
+            }
+            self.start_autosave(save_temp, save_file);
+        }
+        // make sure the next tick's signals exist after this tick's are taken
+        self.world.signals_mut(1);
+        self.world.signals_queue.pop_front().unwrap()
+    }
+    /// true if an autosave is still being written. cleans up the previous autosave if it finished.
+    fn autosave_busy(&mut self) -> bool {
+        if self
+            .autosave_job
+            .as_ref()
+            .is_some_and(|job| !job.handle.is_finished())
+        {
+            return true;
+        }
+        self.wait_for_autosave();
+        false
+    }
+    /// copies the parts of the world which will be saved, then writes them on a new thread,
+    /// so the simulation can continue while the files are being written.
+    /// the temp dir may contain any world, so everything is written to it,
+    /// but the world's own directory already contains the layers which didn't change.
+    fn start_autosave(&mut self, save_temp: bool, save_file: bool) {
+        let file_dir = save_file.then(|| self.save_path.clone()).flatten();
+        let only_changed = file_dir.is_some() && file_dir == self.world.save_dir;
+        let world = if save_temp || !only_changed {
+            self.world.snapshot()
+        } else {
+            self.world.snapshot_changed()
+        };
+        let temp_dir = save_temp.then(Self::temp_save_dir);
+        // the snapshot has the changes now. if saving it fails, the layers are marked as dirty again.
+        // saves to other directories don't change which layers the world's own directory is missing.
+        let mut layers = 0;
+        if only_changed {
+            for (i, layer) in self.world.layers.iter().enumerate() {
+                if layer.is_dirty() {
+                    layers |= 1 << i;
+                    layer.mark_saved();
+                }
+            }
+        }
+        let handle = std::thread::spawn(move || {
+            if let Some(dir) = temp_dir {
+                eprintln!("[info] autosaving to {dir:?}");
+                if let Err(e) = world.save_files(&dir, Compression::None, false, false) {
+                    eprintln!("[warn] Couldn't save world to {dir:?}: {e}");
+                }
+            }
+            if let Some(dir) = file_dir {
+                eprintln!("[info] saving to {dir:?}");
+                let saved = if only_changed {
+                    world.save_changed_to_dir(&dir)
+                } else {
+                    world.save_files(&dir, Compression::None, false, false)
+                };
+                if let Err(e) = saved {
+                    eprintln!("[warn] Couldn't save world to {dir:?}: {e}");
+                    return Err(e);
                 }
             }
+            Ok(())
+        });
+        self.autosave_job = Some(AutosaveJob { handle, layers });
+    }
+    /// blocks until the autosave which is being written in the background, if there is one, is done.
+    /// call this before saving the world synchronously, so the two saves don't write the same files at the same time.
+    /// dropping the runner also waits for it.
+    pub fn wait_for_autosave(&mut self) {
+        if let Some(job) = self.autosave_job.take() {
+            if !matches!(job.handle.join(), Ok(Ok(()))) {
+                mark_dirty(&self.world, job.layers);
+            }
         }
-        if self.world.signals_queue.len() < 2 {
-            self.world.signals_queue.push_back(vec![]);
+    }
+    /// the parts of a tick which happen after the signals were processed
+    fn end_tick(
+        &mut self,
+        signals: Vec<(u32, u8, u64, u8)>,
+        mut modified: Vec<(usize, u64)>,
+    ) -> Option<Changes> {
+        self.world.recycle_signals(signals);
+        for e in self.world.chunk_errors.drain(..) {
+            eprintln!("[warn] Couldn't read an evicted chunk back, it is treated as empty space for now: {e}");
         }
-        for (signal, mut dir_layer, mut pos_chunk, mut pos_inner) in
-            self.world.signals_queue.pop_front().unwrap()
-        {
-            let chunk = self.world.layers[(dir_layer & 0b11111) as usize].get_mut(&pos_chunk);
-            if let Some(block) = chunk[pos_inner as usize].last_mut() {
-                match block {
-                    Block::Color(c) => *c = signal,
-                    Block::Char(c) => *c = signal,
-                    Block::Delay(how_long, direction) => {
-                        if is_side(*direction, dir_layer) {
-                            *how_long = signal;
-                        } else if pos_move(&mut dir_layer, &mut pos_chunk, &mut pos_inner) {
-                            let v = *how_long as _;
-                            self.world
-                                .signals_mut(v)
-                                .push((signal, dir_layer, pos_chunk, pos_inner));
+        self.tick_clocks();
+        if let Some(limit) = &mut self.chunk_limit {
+            limit.after_tick(&mut self.world, self.tick_counter);
+        }
+        if self.track_changes {
+            modified.sort_unstable();
+            modified.dedup();
+            Some(Changes { modified })
+        } else {
+            None
+        }
+    }
+    /// advances every clock which is at the top of its stack, emitting signals for the next tick.
+    /// clocks are emitted in the order (layer, chunk, position), so the order doesn't depend on the chunks' `HashMap` order.
+    /// only the stacks in `Layer::clocks` are looked at, so this doesn't take longer for larger worlds.
+    fn tick_clocks(&mut self) {
+        let wrap_layers = self.world.wrap_layers;
+        let mut emitted = vec![];
+        for (layer_index, layer) in self.world.layers.iter_mut().enumerate() {
+            layer.update_clocks();
+            let mut advanced = false;
+            let Layer {
+                chunks,
+                clocks,
+                clean,
+                ..
+            } = layer;
+            // in ascending order, so `emitted` is sorted
+            clocks.retain(|&(chunk_key, inner)| {
+                let Some(Block::Clock(period, phase)) = chunks
+                    .get_mut(&chunk_key)
+                    .and_then(|chunk| chunk[inner as usize].last_mut())
+                else {
+                    return false;
+                };
+                if *period == 0 {
+                    return true;
+                }
+                *phase += 1;
+                if *phase >= *period {
+                    *phase = 0;
+                    emitted.push((layer_index as u8, chunk_key, inner));
+                }
+                advanced = true;
+                // the chunk's file (if it was evicted before) is out of date now
+                if !clean.is_empty() {
+                    clean.remove(&chunk_key);
+                }
+                true
+            });
+            // the phases are saved, so the layer changed
+            if advanced {
+                layer.mark_dirty();
+            }
+        }
+        for (layer, chunk, inner) in emitted {
+            for direction in [DIR_LEFT, DIR_UP, DIR_DOWN, DIR_RIGHT] {
+                if let Some((dir_layer, pos_chunk, pos_inner)) =
+                    pos_moved(direction | layer, chunk, inner, wrap_layers)
+                {
+                    self.world.signals_queue[0].push((0, dir_layer, pos_chunk, pos_inner));
+                }
+            }
+        }
+    }
+}
+
+/// The stacks and signal queue which `process_signal` works on.
+/// The layer is always taken from the low 5 bits of `dir_layer`.
+trait Cells {
+    /// the topmost block of the stack, if there is one.
+    /// it may be modified, but not replaced by a different kind of block, because `pop` and `push` keep track of the clocks.
+    fn top_mut(&mut self, dir_layer: u8, chunk: u64, inner: u8) -> Option<&mut Block>;
+    fn pop(&mut self, dir_layer: u8, chunk: u64, inner: u8) -> Option<Block>;
+    fn push(&mut self, dir_layer: u8, chunk: u64, inner: u8, block: Block);
+    /// replaces the whole stack, returning the old one
+    fn replace_stack(
+        &mut self,
+        dir_layer: u8,
+        chunk: u64,
+        inner: u8,
+        stack: Vec<Block>,
+    ) -> Vec<Block>;
+    /// how many blocks are on the stack. `&mut` because it may have to read an evicted chunk back.
+    fn height(&mut self, dir_layer: u8, chunk: u64, inner: u8) -> usize;
+    /// adds a signal to the tick `delta_t` ticks after the current one (0 is the next tick)
+    fn emit(&mut self, delta_t: usize, signal: (u32, u8, u64, u8));
+}
+
+/// Chunks which can't be read back (see `Layer::get_loaded_mut`) act like empty space:
+/// signals sent to them and blocks put into them are lost, and the error is kept in `World::chunk_errors`.
+impl Cells for World {
+    fn top_mut(&mut self, dir_layer: u8, chunk: u64, inner: u8) -> Option<&mut Block> {
+        readable(
+            self.layers[(dir_layer & LAYER_MASK) as usize].chunk_mut(&chunk),
+            &mut self.chunk_errors,
+        )
+        .and_then(|chunk| chunk[inner as usize].last_mut())
+    }
+    fn pop(&mut self, dir_layer: u8, chunk: u64, inner: u8) -> Option<Block> {
+        let layer = &mut self.layers[(dir_layer & LAYER_MASK) as usize];
+        let block =
+            readable(layer.chunk_mut(&chunk), &mut self.chunk_errors)?[inner as usize].pop();
+        layer.note_top(chunk, inner);
+        block
+    }
+    fn push(&mut self, dir_layer: u8, chunk: u64, inner: u8, block: Block) {
+        let layer = &mut self.layers[(dir_layer & LAYER_MASK) as usize];
+        match layer.chunk_mut_or_create(&chunk) {
+            Ok(chunk) => chunk[inner as usize].push(block),
+            Err(e) => return self.chunk_errors.push(e),
+        }
+        layer.note_top(chunk, inner);
+    }
+    fn replace_stack(
+        &mut self,
+        dir_layer: u8,
+        chunk: u64,
+        inner: u8,
+        stack: Vec<Block>,
+    ) -> Vec<Block> {
+        let layer = &mut self.layers[(dir_layer & LAYER_MASK) as usize];
+        // only create the chunk if blocks are put into it
+        let old = if stack.is_empty() {
+            readable(layer.chunk_mut(&chunk), &mut self.chunk_errors)
+                .map(|chunk| std::mem::replace(&mut chunk[inner as usize], stack))
+                .unwrap_or_default()
+        } else {
+            match layer.chunk_mut_or_create(&chunk) {
+                Ok(chunk) => std::mem::replace(&mut chunk[inner as usize], stack),
+                Err(e) => {
+                    self.chunk_errors.push(e);
+                    return vec![];
+                }
+            }
+        };
+        layer.note_top(chunk, inner);
+        old
+    }
+    fn height(&mut self, dir_layer: u8, chunk: u64, inner: u8) -> usize {
+        readable(
+            self.layers[(dir_layer & LAYER_MASK) as usize].get_loaded(&chunk),
+            &mut self.chunk_errors,
+        )
+        .map(|chunk| chunk[inner as usize].len())
+        .unwrap_or(0)
+    }
+    fn emit(&mut self, delta_t: usize, signal: (u32, u8, u64, u8)) {
+        self.signals_mut(delta_t).push(signal);
+    }
+}
+
+/// the chunk, or `None` if it can't be read back, in which case the error is added to `errors`
+fn readable<T>(chunk: std::io::Result<Option<T>>, errors: &mut Vec<std::io::Error>) -> Option<T> {
+    chunk.unwrap_or_else(|e| {
+        errors.push(e);
+        None
+    })
+}
+
+/// marks the layers whose bits are set in `layers` as dirty
+fn mark_dirty(world: &World, layers: u32) {
+    for (i, layer) in world.layers.iter().enumerate() {
+        if layers & (1 << i) != 0 {
+            layer.mark_dirty();
+        }
+    }
+}
+
+/// adds a signal which reached a probe to the log
+fn log_probe(
+    log: &mut VecDeque<ProbeRecord>,
+    tick: u64,
+    (value, dir_layer, chunk, inner): (u32, u8, u64, u8),
+) {
+    if log.len() >= PROBE_LOG_LEN {
+        log.pop_front();
+    }
+    let (x, y) = Layer::where_to_coords(chunk, inner);
+    log.push_back(ProbeRecord {
+        tick,
+        layer: (dir_layer & LAYER_MASK) as usize,
+        x,
+        y,
+        value,
+    });
+}
+
+/// processes one signal. `changed` is called for every chunk in which a stack was modified,
+/// and `probed` with every signal which reaches a probe.
+fn process_signal<C: Cells>(
+    cells: &mut C,
+    (signal, mut dir_layer, mut pos_chunk, mut pos_inner): (u32, u8, u64, u8),
+    wrap_layers: bool,
+    max_value_delay: u32,
+    changed: &mut impl FnMut(u8, u64),
+    probed: &mut impl FnMut((u32, u8, u64, u8)),
+) {
+    // signals into empty space don't do anything, so don't create a chunk for them
+    if let Some(block) = cells.top_mut(dir_layer, pos_chunk, pos_inner) {
+        match block {
+            Block::Color(c) | Block::Char(c) => {
+                if *c != signal {
+                    *c = signal;
+                    changed(dir_layer, pos_chunk);
+                }
+            }
+            Block::Delay(how_long, direction) => {
+                if is_side(*direction, dir_layer) {
+                    if *how_long != signal {
+                        *how_long = signal;
+                        changed(dir_layer, pos_chunk);
+                    }
+                } else if pos_move(&mut dir_layer, &mut pos_chunk, &mut pos_inner, wrap_layers) {
+                    let v = if *direction & DELAY_BY_VALUE != 0 {
+                        signal.min(max_value_delay)
+                    } else {
+                        *how_long
+                    };
+                    cells.emit(v as _, (signal, dir_layer, pos_chunk, pos_inner));
+                }
+            }
+            Block::Storage(value, mode, direction) => {
+                if is_side(*direction, dir_layer) {
+                    let old_value = *value;
+                    match mode {
+                        0 => *value = signal,
+                        1 => *value |= signal,
+                        2 => *value &= signal,
+                        3 => *value ^= signal,
+                        4 => *value = value.saturating_add(signal),
+                        5 => *value = value.saturating_sub(signal),
+                        6 => *value = value.saturating_mul(signal),
+                        7 => {
+                            *value = if signal == 0 {
+                                u32::MAX
+                            } else {
+                                value.saturating_div(signal)
+                            }
+                        }
+                        // `x % 0` leaves the value unchanged
+                        8 => *value = value.checked_rem(signal).unwrap_or(*value),
+                        9 => *value = (*value).min(signal),
+                        10 => *value = (*value).max(signal),
+                        14 => *value = value.wrapping_add(signal),
+                        15 => *value = value.wrapping_sub(signal),
+                        16 => *value = value.wrapping_mul(signal),
+                        _ => {}
+                    }
+                    if *value != old_value {
+                        changed(dir_layer, pos_chunk);
+                    }
+                } else if is_same_dir(*direction, dir_layer) {
+                    let new_mode = signal.min(u8::MAX as _) as _;
+                    if *mode != new_mode {
+                        *mode = new_mode;
+                        changed(dir_layer, pos_chunk);
+                    }
+                    let value = *value;
+                    if pos_move(&mut dir_layer, &mut pos_chunk, &mut pos_inner, wrap_layers) {
+                        cells.emit(0, (value, dir_layer, pos_chunk, pos_inner));
+                    }
+                }
+            }
+            Block::Gate(open, direction) => {
+                if is_side(*direction, dir_layer) {
+                    let new_open = (signal == 0) != (*direction & GATE_INVERTED != 0);
+                    if *open != new_open {
+                        *open = new_open;
+                        changed(dir_layer, pos_chunk);
+                    }
+                    if let Some((c_dir_layer, c_pos_chunk, c_pos_inner)) =
+                        gate_control_dir(*direction).and_then(|control| {
+                            pos_moved(
+                                control | (dir_layer & LAYER_MASK),
+                                pos_chunk,
+                                pos_inner,
+                                wrap_layers,
+                            )
+                        })
+                    {
+                        cells.emit(0, (signal, c_dir_layer, c_pos_chunk, c_pos_inner));
+                    }
+                } else if *open
+                    && pos_move(&mut dir_layer, &mut pos_chunk, &mut pos_inner, wrap_layers)
+                {
+                    cells.emit(0, (signal, dir_layer, pos_chunk, pos_inner));
+                }
+            }
+            Block::Clock(period, _) => {
+                if *period != signal {
+                    *period = signal;
+                    changed(dir_layer, pos_chunk);
+                }
+            }
+            Block::Compare(threshold, op, direction) => {
+                if is_side(*direction, dir_layer) {
+                    if *threshold != signal {
+                        *threshold = signal;
+                        changed(dir_layer, pos_chunk);
+                    }
+                } else if is_same_dir(*direction, dir_layer) {
+                    let pass = match op {
+                        0 => signal < *threshold,
+                        1 => signal <= *threshold,
+                        2 => signal == *threshold,
+                        3 => signal >= *threshold,
+                        4 => signal > *threshold,
+                        _ => false,
+                    };
+                    if pass && pos_move(&mut dir_layer, &mut pos_chunk, &mut pos_inner, wrap_layers)
+                    {
+                        cells.emit(0, (signal, dir_layer, pos_chunk, pos_inner));
+                    }
+                }
+            }
+            Block::Not(direction) => {
+                if is_same_dir(*direction, dir_layer)
+                    && pos_move(&mut dir_layer, &mut pos_chunk, &mut pos_inner, wrap_layers)
+                {
+                    cells.emit(0, (!signal, dir_layer, pos_chunk, pos_inner));
+                }
+            }
+            Block::Probe(direction) => {
+                if is_same_dir(*direction, dir_layer) {
+                    probed((signal, dir_layer, pos_chunk, pos_inner));
+                    if pos_move(&mut dir_layer, &mut pos_chunk, &mut pos_inner, wrap_layers) {
+                        cells.emit(0, (signal, dir_layer, pos_chunk, pos_inner));
+                    }
+                }
+            }
+            Block::Random(state) => {
+                let random = next_random(state);
+                changed(dir_layer, pos_chunk);
+                if pos_move(&mut dir_layer, &mut pos_chunk, &mut pos_inner, wrap_layers) {
+                    cells.emit(0, (random, dir_layer, pos_chunk, pos_inner));
+                }
+            }
+            Block::Splitter(direction) => {
+                let direction = *direction;
+                if is_side(direction, dir_layer) {
+                    if let Some((a_dir, a_pos_chunk, a_pos_inner)) =
+                        pos_moved(direction, pos_chunk, pos_inner, wrap_layers)
+                    {
+                        cells.emit(
+                            0,
+                            (
+                                signal,
+                                (a_dir & 0b11100000) | (dir_layer & LAYER_MASK),
+                                a_pos_chunk,
+                                a_pos_inner,
+                            ),
+                        );
+                    }
+                    if let Some((a_dir, a_pos_chunk, a_pos_inner)) =
+                        pos_moved(dir_rev(direction), pos_chunk, pos_inner, wrap_layers)
+                    {
+                        cells.emit(
+                            0,
+                            (
+                                signal,
+                                (a_dir & 0b11100000) | (dir_layer & LAYER_MASK),
+                                a_pos_chunk,
+                                a_pos_inner,
+                            ),
+                        );
+                    }
+                }
+            }
+            Block::Fan(dirs) => {
+                let dirs = *dirs;
+                for (i, direction) in FAN_DIRECTIONS.into_iter().enumerate() {
+                    if dirs & (1 << i) == 0 {
+                        continue;
+                    }
+                    // the signal arrived on the fan's layer, moving up or down a layer starts from there
+                    let dir_layer = direction | (dir_layer & LAYER_MASK);
+                    if let Some(target) = pos_moved(dir_layer, pos_chunk, pos_inner, wrap_layers) {
+                        cells.emit(0, (signal, target.0, target.1, target.2));
+                    }
+                }
+            }
+            Block::Counter(count, direction) => {
+                if is_side(*direction, dir_layer) {
+                    if *count != 0 {
+                        *count = 0;
+                        changed(dir_layer, pos_chunk);
+                    }
+                } else if is_same_dir(*direction, dir_layer) {
+                    if *count != u32::MAX {
+                        *count += 1;
+                        changed(dir_layer, pos_chunk);
+                    }
+                    let count = *count;
+                    if pos_move(&mut dir_layer, &mut pos_chunk, &mut pos_inner, wrap_layers) {
+                        cells.emit(0, (count, dir_layer, pos_chunk, pos_inner));
+                    }
+                }
+            }
+            Block::Height(direction) => {
+                if is_side(*direction, dir_layer) {
+                    let dir_layer = *direction | (dir_layer & LAYER_MASK);
+                    if let Some((a_dir_layer, a_pos_chunk, a_pos_inner)) =
+                        pos_moved(dir_layer, pos_chunk, pos_inner, wrap_layers)
+                    {
+                        let height = cells.height(a_dir_layer, a_pos_chunk, a_pos_inner);
+                        cells.emit(
+                            0,
+                            (
+                                u32::try_from(height).unwrap_or(u32::MAX),
+                                a_dir_layer,
+                                a_pos_chunk,
+                                a_pos_inner,
+                            ),
+                        );
+                    }
+                }
+            }
+            Block::Move(direction) => {
+                if is_side(*direction, dir_layer) {
+                    let layer = dir_layer & LAYER_MASK;
+                    let dir_layer_in_front = dir_rev(*direction) | layer;
+                    let dir_layer_behind = *direction | layer;
+                    let (dir_layer_a, dir_layer_b) = if signal == 0 {
+                        (dir_layer_behind, dir_layer_in_front)
+                    } else {
+                        (dir_layer_in_front, dir_layer_behind)
+                    };
+                    if let (
+                        Some((a_dir_layer, a_pos_chunk, a_pos_inner)),
+                        Some((b_dir_layer, b_pos_chunk, b_pos_inner)),
+                    ) = (
+                        pos_moved(dir_layer_a, pos_chunk, pos_inner, wrap_layers),
+                        pos_moved(dir_layer_b, pos_chunk, pos_inner, wrap_layers),
+                    ) {
+                        // only create the destination chunk if there is a block to move
+                        if let Some(origin) = cells.pop(a_dir_layer, a_pos_chunk, a_pos_inner) {
+                            cells.push(b_dir_layer, b_pos_chunk, b_pos_inner, origin);
+                            changed(a_dir_layer, a_pos_chunk);
+                            changed(b_dir_layer, b_pos_chunk);
                         }
                     }
-                    Block::Storage(value, mode, direction) => {
-                        if is_side(*direction, dir_layer) {
-                            match mode {
-                                0 => *value = signal,
-                                1 => *value |= signal,
-                                2 => *value &= signal,
-                                3 => *value ^= signal,
-                                4 => *value = value.saturating_add(signal),
-                                5 => *value = value.saturating_sub(signal),
-                                6 => *value = value.saturating_mul(signal),
-                                7 => {
-                                    *value = if signal == 0 {
-                                        u32::MAX
-                                    } else {
-                                        value.saturating_div(signal)
-                                    }
+                }
+            }
+            Block::Swap(direction) => {
+                if is_side(*direction, dir_layer) {
+                    let layer = dir_layer & LAYER_MASK;
+                    let dir_layer_a = dir_rev(*direction) | layer;
+                    let dir_layer_b = *direction | layer;
+                    if let (
+                        Some((a_dir_layer, a_pos_chunk, a_pos_inner)),
+                        Some((b_dir_layer, b_pos_chunk, b_pos_inner)),
+                    ) = (
+                        pos_moved(dir_layer_a, pos_chunk, pos_inner, wrap_layers),
+                        pos_moved(dir_layer_b, pos_chunk, pos_inner, wrap_layers),
+                    ) {
+                        if let Some(first) = cells.pop(a_dir_layer, a_pos_chunk, a_pos_inner) {
+                            // if there is no second value, this just pushes back the first value which was removed earlier.
+                            let remaining = match cells.pop(b_dir_layer, b_pos_chunk, b_pos_inner) {
+                                Some(second) => {
+                                    cells.push(b_dir_layer, b_pos_chunk, b_pos_inner, first);
+                                    changed(a_dir_layer, a_pos_chunk);
+                                    changed(b_dir_layer, b_pos_chunk);
+                                    second
                                 }
-                                8 => *value %= signal,
-                                _ => {}
-                            }
-                        } else if is_same_dir(*direction, dir_layer) {
-                            *mode = signal.min(u8::MAX as _) as _;
-                            if pos_move(&mut dir_layer, &mut pos_chunk, &mut pos_inner) {
-                                self.world.signals_queue[0]
-                                    .push((*value, dir_layer, pos_chunk, pos_inner));
-                            }
+                                None => first,
+                            };
+                            cells.push(a_dir_layer, a_pos_chunk, a_pos_inner, remaining)
                         }
                     }
-                    Block::Gate(open, direction) => {
-                        if is_side(*direction, dir_layer) {
-                            *open = signal == 0;
-                        } else if *open {
-                            if pos_move(&mut dir_layer, &mut pos_chunk, &mut pos_inner) {
-                                self.world.signals_queue[0]
-                                    .push((signal, dir_layer, pos_chunk, pos_inner));
-                            }
+                }
+            }
+            Block::Duplicate(direction) => {
+                if signal == 0 && is_side(*direction, dir_layer) {
+                    let layer = dir_layer & LAYER_MASK;
+                    if let (
+                        Some((from_dir_layer, from_chunk, from_inner)),
+                        Some((to_dir_layer, to_chunk, to_inner)),
+                    ) = (
+                        pos_moved(
+                            dir_rev(*direction) | layer,
+                            pos_chunk,
+                            pos_inner,
+                            wrap_layers,
+                        ),
+                        pos_moved(*direction | layer, pos_chunk, pos_inner, wrap_layers),
+                    ) {
+                        if let Some(copy) = cells
+                            .top_mut(from_dir_layer, from_chunk, from_inner)
+                            .cloned()
+                        {
+                            cells.push(to_dir_layer, to_chunk, to_inner, copy);
+                            changed(to_dir_layer, to_chunk);
                         }
                     }
-                    Block::Splitter(direction) => {
-                        if is_side(*direction, dir_layer) {
-                            if let Some((a_dir, a_pos_chunk, a_pos_inner)) =
-                                pos_moved(*direction, pos_chunk, pos_inner)
-                            {
-                                self.world.signals_queue[0].push((
-                                    signal,
-                                    (a_dir & 0b11100000) | (dir_layer & 0b11111),
-                                    a_pos_chunk,
-                                    a_pos_inner,
-                                ));
-                            }
-                            if let Some((a_dir, a_pos_chunk, a_pos_inner)) =
-                                pos_moved(dir_rev(*direction), pos_chunk, pos_inner)
-                            {
-                                self.world.signals_queue[0].push((
-                                    signal,
-                                    (a_dir & 0b11100000) | (dir_layer & 0b11111),
-                                    a_pos_chunk,
-                                    a_pos_inner,
-                                ));
-                            }
+                }
+            }
+            Block::Destroy(direction) => {
+                if is_side(*direction, dir_layer) {
+                    let layer = dir_layer & LAYER_MASK;
+                    if let Some((target_dir_layer, target_chunk, target_inner)) =
+                        pos_moved(*direction | layer, pos_chunk, pos_inner, wrap_layers)
+                    {
+                        if cells
+                            .pop(target_dir_layer, target_chunk, target_inner)
+                            .is_some()
+                        {
+                            changed(target_dir_layer, target_chunk);
                         }
                     }
-                    Block::Move(direction) => {
-                        if is_side(*direction, dir_layer) {
-                            let layer = dir_layer & 0b11111;
-                            let dir_layer_in_front = dir_rev(*direction) | layer;
-                            let dir_layer_behind = *direction | layer;
-                            let (dir_layer_a, dir_layer_b) = if signal == 0 {
-                                (dir_layer_behind, dir_layer_in_front)
-                            } else {
-                                (dir_layer_in_front, dir_layer_behind)
-                            };
-                            if let (
-                                Some((a_dir_layer, a_pos_chunk, a_pos_inner)),
-                                Some((b_dir_layer, b_pos_chunk, b_pos_inner)),
-                            ) = (
-                                pos_moved(dir_layer_a, pos_chunk, pos_inner),
-                                pos_moved(dir_layer_b, pos_chunk, pos_inner),
-                            ) {
-                                if let Some(origin) = self.world.layers
-                                    [(a_dir_layer & 0b11111) as usize]
-                                    .get_mut(&a_pos_chunk)
-                                    [a_pos_inner as usize]
-                                    .pop()
-                                {
-                                    self.world.layers[(b_dir_layer & 0b11111) as usize]
-                                        .get_mut(&b_pos_chunk)
-                                        [b_pos_inner as usize]
-                                        .push(origin)
-                                }
-                            }
+                }
+            }
+            Block::SwapAll(direction) => {
+                if is_side(*direction, dir_layer) {
+                    let layer = dir_layer & LAYER_MASK;
+                    let dir_layer_a = dir_rev(*direction) | layer;
+                    let dir_layer_b = *direction | layer;
+                    if let (Some(a), Some(b)) = (
+                        pos_moved(dir_layer_a, pos_chunk, pos_inner, wrap_layers),
+                        pos_moved(dir_layer_b, pos_chunk, pos_inner, wrap_layers),
+                    ) {
+                        if cells.height(a.0, a.1, a.2) != 0 || cells.height(b.0, b.1, b.2) != 0 {
+                            let mut stack = cells.replace_stack(a.0, a.1, a.2, vec![]);
+                            stack = cells.replace_stack(b.0, b.1, b.2, stack);
+                            cells.replace_stack(a.0, a.1, a.2, stack);
+                            changed(a.0, a.1);
+                            changed(b.0, b.1);
                         }
                     }
-                    Block::Swap(direction) => {
-                        if is_side(*direction, dir_layer) {
-                            let layer = dir_layer & 0b11111;
-                            let dir_layer_a = dir_rev(*direction) | layer;
-                            let dir_layer_b = *direction | layer;
-                            if let (
-                                Some((a_dir_layer, a_pos_chunk, a_pos_inner)),
-                                Some((b_dir_layer, b_pos_chunk, b_pos_inner)),
-                            ) = (
-                                pos_moved(dir_layer_a, pos_chunk, pos_inner),
-                                pos_moved(dir_layer_b, pos_chunk, pos_inner),
-                            ) {
-                                if let Some(mut first) = self.world.layers
-                                    [(a_dir_layer & 0b11111) as usize]
-                                    .get_mut(&a_pos_chunk)
-                                    [a_pos_inner as usize]
-                                    .pop()
-                                {
-                                    if let Some(second) = self.world.layers
-                                        [(b_dir_layer & 0b11111) as usize]
-                                        .get_mut(&b_pos_chunk)
-                                        [b_pos_inner as usize]
-                                        .last_mut()
-                                    {
-                                        std::mem::swap(second, &mut first);
-                                    }
-                                    // push the remaining value to the first stack.
-                                    // if a second value existed, this will be that second value (mem::swap),
-                                    // if there was no second value, this will just push back the first value which was removed earlier.
-                                    self.world.layers[(a_dir_layer & 0b11111) as usize]
-                                        .get_mut(&a_pos_chunk)
-                                        [a_pos_inner as usize]
-                                        .push(first)
-                                }
+                }
+            }
+        }
+    }
+}
+
+#[cfg(feature = "rayon")]
+impl Runner {
+    /// Performs one tick, processing independent signals on multiple threads.
+    /// The world, the signal queue and the returned changes always end up exactly as they would with `tick`.
+    ///
+    /// Conflict model: a signal reads and modifies the stack it is sent to, and Height, Move, Swap, SwapAll,
+    /// Duplicate and Destroy blocks also access the six stacks next to it. Because these blocks change which blocks are on top of
+    /// neighbouring stacks in the middle of a tick, which block a signal will hit isn't known in advance,
+    /// so every signal is assumed to access its stack and all six neighbours.
+    /// Signals whose stacks overlap, directly or through other signals, are processed in their original order on the same thread.
+    /// Signals which don't share any stacks can't influence each other, so they run concurrently.
+    /// Emitted signals are then put into the queue in the order `tick` would have emitted them.
+    ///
+    /// If there is only one group of signals or `threads` allows only one thread, or if a block could access stacks
+    /// which aren't its neighbours, this falls back to processing the signals on the current thread.
+    pub fn tick_parallel(&mut self) -> Option<Changes> {
+        let mut signals = self.start_tick();
+        let wrap_layers = self.world.wrap_layers;
+        let max_value_delay = self.max_value_delay;
+        let track_changes = self.track_changes;
+        // group signals whose stacks overlap (union-find, the root is the group's first signal)
+        let mut parent: Vec<usize> = (0..signals.len()).collect();
+        fn root(parent: &mut [usize], mut i: usize) -> usize {
+            while parent[i] != i {
+                parent[i] = parent[parent[i]];
+                i = parent[i];
+            }
+            i
+        }
+        let mut first_signal_in_cell = HashMap::new();
+        for (i, &(_, dir_layer, chunk, inner)) in signals.iter().enumerate() {
+            for cell in footprint(dir_layer, chunk, inner, wrap_layers)
+                .into_iter()
+                .flatten()
+            {
+                let other = *first_signal_in_cell.entry(cell).or_insert(i);
+                let (a, b) = (root(&mut parent, i), root(&mut parent, other));
+                parent[a.max(b)] = a.min(b);
+            }
+        }
+        let mut groups: Vec<Vec<usize>> = vec![];
+        let mut group_of_root = HashMap::new();
+        for i in 0..signals.len() {
+            let r = root(&mut parent, i);
+            let group = *group_of_root.entry(r).or_insert_with(|| {
+                groups.push(vec![]);
+                groups.len() - 1
+            });
+            groups[group].push(i);
+        }
+        let threads = match self.threads {
+            0 => std::thread::available_parallelism().map_or(1, |n| n.get()),
+            n => n,
+        }
+        .min(groups.len());
+        if threads < 2 {
+            let modified = self.process_signals(&mut signals);
+            return self.end_tick(signals, modified);
+        }
+        // distribute the groups, so that every thread gets about the same amount of signals
+        let mut buckets = vec![(0, vec![]); threads];
+        for group in groups {
+            let bucket = buckets.iter_mut().min_by_key(|bucket| bucket.0).unwrap();
+            bucket.0 += group.len();
+            bucket.1.extend(group);
+        }
+        // take the stacks each thread needs out of the world
+        let mut work = vec![];
+        // chunks which can't be read back are left to `process_signals`, which reports them
+        let mut unreadable = false;
+        for (_, mut indices) in buckets {
+            indices.sort_unstable();
+            let mut cells = TakenCells {
+                stacks: HashMap::new(),
+                source: 0,
+                emitted: vec![],
+            };
+            for &i in &indices {
+                let (_, dir_layer, chunk, inner) = signals[i];
+                for cell in footprint(dir_layer, chunk, inner, wrap_layers)
+                    .into_iter()
+                    .flatten()
+                {
+                    cells.stacks.entry(cell).or_insert_with(|| {
+                        match self.world.layers[cell.0 as usize].chunk_mut(&cell.1) {
+                            Ok(Some(chunk)) => (std::mem::take(&mut chunk[cell.2 as usize]), true),
+                            Ok(None) => (vec![], false),
+                            Err(_) => {
+                                unreadable = true;
+                                (vec![], false)
                             }
                         }
-                    }
+                    });
                 }
             }
+            work.push((cells, indices));
+        }
+        // every block which could be processed during this tick is in one of the taken stacks
+        if unreadable
+            || !work.iter().all(|(cells, _)| {
+                cells
+                    .stacks
+                    .values()
+                    .all(|(stack, _)| stack.iter().all(only_accesses_neighbours))
+            })
+        {
+            for (cells, _) in work {
+                cells.put_back(&mut self.world);
+            }
+            let modified = self.process_signals(&mut signals);
+            return self.end_tick(signals, modified);
+        }
+        let signals_ref = &signals;
+        let results: Vec<_> = std::thread::scope(|scope| {
+            let handles: Vec<_> = work
+                .into_iter()
+                .map(|(mut cells, indices)| {
+                    scope.spawn(move || {
+                        let mut modified = vec![];
+                        let mut dirty = 0u32;
+                        let mut changed = |layer: u8, chunk: u64| {
+                            dirty |= 1 << (layer & LAYER_MASK);
+                            if track_changes {
+                                modified.push(((layer & LAYER_MASK) as usize, chunk));
+                            }
+                        };
+                        let mut probes = vec![];
+                        for i in indices {
+                            cells.source = i;
+                            let mut probed = |signal| probes.push((i, signal));
+                            process_signal(
+                                &mut cells,
+                                signals_ref[i],
+                                wrap_layers,
+                                max_value_delay,
+                                &mut changed,
+                                &mut probed,
+                            );
+                        }
+                        (cells, modified, dirty, probes)
+                    })
+                })
+                .collect();
+            handles
+                .into_iter()
+                .map(|handle| handle.join().unwrap())
+                .collect()
+        });
+        let mut modified = vec![];
+        let mut emitted = vec![];
+        let mut probes = vec![];
+        for (cells, thread_modified, dirty, thread_probes) in results {
+            modified.extend(thread_modified);
+            mark_dirty(&self.world, dirty);
+            emitted.extend(cells.put_back(&mut self.world));
+            probes.extend(thread_probes);
+        }
+        // a signal reaches at most one probe, so this is the order in which `tick` would have logged them
+        probes.sort_unstable_by_key(|(source, _)| *source);
+        for (_, signal) in probes {
+            log_probe(&mut self.probe_log, self.tick_counter, signal);
+        }
+        // stable, so signals emitted by the same signal stay in order
+        emitted.sort_by_key(|(source, _, _)| *source);
+        for (_, delta_t, signal) in emitted {
+            self.world.signals_mut(delta_t).push(signal);
+        }
+        signals.clear();
+        self.end_tick(signals, modified)
+    }
+}
+
+/// the stack a signal is sent to and its six neighbours. `None` where a neighbour would be out of bounds.
+#[cfg(feature = "rayon")]
+fn footprint(
+    dir_layer: u8,
+    chunk: u64,
+    inner: u8,
+    wrap_layers: bool,
+) -> [Option<(u8, u64, u8)>; 7] {
+    let layer = dir_layer & LAYER_MASK;
+    let mut cells = [None; 7];
+    cells[0] = Some((layer, chunk, inner));
+    for (i, dir) in [DIR_UP_L, DIR_DOWN_L, DIR_LEFT, DIR_RIGHT, DIR_UP, DIR_DOWN]
+        .into_iter()
+        .enumerate()
+    {
+        cells[i + 1] = pos_moved(dir | layer, chunk, inner, wrap_layers)
+            .map(|(dir_layer, chunk, inner)| (dir_layer & LAYER_MASK, chunk, inner));
+    }
+    cells
+}
+
+/// false if the block could access a stack which isn't next to it.
+/// this is possible if a Height, Move, Swap, SwapAll, Duplicate or Destroy block's direction has some of the layer bits set.
+#[cfg(feature = "rayon")]
+fn only_accesses_neighbours(block: &Block) -> bool {
+    match block {
+        Block::Height(dir)
+        | Block::Move(dir)
+        | Block::Swap(dir)
+        | Block::SwapAll(dir)
+        | Block::Duplicate(dir)
+        | Block::Destroy(dir) => dir & LAYER_MASK == 0,
+        _ => true,
+    }
+}
+
+/// stacks which were taken out of the world so a thread can work on them
+#[cfg(feature = "rayon")]
+struct TakenCells {
+    /// (layer, chunk, inner) => (stack, whether the chunk has to exist when the stack is put back)
+    stacks: HashMap<(u8, u64, u8), (Vec<Block>, bool)>,
+    /// index of the signal which is being processed
+    source: usize,
+    emitted: Vec<EmittedSignal>,
+}
+/// (index of the signal which emitted it, delta_t, signal)
+#[cfg(feature = "rayon")]
+type EmittedSignal = (usize, usize, (u32, u8, u64, u8));
+#[cfg(feature = "rayon")]
+impl TakenCells {
+    fn stack(&mut self, dir_layer: u8, chunk: u64, inner: u8) -> &mut (Vec<Block>, bool) {
+        self.stacks
+            .get_mut(&(dir_layer & LAYER_MASK, chunk, inner))
+            .expect("signals only access the stacks in their footprint")
+    }
+    /// puts the stacks back into the world, returning the emitted signals
+    fn put_back(self, world: &mut World) -> Vec<EmittedSignal> {
+        for ((layer, chunk, inner), (stack, chunk_exists)) in self.stacks {
+            if chunk_exists {
+                let layer = &mut world.layers[layer as usize];
+                // not `try_get_mut`, which would mark the layer as dirty even if nothing changed
+                layer.chunks.entry(chunk).or_insert_with(create_empty_chunk)[inner as usize] =
+                    stack;
+                layer.note_top(chunk, inner);
+            }
         }
+        self.emitted
+    }
+}
+#[cfg(feature = "rayon")]
+impl Cells for TakenCells {
+    fn top_mut(&mut self, dir_layer: u8, chunk: u64, inner: u8) -> Option<&mut Block> {
+        self.stack(dir_layer, chunk, inner).0.last_mut()
+    }
+    fn pop(&mut self, dir_layer: u8, chunk: u64, inner: u8) -> Option<Block> {
+        self.stack(dir_layer, chunk, inner).0.pop()
+    }
+    fn push(&mut self, dir_layer: u8, chunk: u64, inner: u8, block: Block) {
+        // like `Layer::try_get_mut`, pushing creates the chunk
+        let stack = self.stack(dir_layer, chunk, inner);
+        stack.0.push(block);
+        stack.1 = true;
+    }
+    fn replace_stack(
+        &mut self,
+        dir_layer: u8,
+        chunk: u64,
+        inner: u8,
+        stack: Vec<Block>,
+    ) -> Vec<Block> {
+        let old = self.stack(dir_layer, chunk, inner);
+        if !stack.is_empty() {
+            old.1 = true;
+        }
+        std::mem::replace(&mut old.0, stack)
+    }
+    fn height(&mut self, dir_layer: u8, chunk: u64, inner: u8) -> usize {
+        self.stacks
+            .get(&(dir_layer & LAYER_MASK, chunk, inner))
+            .expect("signals only access the stacks in their footprint")
+            .0
+            .len()
+    }
+    fn emit(&mut self, delta_t: usize, signal: (u32, u8, u64, u8)) {
+        self.emitted.push((self.source, delta_t, signal));
     }
 }
 
-// dir bytes (xor all 3 bytes => reverse direction):
-// 001 - up (layer)
-// 110 - down (layer)
-// 100 - left
-// 011 - right
-// 010 - up
-// 101 - down
+/// advances the state (splitmix64) and returns the upper 32 bits of the result
+fn next_random(state: &mut u64) -> u32 {
+    *state = state.wrapping_add(0x9E3779B97F4A7C15);
+    let mut z = *state;
+    z = (z ^ (z >> 30)).wrapping_mul(0xBF58476D1CE4E5B9);
+    z = (z ^ (z >> 27)).wrapping_mul(0x94D049BB133111EB);
+    ((z ^ (z >> 31)) >> 32) as u32
+}
 
 pub const DIR_UP_L: u8 = 0b00100000;
 pub const DIR_DOWN_L: u8 = 0b11000000;
@@ -228,24 +1149,58 @@ pub const DIR_RIGHT: u8 = 0b01100000;
 pub const DIR_UP: u8 = 0b01000000;
 pub const DIR_DOWN: u8 = 0b10100000;
 
+/// If this bit of a `Block::Delay`'s direction is set, signals wait as many ticks as their value says
+/// (at most `Runner::max_value_delay`) instead of the stored amount, and are then passed on unchanged.
+/// The other layer bits of a delay's direction are unused.
+pub const DELAY_BY_VALUE: u8 = 0b1;
+
+/// If this bit of a `Block::Gate`'s direction is set, the gate is inverted:
+/// it is open if the last side-signal it received was not `0`.
+pub const GATE_INVERTED: u8 = 0b10;
+/// These bits of a `Block::Gate`'s direction store a second direction, in which side-signals are passed on
+/// after setting the open flag (see `gate_control_out`). If they are `0`, the gate keeps its side-signals.
+/// The lowest bit of a gate's direction is unused.
+pub const GATE_CONTROL_MASK: u8 = 0b11100;
+
+/// the direction of a `Block::Gate` pointing in `dir` which passes side-signals on in direction `control` (one of the `DIR_` constants)
+pub fn gate_control_out(dir: u8, control: u8) -> u8 {
+    (dir & !GATE_CONTROL_MASK) | ((control & 0b11100000) >> 3)
+}
+
+/// the direction in which a `Block::Gate` with the direction `dir` passes side-signals on, if it does
+pub fn gate_control_dir(dir: u8) -> Option<u8> {
+    match (dir & GATE_CONTROL_MASK) << 3 {
+        0 | 0b11100000 => None,
+        control => Some(control),
+    }
+}
+
+/// The directions a `Block::Fan` can output to. Bit `i` of the fan's bitmask enables `FAN_DIRECTIONS[i]`.
+pub const FAN_DIRECTIONS: [u8; 6] = [DIR_LEFT, DIR_UP, DIR_DOWN, DIR_RIGHT, DIR_UP_L, DIR_DOWN_L];
+
 /// reverses the direction, keeping the layer bits intact
-fn dir_rev(dir: u8) -> u8 {
+pub fn dir_rev(dir: u8) -> u8 {
     dir ^ 0b11100000
 }
 
 /// returns true if a and b point in the same direction
-fn is_same_dir(a: u8, b: u8) -> bool {
+pub fn is_same_dir(a: u8, b: u8) -> bool {
     (a & 0b11100000) == (b & 0b11100000)
 }
 
 /// returns true if a and b have different orientations, meaning if a is a block's direction and b a signal's, it is a side-signal.
-fn is_side(a: u8, b: u8) -> bool {
+pub fn is_side(a: u8, b: u8) -> bool {
     !(is_same_dir(a, b) || is_same_dir(a, dir_rev(b)))
 }
 
 /// same as pos_move, but doesn't modify the original values
-fn pos_moved(mut dir_layer: u8, mut pos_chunk: u64, mut pos_inner: u8) -> Option<(u8, u64, u8)> {
-    if pos_move(&mut dir_layer, &mut pos_chunk, &mut pos_inner) {
+pub fn pos_moved(
+    mut dir_layer: u8,
+    mut pos_chunk: u64,
+    mut pos_inner: u8,
+    wrap_layers: bool,
+) -> Option<(u8, u64, u8)> {
+    if pos_move(&mut dir_layer, &mut pos_chunk, &mut pos_inner, wrap_layers) {
         Some((dir_layer, pos_chunk, pos_inner))
     } else {
         None
@@ -253,18 +1208,28 @@ fn pos_moved(mut dir_layer: u8, mut pos_chunk: u64, mut pos_inner: u8) -> Option
 }
 /// moves according to the first 3 bits of dir_layer.
 /// direction is retained, layer, chunk- and inner position may be changed.
-/// returns false if the new position would be out of bounds.
-fn pos_move(dir_layer: &mut u8, pos_chunk: &mut u64, pos_inner: &mut u8) -> bool {
+/// returns false if the new position would be out of bounds, which includes moving past the chunk coordinates
+/// which fit into an `i32` (instead of wrapping around to the other side of the world).
+/// if `wrap_layers` is true, moving up from the upmost layer leads to the lowest one and vice versa.
+pub fn pos_move(
+    dir_layer: &mut u8,
+    pos_chunk: &mut u64,
+    pos_inner: &mut u8,
+    wrap_layers: bool,
+) -> bool {
     let dir = *dir_layer & 0b11100000;
     match dir {
         // left
         0b10000000 => {
             if (*pos_inner & 0b1111) == 0 {
                 // we are at the very left of this chunk!
+                // move one chunk to the left, unless this is the leftmost chunk
+                let Some(x) = (*pos_chunk as u32 as i32).checked_sub(1) else {
+                    return false;
+                };
+                *pos_chunk = with_chunk_x(*pos_chunk, x as u32);
                 // set to very right of chunk
                 *pos_inner |= 0b1111;
-                // move one chunk to the left
-                *pos_chunk -= 1;
             } else {
                 // move one pos to the left
                 *pos_inner -= 1;
@@ -274,10 +1239,13 @@ fn pos_move(dir_layer: &mut u8, pos_chunk: &mut u64, pos_inner: &mut u8) -> bool
         0b01100000 => {
             if (*pos_inner & 0b1111) == 0b1111 {
                 // we are at the very right of this chunk! (all 4 bits of the x-part set to 1)
+                // move one chunk to the right, unless this is the rightmost chunk
+                let Some(x) = (*pos_chunk as u32 as i32).checked_add(1) else {
+                    return false;
+                };
+                *pos_chunk = with_chunk_x(*pos_chunk, x as u32);
                 // set to very left of chunk
                 *pos_inner &= 0b11110000;
-                // move one chunk to the right
-                *pos_chunk += 1;
             } else {
                 // move one pos to the left
                 *pos_inner += 1;
@@ -287,10 +1255,13 @@ fn pos_move(dir_layer: &mut u8, pos_chunk: &mut u64, pos_inner: &mut u8) -> bool
         0b01000000 => {
             if (*pos_inner & 0b11110000) == 0 {
                 // we are at the very top of this chunk!
+                // move one chunk up, unless this is the upmost chunk
+                let Some(y) = ((*pos_chunk >> 32) as u32 as i32).checked_sub(1) else {
+                    return false;
+                };
+                *pos_chunk = with_chunk_y(*pos_chunk, y as u32);
                 // set to very bottom of chunk
                 *pos_inner |= 0b11110000;
-                // move one chunk up
-                *pos_chunk -= 1 << 32;
             } else {
                 // move one pos up
                 *pos_inner -= 1 << 4;
@@ -300,10 +1271,13 @@ fn pos_move(dir_layer: &mut u8, pos_chunk: &mut u64, pos_inner: &mut u8) -> bool
         0b10100000 => {
             if (*pos_inner & 0b11110000) == 0b11110000 {
                 // we are at the very bottom of this chunk!
+                // move one chunk down, unless this is the lowest chunk
+                let Some(y) = ((*pos_chunk >> 32) as u32 as i32).checked_add(1) else {
+                    return false;
+                };
+                *pos_chunk = with_chunk_y(*pos_chunk, y as u32);
                 // set to very top of chunk
                 *pos_inner &= 0b1111;
-                // move one chunk down
-                *pos_chunk += 1 << 32;
             } else {
                 // move one pos down
                 *pos_inner += 1 << 4;
@@ -311,9 +1285,13 @@ fn pos_move(dir_layer: &mut u8, pos_chunk: &mut u64, pos_inner: &mut u8) -> bool
         }
         // up (layer)
         0b00100000 => {
-            if (*dir_layer & 0b11111) == 0 {
+            if (*dir_layer & LAYER_MASK) == 0 {
                 // we are at the upmost layer!
-                return false;
+                if !wrap_layers {
+                    return false;
+                }
+                // wrap around to the lowest layer
+                *dir_layer = (*dir_layer & !LAYER_MASK) | (LAYER_COUNT - 1) as u8;
             } else {
                 // move up one layer
                 *dir_layer -= 1;
@@ -321,9 +1299,13 @@ fn pos_move(dir_layer: &mut u8, pos_chunk: &mut u64, pos_inner: &mut u8) -> bool
         }
         // down (layer)
         0b11000000 => {
-            if (*dir_layer & 0b11111) == 0b11111 {
-                // we are at the lowest layer (all 5 bits set to 1)
-                return false;
+            if (*dir_layer & LAYER_MASK) as usize == LAYER_COUNT - 1 {
+                // we are at the lowest layer!
+                if !wrap_layers {
+                    return false;
+                }
+                // wrap around to the upmost layer
+                *dir_layer &= !LAYER_MASK;
             } else {
                 // move down one layer
                 *dir_layer += 1;
@@ -333,3 +1315,1159 @@ fn pos_move(dir_layer: &mut u8, pos_chunk: &mut u64, pos_inner: &mut u8) -> bool
     }
     true
 }
+
+/// replaces the x half (low 32 bits) of a chunk key, without touching the y half
+fn with_chunk_x(chunk: u64, x: u32) -> u64 {
+    (chunk & 0xFFFFFFFF00000000) | x as u64
+}
+/// replaces the y half (high 32 bits) of a chunk key, without touching the x half
+fn with_chunk_y(chunk: u64, y: u32) -> u64 {
+    (chunk & 0xFFFFFFFF) | (y as u64) << 32
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// a world with the blocks on layer 0
+    fn world_with(blocks: &[(i64, i64, Block)]) -> World {
+        let mut world = World::new_empty();
+        for (x, y, block) in blocks {
+            world.layers[0]
+                .try_push_block(*x, *y, block.clone())
+                .unwrap();
+        }
+        world
+    }
+
+    /// the ticks in which the probe log received a signal
+    fn probe_ticks(runner: &Runner) -> Vec<u64> {
+        runner
+            .probe_log()
+            .iter()
+            .map(|record| record.tick)
+            .collect()
+    }
+
+    /// (x, y, value) of every signal in the probe log
+    fn probed(runner: &Runner) -> Vec<(i64, i64, u32)> {
+        runner
+            .probe_log()
+            .iter()
+            .map(|record| (record.x, record.y, record.value))
+            .collect()
+    }
+
+    /// the values of the signals in the probe log
+    fn probed_values(runner: &Runner) -> Vec<u32> {
+        runner
+            .probe_log()
+            .iter()
+            .map(|record| record.value)
+            .collect()
+    }
+
+    /// the value of a storage block in `mode` which starts at `value` and then receives the side-signals one after the other
+    fn storage_after(mode: u8, value: u32, signals: &[u32]) -> u32 {
+        let mut runner = Runner::new(world_with(&[(
+            0,
+            0,
+            Block::Storage(value, mode, DIR_RIGHT),
+        )]));
+        for signal in signals {
+            runner.inject_signal(0, 0, 0, DIR_DOWN, *signal, 0);
+            runner.tick();
+        }
+        match runner.world.layers[0].top_block(0, 0) {
+            Some(Block::Storage(value, m, DIR_RIGHT)) if *m == mode => *value,
+            block => panic!("the storage block became {block:?}"),
+        }
+    }
+
+    #[test]
+    fn injected_signals_target_the_right_cell() {
+        let mut runner = Runner::new(World::new_empty());
+        assert!(runner.inject_signal(-17, 33, 5, DIR_UP | 0b11111, 7, 2));
+        let (chunk, inner) = runner.world.layers[5].get_where(-17, 33);
+        assert_eq!(Layer::where_to_coords(chunk, inner), (-17, 33));
+        assert_eq!(chunk, crate::world::chunk_key(-2, 2));
+        assert_eq!(inner, 1 << 4 | 15);
+        // the layer bits of the direction are ignored
+        assert_eq!(
+            runner.world.signals_queue[2],
+            [(7, DIR_UP | 5, chunk, inner)]
+        );
+        assert_eq!(runner.pending_signals(), 1);
+        assert!(!runner.inject_signal(0, 0, LAYER_COUNT as u8, DIR_UP, 7, 0));
+        assert!(!runner.inject_signal(0, 0, u8::MAX, DIR_UP, 7, 0));
+        assert_eq!(runner.pending_signals(), 1);
+    }
+
+    #[test]
+    fn height_outputs_the_height_of_the_stack_it_points_at() {
+        let mut runner = Runner::new(world_with(&[
+            (0, 0, Block::Height(DIR_RIGHT)),
+            (1, 0, Block::Color(0)),
+            (1, 0, Block::Color(0)),
+            (1, 0, Block::Probe(DIR_RIGHT)),
+        ]));
+        runner.inject_signal(0, 0, 0, DIR_DOWN, 0, 0);
+        // not a side-signal
+        runner.inject_signal(0, 0, 0, DIR_RIGHT, 0, 0);
+        runner.tick_n(2);
+        assert_eq!(probed(&runner), [(1, 0, 3)]);
+    }
+
+    #[test]
+    fn gates_can_pass_control_signals_on() {
+        const GATE: u8 = DIR_RIGHT | GATE_INVERTED;
+        let gate = gate_control_out(GATE, DIR_UP);
+        assert_eq!(gate_control_dir(gate), Some(DIR_UP));
+        assert_eq!(gate & !GATE_CONTROL_MASK, GATE);
+        assert_eq!(gate_control_dir(GATE), None);
+        let mut runner = Runner::new(world_with(&[
+            (0, 0, Block::Gate(true, gate)),
+            (0, -1, Block::Probe(DIR_UP)),
+            (0, 1, Block::Probe(DIR_DOWN)),
+            (1, 0, Block::Probe(DIR_RIGHT)),
+        ]));
+        let top = |runner: &Runner| runner.world.layers[0].top_block(0, 0).cloned();
+        // a control signal arriving from above closes the inverted gate and is passed on upwards
+        runner.inject_signal(0, 0, 0, DIR_DOWN, 0, 0);
+        runner.tick();
+        assert_eq!(top(&runner), Some(Block::Gate(false, gate)));
+        let (chunk, inner) = runner.world.layers[0].get_where(0, -1);
+        assert_eq!(runner.world.signals_queue[0], [(0, DIR_UP, chunk, inner)]);
+        runner.tick();
+        runner.inject_signal(0, 0, 0, DIR_RIGHT, 5, 0);
+        runner.tick_n(2);
+        // opening it again, the control value is passed on unchanged
+        runner.inject_signal(0, 0, 0, DIR_UP, 9, 0);
+        runner.tick_n(2);
+        assert_eq!(top(&runner), Some(Block::Gate(true, gate)));
+        runner.inject_signal(0, 0, 0, DIR_RIGHT, 6, 0);
+        runner.tick_n(2);
+        assert_eq!(probed(&runner), [(0, -1, 0), (0, -1, 9), (1, 0, 6)]);
+        // without a second direction, the gate keeps the control signal
+        runner.world.layers[0].try_pop_block(0, 0).unwrap();
+        runner.world.layers[0]
+            .try_push_block(0, 0, Block::Gate(false, DIR_RIGHT))
+            .unwrap();
+        runner.inject_signal(0, 0, 0, DIR_DOWN, 0, 0);
+        runner.tick();
+        assert_eq!(top(&runner), Some(Block::Gate(true, DIR_RIGHT)));
+        assert!(runner.is_idle());
+    }
+
+    #[test]
+    fn clock_emits_once_every_period() {
+        let mut runner = Runner::new(world_with(&[
+            (0, 0, Block::Clock(4, 0)),
+            (1, 0, Block::Probe(DIR_RIGHT)),
+        ]));
+        runner.tick_n(20);
+        // emitted in ticks 4, 8, 12, 16 and 20, each arriving one tick later
+        assert_eq!(probe_ticks(&runner), [5, 9, 13, 17]);
+        // to all four sides
+        assert_eq!(runner.pending_signals(), 4);
+        // signals from any direction set the period
+        runner.inject_signal(0, 0, 0, DIR_UP, 2, 0);
+        runner.tick_n(6);
+        assert_eq!(probe_ticks(&runner), [5, 9, 13, 17, 21, 23, 25]);
+        assert_eq!(
+            runner.world.layers[0].top_block(0, 0),
+            Some(&Block::Clock(2, 0))
+        );
+    }
+
+    #[test]
+    fn clocks_placed_or_removed_from_outside_are_noticed() {
+        let mut runner = Runner::new(world_with(&[(1, 0, Block::Probe(DIR_RIGHT))]));
+        let (chunk, inner) = runner.world.layers[0].get_where(0, 0);
+        runner.world.layers[0].try_get_mut(&chunk).unwrap()[inner as usize]
+            .push(Block::Clock(2, 0));
+        runner.tick_n(4);
+        assert_eq!(probe_ticks(&runner), [3]);
+        runner.world.layers[0].try_pop_block(0, 0).unwrap();
+        runner.tick_n(8);
+        assert_eq!(probe_ticks(&runner), [3, 5]);
+        assert!(runner.is_idle());
+    }
+
+    #[test]
+    fn storage_mod_by_zero_keeps_the_value() {
+        assert_eq!(storage_after(8, 10, &[0]), 10);
+        assert_eq!(storage_after(8, 10, &[4]), 2);
+        assert_eq!(storage_after(8, 10, &[4, 0, 3]), 2);
+        // div by zero is the largest value instead
+        assert_eq!(storage_after(7, 10, &[0]), u32::MAX);
+    }
+
+    #[test]
+    fn autosaves_are_written_once_the_interval_passed() {
+        let dir =
+            std::env::temp_dir().join(format!("stackmaker-test-autosave-{}", std::process::id()));
+        _ = std::fs::remove_dir_all(&dir);
+        let mut world = world_with(&[(0, 0, Block::Color(1))]);
+        world.save_dir = Some(dir.clone());
+        let mut runner = Runner::new(world);
+        assert_eq!(runner.save_path, Some(dir.clone()));
+        runner.autosave = (0, 3);
+        runner.tick_n(2);
+        runner.wait_for_autosave();
+        assert!(!dir.exists());
+        runner.tick();
+        runner.wait_for_autosave();
+        assert!(dir.join("signals").exists());
+        assert!(dir.join("layer_0").exists());
+        assert!(!runner.world.layers[0].is_dirty());
+        let loaded = World::load_from_dir(&dir, None).unwrap().unwrap();
+        assert_eq!(loaded.layers[0].top_block(0, 0), Some(&Block::Color(1)));
+        _ = std::fs::remove_dir_all(&dir);
+    }
+
+    #[test]
+    fn autosaves_to_other_directories_write_every_layer() {
+        let dir = std::env::temp_dir().join(format!(
+            "stackmaker-test-autosave-path-{}",
+            std::process::id()
+        ));
+        _ = std::fs::remove_dir_all(&dir);
+        let mut world = world_with(&[(0, 0, Block::Color(1))]);
+        world.layers[1]
+            .try_push_block(0, 0, Block::Color(2))
+            .unwrap();
+        world.layers[1].mark_saved();
+        let mut runner = Runner::new(world);
+        assert_eq!(runner.save_path, None);
+        runner.autosave = (0, 1);
+        runner.tick();
+        runner.wait_for_autosave();
+        assert!(!dir.exists());
+        runner.save_path = Some(dir.clone());
+        runner.tick();
+        runner.wait_for_autosave();
+        let loaded = World::load_from_dir(&dir, None).unwrap().unwrap();
+        assert_eq!(loaded.layers[0].top_block(0, 0), Some(&Block::Color(1)));
+        assert_eq!(loaded.layers[1].top_block(0, 0), Some(&Block::Color(2)));
+        // the world's own directory (which it doesn't have) still misses the change
+        assert!(runner.world.layers[0].is_dirty());
+        _ = std::fs::remove_dir_all(&dir);
+    }
+
+    #[test]
+    fn changes_report_recolored_chunks() {
+        let mut runner = Runner::new(world_with(&[
+            (-1, 0, Block::Color(0)),
+            (40, 40, Block::Color(7)),
+        ]));
+        runner.world.layers[3]
+            .try_push_block(5, 5, Block::Char('a' as u32))
+            .unwrap();
+        assert!(runner.tick().is_none());
+        runner.track_changes = true;
+        runner.inject_signal(-1, 0, 0, DIR_RIGHT, 0xFF0000FF, 0);
+        // already has this color
+        runner.inject_signal(40, 40, 0, DIR_RIGHT, 7, 0);
+        runner.inject_signal(5, 5, 3, DIR_DOWN, 'b' as u32, 0);
+        let changes = runner.tick().unwrap();
+        let (chunk, _) = runner.world.layers[0].get_where(-1, 0);
+        let (char_chunk, _) = runner.world.layers[3].get_where(5, 5);
+        assert_eq!(changes.modified, [(0, chunk), (3, char_chunk)]);
+        assert_eq!(
+            runner.world.layers[0].top_block(-1, 0),
+            Some(&Block::Color(0xFF0000FF))
+        );
+        assert_eq!(runner.tick().unwrap().modified, []);
+    }
+
+    #[test]
+    fn tick_n_is_the_same_as_ticking_n_times() {
+        let world = || {
+            world_with(&[
+                (0, 0, Block::Clock(3, 0)),
+                (1, 0, Block::Counter(0, DIR_RIGHT)),
+                (2, 0, Block::Splitter(DIR_UP)),
+                (2, -1, Block::Delay(2, DIR_UP)),
+                (2, -2, Block::Probe(DIR_UP)),
+                (2, 1, Block::Storage(1, 4, DIR_LEFT)),
+                (3, 0, Block::Random(42)),
+                (0, 5, Block::Clock(7, 2)),
+                (0, 6, Block::Move(DIR_RIGHT)),
+                (1, 6, Block::Color(9)),
+            ])
+        };
+        let mut a = Runner::new(world());
+        let mut b = Runner::new(world());
+        a.tick_n(50);
+        for _ in 0..50 {
+            b.tick();
+        }
+        assert_eq!(a.tick_counter, 50);
+        assert_eq!(a.tick_counter, b.tick_counter);
+        let blocks = |runner: &Runner| {
+            let mut blocks: Vec<_> = runner
+                .world
+                .iter_blocks()
+                .unwrap()
+                .map(|(layer, x, y, stack)| (layer, x, y, stack.clone()))
+                .collect();
+            blocks.sort_by_key(|&(layer, x, y, _)| (layer, x, y));
+            blocks
+        };
+        assert_eq!(blocks(&a), blocks(&b));
+        assert_ne!(blocks(&a), blocks(&Runner::new(world())));
+        assert_eq!(a.world.signals_queue, b.world.signals_queue);
+        assert_eq!(a.probe_log(), b.probe_log());
+        assert!(!a.probe_log().is_empty());
+    }
+
+    #[test]
+    fn moving_empty_space_creates_no_chunks() {
+        // the stacks in front of and behind the blocks are in chunks which don't exist yet
+        let mut runner = Runner::new(world_with(&[
+            (15, 0, Block::Move(DIR_RIGHT)),
+            (0, 15, Block::Swap(DIR_DOWN)),
+            (0, 0, Block::SwapAll(DIR_LEFT)),
+            (15, 15, Block::Destroy(DIR_RIGHT)),
+            (3, 3, Block::Duplicate(DIR_LEFT)),
+            (1, 1, Block::Move(DIR_UP_L)),
+        ]));
+        for _ in 0..3 {
+            for (x, y, dir) in [
+                (15, 0, DIR_UP),
+                (0, 15, DIR_LEFT),
+                (0, 0, DIR_UP),
+                (15, 15, DIR_UP),
+                (3, 3, DIR_UP),
+                (1, 1, DIR_UP),
+            ] {
+                runner.inject_signal(x, y, 0, dir, 0, 0);
+                runner.inject_signal(x, y, 0, dir, 1, 0);
+            }
+            // signals into empty space
+            runner.inject_signal(-100, 100, 0, DIR_RIGHT, 1, 0);
+            runner.inject_signal(0, 0, 7, DIR_RIGHT, 1, 0);
+            runner.tick();
+        }
+        assert!(runner.world.layers[0]
+            .chunks
+            .contains_key(&crate::world::chunk_key(0, 0)));
+        let chunks: usize = runner
+            .world
+            .layers
+            .iter()
+            .map(|layer| layer.chunks.len())
+            .sum();
+        assert_eq!(chunks, 1);
+        assert_eq!(runner.world.iter_blocks().unwrap().count(), 6);
+    }
+
+    #[test]
+    fn moves_wrap_around_layers_and_move_each_other() {
+        // on the lowest layer, the Move moves blocks between the layer above it and the upmost layer (through the wrap)
+        let mut world = World::new_empty();
+        world.wrap_layers = true;
+        let lowest = LAYER_COUNT - 1;
+        world.layers[lowest]
+            .try_push_block(0, 0, Block::Move(DIR_UP_L))
+            .unwrap();
+        world.layers[0]
+            .try_push_block(0, 0, Block::Color(1))
+            .unwrap();
+        let mut runner = Runner::new(world);
+        runner.inject_signal(0, 0, lowest as u8, DIR_RIGHT, 1, 0);
+        runner.tick();
+        let top =
+            |runner: &Runner, layer: usize| runner.world.layers[layer].top_block(0, 0).cloned();
+        assert_eq!(top(&runner, lowest - 1), Some(Block::Color(1)));
+        assert_eq!(top(&runner, 0), None);
+        runner.inject_signal(0, 0, lowest as u8, DIR_RIGHT, 0, 0);
+        runner.tick();
+        assert_eq!(top(&runner, 0), Some(Block::Color(1)));
+        assert_eq!(top(&runner, lowest), Some(Block::Move(DIR_UP_L)));
+        // a row of Moves which all move each other in the same tick: none of them is lost or duplicated
+        let mut runner = Runner::new(world_with(&[
+            (0, 0, Block::Move(DIR_RIGHT)),
+            (1, 0, Block::Move(DIR_LEFT)),
+            (2, 0, Block::Move(DIR_RIGHT)),
+            (3, 0, Block::Move(DIR_LEFT)),
+        ]));
+        for tick in 0..6 {
+            for x in -2..6 {
+                runner.inject_signal(x, 0, 0, DIR_UP, tick % 2, 0);
+            }
+            runner.tick();
+            let moves = runner
+                .world
+                .iter_blocks()
+                .unwrap()
+                .flat_map(|(_, _, _, stack)| stack)
+                .filter(|block| matches!(block, Block::Move(_)))
+                .count();
+            assert_eq!(moves, 4);
+        }
+    }
+
+    #[test]
+    fn signals_are_processed_in_order() {
+        // two signals arrive at the storage (which sets its value) in the same tick, from above and from below
+        let run = |first_from_above: bool| {
+            let mut runner = Runner::new(world_with(&[
+                (0, 0, Block::Delay(0, DIR_DOWN)),
+                (0, 1, Block::Storage(0, 0, DIR_RIGHT)),
+                (0, 2, Block::Delay(0, DIR_UP)),
+            ]));
+            let mut signals = [(0, DIR_DOWN, 3), (2, DIR_UP, 5)];
+            if !first_from_above {
+                signals.reverse();
+            }
+            for (y, dir, value) in signals {
+                runner.inject_signal(0, y, 0, dir, value, 0);
+            }
+            runner.tick_n(2);
+            runner.world.layers[0].top_block(0, 1).cloned()
+        };
+        for _ in 0..10 {
+            assert_eq!(run(true), Some(Block::Storage(5, 0, DIR_RIGHT)));
+            assert_eq!(run(false), Some(Block::Storage(3, 0, DIR_RIGHT)));
+        }
+    }
+
+    #[test]
+    fn not_inverts_signals() {
+        let mut runner = Runner::new(world_with(&[
+            (0, 0, Block::Not(DIR_RIGHT)),
+            (1, 0, Block::Probe(DIR_RIGHT)),
+        ]));
+        runner.inject_signal(0, 0, 0, DIR_RIGHT, 0, 0);
+        runner.inject_signal(0, 0, 0, DIR_RIGHT, 0xF0F0F0F0, 1);
+        // side-signals and signals from the front are ignored
+        runner.inject_signal(0, 0, 0, DIR_DOWN, 1, 0);
+        runner.inject_signal(0, 0, 0, DIR_LEFT, 1, 0);
+        runner.tick_n(3);
+        assert_eq!(probed_values(&runner), [u32::MAX, 0x0F0F0F0F]);
+        assert_eq!(
+            runner.world.layers[0].top_block(0, 0),
+            Some(&Block::Not(DIR_RIGHT))
+        );
+    }
+
+    #[test]
+    fn random_sequences_only_depend_on_the_seed() {
+        let world = |seed| {
+            world_with(&[
+                (0, 0, Block::Clock(1, 0)),
+                (1, 0, Block::Random(seed)),
+                (2, 0, Block::Probe(DIR_RIGHT)),
+            ])
+        };
+        let mut a = Runner::new(world(1234));
+        let mut b = Runner::new(world(1234));
+        let mut other = Runner::new(world(1235));
+        a.tick_n(100);
+        b.tick_n(100);
+        other.tick_n(100);
+        assert_eq!(probed_values(&a).len(), 98);
+        assert_eq!(probed_values(&a), probed_values(&b));
+        assert_ne!(probed_values(&a), probed_values(&other));
+        // the generator's state is saved, so a reloaded world continues the same sequence
+        let dir =
+            std::env::temp_dir().join(format!("stackmaker-test-random-{}", std::process::id()));
+        let mut before = Runner::new(world(1234));
+        before.tick_n(40);
+        before.world.save_to_dir(&dir).unwrap();
+        let mut after = Runner::new(World::load_from_dir(&dir, None).unwrap().unwrap());
+        after.tick_n(60);
+        let mut reloaded = probed_values(&before);
+        reloaded.extend(probed_values(&after));
+        assert_eq!(reloaded, probed_values(&a));
+        _ = std::fs::remove_dir_all(&dir);
+    }
+
+    #[test]
+    fn compare_passes_signals_which_match() {
+        let passed = |op: u8| -> Vec<u32> {
+            let mut runner = Runner::new(world_with(&[
+                (0, 0, Block::Compare(0, op, DIR_RIGHT)),
+                (1, 0, Block::Probe(DIR_RIGHT)),
+            ]));
+            // sets the threshold
+            runner.inject_signal(0, 0, 0, DIR_UP, 5, 0);
+            for value in [4, 5, 6] {
+                runner.inject_signal(0, 0, 0, DIR_RIGHT, value, 1);
+            }
+            runner.tick_n(3);
+            assert_eq!(
+                runner.world.layers[0].top_block(0, 0),
+                Some(&Block::Compare(5, op, DIR_RIGHT))
+            );
+            probed_values(&runner)
+        };
+        assert_eq!(passed(0), [4]);
+        assert_eq!(passed(1), [4, 5]);
+        assert_eq!(passed(2), [5]);
+        assert_eq!(passed(3), [5, 6]);
+        assert_eq!(passed(4), [6]);
+        assert_eq!(passed(5), []);
+    }
+
+    #[test]
+    fn storage_can_wrap_instead_of_saturating() {
+        assert_eq!(storage_after(14, u32::MAX - 1, &[5]), 3);
+        assert_eq!(storage_after(14, 10, &[u32::MAX, u32::MAX]), 8);
+        assert_eq!(storage_after(15, 2, &[5]), u32::MAX - 2);
+        assert_eq!(storage_after(16, 0x8000_0001, &[4]), 4);
+        assert_eq!(storage_after(16, 3, &[5, 7]), 105);
+        // the saturating modes stop at the limits
+        assert_eq!(storage_after(4, u32::MAX - 1, &[5]), u32::MAX);
+        assert_eq!(storage_after(5, 2, &[5]), 0);
+        assert_eq!(storage_after(6, 0x8000_0001, &[4]), u32::MAX);
+    }
+
+    #[test]
+    fn directions() {
+        use crate::world::chunk_key;
+        const PLANAR: [u8; 4] = [DIR_LEFT, DIR_RIGHT, DIR_UP, DIR_DOWN];
+        const ALL: [u8; 6] = [DIR_LEFT, DIR_RIGHT, DIR_UP, DIR_DOWN, DIR_UP_L, DIR_DOWN_L];
+        let layer = Layer::default();
+        // (x, y, layer) after moving one step in every direction from (x, y) on layer 4
+        let step = |dir: u8, x: i64, y: i64| {
+            let (chunk, inner) = layer.get_where(x, y);
+            pos_moved(dir | 4, chunk, inner, false).map(|(dir_layer, chunk, inner)| {
+                assert!(is_same_dir(dir_layer, dir));
+                let (x, y) = Layer::where_to_coords(chunk, inner);
+                (x, y, dir_layer & LAYER_MASK)
+            })
+        };
+        // inside a chunk and across every chunk border
+        for (x, y) in [(5, 5), (0, 0), (15, 15), (-1, -1), (-16, 0), (16, -17)] {
+            assert_eq!(step(DIR_LEFT, x, y), Some((x - 1, y, 4)));
+            assert_eq!(step(DIR_RIGHT, x, y), Some((x + 1, y, 4)));
+            assert_eq!(step(DIR_UP, x, y), Some((x, y - 1, 4)));
+            assert_eq!(step(DIR_DOWN, x, y), Some((x, y + 1, 4)));
+            assert_eq!(step(DIR_UP_L, x, y), Some((x, y, 3)));
+            assert_eq!(step(DIR_DOWN_L, x, y), Some((x, y, 5)));
+        }
+        // the upmost and lowest layer, with and without wrapping
+        let lowest = (LAYER_COUNT - 1) as u8;
+        let (chunk, inner) = (chunk_key(-3, 2), 17);
+        assert_eq!(pos_moved(DIR_UP_L, chunk, inner, false), None);
+        assert_eq!(pos_moved(DIR_DOWN_L | lowest, chunk, inner, false), None);
+        assert_eq!(
+            pos_moved(DIR_DOWN_L, chunk, inner, false),
+            Some((DIR_DOWN_L | 1, chunk, inner))
+        );
+        assert_eq!(
+            pos_moved(DIR_UP_L | lowest, chunk, inner, false),
+            Some((DIR_UP_L | (lowest - 1), chunk, inner))
+        );
+        assert_eq!(
+            pos_moved(DIR_UP_L, chunk, inner, true),
+            Some((DIR_UP_L | lowest, chunk, inner))
+        );
+        assert_eq!(
+            pos_moved(DIR_DOWN_L | lowest, chunk, inner, true),
+            Some((DIR_DOWN_L, chunk, inner))
+        );
+        // pos_move leaves the position unchanged if it can't move
+        let (mut dir_layer, mut pos_chunk, mut pos_inner) = (DIR_UP_L, chunk, inner);
+        assert!(!pos_move(
+            &mut dir_layer,
+            &mut pos_chunk,
+            &mut pos_inner,
+            false
+        ));
+        assert_eq!((dir_layer, pos_chunk, pos_inner), (DIR_UP_L, chunk, inner));
+        assert!(pos_move(
+            &mut dir_layer,
+            &mut pos_chunk,
+            &mut pos_inner,
+            true
+        ));
+        assert_eq!(
+            (dir_layer, pos_chunk, pos_inner),
+            (DIR_UP_L | lowest, chunk, inner)
+        );
+        // the unused direction doesn't move
+        assert_eq!(pos_moved(0b11100000 | 4, chunk, inner, true), None);
+        assert_eq!(pos_moved(4, chunk, inner, true), None);
+        // reversing
+        for (a, b) in [
+            (DIR_LEFT, DIR_RIGHT),
+            (DIR_UP, DIR_DOWN),
+            (DIR_UP_L, DIR_DOWN_L),
+        ] {
+            assert_eq!(dir_rev(a), b);
+            assert_eq!(dir_rev(b | 7), a | 7);
+        }
+        // side-signals come from the four directions which aren't along the block's axis
+        for a in ALL {
+            for b in ALL {
+                let same_axis = b == a || b == dir_rev(a);
+                assert_eq!(is_side(a, b | 9), !same_axis, "{a:08b} {b:08b}");
+                assert_eq!(is_same_dir(a | 1, b), a == b);
+            }
+        }
+        assert!(PLANAR
+            .iter()
+            .all(|&dir| is_side(dir, DIR_UP_L) && is_side(DIR_DOWN_L, dir)));
+    }
+
+    #[test]
+    fn signals_can_wrap_around_the_layers() {
+        let lowest = LAYER_COUNT - 1;
+        let probed = |wrap_layers: bool| -> Vec<usize> {
+            let mut world = World::new_empty();
+            world.wrap_layers = wrap_layers;
+            world.layers[0]
+                .try_push_block(0, 0, Block::Delay(0, DIR_UP_L))
+                .unwrap();
+            world.layers[lowest]
+                .try_push_block(0, 0, Block::Probe(DIR_UP_L))
+                .unwrap();
+            world.layers[lowest]
+                .try_push_block(1, 0, Block::Delay(0, DIR_DOWN_L))
+                .unwrap();
+            world.layers[0]
+                .try_push_block(1, 0, Block::Probe(DIR_DOWN_L))
+                .unwrap();
+            let mut runner = Runner::new(world);
+            runner.inject_signal(0, 0, 0, DIR_UP_L, 1, 0);
+            runner.inject_signal(1, 0, lowest as u8, DIR_DOWN_L, 2, 0);
+            runner.tick_n(3);
+            runner
+                .probe_log()
+                .iter()
+                .map(|record| record.layer)
+                .collect()
+        };
+        assert_eq!(probed(true), [lowest, 0]);
+        assert_eq!(probed(false), []);
+    }
+
+    #[test]
+    fn signal_buffers_are_reused() {
+        // 20 signals going around in circles forever
+        let mut runner = Runner::new(world_with(&[
+            (0, 0, Block::Fan(1 << 3)),
+            (1, 0, Block::Fan(1 << 2)),
+            (1, 1, Block::Fan(1 << 0)),
+            (0, 1, Block::Fan(1 << 1)),
+        ]));
+        for i in 0..20 {
+            runner.inject_signal(0, 0, 0, DIR_RIGHT, i, i as usize % 3);
+        }
+        runner.tick_n(10);
+        let buffers = |runner: &Runner| -> Vec<*const (u32, u8, u64, u8)> {
+            let world = &runner.world;
+            world
+                .signals_queue
+                .iter()
+                .chain(&world.spare_signals)
+                .filter(|buffer| buffer.capacity() > 0)
+                .map(|buffer| buffer.as_ptr())
+                .collect()
+        };
+        let allocated = buffers(&runner);
+        for _ in 0..1000 {
+            runner.tick();
+            for buffer in buffers(&runner) {
+                assert!(
+                    allocated.contains(&buffer),
+                    "a new signal buffer was allocated"
+                );
+            }
+        }
+        assert_eq!(runner.pending_signals(), 20);
+    }
+
+    #[test]
+    fn counters_count_and_reset() {
+        let mut runner = Runner::new(world_with(&[
+            (0, 0, Block::Counter(0, DIR_RIGHT)),
+            (1, 0, Block::Probe(DIR_RIGHT)),
+        ]));
+        for i in 0..3 {
+            runner.inject_signal(0, 0, 0, DIR_RIGHT, 100, i);
+        }
+        runner.tick_n(4);
+        assert_eq!(probed_values(&runner), [1, 2, 3]);
+        assert_eq!(
+            runner.world.layers[0].top_block(0, 0),
+            Some(&Block::Counter(3, DIR_RIGHT))
+        );
+        runner.inject_signal(0, 0, 0, DIR_DOWN, 100, 0);
+        runner.tick_n(2);
+        assert_eq!(
+            runner.world.layers[0].top_block(0, 0),
+            Some(&Block::Counter(0, DIR_RIGHT))
+        );
+        runner.inject_signal(0, 0, 0, DIR_RIGHT, 100, 0);
+        runner.tick_n(2);
+        assert_eq!(probed_values(&runner), [1, 2, 3, 1]);
+        // the count saturates
+        let mut runner = Runner::new(world_with(&[(0, 0, Block::Counter(u32::MAX, DIR_RIGHT))]));
+        runner.inject_signal(0, 0, 0, DIR_RIGHT, 0, 0);
+        runner.tick();
+        assert_eq!(
+            runner.world.layers[0].top_block(0, 0),
+            Some(&Block::Counter(u32::MAX, DIR_RIGHT))
+        );
+    }
+
+    #[test]
+    fn idle_once_every_signal_arrived() {
+        let mut runner = Runner::new(World::new_empty());
+        assert!(runner.is_idle());
+        runner.inject_signal(3, 3, 0, DIR_UP, 1, 4);
+        for _ in 0..4 {
+            assert!(!runner.is_idle());
+            assert_eq!(runner.pending_signals(), 1);
+            runner.tick();
+        }
+        runner.tick();
+        assert!(runner.is_idle());
+        assert_eq!(runner.pending_signals(), 0);
+        // signals which are passed on keep the runner busy
+        let mut runner = Runner::new(world_with(&[(0, 0, Block::Delay(2, DIR_RIGHT))]));
+        runner.inject_signal(0, 0, 0, DIR_RIGHT, 1, 0);
+        runner.tick();
+        assert_eq!(runner.pending_signals(), 1);
+        runner.tick_n(2);
+        assert!(!runner.is_idle());
+        runner.tick();
+        assert!(runner.is_idle());
+    }
+
+    #[test]
+    fn step_until_stable_stops_at_the_cap() {
+        // a signal passing two delays settles
+        let mut runner = Runner::new(world_with(&[
+            (0, 0, Block::Delay(3, DIR_RIGHT)),
+            (1, 0, Block::Delay(1, DIR_RIGHT)),
+        ]));
+        runner.inject_signal(0, 0, 0, DIR_RIGHT, 1, 0);
+        assert_eq!(runner.step_until_stable(100), 7);
+        assert_eq!(runner.tick_counter, 7);
+        assert_eq!(runner.step_until_stable(100), 0);
+        // a signal going around in a circle never does
+        let mut runner = Runner::new(world_with(&[
+            (0, 0, Block::Fan(1 << 3)),
+            (1, 0, Block::Fan(1 << 2)),
+            (1, 1, Block::Fan(1 << 0)),
+            (0, 1, Block::Fan(1 << 1)),
+        ]));
+        runner.inject_signal(0, 0, 0, DIR_RIGHT, 1, 0);
+        assert_eq!(runner.step_until_stable(50), 50);
+        assert_eq!(runner.tick_counter, 50);
+        assert_eq!(runner.pending_signals(), 1);
+    }
+
+    #[test]
+    fn storage_keeps_the_smallest_or_largest_value() {
+        let signals = [7, 3, 9, 3, 12, 0, 5];
+        let running = |mode, start| -> Vec<u32> {
+            (1..=signals.len())
+                .map(|n| storage_after(mode, start, &signals[..n]))
+                .collect()
+        };
+        assert_eq!(running(9, u32::MAX), [7, 3, 3, 3, 3, 0, 0]);
+        assert_eq!(running(10, 0), [7, 7, 9, 9, 12, 12, 12]);
+        assert_eq!(running(9, 5), [5, 3, 3, 3, 3, 0, 0]);
+        assert_eq!(running(10, 8), [8, 8, 9, 9, 12, 12, 12]);
+    }
+
+    #[test]
+    fn fans_emit_in_every_enabled_direction() {
+        let mut runner = Runner::new(world_with(&[
+            (0, 0, Block::Fan(0b111)),
+            (-1, 0, Block::Probe(DIR_LEFT)),
+            (0, -1, Block::Probe(DIR_UP)),
+            (0, 1, Block::Probe(DIR_DOWN)),
+            (1, 0, Block::Probe(DIR_RIGHT)),
+        ]));
+        runner.world.layers[1]
+            .try_push_block(0, 0, Block::Probe(DIR_DOWN_L))
+            .unwrap();
+        runner.inject_signal(0, 0, 0, DIR_RIGHT, 42, 0);
+        runner.tick();
+        assert_eq!(runner.pending_signals(), 3);
+        runner.tick();
+        let mut log = probed(&runner);
+        log.sort_unstable();
+        assert_eq!(log, [(-1, 0, 42), (0, -1, 42), (0, 1, 42)]);
+    }
+
+    #[test]
+    fn probes_log_signals_in_order() {
+        let mut runner = Runner::new(world_with(&[
+            (0, 0, Block::Probe(DIR_RIGHT)),
+            (1, 0, Block::Probe(DIR_RIGHT)),
+        ]));
+        for (value, delay) in [(5, 0), (6, 0), (7, 2)] {
+            runner.inject_signal(0, 0, 0, DIR_RIGHT, value, delay);
+        }
+        // only signals from behind are logged
+        runner.inject_signal(0, 0, 0, DIR_UP, 8, 0);
+        runner.tick_n(5);
+        let log: Vec<_> = runner
+            .probe_log()
+            .iter()
+            .map(|record| (record.tick, record.x, record.value))
+            .collect();
+        // each signal is passed on to the second probe, unchanged
+        assert_eq!(
+            log,
+            [
+                (1, 0, 5),
+                (1, 0, 6),
+                (2, 1, 5),
+                (2, 1, 6),
+                (3, 0, 7),
+                (4, 1, 7)
+            ]
+        );
+        // the oldest records are dropped once the log is full
+        for i in 0..PROBE_LOG_LEN as u32 {
+            runner.inject_signal(1, 0, 0, DIR_RIGHT, 100 + i, 0);
+        }
+        runner.tick();
+        assert_eq!(runner.probe_log().len(), PROBE_LOG_LEN);
+        assert_eq!(runner.probe_log().front().unwrap().value, 100);
+        runner.clear_probe_log();
+        assert!(runner.probe_log().is_empty());
+    }
+
+    #[test]
+    fn swap_all_exchanges_whole_stacks() {
+        let tall = [Block::Color(1), Block::Color(2), Block::Char('c' as u32)];
+        let mut runner = Runner::new(world_with(&[
+            (-1, 0, tall[0].clone()),
+            (-1, 0, tall[1].clone()),
+            (-1, 0, tall[2].clone()),
+            (0, 0, Block::SwapAll(DIR_RIGHT)),
+            (1, 0, Block::Color(9)),
+            (0, 5, Block::Swap(DIR_RIGHT)),
+        ]));
+        let stack = |runner: &Runner, x, y| -> Vec<Block> {
+            let (chunk, inner) = runner.world.layers[0].get_where(x, y);
+            runner.world.layers[0]
+                .get(&chunk)
+                .map_or(vec![], |chunk| chunk[inner as usize].clone())
+        };
+        runner.inject_signal(0, 0, 0, DIR_UP, 0, 0);
+        runner.tick();
+        assert_eq!(stack(&runner, -1, 0), [Block::Color(9)]);
+        assert_eq!(stack(&runner, 1, 0), tall);
+        // with an empty stack, the other one is moved over
+        runner.world.layers[0].try_pop_block(-1, 0).unwrap();
+        runner.inject_signal(0, 0, 0, DIR_DOWN, 1, 0);
+        runner.tick();
+        assert_eq!(stack(&runner, -1, 0), tall);
+        assert_eq!(stack(&runner, 1, 0), []);
+        assert_eq!(stack(&runner, 0, 0), [Block::SwapAll(DIR_RIGHT)]);
+        // a Swap only exchanges the top blocks
+        for block in &tall {
+            runner.world.layers[0]
+                .try_push_block(-1, 5, block.clone())
+                .unwrap();
+        }
+        runner.world.layers[0]
+            .try_push_block(1, 5, Block::Color(9))
+            .unwrap();
+        runner.inject_signal(0, 5, 0, DIR_UP, 0, 0);
+        runner.tick();
+        assert_eq!(
+            stack(&runner, -1, 5),
+            [tall[0].clone(), tall[1].clone(), Block::Color(9)]
+        );
+        assert_eq!(stack(&runner, 1, 5), [tall[2].clone()]);
+    }
+
+    #[test]
+    fn duplicate_copies_the_top_block() {
+        let mut runner = Runner::new(world_with(&[
+            (-1, 0, Block::Char('a' as u32)),
+            (-1, 0, Block::Color(0xFF112233)),
+            (0, 0, Block::Duplicate(DIR_RIGHT)),
+            (1, 5, Block::Duplicate(DIR_RIGHT)),
+        ]));
+        let top = |runner: &Runner, x, y| runner.world.layers[0].top_block(x, y).cloned();
+        // only `0` side-signals duplicate
+        runner.inject_signal(0, 0, 0, DIR_UP, 1, 0);
+        runner.inject_signal(0, 0, 0, DIR_RIGHT, 0, 0);
+        runner.tick();
+        assert_eq!(top(&runner, 1, 0), None);
+        runner.inject_signal(0, 0, 0, DIR_DOWN, 0, 0);
+        runner.tick();
+        assert_eq!(top(&runner, 1, 0), Some(Block::Color(0xFF112233)));
+        assert_eq!(top(&runner, -1, 0), Some(Block::Color(0xFF112233)));
+        runner.inject_signal(0, 0, 0, DIR_DOWN, 0, 0);
+        runner.tick();
+        assert_eq!(runner.world.layers[0].get(&0).unwrap()[1].len(), 2);
+        // nothing in front
+        runner.inject_signal(1, 5, 0, DIR_UP, 0, 0);
+        runner.tick();
+        assert_eq!(top(&runner, 2, 5), None);
+    }
+
+    #[test]
+    fn destroy_removes_the_top_block() {
+        let mut runner = Runner::new(world_with(&[
+            (0, 0, Block::Destroy(DIR_RIGHT)),
+            (1, 0, Block::Color(1)),
+            (1, 0, Block::Color(2)),
+            (-1, 0, Block::Color(3)),
+        ]));
+        let height = |runner: &Runner, x| runner.world.layers[0].get(&0).unwrap()[x as usize].len();
+        runner.inject_signal(0, 0, 0, DIR_UP, 7, 0);
+        runner.tick();
+        assert_eq!(height(&runner, 1), 1);
+        assert_eq!(
+            runner.world.layers[0].top_block(1, 0),
+            Some(&Block::Color(1))
+        );
+        // signals along its axis don't destroy anything
+        runner.inject_signal(0, 0, 0, DIR_RIGHT, 7, 0);
+        runner.tick();
+        assert_eq!(height(&runner, 1), 1);
+        runner.inject_signal(0, 0, 0, DIR_DOWN, 0, 0);
+        runner.inject_signal(0, 0, 0, DIR_DOWN, 0, 0);
+        runner.tick();
+        assert_eq!(height(&runner, 1), 0);
+        assert_eq!(height(&runner, 0), 1);
+        assert_eq!(
+            runner.world.layers[0].top_block(-1, 0),
+            Some(&Block::Color(3))
+        );
+    }
+
+    #[test]
+    fn gates_and_inverted_gates() {
+        // a normal gate at y = 0, an inverted one at y = 2
+        let mut runner = Runner::new(world_with(&[
+            (0, 0, Block::Gate(false, DIR_RIGHT)),
+            (1, 0, Block::Probe(DIR_RIGHT)),
+            (0, 2, Block::Gate(false, DIR_RIGHT | GATE_INVERTED)),
+            (1, 2, Block::Probe(DIR_RIGHT)),
+        ]));
+        let mut passed = |control: u32| -> Vec<i64> {
+            runner.clear_probe_log();
+            for y in [0, 2] {
+                runner.inject_signal(0, y, 0, DIR_UP, control, 0);
+                runner.inject_signal(0, y, 0, DIR_RIGHT, 1, 1);
+            }
+            runner.tick_n(3);
+            runner.probe_log().iter().map(|record| record.y).collect()
+        };
+        assert_eq!(passed(0), [0]);
+        assert_eq!(passed(1), [2]);
+        assert_eq!(passed(u32::MAX), [2]);
+        assert_eq!(passed(0), [0]);
+        assert_eq!(
+            runner.world.layers[0].top_block(0, 0),
+            Some(&Block::Gate(true, DIR_RIGHT))
+        );
+        assert_eq!(
+            runner.world.layers[0].top_block(0, 2),
+            Some(&Block::Gate(false, DIR_RIGHT | GATE_INVERTED))
+        );
+    }
+
+    #[test]
+    fn delays_by_value_wait_as_long_as_the_value_says() {
+        let mut runner = Runner::new(world_with(&[
+            (0, 0, Block::Delay(0, DIR_RIGHT | DELAY_BY_VALUE)),
+            (1, 0, Block::Probe(DIR_RIGHT)),
+            (0, 1, Block::Delay(5, DIR_RIGHT)),
+            (1, 1, Block::Probe(DIR_RIGHT)),
+        ]));
+        runner.max_value_delay = 10;
+        runner.inject_signal(0, 0, 0, DIR_RIGHT, 5, 0);
+        runner.inject_signal(0, 1, 0, DIR_RIGHT, 5, 0);
+        // waits `max_value_delay` ticks
+        runner.inject_signal(0, 0, 0, DIR_RIGHT, 5000, 1);
+        runner.inject_signal(0, 0, 0, DIR_RIGHT, 0, 2);
+        runner.tick_n(20);
+        let log: Vec<_> = runner
+            .probe_log()
+            .iter()
+            .map(|record| (record.tick, record.y, record.value))
+            .collect();
+        // received in tick 1, then waits 5 ticks just like a `Delay(5)`
+        assert_eq!(log, [(4, 0, 0), (7, 0, 5), (7, 1, 5), (13, 0, 5000)]);
+    }
+
+    #[test]
+    fn huge_delays_are_clamped() {
+        use crate::world::MAX_DELAY;
+        let mut runner = Runner::new(world_with(&[
+            (0, 0, Block::Delay(u32::MAX, DIR_RIGHT)),
+            (1, 0, Block::Delay(0, DIR_RIGHT | DELAY_BY_VALUE)),
+            (2, 0, Block::Probe(DIR_RIGHT)),
+        ]));
+        runner.max_value_delay = u32::MAX;
+        runner.inject_signal(0, 0, 0, DIR_RIGHT, u32::MAX, 0);
+        runner.inject_signal(0, 0, 0, DIR_RIGHT, 1, usize::MAX);
+        assert_eq!(runner.world.signals_queue.len(), MAX_DELAY + 1);
+        runner.tick();
+        assert_eq!(runner.world.signals_queue.len(), MAX_DELAY + 1);
+        runner.tick_n(MAX_DELAY as u64);
+        // the value delay is clamped, too
+        assert!(runner.world.signals_queue.len() <= MAX_DELAY + 1);
+        assert_eq!(runner.pending_signals(), 2);
+        runner.tick_n(MAX_DELAY as u64 + 2);
+        assert_eq!(probe_ticks(&runner), [2 * MAX_DELAY as u64 + 3]);
+    }
+
+    #[test]
+    fn signals_stop_at_the_edge_of_the_world() {
+        let (min, max) = (i32::MIN as i64 * 16, i32::MAX as i64 * 16 + 15);
+        let layer = Layer::default();
+        // (x, y, direction past the edge, the step back into the world)
+        for (x, y, dir, (dx, dy)) in [
+            (max, 0, DIR_RIGHT, (-1, 0)),
+            (min, -3, DIR_LEFT, (1, 0)),
+            (5, min, DIR_UP, (0, 1)),
+            (-5, max, DIR_DOWN, (0, -1)),
+            (max, max, DIR_DOWN, (0, -1)),
+            (min, max, DIR_LEFT, (1, 0)),
+        ] {
+            let (chunk, inner) = layer.get_where(x, y);
+            assert_eq!(pos_moved(dir, chunk, inner, true), None, "({x}, {y})");
+            let (back_chunk, back_inner) = layer.get_where(x + dx, y + dy);
+            assert_eq!(
+                pos_moved(dir_rev(dir), chunk, inner, true),
+                Some((dir_rev(dir), back_chunk, back_inner))
+            );
+        }
+        let mut runner = Runner::new(world_with(&[
+            (max, 7, Block::Delay(0, DIR_RIGHT)),
+            (max - 1, min, Block::Splitter(DIR_UP)),
+        ]));
+        runner.inject_signal(max, 7, 0, DIR_RIGHT, 1, 0);
+        runner.inject_signal(max - 1, min, 0, DIR_RIGHT, 1, 0);
+        runner.tick();
+        // the splitter's other output is still inside the world
+        let (chunk, inner) = layer.get_where(max - 1, min + 1);
+        assert_eq!(runner.world.signals_queue[0], [(1, DIR_DOWN, chunk, inner)]);
+        runner.tick();
+        assert!(runner.is_idle());
+        assert_eq!(runner.world.layers[0].chunks.len(), 2);
+    }
+}
+
+#[cfg(all(test, feature = "rayon"))]
+mod parallel_tests {
+    use super::*;
+
+    const DIRS: [u8; 6] = [DIR_UP_L, DIR_DOWN_L, DIR_LEFT, DIR_RIGHT, DIR_UP, DIR_DOWN];
+
+    /// xorshift, so every seed always builds the same world
+    struct Rng(u64);
+    impl Rng {
+        fn below(&mut self, n: u64) -> u64 {
+            self.0 ^= self.0 << 13;
+            self.0 ^= self.0 >> 7;
+            self.0 ^= self.0 << 17;
+            self.0 % n
+        }
+    }
+
+    /// a small world full of random blocks (of every kind) and signals
+    fn random_world(seed: u64) -> World {
+        let mut rng = Rng(seed.wrapping_mul(2654435761) + 12345);
+        let mut world = World::new_empty();
+        world.wrap_layers = rng.below(2) == 0;
+        let layers = [0, 1, 2, LAYER_COUNT - 1];
+        let size = 4 + rng.below(40) as i64;
+        let pos = |rng: &mut Rng| {
+            let x = rng.below(size as u64) as i64 - size / 2;
+            let y = rng.below(size as u64) as i64 - size / 2;
+            (layers[rng.below(4) as usize], x, y)
+        };
+        for _ in 0..50 + rng.below(400) {
+            let (layer, x, y) = pos(&mut rng);
+            let mut d = DIRS[rng.below(6) as usize];
+            // makes `tick_parallel` fall back to processing the signals on one thread
+            if rng.below(300) == 0 {
+                d |= 1;
+            }
+            let v = rng.below(5) as u32;
+            let block = match rng.below(20) {
+                0 => Block::Color(v),
+                1 => Block::Char(v),
+                2 => Block::Delay(v, d | (rng.below(2) as u8 * DELAY_BY_VALUE)),
+                3 => Block::Storage(v, rng.below(20) as u8, d),
+                4 => Block::Gate(rng.below(2) == 0, d | rng.below(4) as u8),
+                5 => Block::Splitter(d),
+                6 => Block::Fan(rng.below(64) as u8),
+                7 => Block::Clock(v, rng.below(3) as u32),
+                8 => Block::Compare(v, rng.below(6) as u8, d),
+                9 => Block::Not(d),
+                10 => Block::Probe(d),
+                11 => Block::Random(rng.below(u64::MAX)),
+                12 => Block::Counter(v, d),
+                13 => Block::Height(d),
+                14 | 15 => Block::Move(d),
+                16 => Block::Swap(d),
+                17 => Block::SwapAll(d),
+                18 => Block::Duplicate(d),
+                _ => Block::Destroy(d),
+            };
+            world.layers[layer].try_push_block(x, y, block).unwrap();
+        }
+        for delta_t in 0..3 {
+            for _ in 0..rng.below(60) {
+                let (layer, x, y) = pos(&mut rng);
+                let (chunk, inner) = world.layers[0].get_where(x, y);
+                let dir = DIRS[rng.below(6) as usize];
+                let value = rng.below(4) as u32;
+                world
+                    .signals_mut(delta_t)
+                    .push((value, dir | layer as u8, chunk, inner));
+            }
+        }
+        world
+    }
+
+    /// compares every chunk (including empty ones), the dirty flags and the signal queue
+    fn same_world(a: &World, b: &World) -> bool {
+        a.layers
+            .iter()
+            .zip(&b.layers)
+            .all(|(a, b)| a.chunks == b.chunks && a.is_dirty() == b.is_dirty())
+            && a.signals_queue == b.signals_queue
+    }
+
+    #[test]
+    fn tick_parallel_matches_tick() {
+        for seed in 1..=1000 {
+            let mut serial = Runner::new(random_world(seed));
+            let mut parallel = Runner::new(random_world(seed));
+            serial.track_changes = seed % 2 == 0;
+            parallel.track_changes = seed % 2 == 0;
+            // even if the system can only run one thread at a time
+            parallel.threads = 4;
+            for tick in 0..40 {
+                // value-dependent delays can make the queue very long, which the test doesn't need
+                if serial.world.signals_queue.len() > 64 || serial.pending_signals() > 20000 {
+                    break;
+                }
+                let changes = serial.tick().map(|changes| changes.modified);
+                let parallel_changes = parallel.tick_parallel().map(|changes| changes.modified);
+                assert_eq!(changes, parallel_changes, "seed {seed}, tick {tick}");
+                assert!(
+                    same_world(&serial.world, &parallel.world),
+                    "seed {seed}, tick {tick}: the worlds differ"
+                );
+                assert_eq!(
+                    serial.probe_log(),
+                    parallel.probe_log(),
+                    "seed {seed}, tick {tick}"
+                );
+            }
+        }
+    }
+}
@@ -0,0 +1,104 @@
+//! A tiny text format for building small worlds by hand, for example for tests.
+//!
+//! Every character is one block on layer 0. The character in row `y` and column `x` (both starting at 0)
+//! is placed at `(x, y)`.
+//!
+//! | character            | block                                                                   |
+//! |----------------------|-------------------------------------------------------------------------|
+//! | space or `.`         | nothing                                                                 |
+//! | `#`                  | `Color(0xFFFFFFFF)`                                                     |
+//! | `>` `<` `^` `v`      | `Splitter` facing right, left, up or down                               |
+//! | `R` `L` `U` `D`      | `Delay(0, ..)` facing right, left, up or down, to carry signals forward |
+//! | `0` to `9`           | `Storage(0, mode, DIR_UP)`, so signals moving left or right change it   |
+
+use crate::{
+    runner::{DIR_DOWN, DIR_LEFT, DIR_RIGHT, DIR_UP},
+    world::{Block, World},
+};
+
+#[derive(Debug)]
+pub struct SchematicError(pub String);
+
+impl std::fmt::Display for SchematicError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "invalid schematic: {}", self.0)
+    }
+}
+impl std::error::Error for SchematicError {}
+
+impl World {
+    /// Builds a world from a schematic, see the module documentation for the format.
+    pub fn from_schematic(src: &str) -> Result<Self, SchematicError> {
+        let mut world = Self::new_empty();
+        for (y, line) in src.lines().enumerate() {
+            for (x, ch) in line.chars().enumerate() {
+                let block = match ch {
+                    ' ' | '.' => continue,
+                    '#' => Block::Color(0xFFFFFFFF),
+                    '>' => Block::Splitter(DIR_RIGHT),
+                    '<' => Block::Splitter(DIR_LEFT),
+                    '^' => Block::Splitter(DIR_UP),
+                    'v' => Block::Splitter(DIR_DOWN),
+                    'R' => Block::Delay(0, DIR_RIGHT),
+                    'L' => Block::Delay(0, DIR_LEFT),
+                    'U' => Block::Delay(0, DIR_UP),
+                    'D' => Block::Delay(0, DIR_DOWN),
+                    '0'..='9' => Block::Storage(0, ch as u8 - b'0', DIR_UP),
+                    other => {
+                        return Err(SchematicError(format!(
+                            "unknown block {other:?} at line {}, column {}",
+                            y + 1,
+                            x + 1
+                        )))
+                    }
+                };
+                world.layers[0]
+                    .try_push_block(x as i64, y as i64, block)
+                    .map_err(|e| SchematicError(e.to_string()))?;
+            }
+        }
+        Ok(world)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::runner::Runner;
+
+    #[test]
+    fn schematics_can_be_simulated() {
+        let world = World::from_schematic(
+            "\
+..#
+RR^
+..#
+R4.",
+        )
+        .unwrap();
+        assert_eq!(
+            world.layers[0].top_block(1, 1),
+            Some(&Block::Delay(0, DIR_RIGHT))
+        );
+        assert_eq!(world.layers[0].top_block(1, 0), None);
+        let mut runner = Runner::new(world);
+        // split up and down into the colors
+        runner.inject_signal(0, 1, 0, DIR_RIGHT, 0xFF00FF00, 0);
+        // added to the storage
+        runner.inject_signal(0, 3, 0, DIR_RIGHT, 2, 0);
+        runner.inject_signal(0, 3, 0, DIR_RIGHT, 3, 1);
+        assert_eq!(runner.step_until_stable(10), 4);
+        let top = |x, y| runner.world.layers[0].top_block(x, y).cloned();
+        assert_eq!(top(2, 0), Some(Block::Color(0xFF00FF00)));
+        assert_eq!(top(2, 2), Some(Block::Color(0xFF00FF00)));
+        assert_eq!(top(1, 3), Some(Block::Storage(5, 4, DIR_UP)));
+    }
+
+    #[test]
+    fn unknown_characters_are_rejected() {
+        let Err(err) = World::from_schematic("RR\n.x") else {
+            panic!("the schematic was accepted");
+        };
+        assert_eq!(err.0, "unknown block 'x' at line 2, column 2");
+    }
+}
@@ -0,0 +1,117 @@
+//! Counts of the blocks and signals in a world, for debugging and balancing large worlds.
+
+use std::collections::HashMap;
+
+use crate::world::World;
+
+#[derive(Clone, Debug, Default)]
+pub struct WorldStats {
+    /// The stats of every layer, `layers[0]` is layer 0.
+    pub layers: Vec<LayerStats>,
+    /// The blocks on all layers combined, by `Block::type_name`.
+    pub blocks: HashMap<&'static str, usize>,
+    /// Chunks which contain at least one block, on all layers combined.
+    pub chunks: usize,
+    /// Signals which are waiting to be processed, in the next tick and all future ones.
+    pub pending_signals: usize,
+}
+
+#[derive(Clone, Debug, Default)]
+pub struct LayerStats {
+    /// The blocks on this layer, by `Block::type_name`.
+    pub blocks: HashMap<&'static str, usize>,
+    /// Chunks which contain at least one block.
+    pub chunks: usize,
+}
+
+impl WorldStats {
+    /// How many blocks there are in total.
+    pub fn total_blocks(&self) -> usize {
+        self.blocks.values().sum()
+    }
+}
+
+impl World {
+    /// Counts every block in the world. Takes time proportional to the number of blocks and chunks,
+    /// but only allocates once for every type of block (not once for every block).
+    /// Evicted chunks are read from their files, see `Layer::read_chunk`.
+    pub fn stats(&self) -> std::io::Result<WorldStats> {
+        let mut stats = WorldStats {
+            pending_signals: self.signals_queue.iter().map(|v| v.len()).sum(),
+            ..Default::default()
+        };
+        for layer in self.layers.iter() {
+            let mut layer_stats = LayerStats::default();
+            for key in layer.chunk_keys() {
+                let Some(chunk) = layer.read_chunk(&key)? else {
+                    continue;
+                };
+                let mut empty = true;
+                for block in chunk.iter().flatten() {
+                    empty = false;
+                    *layer_stats.blocks.entry(block.type_name()).or_default() += 1;
+                }
+                if !empty {
+                    layer_stats.chunks += 1;
+                }
+            }
+            for (name, count) in &layer_stats.blocks {
+                *stats.blocks.entry(name).or_default() += count;
+            }
+            stats.chunks += layer_stats.chunks;
+            stats.layers.push(layer_stats);
+        }
+        Ok(stats)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        runner::{DIR_LEFT, DIR_RIGHT},
+        world::{chunk_key, Block},
+    };
+
+    #[test]
+    fn blocks_are_counted() {
+        let mut world = World::new_empty();
+        world.layers[0]
+            .try_push_block(0, 0, Block::Color(1))
+            .unwrap();
+        world.layers[0]
+            .try_push_block(0, 0, Block::Color(2))
+            .unwrap();
+        world.layers[0]
+            .try_push_block(-1, 0, Block::Delay(1, DIR_RIGHT))
+            .unwrap();
+        world.layers[0]
+            .try_push_block(100, 100, Block::Storage(0, 4, DIR_LEFT))
+            .unwrap();
+        world.layers[7]
+            .try_push_block(5, 5, Block::Color(3))
+            .unwrap();
+        world.layers[7]
+            .try_push_block(6, 5, Block::Storage(0, 4, DIR_LEFT))
+            .unwrap();
+        // empty chunks don't count
+        world.layers[7].try_get_mut(&chunk_key(-50, 50)).unwrap();
+        world.signals_mut(0).push((0, DIR_LEFT, 0, 0));
+        world.signals_mut(3).push((0, DIR_LEFT, 0, 0));
+        world.signals_mut(3).push((0, DIR_LEFT, 0, 0));
+        let stats = world.stats().unwrap();
+        assert_eq!(stats.layers.len(), 32);
+        assert_eq!(stats.total_blocks(), 6);
+        assert_eq!(stats.blocks.len(), 3);
+        assert_eq!(stats.blocks["color"], 3);
+        assert_eq!(stats.blocks["delay"], 1);
+        assert_eq!(stats.blocks["storage/add"], 2);
+        assert_eq!(stats.chunks, 4);
+        assert_eq!(stats.pending_signals, 3);
+        assert_eq!(stats.layers[0].blocks["color"], 2);
+        assert_eq!(stats.layers[0].chunks, 3);
+        assert_eq!(stats.layers[7].blocks.get("delay"), None);
+        assert_eq!(stats.layers[7].chunks, 1);
+        assert!(stats.layers[1].blocks.is_empty());
+    }
+}
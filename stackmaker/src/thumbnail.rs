@@ -0,0 +1,222 @@
+//! Small top-down images of layer 0, saved as `thumbnail.png` next to a world's save files
+//! so the client can show them in its list of saves.
+
+use crate::{
+    compression::{crc32, zlib},
+    world::{Block, Layer},
+};
+
+/// The name of the thumbnail file in a world's save directory.
+pub const THUMBNAIL_FILE: &str = "thumbnail.png";
+/// The largest width and height of a thumbnail, in pixels.
+pub const THUMBNAIL_SIZE: u32 = 64;
+
+/// Renders the topmost block of every stack in the populated area of the layer, one color per block.
+/// Areas larger than `THUMBNAIL_SIZE` blocks are scaled down, smaller ones are scaled up.
+/// Returns (width, height, rgba pixels), or `None` if the layer is empty. Fails if an evicted chunk can't be read.
+pub fn render(layer: &Layer) -> std::io::Result<Option<(u32, u32, Vec<u8>)>> {
+    let Some((x1, y1, x2, y2)) = layer.extents()? else {
+        return Ok(None);
+    };
+    let (blocks_w, blocks_h) = ((x2 - x1 + 1) as u64, (y2 - y1 + 1) as u64);
+    let largest = blocks_w.max(blocks_h);
+    let size = THUMBNAIL_SIZE as u64;
+    // either several pixels per block or several blocks per pixel, in which case stacks between the sampled ones are skipped
+    let (pixels_per_block, blocks_per_pixel) = if largest <= size {
+        (size / largest, 1)
+    } else {
+        (1, largest.div_ceil(size))
+    };
+    let width = (blocks_w * pixels_per_block).div_ceil(blocks_per_pixel);
+    let height = (blocks_h * pixels_per_block).div_ceil(blocks_per_pixel);
+    // the pixels a block covers, empty if it is skipped
+    let pixels_of = |block: u64| {
+        (block * pixels_per_block).div_ceil(blocks_per_pixel)
+            ..((block + 1) * pixels_per_block).div_ceil(blocks_per_pixel)
+    };
+    let mut pixels = vec![0; (width * height * 4) as usize];
+    // chunk by chunk, so every evicted chunk is only read once
+    for key in layer.chunk_keys() {
+        let Some(chunk) = layer.read_chunk(&key)? else {
+            continue;
+        };
+        for (inner, stack) in chunk.iter().enumerate() {
+            let Some(block) = stack.last() else {
+                continue;
+            };
+            let (x, y) = Layer::where_to_coords(key, inner as u8);
+            let color = block_color(block);
+            for py in pixels_of((y - y1) as u64) {
+                for px in pixels_of((x - x1) as u64) {
+                    let i = ((py * width + px) * 4) as usize;
+                    pixels[i..i + 4].copy_from_slice(&color);
+                }
+            }
+        }
+    }
+    Ok(Some((width as u32, height as u32, pixels)))
+}
+
+/// The color a block has in thumbnails. Color blocks use their own color, but are always opaque.
+pub fn block_color(block: &Block) -> [u8; 4] {
+    let [r, g, b] = match block {
+        Block::Color(c) => [(c >> 16) as u8, (c >> 8) as u8, *c as u8],
+        Block::Char(_) => [220, 220, 220],
+        Block::Delay(..) => [90, 90, 110],
+        Block::Storage(..) => [40, 120, 200],
+        Block::Gate(true, _) => [60, 180, 60],
+        Block::Gate(false, _) => [160, 50, 50],
+        Block::Splitter(_) => [200, 160, 40],
+        Block::Fan(_) => [220, 190, 70],
+        Block::Clock(..) => [230, 120, 30],
+        Block::Compare(..) => [150, 80, 200],
+        Block::Not(_) => [200, 60, 150],
+        Block::Probe(_) => [240, 240, 120],
+        Block::Random(_) => [120, 200, 200],
+        Block::Counter(..) => [80, 200, 120],
+        Block::Height(_) => [180, 180, 80],
+        Block::Move(_) => [200, 100, 60],
+        Block::Swap(_) => [100, 60, 200],
+        Block::SwapAll(_) => [80, 40, 170],
+        Block::Duplicate(_) => [60, 150, 210],
+        Block::Destroy(_) => [120, 30, 30],
+    };
+    [r, g, b, 255]
+}
+
+/// Encodes 8-bit rgba pixels as a png file.
+pub fn encode_png(width: u32, height: u32, rgba: &[u8]) -> Vec<u8> {
+    fn chunk(out: &mut Vec<u8>, kind: &[u8; 4], data: &[u8]) {
+        out.extend_from_slice(&(data.len() as u32).to_be_bytes());
+        let start = out.len();
+        out.extend_from_slice(kind);
+        out.extend_from_slice(data);
+        let crc = crc32(&out[start..]);
+        out.extend_from_slice(&crc.to_be_bytes());
+    }
+    let mut out = b"\x89PNG\r\n\x1a\n".to_vec();
+    let mut header = Vec::with_capacity(13);
+    header.extend_from_slice(&width.to_be_bytes());
+    header.extend_from_slice(&height.to_be_bytes());
+    // 8 bits per channel, rgba, deflate, adaptive filtering, not interlaced
+    header.extend_from_slice(&[8, 6, 0, 0, 0]);
+    chunk(&mut out, b"IHDR", &header);
+    // every row starts with its filter type, 0 (none)
+    let row_len = width as usize * 4;
+    let mut raw = Vec::with_capacity((row_len + 1) * height as usize);
+    for row in rgba.chunks(row_len.max(1)).take(height as usize) {
+        raw.push(0);
+        raw.extend_from_slice(row);
+    }
+    chunk(&mut out, b"IDAT", &zlib(&raw));
+    chunk(&mut out, b"IEND", &[]);
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        compression::{adler32, inflate, Crc32},
+        runner::DIR_RIGHT,
+        world::World,
+    };
+
+    /// (kind, data) of every chunk in a png file, checking the signature and every chunk's crc
+    fn png_chunks(png: &[u8]) -> Vec<([u8; 4], Vec<u8>)> {
+        assert_eq!(png[..8], *b"\x89PNG\r\n\x1a\n");
+        let mut chunks = vec![];
+        let mut rest = &png[8..];
+        while !rest.is_empty() {
+            let len = u32::from_be_bytes(rest[..4].try_into().unwrap()) as usize;
+            let (kind, data) = (&rest[4..8], &rest[8..8 + len]);
+            let mut crc = Crc32::new();
+            crc.update(kind);
+            crc.update(data);
+            assert_eq!(rest[8 + len..12 + len], crc.finish().to_be_bytes());
+            chunks.push((kind.try_into().unwrap(), data.to_vec()));
+            rest = &rest[12 + len..];
+        }
+        chunks
+    }
+
+    #[test]
+    fn pngs_contain_the_pixels() {
+        // 3x2, every pixel different
+        let rgba: Vec<u8> = (0..24).collect();
+        let chunks = png_chunks(&encode_png(3, 2, &rgba));
+        let kinds: Vec<_> = chunks.iter().map(|(kind, _)| kind).collect();
+        assert_eq!(kinds, [b"IHDR", b"IDAT", b"IEND"]);
+        let header = &chunks[0].1;
+        assert_eq!(header[..4], 3u32.to_be_bytes());
+        assert_eq!(header[4..8], 2u32.to_be_bytes());
+        assert_eq!(header[8..], [8, 6, 0, 0, 0]);
+        // zlib: a valid header, the deflate stream, then the adler-32 of the data
+        let idat = &chunks[1].1;
+        assert_eq!(u16::from_be_bytes([idat[0], idat[1]]) % 31, 0);
+        let (raw, len) = inflate(&idat[2..]).unwrap();
+        assert_eq!(idat[2 + len..], adler32(&raw).to_be_bytes());
+        let mut expected = vec![0];
+        expected.extend(0..12);
+        expected.push(0);
+        expected.extend(12..24);
+        assert_eq!(raw, expected);
+        assert!(chunks[2].1.is_empty());
+    }
+
+    #[test]
+    fn colors_are_opaque() {
+        assert_eq!(
+            block_color(&Block::Color(0x123456)),
+            [0x12, 0x34, 0x56, 255]
+        );
+        assert_eq!(
+            block_color(&Block::Color(0xAB000000)),
+            block_color(&Block::Color(0))
+        );
+        assert_eq!(block_color(&Block::Gate(true, 0))[3], 255);
+        assert_ne!(
+            block_color(&Block::Gate(true, 0)),
+            block_color(&Block::Gate(false, 0))
+        );
+    }
+
+    #[test]
+    fn small_layers_are_scaled_up() {
+        let mut world = World::new_empty();
+        assert_eq!(render(&world.layers[0]).unwrap(), None);
+        let layer = &mut world.layers[0];
+        layer.try_push_block(0, 0, Block::Color(0xFF0000)).unwrap();
+        layer.try_push_block(2, 1, Block::Color(1)).unwrap();
+        layer.try_push_block(2, 1, Block::Not(DIR_RIGHT)).unwrap();
+        // 3x2 blocks, 21 pixels each
+        let (width, height, pixels) = render(layer).unwrap().unwrap();
+        assert_eq!((width, height), (63, 42));
+        let pixel = |x: u32, y: u32| {
+            let i = ((y * width + x) * 4) as usize;
+            [pixels[i], pixels[i + 1], pixels[i + 2], pixels[i + 3]]
+        };
+        assert_eq!(pixel(0, 0), [255, 0, 0, 255]);
+        assert_eq!(pixel(20, 20), [255, 0, 0, 255]);
+        // empty stacks are transparent
+        assert_eq!(pixel(21, 0), [0; 4]);
+        assert_eq!(pixel(62, 0), [0; 4]);
+        // only the topmost block is drawn
+        assert_eq!(pixel(42, 21), block_color(&Block::Not(DIR_RIGHT)));
+        assert_eq!(pixel(62, 41), block_color(&Block::Not(DIR_RIGHT)));
+    }
+
+    #[test]
+    fn large_layers_are_scaled_down() {
+        let mut world = World::new_empty();
+        let layer = &mut world.layers[0];
+        layer.try_push_block(-100, 5, Block::Color(0xFF)).unwrap();
+        layer.try_push_block(99, 5, Block::Color(0xFF00)).unwrap();
+        // 200 blocks wide, so every 4th one is drawn
+        let (width, height, pixels) = render(layer).unwrap().unwrap();
+        assert_eq!((width, height), (50, 1));
+        assert_eq!(pixels[..4], [0, 0, 255, 255]);
+        assert_eq!(pixels[pixels.len() - 4..], [0; 4]);
+        assert_eq!(pixels.len(), 50 * 4);
+    }
+}
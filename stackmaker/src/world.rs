@@ -1,31 +1,140 @@
 use std::{
-    collections::{HashMap, VecDeque},
+    borrow::Cow,
+    cell::Cell,
+    collections::{BTreeSet, HashMap, HashSet, VecDeque},
     fs,
-    io::{Read, Write},
+    hash::{BuildHasherDefault, Hasher},
+    io::{BufRead, BufReader, BufWriter, Read, Seek, SeekFrom, Write},
     path::{Path, PathBuf},
     sync::{Arc, Mutex},
 };
 
+use crate::{
+    compression::{Compression, Crc32},
+    eviction::EvictedChunk,
+    runner::{gate_control_dir, DELAY_BY_VALUE, GATE_INVERTED},
+    thumbnail,
+};
+
+/// How many layers every world has.
+pub const LAYER_COUNT: usize = 32;
+/// The bits of a signal's (or a position's) `dir_layer` byte which store the layer, see the `runner` module.
+pub const LAYER_MASK: u8 = 0b11111;
+// every layer must be reachable using the layer bits
+const _: () = assert!(LAYER_COUNT <= LAYER_MASK as usize + 1);
+
+/// The most ticks a signal can be delayed by, see `World::signals_mut`.
+/// Every tick a signal waits for needs its own buffer in the signals queue, so without a limit,
+/// a single Delay block with a huge delay would try to allocate billions of them.
+pub const MAX_DELAY: usize = 65535;
+
+/// How many empty signal buffers a world keeps around for reuse, see `World::recycle_signals`.
+const MAX_SPARE_SIGNALS: usize = 16;
+
 pub struct World {
     pub save_dir: Option<PathBuf>,
-    pub layers: [Layer; 32],
+    pub layers: [Layer; LAYER_COUNT],
     /// (signal, (dir (3b) + layer (5b)), target_chunk, target_pos)
+    /// `signals_queue[n]` holds the signals which will arrive in `n + 1` ticks.
+    /// Within one tick, signals are processed in the order they were pushed (FIFO),
+    /// and saving/loading preserves that order, so a simulation always has the same outcome.
     pub signals_queue: VecDeque<Vec<(u32, u8, u64, u8)>>,
+    /// If true, signals moving up from the upmost layer arrive on the lowest one and vice versa. Saved in the `options` file.
+    pub wrap_layers: bool,
+    /// Where the player was looking when the world was saved, so a client can continue from there. Saved in the `view` file.
+    pub view: Option<View>,
+    /// Empty signal buffers of past ticks, reused by `signals_mut` so busy worlds don't allocate a new buffer every tick.
+    pub(crate) spare_signals: Vec<Vec<(u32, u8, u64, u8)>>,
+    /// Evicted chunks which a tick couldn't read back, reported at the end of the tick. See `Layer::get_loaded_mut`.
+    pub(crate) chunk_errors: Vec<std::io::Error>,
+}
+
+/// A client's camera. It doesn't affect the simulation.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct View {
+    /// The position (in blocks) at the center of the screen
+    pub position: (f32, f32),
+    /// The size of a block on screen is `2^zoom` pixels
+    pub zoom: f32,
+    pub layer: usize,
 }
 
+/// A layer's chunks by their position, see `Layer::get_where`.
+pub type ChunkMap = HashMap<u64, [Vec<Block>; 256], BuildHasherDefault<ChunkHasher>>;
+
+/// The hasher of `ChunkMap`. Chunks are looked up for almost every signal in every tick,
+/// and std's default hasher, which is designed to resist keys chosen by an attacker, is slow for that.
+/// This multiplies the key by a large odd constant (like the `FxHasher` used by rustc), then moves
+/// the best mixed bits of the product to the bottom, where the hash map takes the bucket index from.
+#[derive(Default)]
+pub struct ChunkHasher(u64);
+impl Hasher for ChunkHasher {
+    fn write(&mut self, bytes: &[u8]) {
+        for part in bytes.chunks(8) {
+            let mut word = [0; 8];
+            word[..part.len()].copy_from_slice(part);
+            self.write_u64(u64::from_le_bytes(word));
+        }
+    }
+    fn write_u64(&mut self, n: u64) {
+        self.0 = (self.0.rotate_left(5) ^ n).wrapping_mul(0x517c_c1b7_2722_0a95);
+    }
+    fn finish(&self) -> u64 {
+        self.0.rotate_left(26)
+    }
+}
+
+#[derive(Clone)]
 pub struct Layer {
-    pub chunks: HashMap<u64, [Vec<Block>; 256]>,
+    /// The loaded chunks, see the `eviction` module. Code in this crate which changes them directly,
+    /// instead of using `chunk_mut` or `chunk_mut_or_create`, has to call `note_top` for the stacks it changed.
+    pub(crate) chunks: ChunkMap,
+    /// The chunks which were evicted, see the `eviction` module.
+    pub(crate) evicted: HashMap<u64, Arc<EvictedChunk>, BuildHasherDefault<ChunkHasher>>,
+    /// The files of loaded chunks which didn't change since they were read back, so evicting them again doesn't have to write them.
+    pub(crate) clean: HashMap<u64, Arc<EvictedChunk>, BuildHasherDefault<ChunkHasher>>,
+    /// The stacks which have a `Block::Clock` on top as (chunk, inner), so a tick only has to look at these to advance the clocks.
+    /// Sorted, so clocks always emit their signals in the same order.
+    pub(crate) clocks: BTreeSet<(u64, u8)>,
+    /// Chunks which might have been changed by code outside of this crate, see `update_clocks`.
+    unscanned: HashSet<u64, BuildHasherDefault<ChunkHasher>>,
+    /// Whether the layer changed since it was last saved, see `World::save_changed_to_dir`.
+    /// A `Cell` so that saving, which only needs `&World`, can reset it.
+    dirty: Cell<bool>,
+}
+
+/// New layers are dirty because they haven't been saved yet.
+impl Default for Layer {
+    fn default() -> Self {
+        Self {
+            chunks: ChunkMap::default(),
+            evicted: Default::default(),
+            clean: Default::default(),
+            clocks: BTreeSet::new(),
+            unscanned: Default::default(),
+            dirty: Cell::new(true),
+        }
+    }
 }
 
-#[derive(Clone, Debug)]
+#[derive(Clone, Debug, PartialEq, Eq)]
 pub enum Block {
     // < Basic >
     /// Passes a received signal on after the given amount of game ticks.
-    /// Side-signals set the amount of ticks to wait.
+    /// Side-signals set the amount of ticks to wait, delays longer than `MAX_DELAY` ticks wait `MAX_DELAY` ticks.
+    /// With `runner::DELAY_BY_VALUE` set in the direction, every signal waits as many ticks as its value instead,
+    /// and the stored amount is unused.
     Delay(u32, u8),
     /// Outputs two identical signals upon receiving one.
     /// This block is triggered exclusively by side-signals.
     Splitter(u8),
+    /// Outputs a copy of every signal it receives (from any direction) in each of the directions it is set to.
+    /// Stored as a bitmask of the directions, see `runner::FAN_DIRECTIONS`.
+    Fan(u8),
+    /// Outputs a `0` signal to the four stacks next to it on its layer once every `period` ticks, without needing any input.
+    /// A period of `0` disables the clock. Every signal it receives sets the period.
+    /// Stored as (period, phase), where phase counts the ticks since the last output.
+    Clock(u32, u32),
 
     // < Output >
     /// A single-color block; format is argb. If it receives any signal, its internal value will be set to that of the signal.
@@ -44,19 +153,60 @@ pub enum Block {
     /// - 5 (sub): side-signal values will be subtracted from the stored value. value saturates at integer boundaries.
     /// - 6 (mul): the stored value will be multiplied with the value from the side-signal. value saturates at integer boundaries.
     /// - 7 (div): the stored value will be divided by the value from the side-signal. dividing by zero gives the max value.
-    /// - 8 (mod): the stored value will be divided by the value from the side-signal, and the remainder will be stored.
+    /// - 8 (mod): the stored value will be divided by the value from the side-signal, and the remainder will be stored. dividing by zero leaves the value unchanged.
+    /// - 9 (min): the smaller one of the stored value and the side-signal value will be stored.
+    /// - 10 (max): the larger one of the stored value and the side-signal value will be stored.
+    /// - 14 (wadd): like add, but the value wraps around at integer boundaries.
+    /// - 15 (wsub): like sub, but the value wraps around at integer boundaries.
+    /// - 16 (wmul): like mul, but the value wraps around at integer boundaries.
     /// - default: the stored value will not be changed at all if the mode was set to any other value.
+    ///
     /// Stored as (value, mode, direction)
     Storage(u32, u8, u8),
     /// Only lets a signal pass if it is open, that is, the last side-signal received was `0`.
     /// In combination with the Storage Block, this can be used to implement all kinds of conditions.
+    /// If the direction has a second direction in `runner::GATE_CONTROL_MASK` (see `runner::gate_control_out`),
+    /// side-signals are passed on in that direction after setting the open flag.
+    /// With `runner::GATE_INVERTED` set, it is the other way around: the gate is open if the last side-signal was not `0`.
     Gate(bool, u8),
+    /// Only lets a signal pass if comparing its value to the threshold gives true. Side-signals set the threshold.
+    /// - 0 (lt): `signal < threshold`
+    /// - 1 (le): `signal <= threshold`
+    /// - 2 (eq): `signal == threshold`
+    /// - 3 (ge): `signal >= threshold`
+    /// - 4 (gt): `signal > threshold`
+    /// - default: no signal will pass if the operation is set to any other value.
+    ///
+    /// Stored as (threshold, operation, direction)
+    Compare(u32, u8, u8),
+    /// Outputs the bitwise inverse of signals received from behind. Side-signals are ignored.
+    Not(u8),
+    /// Passes signals received from behind on unchanged, recording each one in the runner's `probe_log`.
+    /// Side-signals are ignored.
+    Probe(u8),
+    /// Replaces any signal passing through it with a pseudo-random value. The signal keeps its direction.
+    /// Stores the state of the random number generator, so reloading a world reproduces the same sequence.
+    Random(u64),
+    /// Counts the signals it receives from behind: each one adds 1 to the count (saturating) and the new count is sent on.
+    /// A side-signal resets the count to 0.
+    ///
+    /// Stored as (count, direction)
+    Counter(u32, u8),
+    /// Upon receiving a side-signal, counts the blocks on the stack it points at and sends that number to it (saturating at `u32::MAX`).
+    Height(u8),
 
     // < World >
     /// Upon receiving a `0` side-signal, takes a block from one stack and puts it on another, following the provided direction. If it receives any other signal, moves a block back.
     Move(u8),
     /// Upon receiving any side-signal, swaps the blocks in front/behind itself
     Swap(u8),
+    /// Like `Swap`, but swaps the entire stacks in front/behind itself instead of only their topmost blocks.
+    SwapAll(u8),
+    /// Upon receiving a `0` side-signal, puts a copy of the topmost block in front of itself onto the stack behind itself,
+    /// where front and behind are the same as for a `Move` block. Other signals are ignored.
+    Duplicate(u8),
+    /// Upon receiving any side-signal, removes the topmost block from the stack it points at.
+    Destroy(u8),
 }
 
 impl World {
@@ -65,14 +215,139 @@ impl World {
             save_dir: None,
             layers: Default::default(),
             signals_queue: VecDeque::new(),
+            wrap_layers: false,
+            view: None,
+            spare_signals: vec![],
+            chunk_errors: vec![],
         }
     }
+    /// The signals which will arrive in `delta_t + 1` ticks. Push to the end to keep the FIFO order.
+    /// `delta_t` is clamped to `MAX_DELAY`, so signals which should wait longer arrive after `MAX_DELAY + 1` ticks.
     pub fn signals_mut(&mut self, delta_t: usize) -> &mut Vec<(u32, u8, u64, u8)> {
+        let delta_t = delta_t.min(MAX_DELAY);
         while delta_t >= self.signals_queue.len() {
-            self.signals_queue.push_back(vec![]);
+            let buffer = self.spare_signals.pop().unwrap_or_default();
+            self.signals_queue.push_back(buffer);
         }
         &mut self.signals_queue[delta_t]
     }
+    /// Like `Layer::iter_blocks` for all layers, as (layer, x, y, stack). Layers are visited in order, starting at layer 0.
+    /// Fails if any layer has evicted chunks.
+    pub fn iter_blocks(
+        &self,
+    ) -> std::io::Result<impl Iterator<Item = (usize, i64, i64, &Vec<Block>)>> {
+        let layers = self
+            .layers
+            .iter()
+            .map(Layer::iter_blocks)
+            .collect::<std::io::Result<Vec<_>>>()?;
+        Ok(layers
+            .into_iter()
+            .enumerate()
+            .flat_map(|(layer, blocks)| blocks.map(move |(x, y, stack)| (layer, x, y, stack))))
+    }
+    /// A deep copy of the world (all layers, the signal queue and the options), independent of the original.
+    /// Ticking the copy has exactly the same outcome as ticking the original.
+    pub fn snapshot(&self) -> World {
+        Self {
+            save_dir: self.save_dir.clone(),
+            layers: self.layers.clone(),
+            signals_queue: self.signals_queue.clone(),
+            wrap_layers: self.wrap_layers,
+            view: self.view,
+            spare_signals: vec![],
+            chunk_errors: vec![],
+        }
+    }
+    /// Like `snapshot`, but only the dirty layers are copied, the other ones are left empty (and not dirty).
+    /// Saving the copy using `save_changed_to_dir` writes the same files as saving the original would.
+    pub fn snapshot_changed(&self) -> World {
+        Self {
+            layers: std::array::from_fn(|i| {
+                if self.layers[i].is_dirty() {
+                    self.layers[i].clone()
+                } else {
+                    Layer {
+                        dirty: Cell::new(false),
+                        ..Default::default()
+                    }
+                }
+            }),
+            save_dir: self.save_dir.clone(),
+            signals_queue: self.signals_queue.clone(),
+            wrap_layers: self.wrap_layers,
+            view: self.view,
+            spare_signals: vec![],
+            chunk_errors: vec![],
+        }
+    }
+    /// Clears a tick's signal buffer and keeps it for `signals_mut` to reuse.
+    pub(crate) fn recycle_signals(&mut self, mut signals: Vec<(u32, u8, u64, u8)>) {
+        // buffers which never held a signal have nothing worth keeping
+        if signals.capacity() > 0 && self.spare_signals.len() < MAX_SPARE_SIGNALS {
+            signals.clear();
+            self.spare_signals.push(signals);
+        }
+    }
+    /// Reads all evicted chunks of all layers back, see `Layer::reload_all`.
+    pub fn reload_all_chunks(&mut self) -> std::io::Result<()> {
+        self.layers.iter_mut().try_for_each(Layer::reload_all)
+    }
+    /// Removes all chunks which don't contain any blocks, returning how many were removed.
+    pub fn gc_empty_chunks(&mut self) -> usize {
+        self.layers
+            .iter_mut()
+            .map(|layer| layer.gc_empty_chunks())
+            .sum()
+    }
+    /// The smallest area containing all blocks on all layers, see `Layer::extents`.
+    pub fn extents(&self) -> std::io::Result<Option<(i64, i64, i64, i64)>> {
+        let mut out: Option<(i64, i64, i64, i64)> = None;
+        for layer in self.layers.iter() {
+            if let Some(b) = layer.extents()? {
+                out = Some(match out {
+                    None => b,
+                    Some(a) => (a.0.min(b.0), a.1.min(b.1), a.2.max(b.2), a.3.max(b.3)),
+                });
+            }
+        }
+        Ok(out)
+    }
+    /// Copies every block of `other` into this world, moved by `(dx, dy)` and `layer_offset` layers.
+    /// The copied blocks are put on top of the stacks which are already there.
+    /// Blocks which would end up on a layer that doesn't exist are skipped, the signals of `other` are not copied.
+    /// Fails like `Layer::try_get_mut` and `Layer::read_chunk`, the blocks copied until then stay in this world.
+    pub fn try_merge(
+        &mut self,
+        other: &World,
+        dx: i64,
+        dy: i64,
+        layer_offset: i8,
+    ) -> std::io::Result<()> {
+        for (layer, other_layer) in other.layers.iter().enumerate() {
+            let layer = layer as i64 + layer_offset as i64;
+            if !(0..LAYER_COUNT as i64).contains(&layer) {
+                continue;
+            }
+            let layer = &mut self.layers[layer as usize];
+            for key in other_layer.chunk_keys() {
+                let Some(other_chunk) = other_layer.read_chunk(&key)? else {
+                    continue;
+                };
+                for (inner, stack) in other_chunk.iter().enumerate() {
+                    if stack.is_empty() {
+                        continue;
+                    }
+                    let (x, y) = Layer::where_to_coords(key, inner as u8);
+                    let (chunk, inner) = layer.get_where(x + dx, y + dy);
+                    layer.chunk_mut_or_create(&chunk)?[inner as usize]
+                        .extend(stack.iter().cloned());
+                    layer.note_top(chunk, inner);
+                }
+            }
+        }
+        Ok(())
+    }
 }
 
 impl Layer {
@@ -97,122 +372,1069 @@ impl Layer {
                 .to_ne_bytes(),
         ) as u64;
         let chunk = y2 << 32 | x2;
-        let inchunk = (((y % 16 + 16) % 16) << 4) | ((x % 16 + 16) % 16);
+        let inchunk = (y.rem_euclid(16) << 4) | x.rem_euclid(16);
         (chunk, inchunk as u8)
     }
-    /// Will create the chunk if it doesn't exist
-    pub fn get_mut(&mut self, chunk: &u64) -> &mut [Vec<Block>; 256] {
-        if !self.chunks.contains_key(chunk) {
-            self.chunks.insert(*chunk, create_empty_chunk());
+    /// The inverse of `get_where`: turns a chunk key and the position inside that chunk back into `(x, y)`.
+    pub fn where_to_coords(chunk: u64, inner: u8) -> (i64, i64) {
+        let (chunk_x, chunk_y) = chunk_pos(chunk);
+        (
+            chunk_x as i64 * 16 + (inner & 15) as i64,
+            chunk_y as i64 * 16 + (inner >> 4) as i64,
+        )
+    }
+    /// Will create the chunk if it doesn't exist.
+    /// Marks the layer as dirty, because the chunk might be changed using the returned reference.
+    /// Fails if the chunk was evicted and its file can't be read back, see `get_loaded_mut`.
+    pub fn try_get_mut(&mut self, chunk: &u64) -> std::io::Result<&mut [Vec<Block>; 256]> {
+        self.unscanned.insert(*chunk);
+        self.chunk_mut_or_create(chunk)
+    }
+    /// Like `get`, but reads the chunk back first if it was evicted.
+    pub fn get_loaded(&mut self, chunk: &u64) -> std::io::Result<Option<&[Vec<Block>; 256]>> {
+        self.reload(chunk)?;
+        Ok(self.chunks.get(chunk))
+    }
+    /// Like `chunks.get_mut`, but reads the chunk back first if it was evicted.
+    /// Unlike `try_get_mut`, this doesn't create the chunk or mark the layer as dirty.
+    /// If the chunk's file can't be read, the chunk stays evicted, so trying again later (or saving the layer) can still succeed.
+    pub fn get_loaded_mut(
+        &mut self,
+        chunk: &u64,
+    ) -> std::io::Result<Option<&mut [Vec<Block>; 256]>> {
+        self.unscanned.insert(*chunk);
+        self.chunk_mut(chunk)
+    }
+    /// The loaded chunks as (key, chunk), in `HashMap` order. Evicted chunks are skipped, see `read_chunk`.
+    pub fn loaded_chunks(&self) -> impl ExactSizeIterator<Item = (u64, &[Vec<Block>; 256])> {
+        self.chunks.iter().map(|(key, chunk)| (*key, chunk))
+    }
+    /// Like `try_get_mut`, but the caller has to call `note_top` for every stack whose top block it replaces.
+    pub(crate) fn chunk_mut_or_create(
+        &mut self,
+        chunk: &u64,
+    ) -> std::io::Result<&mut [Vec<Block>; 256]> {
+        self.reload(chunk)?;
+        self.mark_dirty();
+        self.clean.remove(chunk);
+        Ok(self.chunks.entry(*chunk).or_insert_with(create_empty_chunk))
+    }
+    /// Like `get_loaded_mut`, but the caller has to call `note_top` for every stack whose top block it replaces.
+    pub(crate) fn chunk_mut(
+        &mut self,
+        chunk: &u64,
+    ) -> std::io::Result<Option<&mut [Vec<Block>; 256]>> {
+        self.reload(chunk)?;
+        // the chunk might be changed, so its file can't be reused
+        if !self.clean.is_empty() {
+            self.clean.remove(chunk);
+        }
+        Ok(self.chunks.get_mut(chunk))
+    }
+    /// Adds the stack to or removes it from `clocks`, depending on whether its top block is a clock now.
+    pub(crate) fn note_top(&mut self, chunk: u64, inner: u8) {
+        let is_clock = self
+            .chunks
+            .get(&chunk)
+            .is_some_and(|c| matches!(c[inner as usize].last(), Some(Block::Clock(..))));
+        if is_clock {
+            self.clocks.insert((chunk, inner));
+        } else if !self.clocks.is_empty() {
+            self.clocks.remove(&(chunk, inner));
+        }
+    }
+    /// Code outside of this crate doesn't call `note_top`, so the chunks it could have changed
+    /// (through `try_get_mut` or `get_loaded_mut`) are searched for clocks here.
+    /// Also used for chunks which were just loaded.
+    pub(crate) fn update_clocks(&mut self) {
+        if self.unscanned.is_empty() {
+            return;
+        }
+        for chunk in std::mem::take(&mut self.unscanned) {
+            for inner in 0..=255 {
+                self.note_top(chunk, inner);
+            }
+        }
+    }
+    /// Reads the chunk back if it was evicted. If its file can't be read, the chunk stays evicted.
+    fn reload(&mut self, chunk: &u64) -> std::io::Result<()> {
+        if self.evicted.is_empty() {
+            return Ok(());
+        }
+        if let Some(file) = self.evicted.remove(chunk) {
+            match Self::read_evicted(&file) {
+                Ok(loaded) => {
+                    self.chunks.insert(*chunk, loaded);
+                    self.clean.insert(*chunk, file);
+                }
+                Err(e) => {
+                    self.evicted.insert(*chunk, file);
+                    return Err(e);
+                }
+            }
+        }
+        Ok(())
+    }
+    fn read_evicted(file: &EvictedChunk) -> std::io::Result<[Vec<Block>; 256]> {
+        file.read_chunk()
+            .map_err(|e| std::io::Error::new(e.kind(), format!("{:?}: {e}", file.path)))
+    }
+    /// The keys of all chunks, the loaded ones first, then the evicted ones.
+    pub fn chunk_keys(&self) -> impl Iterator<Item = u64> + '_ {
+        self.chunks.keys().chain(self.evicted.keys()).copied()
+    }
+    /// Like `get`, but reads an evicted chunk from its file, without loading it.
+    pub fn read_chunk(&self, chunk: &u64) -> std::io::Result<Option<Cow<'_, [Vec<Block>; 256]>>> {
+        if let Some(loaded) = self.chunks.get(chunk) {
+            return Ok(Some(Cow::Borrowed(loaded)));
+        }
+        match self.evicted.get(chunk) {
+            Some(file) => Ok(Some(Cow::Owned(Self::read_evicted(file)?))),
+            None => Ok(None),
+        }
+    }
+    /// Reads all evicted chunks back, so that `chunks` contains the whole layer again.
+    /// Stops at the first chunk which can't be read, the remaining ones stay evicted.
+    pub fn reload_all(&mut self) -> std::io::Result<()> {
+        let keys: Vec<u64> = self.evicted.keys().copied().collect();
+        keys.iter().try_for_each(|key| self.reload(key))
+    }
+    /// Writes the chunk to a file at `path` and removes it from `chunks`, see the `eviction` module.
+    /// A chunk which didn't change since it was read back keeps its old file instead.
+    pub(crate) fn evict(&mut self, chunk: u64, path: PathBuf) -> std::io::Result<()> {
+        let file = match self.clean.remove(&chunk) {
+            Some(file) => file,
+            None => {
+                let Some(data) = self.chunks.get(&chunk) else {
+                    return Ok(());
+                };
+                let mut buf = vec![];
+                save_chunk(chunk, data, &mut buf);
+                Arc::new(EvictedChunk::write(path, &buf)?)
+            }
+        };
+        self.chunks.remove(&chunk);
+        self.evicted.insert(chunk, file);
+        Ok(())
+    }
+    /// The topmost block at the given position, if there is one
+    pub fn top_block(&self, x: i64, y: i64) -> Option<&Block> {
+        let (chunk, inner) = self.get_where(x, y);
+        self.get(&chunk)?[inner as usize].last()
+    }
+    /// Puts a block on top of the stack at the given position, creating the chunk if necessary.
+    /// Fails like `try_get_mut`.
+    pub fn try_push_block(&mut self, x: i64, y: i64, block: Block) -> std::io::Result<()> {
+        let (chunk, inner) = self.get_where(x, y);
+        self.chunk_mut_or_create(&chunk)?[inner as usize].push(block);
+        self.note_top(chunk, inner);
+        Ok(())
+    }
+    /// Removes the topmost block at the given position. Doesn't create a chunk if there is none.
+    /// Fails like `try_get_mut`.
+    pub fn try_pop_block(&mut self, x: i64, y: i64) -> std::io::Result<Option<Block>> {
+        let (chunk, inner) = self.get_where(x, y);
+        let Some(chunk_ref) = self.chunk_mut(&chunk)? else {
+            return Ok(None);
+        };
+        let block = chunk_ref[inner as usize].pop();
+        if block.is_some() {
+            self.mark_dirty();
+            self.note_top(chunk, inner);
+        }
+        Ok(block)
+    }
+    /// Removes all chunks which don't contain any blocks, returning how many were removed.
+    pub fn gc_empty_chunks(&mut self) -> usize {
+        let len = self.chunks.len();
+        self.chunks
+            .retain(|_, chunk| chunk.iter().any(|blocks| !blocks.is_empty()));
+        let chunks = &self.chunks;
+        self.clean.retain(|key, _| chunks.contains_key(key));
+        let removed = len - self.chunks.len();
+        if removed > 0 {
+            self.mark_dirty();
+        }
+        removed
+    }
+    /// Whether the layer changed since it was last saved or loaded
+    pub fn is_dirty(&self) -> bool {
+        self.dirty.get()
+    }
+    /// Makes the next `World::save_changed_to_dir` write this layer
+    pub fn mark_dirty(&self) {
+        self.dirty.set(true);
+    }
+    pub(crate) fn mark_saved(&self) {
+        self.dirty.set(false);
+    }
+    /// The smallest area containing all blocks on this layer as (min x, min y, max x, max y), all inclusive.
+    /// Returns `None` if there are no blocks. Evicted chunks are read from their files, see `read_chunk`.
+    pub fn extents(&self) -> std::io::Result<Option<(i64, i64, i64, i64)>> {
+        let mut out: Option<(i64, i64, i64, i64)> = None;
+        for key in self.chunk_keys() {
+            let Some(chunk) = self.read_chunk(&key)? else {
+                continue;
+            };
+            for (inner, _) in chunk.iter().enumerate().filter(|(_, s)| !s.is_empty()) {
+                let (x, y) = Self::where_to_coords(key, inner as u8);
+                out = Some(match out {
+                    None => (x, y, x, y),
+                    Some((x1, y1, x2, y2)) => (x1.min(x), y1.min(y), x2.max(x), y2.max(y)),
+                });
+            }
         }
-        self.chunks.get_mut(chunk).unwrap()
+        Ok(out)
+    }
+    /// Every stack which contains at least one block as (x, y, stack), with the same coordinates as `get_where` uses.
+    /// The order depends on the chunks' `HashMap` order.
+    /// Fails if chunks were evicted, because their stacks can't be borrowed. Call `World::reload_all_chunks` first.
+    pub fn iter_blocks(&self) -> std::io::Result<impl Iterator<Item = (i64, i64, &Vec<Block>)>> {
+        if !self.evicted.is_empty() {
+            return Err(std::io::Error::other(format!(
+                "{} chunks are evicted, they have to be reloaded first",
+                self.evicted.len()
+            )));
+        }
+        Ok(self.chunks.iter().flat_map(|(key, chunk)| {
+            chunk
+                .iter()
+                .enumerate()
+                .filter(|(_, blocks)| !blocks.is_empty())
+                .map(|(inner, blocks)| {
+                    let (x, y) = Self::where_to_coords(*key, inner as u8);
+                    (x, y, blocks)
+                })
+        }))
     }
 }
 
 impl Block {}
 
-fn create_empty_chunk<T>() -> [Vec<T>; 256] {
-    unsafe {
-        #[allow(invalid_value)]
-        let mut arr: [Vec<T>; 256] = std::mem::MaybeUninit::uninit().assume_init();
-        for item in &mut arr[..] {
-            std::ptr::write(item, vec![]);
-        }
-        arr
-    }
+pub(crate) fn create_empty_chunk<T>() -> [Vec<T>; 256] {
+    std::array::from_fn(|_| vec![])
 }
 
-impl Default for Layer {
-    fn default() -> Self {
-        Self {
-            chunks: HashMap::new(),
-        }
-    }
+/// chunk keys store the chunk's y coordinate in the upper and its x coordinate in the lower 32 bits.
+pub(crate) fn chunk_key(x: i32, y: i32) -> u64 {
+    (y as u32 as u64) << 32 | x as u32 as u64
+}
+/// the inverse of `chunk_key`, returns (x, y)
+pub(crate) fn chunk_pos(key: u64) -> (i32, i32) {
+    (key as u32 as i32, (key >> 32) as u32 as i32)
 }
 
 // SAVING
 
+/// Bit in the `options` file which is set if `World::wrap_layers` is true
+const OPTION_WRAP_LAYERS: u8 = 0b1;
+
+/// Every save file starts with these four bytes, followed by the format version as a `u32`.
+/// Files without them are assumed to be from before the header was introduced (version 0).
+const SAVE_MAGIC: &[u8; 4] = b"SMKR";
+/// The save format version written by this version of stackmaker.
+/// Files with a higher version are rejected instead of being misinterpreted.
+/// - 1: adds the header
+/// - 2: run-length encodes empty stacks in layers
+/// - 3: adds a `Compression` byte after the version, the rest of the file is compressed accordingly
+/// - 4: adds a CRC-32 of everything before it as a `u32` at the end of the file
+/// - 5: layers contain an index of their chunks, see `Layer::save_indexed`
+pub const SAVE_VERSION: u32 = 5;
+/// The magic bytes, the version and the compression byte
+const SAVE_HEADER_LEN: u64 = SAVE_MAGIC.len() as u64 + 5;
+
+#[derive(Debug)]
+pub enum LoadError {
+    Io(std::io::Error),
+    /// The file has no header and isn't a valid headerless save either.
+    BadMagic {
+        file: PathBuf,
+    },
+    /// The file was saved using a newer format than this version of stackmaker supports.
+    UnsupportedVersion {
+        file: PathBuf,
+        version: u32,
+    },
+    /// The file's checksum doesn't match its contents, so it was truncated or damaged.
+    Corrupt {
+        file: PathBuf,
+    },
+    /// Single chunks can't be read from the layer file, because it is compressed or from before save format version 5.
+    NotIndexed {
+        file: PathBuf,
+    },
+}
+
 impl World {
+    /// Returns `Ok(None)` if a file has a valid header but its contents couldn't be parsed (truncated or damaged).
+    /// Damaged files from save format version 4 onwards are detected using their checksum and return `LoadError::Corrupt` instead.
+    /// Compressed and uncompressed saves can both be loaded, the compression is detected automatically.
+    /// If `prog` is given, it is set to the fraction of files (the signals file plus all layers) read so far.
     pub fn load_from_dir<P: AsRef<Path>>(
         dir: P,
         prog: Option<Arc<Mutex<f32>>>,
-    ) -> Result<Option<Self>, std::io::Error> {
+    ) -> Result<Option<Self>, LoadError> {
         fn p(prog: &Option<Arc<Mutex<f32>>>, v: f32) {
             if let Some(prog) = prog {
                 *prog.lock().unwrap() = v;
             }
         }
-        p(&prog, 1.0);
-        let signals_queue = {
-            let mut buf = Vec::new();
-            fs::File::open(dir.as_ref().join("signals"))?.read_to_end(&mut buf)?;
-            if let Some(v) = SaveLoad::load(&mut buf.into_iter()) {
-                v
-            } else {
-                return Ok(None);
-            }
+        let files_total = 1.0 + LAYER_COUNT as f32;
+        p(&prog, 0.0);
+        let signals_queue = if let Some(v) =
+            load_save_file(&dir.as_ref().join("signals"), |_, src| SaveLoad::load(src))?
+        {
+            v
+        } else {
+            return Ok(None);
         };
-        p(&prog, 0.5);
+        p(&prog, 1.0 / files_total);
         let layers = {
-            let mut layers: [Layer; 32] = Default::default();
+            let mut layers: [Layer; LAYER_COUNT] = Default::default();
             for (i, layer) in layers.iter_mut().enumerate() {
-                p(&prog, 0.5 + i as f32 / 32.0);
-                let mut buf = Vec::new();
-                fs::File::open(dir.as_ref().join(format!("layer_{i}")))?.read_to_end(&mut buf)?;
-                *layer = if let Some(v) = SaveLoad::load(&mut buf.into_iter()) {
+                *layer = if let Some(v) =
+                    load_save_file(&dir.as_ref().join(format!("layer_{i}")), |version, src| {
+                        if version < 2 {
+                            Layer::load_unpacked(src)
+                        } else if version < 5 {
+                            Layer::load_unindexed(src)
+                        } else {
+                            Layer::load(src)
+                        }
+                    })? {
                     v
                 } else {
                     return Ok(None);
                 };
+                p(&prog, (2 + i) as f32 / files_total);
             }
             layers
         };
+        // saves from before the options file was added use the default options
+        let options_path = dir.as_ref().join("options");
+        let wrap_layers = if options_path.exists() {
+            if let Some(v) = load_save_file(&options_path, |_, src| u8::load(src))? {
+                v & OPTION_WRAP_LAYERS != 0
+            } else {
+                return Ok(None);
+            }
+        } else {
+            false
+        };
+        // the view isn't needed to load the world, so a missing or damaged view file is ignored
+        let view_path = dir.as_ref().join("view");
+        let view = if view_path.exists() {
+            match load_save_file(&view_path, |_, src| View::load(src)) {
+                Ok(Some(view)) => Some(view),
+                Ok(None) => {
+                    eprintln!("[warn] Couldn't load the view from {view_path:?}: invalid data");
+                    None
+                }
+                Err(e) => {
+                    eprintln!("[warn] Couldn't load the view from {view_path:?}: {e}");
+                    None
+                }
+            }
+        } else {
+            None
+        };
         p(&prog, 1.0);
         Ok(Some(Self {
             save_dir: Some(dir.as_ref().to_path_buf()),
             layers,
             signals_queue,
+            wrap_layers,
+            view,
+            spare_signals: vec![],
+            chunk_errors: vec![],
         }))
     }
+    /// Creates `dir` if it doesn't exist yet.
+    /// All files are written to `.tmp` files first and only renamed once every one of them was written,
+    /// so a failed or interrupted save leaves the previous save intact.
+    /// Afterwards, no layer is dirty anymore (see `save_changed_to_dir`).
     pub fn save_to_dir<P: AsRef<Path>>(&self, dir: P) -> Result<(), std::io::Error> {
-        self.save_signals_queue(dir.as_ref().join("signals"))?;
-        for i in 0..self.layers.len() {
-            self.save_layer(dir.as_ref().join(format!("layer_{i}")), i)?;
+        self.save_to_dir_compressed(dir, Compression::None)
+    }
+    /// Like `save_to_dir`, but compresses every file using the given method.
+    pub fn save_to_dir_compressed<P: AsRef<Path>>(
+        &self,
+        dir: P,
+        compression: Compression,
+    ) -> Result<(), std::io::Error> {
+        self.save_files(dir.as_ref(), compression, false, true)
+    }
+    /// Like `save_to_dir`, but only writes the layers which changed since the world was loaded or last saved (see `Layer::is_dirty`).
+    /// The signals queue, options and view are always written, because they change on almost every tick.
+    /// `dir` should be the directory the world was loaded from or last saved to, otherwise it could end up with other worlds' layers.
+    pub fn save_changed_to_dir<P: AsRef<Path>>(&self, dir: P) -> Result<(), std::io::Error> {
+        self.save_files(dir.as_ref(), Compression::None, true, true)
+    }
+    /// Saves the world to `dir`. If `only_changed` is true, layers which aren't dirty are skipped.
+    /// If `clear_dirty` is true, all layers are marked as saved once every file was written.
+    pub(crate) fn save_files(
+        &self,
+        dir: &Path,
+        compression: Compression,
+        only_changed: bool,
+        clear_dirty: bool,
+    ) -> Result<(), std::io::Error> {
+        fs::create_dir_all(dir)?;
+        let mut files = Vec::with_capacity(3 + self.layers.len());
+        let written = (|| {
+            let path = dir.join("signals");
+            files.push((tmp_path(&path), path));
+            write_save_file(&files[0].0, &self.signals_queue, compression)?;
+            for (i, layer) in self.layers.iter().enumerate() {
+                if only_changed && !layer.is_dirty() {
+                    continue;
+                }
+                let path = dir.join(format!("layer_{i}"));
+                files.push((tmp_path(&path), path));
+                write_save_file(&files[files.len() - 1].0, layer, compression)?;
+            }
+            let path = dir.join("options");
+            files.push((tmp_path(&path), path));
+            write_save_file(&files[files.len() - 1].0, &self.options(), compression)?;
+            if let Some(view) = &self.view {
+                let path = dir.join("view");
+                files.push((tmp_path(&path), path));
+                write_save_file(&files[files.len() - 1].0, view, compression)?;
+            }
+            Ok(())
+        })();
+        if let Err(e) = written {
+            for (tmp, _) in &files {
+                _ = fs::remove_file(tmp);
+            }
+            return Err(e);
+        }
+        for (tmp, path) in &files {
+            fs::rename(tmp, path)?;
+        }
+        // the thumbnail only shows layer 0
+        if !only_changed || self.layers[0].is_dirty() {
+            // the thumbnail isn't needed to load the world, so not being able to write it doesn't fail the save
+            if let Err(e) = self.save_thumbnail(dir) {
+                eprintln!("[warn] Couldn't save the thumbnail to {dir:?}: {e}");
+            }
+        }
+        if clear_dirty {
+            for layer in &self.layers {
+                layer.mark_saved();
+            }
         }
         Ok(())
     }
-    pub fn save_signals_queue<P: AsRef<Path>>(&self, path: P) -> Result<(), std::io::Error> {
-        let mut buf = vec![];
-        self.signals_queue.save(&mut buf);
-        fs::File::create(path)?.write_all(&buf)?;
-        Ok(())
+    /// Writes a thumbnail of layer 0 to `thumbnail::THUMBNAIL_FILE` in `dir`, see `thumbnail::render`.
+    /// If layer 0 is empty, an old thumbnail is removed instead.
+    pub fn save_thumbnail<P: AsRef<Path>>(&self, dir: P) -> Result<(), std::io::Error> {
+        let path = dir.as_ref().join(thumbnail::THUMBNAIL_FILE);
+        match thumbnail::render(&self.layers[0])? {
+            Some((width, height, pixels)) => {
+                let tmp = tmp_path(&path);
+                fs::write(&tmp, thumbnail::encode_png(width, height, &pixels))?;
+                fs::rename(tmp, path)
+            }
+            None => match fs::remove_file(path) {
+                Err(e) if e.kind() != std::io::ErrorKind::NotFound => Err(e),
+                _ => Ok(()),
+            },
+        }
     }
-    pub fn save_layer<P: AsRef<Path>>(&self, path: P, layer: usize) -> Result<(), std::io::Error> {
-        let mut buf = vec![];
-        self.layers[layer].save(&mut buf);
-        fs::File::create(path)?.write_all(&buf)?;
-        Ok(())
+    /// the options, packed into one byte (see `OPTION_WRAP_LAYERS`)
+    fn options(&self) -> u8 {
+        if self.wrap_layers {
+            OPTION_WRAP_LAYERS
+        } else {
+            0
+        }
+    }
+    pub fn save_signals_queue<P: AsRef<Path>>(
+        &self,
+        path: P,
+        compression: Compression,
+    ) -> Result<(), std::io::Error> {
+        write_save_file_atomic(path.as_ref(), &self.signals_queue, compression)
+    }
+    pub fn save_layer<P: AsRef<Path>>(
+        &self,
+        path: P,
+        layer: usize,
+        compression: Compression,
+    ) -> Result<(), std::io::Error> {
+        write_save_file_atomic(path.as_ref(), &self.layers[layer], compression)
+    }
+}
+
+/// `path` with `.tmp` appended to the file name
+fn tmp_path(path: &Path) -> PathBuf {
+    let mut name = path.file_name().unwrap_or_default().to_os_string();
+    name.push(".tmp");
+    path.with_file_name(name)
+}
+
+/// Like `write_save_file`, but writes to a temporary file first and then renames it to `path`,
+/// so `path` never contains a partially written file.
+fn write_save_file_atomic<D: SaveFile>(
+    path: &Path,
+    data: &D,
+    compression: Compression,
+) -> Result<(), std::io::Error> {
+    let tmp = tmp_path(path);
+    if let Err(e) = write_save_file(&tmp, data, compression) {
+        _ = fs::remove_file(&tmp);
+        return Err(e);
+    }
+    fs::rename(tmp, path)
+}
+
+/// Writes the header, the (possibly compressed) `data` and the checksum to `path`.
+/// Uncompressed data is written while it is being serialized, so it never has to fit into memory all at once.
+fn write_save_file<D: SaveFile>(
+    path: &Path,
+    data: &D,
+    compression: Compression,
+) -> Result<(), std::io::Error> {
+    let mut w = CrcWriter {
+        inner: BufWriter::new(fs::File::create(path)?),
+        crc: Crc32::new(),
+    };
+    w.write_all(SAVE_MAGIC)?;
+    SAVE_VERSION.save_to(&mut w)?;
+    (compression as u8).save_to(&mut w)?;
+    match compression {
+        Compression::None => data.save_file(&mut w)?,
+        // the encoder needs all of the data at once
+        compression => {
+            let mut payload = vec![];
+            data.save_file(&mut payload)?;
+            w.write_all(&compression.compress(payload))?;
+        }
+    }
+    let CrcWriter { mut inner, crc } = w;
+    crc.finish().save_to(&mut inner)?;
+    inner.flush()
+}
+
+/// The bytes `load_save_file` passes to its `load` function
+type SaveBytes<'a> = &'a mut dyn Iterator<Item = u8>;
+
+/// Reads a file written by `write_save_file`, falling back to the headerless format if the magic bytes are missing.
+/// `load` receives the file's format version (0 for headerless files) and the bytes following the header.
+/// Uncompressed files from version 4 onwards are parsed while they are being read, older and compressed ones are read into memory first.
+fn load_save_file<D, F>(path: &Path, load: F) -> Result<Option<D>, LoadError>
+where
+    F: FnOnce(u32, &mut SaveBytes) -> Option<D>,
+{
+    let file = fs::File::open(path)?;
+    let len = file.metadata()?.len();
+    let mut reader = CrcReader {
+        inner: BufReader::new(file),
+        crc: Crc32::new(),
+    };
+    let mut buf = Vec::new();
+    (&mut reader).take(SAVE_HEADER_LEN).read_to_end(&mut buf)?;
+    if let Some(header) = buf.strip_prefix(SAVE_MAGIC) {
+        let version = u32::load(&mut header.iter().copied());
+        if version.is_some_and(|v| (4..=SAVE_VERSION).contains(&v))
+            && header.get(4) == Some(&(Compression::None as u8))
+            && len >= SAVE_HEADER_LEN + 4
+        {
+            let mut src = ReadBytes::new((&mut reader).take(len - SAVE_HEADER_LEN - 4));
+            let data = load(version.unwrap(), &mut (&mut src as SaveBytes));
+            if let Some(e) = src.error {
+                return Err(e.into());
+            }
+            // the rest of the data still counts towards the checksum
+            std::io::copy(&mut src.reader, &mut std::io::sink())?;
+            drop(src);
+            let mut checksum = [0; 4];
+            reader.inner.read_exact(&mut checksum)?;
+            if u32::from_be_bytes(checksum) != reader.crc.finish() {
+                return Err(LoadError::Corrupt {
+                    file: path.to_path_buf(),
+                });
+            }
+            return Ok(data);
+        }
+    }
+    reader.inner.read_to_end(&mut buf)?;
+    if let Some(rest) = buf.strip_prefix(SAVE_MAGIC) {
+        let mut src = rest.iter().copied();
+        let version = match u32::load(&mut src) {
+            Some(v) => v,
+            None => return Ok(None),
+        };
+        if version > SAVE_VERSION {
+            return Err(LoadError::UnsupportedVersion {
+                file: path.to_path_buf(),
+                version,
+            });
+        }
+        if version < 3 {
+            return Ok(load(version, &mut (&mut src as SaveBytes)));
+        }
+        let rest = if version < 4 {
+            rest
+        } else {
+            let content_len = buf.len().saturating_sub(4).max(SAVE_MAGIC.len() + 4);
+            let (content, checksum) = buf.split_at(content_len);
+            let mut crc = Crc32::new();
+            crc.update(content);
+            if u32::load(&mut checksum.iter().copied()) != Some(crc.finish()) {
+                return Err(LoadError::Corrupt {
+                    file: path.to_path_buf(),
+                });
+            }
+            &content[SAVE_MAGIC.len()..]
+        };
+        let payload = match rest
+            .get(4)
+            .and_then(|b| Compression::from_byte(*b))
+            .and_then(|compression| compression.decompress(&rest[5..]))
+        {
+            Some(v) => v,
+            None => return Ok(None),
+        };
+        Ok(load(
+            version,
+            &mut (&mut payload.iter().copied() as SaveBytes),
+        ))
+    } else {
+        // saved before the header was introduced
+        match load(0, &mut (&mut buf.iter().copied() as SaveBytes)) {
+            Some(v) => Ok(Some(v)),
+            None => Err(LoadError::BadMagic {
+                file: path.to_path_buf(),
+            }),
+        }
+    }
+}
+
+/// Passes everything written to it on to `inner`, computing the CRC-32 of it.
+struct CrcWriter<W: Write> {
+    inner: W,
+    crc: Crc32,
+}
+impl<W: Write> Write for CrcWriter<W> {
+    fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+        let n = self.inner.write(buf)?;
+        self.crc.update(&buf[..n]);
+        Ok(n)
+    }
+    fn flush(&mut self) -> std::io::Result<()> {
+        self.inner.flush()
+    }
+}
+/// Reads from `inner`, computing the CRC-32 of everything read.
+struct CrcReader<R: Read> {
+    inner: R,
+    crc: Crc32,
+}
+impl<R: Read> Read for CrcReader<R> {
+    fn read(&mut self, buf: &mut [u8]) -> std::io::Result<usize> {
+        let n = self.inner.read(buf)?;
+        self.crc.update(&buf[..n]);
+        Ok(n)
+    }
+}
+
+/// The bytes of a reader, buffered, so that `SaveLoad::load` can read from it without reading all of it into memory first.
+/// If reading fails, the iterator ends and the error is stored in `error`.
+pub struct ReadBytes<R: Read> {
+    reader: BufReader<R>,
+    pub error: Option<std::io::Error>,
+}
+impl<R: Read> ReadBytes<R> {
+    pub fn new(reader: R) -> Self {
+        Self {
+            reader: BufReader::new(reader),
+            error: None,
+        }
+    }
+}
+impl<R: Read> Iterator for ReadBytes<R> {
+    type Item = u8;
+    fn next(&mut self) -> Option<u8> {
+        if self.error.is_some() {
+            return None;
+        }
+        loop {
+            match self.reader.fill_buf() {
+                Ok(buf) => {
+                    let b = *buf.first()?;
+                    self.reader.consume(1);
+                    return Some(b);
+                }
+                Err(e) if e.kind() == std::io::ErrorKind::Interrupted => {}
+                Err(e) => {
+                    self.error = Some(e);
+                    return None;
+                }
+            }
+        }
+    }
+}
+
+impl From<std::io::Error> for LoadError {
+    fn from(e: std::io::Error) -> Self {
+        Self::Io(e)
+    }
+}
+impl std::fmt::Display for LoadError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::Io(e) => write!(f, "{e}"),
+            Self::BadMagic { file } => write!(f, "{file:?} is not a stackmaker save file"),
+            Self::UnsupportedVersion { file, version } => write!(
+                f,
+                "{file:?} uses save format version {version}, but only versions up to {SAVE_VERSION} are supported"
+            ),
+            Self::Corrupt { file } => write!(f, "{file:?} is damaged (its checksum doesn't match)"),
+            Self::NotIndexed { file } => write!(
+                f,
+                "{file:?} is compressed or from an old version, so its chunks can't be read individually"
+            ),
+        }
     }
 }
+impl std::error::Error for LoadError {}
+
+/// Loading reserves room for at most this many items up front. The length in a damaged file can be anything,
+/// and reserving room for all of it could fail (or abort) before the damage is noticed.
+const MAX_PREALLOCATED: usize = 4096;
 
 pub trait SaveLoad: Sized {
     fn save(&self, buf: &mut Vec<u8>);
     fn load<T: Iterator<Item = u8>>(src: &mut T) -> Option<Self>;
+    /// Writes the same bytes as `save`, but to a writer.
+    /// Large types write their parts one at a time instead of building all of the bytes in memory first.
+    fn save_to<W: Write>(&self, w: &mut W) -> std::io::Result<()> {
+        let mut buf = vec![];
+        self.save(&mut buf);
+        w.write_all(&buf)
+    }
+    /// Like `load`, but reads from a reader (see `ReadBytes`). Returns `Ok(None)` if the data couldn't be parsed.
+    fn load_from<R: Read>(reader: R) -> std::io::Result<Option<Self>> {
+        load_with(reader, Self::load)
+    }
+}
+/// Parses the bytes of `reader` using `load`, returning the reader's error if there was one.
+fn load_with<R: Read, D>(
+    reader: R,
+    load: impl FnOnce(&mut ReadBytes<R>) -> Option<D>,
+) -> std::io::Result<Option<D>> {
+    let mut src = ReadBytes::new(reader);
+    let v = load(&mut src);
+    match src.error {
+        Some(e) => Err(e),
+        None => Ok(v),
+    }
 }
 
-impl SaveLoad for Layer {
-    fn save(&self, buf: &mut Vec<u8>) {
-        self.chunks.len().save(buf);
+/// What `write_save_file` can write: everything which implements `SaveLoad`, and layers,
+/// which can't implement it because saving them reads their evicted chunks, which can fail.
+trait SaveFile {
+    fn save_file<W: Write>(&self, w: &mut W) -> std::io::Result<()>;
+}
+impl<T: SaveLoad> SaveFile for T {
+    fn save_file<W: Write>(&self, w: &mut W) -> std::io::Result<()> {
+        self.save_to(w)
+    }
+}
+impl SaveFile for Layer {
+    fn save_file<W: Write>(&self, w: &mut W) -> std::io::Result<()> {
+        self.save_to(w)
+    }
+}
+
+/// Empty stacks are run-length encoded:
+/// a stack of length `0` is followed by a `u8` saying how many of the following stacks are empty, too.
+/// The chunks are followed by an index, see `save_indexed`.
+impl Layer {
+    /// Like `SaveLoad::save_to`, writes one chunk at a time. Fails if an evicted chunk can't be read.
+    pub fn save_to<W: Write>(&self, w: &mut W) -> std::io::Result<()> {
+        self.save_indexed(w)
+    }
+    /// Like `SaveLoad::load`, reads a layer written by `save_to`.
+    pub fn load<T: Iterator<Item = u8>>(src: &mut T) -> Option<Self> {
+        let len: usize = SaveLoad::load(src)?;
+        let mut chunks =
+            ChunkMap::with_capacity_and_hasher(len.min(MAX_PREALLOCATED), Default::default());
+        for _ in 0..len {
+            let slot_len = u32::load(src)?;
+            let mut slot = src.by_ref().take(slot_len as usize);
+            let (pos, chunk) = load_chunk(&mut slot)?;
+            // the rest of the slot is padding, see `Layer::rewrite_chunk_at`
+            slot.for_each(drop);
+            chunks.insert(pos, chunk);
+        }
+        // the index is only needed to read single chunks
+        for _ in 0..len {
+            <(u64, u64)>::load(src)?;
+        }
+        u64::load(src)?;
+        Some(Self::loaded(chunks))
+    }
+    /// Like `SaveLoad::load_from`, see `load`.
+    pub fn load_from<R: Read>(reader: R) -> std::io::Result<Option<Self>> {
+        load_with(reader, Self::load)
+    }
+}
+/// Checks the header of a layer file and looks the chunk up in its index (see `Layer::save_indexed`).
+/// Returns where the chunk's slot starts in the file (after its length) and how long it is.
+fn find_indexed_chunk(
+    file: &mut fs::File,
+    path: &Path,
+    key: u64,
+) -> Result<Option<(u64, u32)>, LoadError> {
+    let corrupt = || LoadError::Corrupt {
+        file: path.to_path_buf(),
+    };
+    fn read_u64(file: &mut fs::File) -> std::io::Result<u64> {
+        let mut bytes = [0; 8];
+        file.read_exact(&mut bytes)?;
+        Ok(u64::from_be_bytes(bytes))
+    }
+    let file_len = file.metadata()?.len();
+    let mut header = [0; SAVE_HEADER_LEN as usize];
+    file.read_exact(&mut header)?;
+    let Some(version) = header
+        .strip_prefix(SAVE_MAGIC)
+        .and_then(|rest| u32::load(&mut rest.iter().copied()))
+    else {
+        return Err(LoadError::BadMagic {
+            file: path.to_path_buf(),
+        });
+    };
+    if version > SAVE_VERSION {
+        return Err(LoadError::UnsupportedVersion {
+            file: path.to_path_buf(),
+            version,
+        });
+    }
+    if version < 5 || header[SAVE_HEADER_LEN as usize - 1] != Compression::None as u8 {
+        return Err(LoadError::NotIndexed {
+            file: path.to_path_buf(),
+        });
+    }
+    // the chunk count, the index' offset and the checksum
+    if file_len < SAVE_HEADER_LEN + 20 {
+        return Err(corrupt());
+    }
+    let count = read_u64(file)?;
+    // the index' offset is right before the checksum
+    file.seek(SeekFrom::Start(file_len - 12))?;
+    let index_offset = read_u64(file)?;
+    let index_start = SAVE_HEADER_LEN.saturating_add(index_offset);
+    if count
+        .checked_mul(16)
+        .and_then(|len| len.checked_add(index_start))
+        != Some(file_len - 12)
+    {
+        return Err(corrupt());
+    }
+    file.seek(SeekFrom::Start(index_start))?;
+    let mut index = vec![0; count as usize * 16];
+    file.read_exact(&mut index)?;
+    let Some(slot_offset) = index
+        .chunks_exact(16)
+        .find(|entry| u64::load(&mut entry.iter().copied()) == Some(key))
+        .and_then(|entry| u64::load(&mut entry[8..].iter().copied()))
+    else {
+        return Ok(None);
+    };
+    let slot_start = SAVE_HEADER_LEN.saturating_add(slot_offset);
+    if slot_start.saturating_add(4) > index_start {
+        return Err(corrupt());
+    }
+    file.seek(SeekFrom::Start(slot_start))?;
+    let mut len = [0; 4];
+    file.read_exact(&mut len)?;
+    let len = u32::from_be_bytes(len);
+    if slot_start + 4 + len as u64 > index_start {
+        return Err(corrupt());
+    }
+    Ok(Some((slot_start + 4, len)))
+}
+/// the inverse of `save_chunk`
+pub(crate) fn load_chunk<T: Iterator<Item = u8>>(src: &mut T) -> Option<(u64, [Vec<Block>; 256])> {
+    let pos = SaveLoad::load(src)?;
+    let mut chunk: [Vec<Block>; 256] = create_empty_chunk();
+    let mut i = 0;
+    while i < chunk.len() {
+        chunk[i] = SaveLoad::load(src)?;
+        if chunk[i].is_empty() {
+            // the following stacks are already empty, so we can just skip them
+            i += u8::load(src)? as usize;
+            if i >= chunk.len() {
+                return None;
+            }
+        }
+        i += 1;
+    }
+    Some((pos, chunk))
+}
+fn save_chunk(pos: u64, chunk: &[Vec<Block>; 256], buf: &mut Vec<u8>) {
+    pos.save(buf);
+    let mut i = 0;
+    while i < chunk.len() {
+        chunk[i].save(buf);
+        if chunk[i].is_empty() {
+            let run = chunk[i + 1..]
+                .iter()
+                .take_while(|blocks| blocks.is_empty())
+                .count();
+            (run as u8).save(buf);
+            i += run;
+        }
+        i += 1;
+    }
+}
+impl Layer {
+    /// Writes the layer so that single chunks can be read from (and replaced in) the file later, see `load_chunk_at`.
+    /// This is the layer format from save format version 5 onwards:
+    /// the number of chunks as a `u64`, then every chunk in a slot (its length as a `u32`, then the chunk, then padding up to that length),
+    /// then the index: for every chunk its key and the offset of its slot as `u64`s, and finally the offset of the index as a `u64`.
+    /// Offsets count from the start of the layer data, not from the start of the file. Evicted chunks are copied from their files.
+    pub fn save_indexed<W: Write>(&self, w: &mut W) -> std::io::Result<()> {
+        let count = self.chunks.len() + self.evicted.len();
+        count.save_to(w)?;
+        let mut offset = 8;
+        let mut index = Vec::with_capacity(count);
+        let mut write_slot = |key: u64, chunk: &[u8]| {
+            let len = u32::try_from(chunk.len()).map_err(|_| {
+                std::io::Error::new(std::io::ErrorKind::InvalidData, "chunk is too large")
+            })?;
+            index.push((key, offset));
+            len.save_to(w)?;
+            w.write_all(chunk)?;
+            offset += 4 + len as u64;
+            Ok::<_, std::io::Error>(())
+        };
+        let mut buf = vec![];
         for (pos, chunk) in self.chunks.iter() {
-            pos.save(buf);
-            for blocks in chunk {
-                blocks.save(buf);
+            buf.clear();
+            save_chunk(*pos, chunk, &mut buf);
+            write_slot(*pos, &buf)?;
+        }
+        for (pos, file) in self.evicted.iter() {
+            write_slot(*pos, &file.read()?)?;
+        }
+        for entry in &index {
+            entry.save_to(w)?;
+        }
+        offset.save_to(w)
+    }
+    /// Reads the chunk `key` from a layer file (see `World::save_layer`) without reading the other chunks.
+    /// Returns `Ok(None)` if the layer doesn't contain the chunk.
+    /// Only works for uncompressed files from save format version 5 onwards, others return `LoadError::NotIndexed`.
+    /// The checksum isn't verified, because that would mean reading the whole file.
+    pub fn load_chunk_at<P: AsRef<Path>>(
+        path: P,
+        key: u64,
+    ) -> Result<Option<[Vec<Block>; 256]>, LoadError> {
+        let path = path.as_ref();
+        let mut file = fs::File::open(path)?;
+        let Some((start, len)) = find_indexed_chunk(&mut file, path, key)? else {
+            return Ok(None);
+        };
+        let mut slot = vec![0; len as usize];
+        file.seek(SeekFrom::Start(start))?;
+        file.read_exact(&mut slot)?;
+        match load_chunk(&mut slot.into_iter()) {
+            Some((pos, chunk)) if pos == key => Ok(Some(chunk)),
+            _ => Err(LoadError::Corrupt {
+                file: path.to_path_buf(),
+            }),
+        }
+    }
+    /// Replaces the chunk `key` in a layer file without rewriting the rest of the file, see `load_chunk_at`.
+    /// The chunk has to fit into the slot the file has for it, so it can't be longer (in bytes) than the chunk it was saved with.
+    /// Returns `Ok(false)` if the file doesn't contain the chunk or the chunk doesn't fit, then the whole layer has to be saved instead.
+    /// Updating the checksum reads the whole file (but doesn't parse it). The file is changed in place, so if that is interrupted,
+    /// its checksum doesn't match anymore and loading it fails with `LoadError::Corrupt` instead of loading a mix of both chunks.
+    pub fn rewrite_chunk_at<P: AsRef<Path>>(
+        path: P,
+        key: u64,
+        chunk: &[Vec<Block>; 256],
+    ) -> Result<bool, LoadError> {
+        let path = path.as_ref();
+        let mut file = fs::OpenOptions::new().read(true).write(true).open(path)?;
+        let Some((start, len)) = find_indexed_chunk(&mut file, path, key)? else {
+            return Ok(false);
+        };
+        let mut slot = vec![];
+        save_chunk(key, chunk, &mut slot);
+        if slot.len() > len as usize {
+            return Ok(false);
+        }
+        slot.resize(len as usize, 0);
+        // check the old checksum and compute the new one in the same pass
+        let file_len = file.metadata()?.len();
+        file.seek(SeekFrom::Start(0))?;
+        let (mut old_crc, mut new_crc) = (Crc32::new(), Crc32::new());
+        let mut reader = BufReader::new(&mut file).take(file_len - 4);
+        let mut buf = vec![0; 1 << 16];
+        let mut pos = 0;
+        loop {
+            let n = reader.read(&mut buf)?;
+            if n == 0 {
+                break;
+            }
+            let read = &buf[..n];
+            old_crc.update(read);
+            // the part of `read` which the new slot replaces
+            let from = start.saturating_sub(pos).min(n as u64) as usize;
+            let to = (start + len as u64).saturating_sub(pos).min(n as u64) as usize;
+            new_crc.update(&read[..from]);
+            if from < to {
+                let in_slot = (pos + from as u64 - start) as usize;
+                new_crc.update(&slot[in_slot..in_slot + (to - from)]);
             }
+            new_crc.update(&read[to..]);
+            pos += n as u64;
+        }
+        let mut checksum = [0; 4];
+        reader.into_inner().read_exact(&mut checksum)?;
+        if u32::from_be_bytes(checksum) != old_crc.finish() {
+            return Err(LoadError::Corrupt {
+                file: path.to_path_buf(),
+            });
         }
+        file.seek(SeekFrom::Start(start))?;
+        file.write_all(&slot)?;
+        file.seek(SeekFrom::Start(file_len - 4))?;
+        new_crc.finish().save_to(&mut file)?;
+        Ok(true)
     }
-    fn load<T: Iterator<Item = u8>>(src: &mut T) -> Option<Self> {
-        let len = SaveLoad::load(src)?;
-        let mut chunks = HashMap::with_capacity(len);
+    /// a layer which contains the chunks and isn't dirty, because they were just loaded
+    pub(crate) fn loaded(chunks: ChunkMap) -> Self {
+        Self {
+            // the clocks are found before the next tick
+            unscanned: chunks.keys().copied().collect(),
+            chunks,
+            dirty: Cell::new(false),
+            ..Default::default()
+        }
+    }
+    /// loads a layer saved before save format version 5, which has no index.
+    fn load_unindexed<T: Iterator<Item = u8>>(src: &mut T) -> Option<Self> {
+        let len: usize = SaveLoad::load(src)?;
+        let mut chunks =
+            ChunkMap::with_capacity_and_hasher(len.min(MAX_PREALLOCATED), Default::default());
+        for _ in 0..len {
+            let (pos, chunk) = load_chunk(src)?;
+            chunks.insert(pos, chunk);
+        }
+        Some(Self::loaded(chunks))
+    }
+    /// loads a layer saved before save format version 2, where every stack was saved individually.
+    fn load_unpacked<T: Iterator<Item = u8>>(src: &mut T) -> Option<Self> {
+        let len: usize = SaveLoad::load(src)?;
+        let mut chunks =
+            ChunkMap::with_capacity_and_hasher(len.min(MAX_PREALLOCATED), Default::default());
         for _ in 0..len {
             let pos = SaveLoad::load(src)?;
             let mut chunk = create_empty_chunk();
@@ -221,7 +1443,7 @@ impl SaveLoad for Layer {
             }
             chunks.insert(pos, chunk);
         }
-        Some(Self { chunks })
+        Some(Self::loaded(chunks))
     }
 }
 
@@ -248,14 +1470,50 @@ impl SaveLoad for Block {
                 dir.save(buf);
             }
             Self::Gate(open, dir) => {
-                b'g'.save(buf);
-                let as_one = if *open { *dir | 0b1 } else { *dir };
-                as_one.save(buf);
+                b'o'.save(buf);
+                open.save(buf);
+                dir.save(buf);
             }
             Self::Splitter(dir) => {
                 b'G'.save(buf);
                 dir.save(buf);
             }
+            Self::Fan(dirs) => {
+                b'f'.save(buf);
+                dirs.save(buf);
+            }
+            Self::Clock(period, phase) => {
+                b'k'.save(buf);
+                period.save(buf);
+                phase.save(buf);
+            }
+            Self::Compare(threshold, op, dir) => {
+                b'<'.save(buf);
+                threshold.save(buf);
+                op.save(buf);
+                dir.save(buf);
+            }
+            Self::Not(dir) => {
+                b'n'.save(buf);
+                dir.save(buf);
+            }
+            Self::Probe(dir) => {
+                b'p'.save(buf);
+                dir.save(buf);
+            }
+            Self::Random(state) => {
+                b'r'.save(buf);
+                state.save(buf);
+            }
+            Self::Counter(count, dir) => {
+                b'+'.save(buf);
+                count.save(buf);
+                dir.save(buf);
+            }
+            Self::Height(dir) => {
+                b'h'.save(buf);
+                dir.save(buf);
+            }
             Self::Move(dir) => {
                 b'm'.save(buf);
                 dir.save(buf);
@@ -264,6 +1522,18 @@ impl SaveLoad for Block {
                 b'M'.save(buf);
                 dir.save(buf);
             }
+            Self::SwapAll(dir) => {
+                b'S'.save(buf);
+                dir.save(buf);
+            }
+            Self::Duplicate(dir) => {
+                b'D'.save(buf);
+                dir.save(buf);
+            }
+            Self::Destroy(dir) => {
+                b'x'.save(buf);
+                dir.save(buf);
+            }
         }
     }
     fn load<T: Iterator<Item = u8>>(src: &mut T) -> Option<Self> {
@@ -276,6 +1546,8 @@ impl SaveLoad for Block {
                 SaveLoad::load(src)?,
                 SaveLoad::load(src)?,
             ),
+            b'o' => Self::Gate(SaveLoad::load(src)?, SaveLoad::load(src)?),
+            // older saves pack the open flag into the lowest bit of the direction
             b'g' => {
                 let as_one: u8 = SaveLoad::load(src)?;
                 if as_one & 1 == 1 {
@@ -286,8 +1558,23 @@ impl SaveLoad for Block {
                 }
             }
             b'G' => Self::Splitter(SaveLoad::load(src)?),
+            b'f' => Self::Fan(SaveLoad::load(src)?),
+            b'k' => Self::Clock(SaveLoad::load(src)?, SaveLoad::load(src)?),
+            b'<' => Self::Compare(
+                SaveLoad::load(src)?,
+                SaveLoad::load(src)?,
+                SaveLoad::load(src)?,
+            ),
+            b'n' => Self::Not(SaveLoad::load(src)?),
+            b'p' => Self::Probe(SaveLoad::load(src)?),
+            b'r' => Self::Random(SaveLoad::load(src)?),
+            b'+' => Self::Counter(SaveLoad::load(src)?, SaveLoad::load(src)?),
+            b'h' => Self::Height(SaveLoad::load(src)?),
             b'm' => Self::Move(SaveLoad::load(src)?),
             b'M' => Self::Swap(SaveLoad::load(src)?),
+            b'S' => Self::SwapAll(SaveLoad::load(src)?),
+            b'D' => Self::Duplicate(SaveLoad::load(src)?),
+            b'x' => Self::Destroy(SaveLoad::load(src)?),
             _ => return None,
         })
     }
@@ -303,9 +1590,16 @@ where
             v.save(buf);
         }
     }
+    fn save_to<W: Write>(&self, w: &mut W) -> std::io::Result<()> {
+        self.len().save_to(w)?;
+        for v in self {
+            v.save_to(w)?;
+        }
+        Ok(())
+    }
     fn load<T: Iterator<Item = u8>>(src: &mut T) -> Option<Self> {
-        let len = SaveLoad::load(src)?;
-        let mut o = Vec::with_capacity(len);
+        let len: usize = SaveLoad::load(src)?;
+        let mut o = Vec::with_capacity(len.min(MAX_PREALLOCATED));
         for _ in 0..len {
             o.push(SaveLoad::load(src)?)
         }
@@ -322,19 +1616,41 @@ where
             v.save(buf);
         }
     }
+    fn save_to<W: Write>(&self, w: &mut W) -> std::io::Result<()> {
+        self.len().save_to(w)?;
+        for v in self {
+            v.save_to(w)?;
+        }
+        Ok(())
+    }
     fn load<T: Iterator<Item = u8>>(src: &mut T) -> Option<Self> {
-        let len = SaveLoad::load(src)?;
-        let mut o = VecDeque::with_capacity(len);
+        let len: usize = SaveLoad::load(src)?;
+        let mut o = VecDeque::with_capacity(len.min(MAX_PREALLOCATED));
         for _ in 0..len {
             o.push_back(SaveLoad::load(src)?)
         }
         Some(o)
     }
 }
+impl SaveLoad for bool {
+    fn save(&self, buf: &mut Vec<u8>) {
+        buf.push(*self as u8);
+    }
+    fn load<T: Iterator<Item = u8>>(src: &mut T) -> Option<Self> {
+        match src.next()? {
+            0 => Some(false),
+            1 => Some(true),
+            _ => None,
+        }
+    }
+}
 impl SaveLoad for u8 {
     fn save(&self, buf: &mut Vec<u8>) {
         buf.push(*self);
     }
+    fn save_to<W: Write>(&self, w: &mut W) -> std::io::Result<()> {
+        w.write_all(&[*self])
+    }
     fn load<T: Iterator<Item = u8>>(src: &mut T) -> Option<Self> {
         src.next()
     }
@@ -343,6 +1659,9 @@ impl SaveLoad for u32 {
     fn save(&self, buf: &mut Vec<u8>) {
         buf.extend_from_slice(&self.to_be_bytes())
     }
+    fn save_to<W: Write>(&self, w: &mut W) -> std::io::Result<()> {
+        w.write_all(&self.to_be_bytes())
+    }
     fn load<T: Iterator<Item = u8>>(src: &mut T) -> Option<Self> {
         Some(Self::from_be_bytes([
             src.next()?,
@@ -356,6 +1675,9 @@ impl SaveLoad for u64 {
     fn save(&self, buf: &mut Vec<u8>) {
         buf.extend_from_slice(&self.to_be_bytes())
     }
+    fn save_to<W: Write>(&self, w: &mut W) -> std::io::Result<()> {
+        w.write_all(&self.to_be_bytes())
+    }
     fn load<T: Iterator<Item = u8>>(src: &mut T) -> Option<Self> {
         Some(Self::from_be_bytes([
             src.next()?,
@@ -373,11 +1695,37 @@ impl SaveLoad for usize {
     fn save(&self, buf: &mut Vec<u8>) {
         (*self as u64).save(buf)
     }
+    fn save_to<W: Write>(&self, w: &mut W) -> std::io::Result<()> {
+        (*self as u64).save_to(w)
+    }
     fn load<T: Iterator<Item = u8>>(src: &mut T) -> Option<Self> {
         Some(u64::load(src)? as _)
     }
 }
 
+impl SaveLoad for f32 {
+    fn save(&self, buf: &mut Vec<u8>) {
+        self.to_bits().save(buf)
+    }
+    fn load<T: Iterator<Item = u8>>(src: &mut T) -> Option<Self> {
+        Some(Self::from_bits(u32::load(src)?))
+    }
+}
+impl SaveLoad for View {
+    fn save(&self, buf: &mut Vec<u8>) {
+        self.position.save(buf);
+        self.zoom.save(buf);
+        self.layer.save(buf);
+    }
+    fn load<T: Iterator<Item = u8>>(src: &mut T) -> Option<Self> {
+        Some(Self {
+            position: SaveLoad::load(src)?,
+            zoom: SaveLoad::load(src)?,
+            layer: SaveLoad::load(src)?,
+        })
+    }
+}
+
 impl<A, B> SaveLoad for (A, B)
 where
     A: SaveLoad,
@@ -387,6 +1735,10 @@ where
         self.0.save(buf);
         self.1.save(buf);
     }
+    fn save_to<W: Write>(&self, w: &mut W) -> std::io::Result<()> {
+        self.0.save_to(w)?;
+        self.1.save_to(w)
+    }
     fn load<T: Iterator<Item = u8>>(src: &mut T) -> Option<Self> {
         Some((SaveLoad::load(src)?, SaveLoad::load(src)?))
     }
@@ -402,6 +1754,11 @@ where
         self.1.save(buf);
         self.2.save(buf);
     }
+    fn save_to<W: Write>(&self, w: &mut W) -> std::io::Result<()> {
+        self.0.save_to(w)?;
+        self.1.save_to(w)?;
+        self.2.save_to(w)
+    }
     fn load<T: Iterator<Item = u8>>(src: &mut T) -> Option<Self> {
         Some((
             SaveLoad::load(src)?,
@@ -423,6 +1780,12 @@ where
         self.2.save(buf);
         self.3.save(buf);
     }
+    fn save_to<W: Write>(&self, w: &mut W) -> std::io::Result<()> {
+        self.0.save_to(w)?;
+        self.1.save_to(w)?;
+        self.2.save_to(w)?;
+        self.3.save_to(w)
+    }
     fn load<T: Iterator<Item = u8>>(src: &mut T) -> Option<Self> {
         Some((
             SaveLoad::load(src)?,
@@ -493,6 +1856,7 @@ impl Block {
         match self {
             Self::Color(..) => "color",
             Self::Char(..) => "char",
+            Self::Delay(_, dir) if dir & DELAY_BY_VALUE != 0 => "delay/value",
             Self::Delay(..) => "delay",
             Self::Storage(_, 0, _) => "storage/sto",
             Self::Storage(_, 1, _) => "storage/or",
@@ -503,12 +1867,767 @@ impl Block {
             Self::Storage(_, 6, _) => "storage/mul",
             Self::Storage(_, 7, _) => "storage/div",
             Self::Storage(_, 8, _) => "storage/mod",
+            Self::Storage(_, 9, _) => "storage/min",
+            Self::Storage(_, 10, _) => "storage/max",
+            Self::Storage(_, 14, _) => "storage/wadd",
+            Self::Storage(_, 15, _) => "storage/wsub",
+            Self::Storage(_, 16, _) => "storage/wmul",
             Self::Storage(_, _, _) => "storage/default",
-            Self::Gate(true, _) => "gate/open",
-            Self::Gate(false, _) => "gate/closed",
+            Self::Gate(open, dir) => {
+                match (
+                    open,
+                    dir & GATE_INVERTED != 0,
+                    gate_control_dir(*dir).is_some(),
+                ) {
+                    (true, false, false) => "gate/open",
+                    (false, false, false) => "gate/closed",
+                    (true, true, false) => "gate/open-inv",
+                    (false, true, false) => "gate/closed-inv",
+                    (true, false, true) => "gate/open/pass",
+                    (false, false, true) => "gate/closed/pass",
+                    (true, true, true) => "gate/open-inv/pass",
+                    (false, true, true) => "gate/closed-inv/pass",
+                }
+            }
             Self::Splitter(_) => "splitter",
+            Self::Fan(_) => "fan",
+            Self::Clock(..) => "clock",
+            Self::Compare(_, 0, _) => "compare/lt",
+            Self::Compare(_, 1, _) => "compare/le",
+            Self::Compare(_, 2, _) => "compare/eq",
+            Self::Compare(_, 3, _) => "compare/ge",
+            Self::Compare(_, 4, _) => "compare/gt",
+            Self::Compare(_, _, _) => "compare/default",
+            Self::Not(_) => "not",
+            Self::Probe(_) => "probe",
+            Self::Random(_) => "random",
+            Self::Counter(..) => "counter",
+            Self::Height(_) => "height",
             Self::Move(..) => "move",
             Self::Swap(..) => "swap",
+            Self::SwapAll(..) => "swap/all",
+            Self::Duplicate(..) => "duplicate",
+            Self::Destroy(..) => "destroy",
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::runner::{gate_control_out, DIR_DOWN, DIR_DOWN_L, DIR_LEFT, DIR_RIGHT, DIR_UP};
+
+    /// a new empty directory in the system's temp dir
+    fn temp_dir(name: &str) -> PathBuf {
+        let dir =
+            std::env::temp_dir().join(format!("stackmaker-test-{name}-{}", std::process::id()));
+        _ = fs::remove_dir_all(&dir);
+        dir
+    }
+
+    /// every non-empty stack as (layer, x, y, stack), sorted
+    fn blocks(world: &World) -> Vec<(usize, i64, i64, Vec<Block>)> {
+        let mut blocks: Vec<_> = world
+            .iter_blocks()
+            .unwrap()
+            .map(|(layer, x, y, stack)| (layer, x, y, stack.clone()))
+            .collect();
+        blocks.sort_by_key(|&(layer, x, y, _)| (layer, x, y));
+        blocks
+    }
+
+    /// every non-empty stack of the layer as (x, y, stack), sorted
+    fn blocks_of(layer: &Layer) -> Vec<(i64, i64, Vec<Block>)> {
+        let mut blocks: Vec<_> = layer
+            .iter_blocks()
+            .unwrap()
+            .map(|(x, y, stack)| (x, y, stack.clone()))
+            .collect();
+        blocks.sort_by_key(|&(x, y, _)| (x, y));
+        blocks
+    }
+
+    fn assert_same_world(a: &World, b: &World) {
+        assert_eq!(blocks(a), blocks(b));
+        assert_eq!(a.signals_queue, b.signals_queue);
+        assert_eq!(a.wrap_layers, b.wrap_layers);
+        assert_eq!(a.view, b.view);
+    }
+
+    /// blocks spread over a few chunks (including negative ones) and layers, and some signals
+    fn sample_world() -> World {
+        let mut world = World::new_empty();
+        for i in 0..40 {
+            let layer = &mut world.layers[i as usize % 3 * 10];
+            layer
+                .try_push_block(
+                    i * 7 - 100,
+                    i * 3 - 50,
+                    Block::Storage(i as u32, 4, DIR_RIGHT),
+                )
+                .unwrap();
+            layer
+                .try_push_block(i * 7 - 100, i * 3 - 50, Block::Gate(i % 2 == 0, DIR_UP))
+                .unwrap();
+            layer
+                .try_push_block(-i, i, Block::Color(0xFF00FF00 | i as u32))
+                .unwrap();
+        }
+        world
+            .signals_mut(0)
+            .push((1, DIR_LEFT | 3, chunk_key(-1, 2), 17));
+        world
+            .signals_mut(4)
+            .push((u32::MAX, DIR_DOWN, chunk_key(0, 0), 255));
+        world.wrap_layers = true;
+        world
+    }
+
+    #[test]
+    fn compressed_saves_load() {
+        let dir = temp_dir("compressed");
+        let world = sample_world();
+        world
+            .save_to_dir_compressed(&dir, Compression::Gzip)
+            .unwrap();
+        let loaded = World::load_from_dir(&dir, None).unwrap().unwrap();
+        assert_same_world(&world, &loaded);
+        let mut file = fs::File::open(dir.join("layer_0")).unwrap();
+        let mut header = [0; SAVE_HEADER_LEN as usize];
+        file.read_exact(&mut header).unwrap();
+        assert_eq!(
+            header[SAVE_HEADER_LEN as usize - 1],
+            Compression::Gzip as u8
+        );
+        _ = fs::remove_dir_all(&dir);
+    }
+
+    #[test]
+    fn compressed_saves_are_smaller() {
+        let (raw, compressed) = (temp_dir("raw"), temp_dir("gzip"));
+        let mut world = World::new_empty();
+        // a lot of chunks which are empty or contain a single block
+        for i in 0..200 {
+            world.layers[0].try_get_mut(&chunk_key(i, -i)).unwrap();
+            world.layers[0]
+                .try_push_block(i as i64 * 16, 0, Block::Delay(1, DIR_RIGHT))
+                .unwrap();
+        }
+        world.save_to_dir(&raw).unwrap();
+        world
+            .save_to_dir_compressed(&compressed, Compression::Gzip)
+            .unwrap();
+        let size = |dir: &Path| fs::metadata(dir.join("layer_0")).unwrap().len();
+        assert!(
+            size(&compressed) * 2 < size(&raw),
+            "{} bytes compressed, {} raw",
+            size(&compressed),
+            size(&raw)
+        );
+        let loaded = World::load_from_dir(&compressed, None).unwrap().unwrap();
+        assert_same_world(&world, &loaded);
+        assert_eq!(loaded.layers[0].chunks.len(), 399);
+        _ = fs::remove_dir_all(&raw);
+        _ = fs::remove_dir_all(&compressed);
+    }
+
+    #[test]
+    fn loading_reports_progress() {
+        let dir = temp_dir("progress");
+        sample_world().save_to_dir(&dir).unwrap();
+        let prog = Arc::new(Mutex::new(0.5));
+        World::load_from_dir(&dir, Some(Arc::clone(&prog)))
+            .unwrap()
+            .unwrap();
+        assert_eq!(*prog.lock().unwrap(), 1.0);
+        // a failed load stops before the end
+        fs::remove_file(dir.join("layer_7")).unwrap();
+        assert!(World::load_from_dir(&dir, Some(Arc::clone(&prog))).is_err());
+        let stopped = *prog.lock().unwrap();
+        assert!((0.0..1.0).contains(&stopped), "{stopped}");
+        assert_eq!(stopped, 8.0 / (1.0 + LAYER_COUNT as f32));
+        _ = fs::remove_dir_all(&dir);
+    }
+
+    #[test]
+    fn empty_stacks_are_run_length_encoded() {
+        let mut chunk = create_empty_chunk();
+        for i in [1, 2, 5, 100, 101, 254] {
+            chunk[i].push(Block::Color(i as u32));
+        }
+        chunk[2].push(Block::Delay(3, DIR_DOWN));
+        let mut full = create_empty_chunk();
+        full.iter_mut()
+            .for_each(|stack| stack.push(Block::Not(DIR_UP)));
+        let mut last = create_empty_chunk();
+        last[255].push(Block::Color(7));
+        for chunk in [chunk, create_empty_chunk(), full, last] {
+            let mut buf = vec![];
+            save_chunk(chunk_key(-3, 9), &chunk, &mut buf);
+            let mut src = buf.into_iter();
+            assert_eq!(load_chunk(&mut src), Some((chunk_key(-3, 9), chunk)));
+            assert_eq!(src.next(), None);
+        }
+        // the key, then a single empty stack followed by the 255 other empty ones
+        let mut buf = vec![];
+        save_chunk(0, &create_empty_chunk(), &mut buf);
+        let mut empty = vec![];
+        Vec::<Block>::new().save(&mut empty);
+        assert_eq!(buf.len(), 8 + empty.len() + 1);
+        assert_eq!(buf.last(), Some(&255));
+    }
+
+    #[test]
+    fn gc_removes_only_empty_chunks() {
+        let mut world = World::new_empty();
+        let (empty, _) = world.layers[2].get_where(-40, 7);
+        world.layers[2].try_get_mut(&empty).unwrap();
+        world.layers[2]
+            .try_push_block(5, 5, Block::Color(1))
+            .unwrap();
+        // emptied by popping its only block
+        world.layers[9]
+            .try_push_block(100, -100, Block::Color(2))
+            .unwrap();
+        world.layers[9].try_pop_block(100, -100).unwrap();
+        world.layers[2].mark_saved();
+        assert_eq!(world.gc_empty_chunks(), 2);
+        assert!(!world.layers[2].chunks.contains_key(&empty));
+        assert!(world.layers[9].chunks.is_empty());
+        assert_eq!(world.layers[2].top_block(5, 5), Some(&Block::Color(1)));
+        assert!(world.layers[2].is_dirty());
+        world.layers[2].mark_saved();
+        assert_eq!(world.gc_empty_chunks(), 0);
+        assert!(!world.layers[2].is_dirty());
+    }
+
+    #[test]
+    fn extents_cover_all_blocks() {
+        let mut world = World::new_empty();
+        assert_eq!(world.extents().unwrap(), None);
+        // an empty chunk doesn't count
+        world.layers[0].try_get_mut(&chunk_key(-100, -100)).unwrap();
+        assert_eq!(world.layers[0].extents().unwrap(), None);
+        world.layers[0]
+            .try_push_block(-1, -1, Block::Color(0))
+            .unwrap();
+        assert_eq!(world.layers[0].extents().unwrap(), Some((-1, -1, -1, -1)));
+        world.layers[0]
+            .try_push_block(-17, 3, Block::Color(0))
+            .unwrap();
+        world.layers[0]
+            .try_push_block(-16, -33, Block::Color(0))
+            .unwrap();
+        assert_eq!(world.layers[0].extents().unwrap(), Some((-17, -33, -1, 3)));
+        world.layers[31]
+            .try_push_block(i32::MIN as i64 * 16, 40, Block::Color(0))
+            .unwrap();
+        world.layers[5]
+            .try_push_block(0, i32::MAX as i64 * 16 + 15, Block::Color(0))
+            .unwrap();
+        assert_eq!(
+            world.extents().unwrap(),
+            Some((i32::MIN as i64 * 16, -33, 0, i32::MAX as i64 * 16 + 15))
+        );
+    }
+
+    #[test]
+    fn blocks_by_coordinates() {
+        let mut layer = Layer::default();
+        // both sides of the chunk borders around the origin
+        let positions = [
+            (-1, -1),
+            (0, 0),
+            (15, 15),
+            (16, 16),
+            (-16, 15),
+            (-17, -16),
+            (15, -1),
+        ];
+        for (i, &(x, y)) in positions.iter().enumerate() {
+            layer.try_push_block(x, y, Block::Color(i as u32)).unwrap();
+        }
+        assert_eq!(layer.chunks.len(), 6);
+        for (i, &(x, y)) in positions.iter().enumerate() {
+            assert_eq!(
+                layer.top_block(x, y),
+                Some(&Block::Color(i as u32)),
+                "({x}, {y})"
+            );
+        }
+        layer
+            .try_push_block(-1, -1, Block::Char('a' as u32))
+            .unwrap();
+        assert_eq!(layer.top_block(-1, -1), Some(&Block::Char('a' as u32)));
+        assert_eq!(
+            layer.try_pop_block(-1, -1).unwrap(),
+            Some(Block::Char('a' as u32))
+        );
+        assert_eq!(layer.try_pop_block(-1, -1).unwrap(), Some(Block::Color(0)));
+        assert_eq!(layer.try_pop_block(-1, -1).unwrap(), None);
+        assert_eq!(layer.top_block(-1, -1), None);
+        assert_eq!(layer.top_block(0, 0), Some(&Block::Color(1)));
+        // reading and popping where there is no chunk doesn't create one
+        assert_eq!(layer.top_block(-1000, 1000), None);
+        assert_eq!(layer.try_pop_block(-1000, 1000).unwrap(), None);
+        assert_eq!(layer.top_block(16 * 40, -16 * 40), None);
+        assert_eq!(layer.chunks.len(), 6);
+    }
+
+    #[test]
+    fn where_to_coords_inverts_get_where() {
+        let layer = Layer::default();
+        for x in -40..40 {
+            for y in -40..40 {
+                let (chunk, inner) = layer.get_where(x, y);
+                assert_eq!(Layer::where_to_coords(chunk, inner), (x, y));
+            }
+        }
+        let (min, max) = (i32::MIN as i64 * 16, i32::MAX as i64 * 16 + 15);
+        for (x, y) in [
+            (min, max),
+            (max, min),
+            (min, min),
+            (max, max),
+            (min + 17, -max + 3),
+        ] {
+            let (chunk, inner) = layer.get_where(x, y);
+            assert_eq!(Layer::where_to_coords(chunk, inner), (x, y));
+        }
+        assert_eq!(layer.get_where(-1, 16), (chunk_key(-1, 1), 15));
+    }
+
+    #[test]
+    fn blocks_equal_their_clones() {
+        let blocks = [
+            Block::Delay(3, DIR_RIGHT),
+            Block::Splitter(DIR_LEFT),
+            Block::Fan(DIR_UP),
+            Block::Clock(10, 4),
+            Block::Color(0xFF00FF00),
+            Block::Char('x' as u32),
+            Block::Storage(7, 4, DIR_RIGHT),
+            Block::Gate(true, DIR_LEFT),
+            Block::Compare(7, 2, DIR_UP),
+            Block::Not(DIR_DOWN),
+            Block::Probe(DIR_RIGHT),
+            Block::Random(12345),
+            Block::Counter(2, DIR_LEFT),
+            Block::Height(DIR_UP),
+            Block::Move(DIR_DOWN),
+            Block::Swap(DIR_RIGHT),
+            Block::SwapAll(DIR_LEFT),
+            Block::Duplicate(DIR_UP),
+            Block::Destroy(DIR_DOWN),
+        ];
+        for (i, block) in blocks.iter().enumerate() {
+            assert_eq!(&block.clone(), block);
+            for other in &blocks[i + 1..] {
+                assert_ne!(block, other);
+            }
+        }
+        assert_ne!(Block::Gate(true, DIR_LEFT), Block::Gate(false, DIR_LEFT));
+        assert_ne!(
+            Block::Storage(7, 4, DIR_RIGHT),
+            Block::Storage(7, 5, DIR_RIGHT)
+        );
+    }
+
+    #[test]
+    fn failed_saves_leave_the_old_save_intact() {
+        let dir = temp_dir("atomic");
+        let old = sample_world();
+        old.save_to_dir(&dir).unwrap();
+        let mut new = World::new_empty();
+        new.layers[0].try_push_block(1, 2, Block::Color(3)).unwrap();
+        // writing layer 5 fails, because its temp file can't be created
+        fs::create_dir(dir.join("layer_5.tmp")).unwrap();
+        assert!(new.save_to_dir(&dir).is_err());
+        assert!(new.layers[0].is_dirty());
+        for name in [
+            "signals.tmp",
+            "layer_0.tmp",
+            "layer_4.tmp",
+            "layer_6.tmp",
+            "options.tmp",
+        ] {
+            assert!(!dir.join(name).exists(), "{name} wasn't removed");
+        }
+        let loaded = World::load_from_dir(&dir, None).unwrap().unwrap();
+        assert_same_world(&old, &loaded);
+        _ = fs::remove_dir_all(&dir);
+    }
+
+    #[test]
+    fn snapshots_are_independent() {
+        let mut world = sample_world();
+        world.layers[0]
+            .try_push_block(0, 0, Block::Clock(2, 0))
+            .unwrap();
+        world.layers[0]
+            .try_push_block(1, 0, Block::Counter(0, DIR_RIGHT))
+            .unwrap();
+        let mut runner = crate::runner::Runner::new(world);
+        let snapshot = runner.world.snapshot();
+        let before = blocks(&snapshot);
+        runner.tick_n(10);
+        assert_ne!(blocks(&runner.world), before);
+        assert_ne!(runner.world.signals_queue, snapshot.signals_queue);
+        assert_eq!(blocks(&snapshot), before);
+        assert_eq!(
+            snapshot.layers[0].top_block(1, 0),
+            Some(&Block::Counter(0, DIR_RIGHT))
+        );
+        // and the other way around, ticking the snapshot has the same outcome
+        let mut copy = crate::runner::Runner::new(snapshot);
+        copy.tick_n(10);
+        assert_same_world(&runner.world, &copy.world);
+        copy.world.layers[0].try_pop_block(1, 0).unwrap();
+        assert_eq!(
+            runner.world.layers[0].top_block(1, 0),
+            Some(&Block::Counter(4, DIR_RIGHT))
+        );
+    }
+
+    #[test]
+    fn gates_roundtrip() {
+        for gate in [
+            Block::Gate(true, DIR_RIGHT),
+            Block::Gate(false, DIR_LEFT),
+            Block::Gate(true, gate_control_out(DIR_UP | GATE_INVERTED, DIR_LEFT)),
+            Block::Gate(false, gate_control_out(DIR_DOWN, DIR_DOWN_L)),
+        ] {
+            let mut buf = vec![];
+            gate.save(&mut buf);
+            assert_eq!(buf[0], b'o');
+            assert_eq!(Block::load(&mut buf.into_iter()), Some(gate));
+        }
+        // the old form, with the open flag in the direction's lowest bit
+        let load = |bytes: &[u8]| Block::load(&mut bytes.iter().copied());
+        assert_eq!(
+            load(&[b'g', DIR_RIGHT | 1]),
+            Some(Block::Gate(true, DIR_RIGHT))
+        );
+        assert_eq!(load(&[b'g', DIR_LEFT]), Some(Block::Gate(false, DIR_LEFT)));
+        assert_eq!(load(&[b'o', 2, DIR_LEFT]), None);
+        assert_eq!(bool::load(&mut [1].into_iter()), Some(true));
+        assert_eq!(bool::load(&mut [0].into_iter()), Some(false));
+    }
+
+    #[test]
+    fn damaged_files_are_detected() {
+        let dir = temp_dir("crc");
+        let world = sample_world();
+        for compression in [Compression::None, Compression::Gzip] {
+            world.save_to_dir_compressed(&dir, compression).unwrap();
+            let path = dir.join("layer_10");
+            let good = fs::read(&path).unwrap();
+            let corrupt = |bytes: &[u8]| {
+                fs::write(&path, bytes).unwrap();
+                matches!(World::load_from_dir(&dir, None), Err(LoadError::Corrupt { file }) if file == path)
+            };
+            for i in [
+                SAVE_HEADER_LEN as usize,
+                good.len() / 2,
+                good.len() - 5,
+                good.len() - 1,
+            ] {
+                let mut flipped = good.clone();
+                flipped[i] ^= 0b100;
+                assert!(corrupt(&flipped), "flipped byte {i} of {}", good.len());
+            }
+            assert!(corrupt(&good[..good.len() - 1]));
+            assert!(corrupt(&good[..good.len() / 2]));
+            fs::write(&path, &good).unwrap();
+            assert_same_world(&world, &World::load_from_dir(&dir, None).unwrap().unwrap());
+        }
+        _ = fs::remove_dir_all(&dir);
+    }
+
+    #[test]
+    fn iter_blocks_yields_every_stack_once() {
+        let mut world = World::new_empty();
+        let cells = [
+            (0, -1, -1),
+            (0, 0, 0),
+            (0, 15, -16),
+            (0, -17, 33),
+            (4, 3, 3),
+            (31, -1000, 1000),
+        ];
+        for (layer, x, y) in cells {
+            world.layers[layer]
+                .try_push_block(x, y, Block::Color(x as u32))
+                .unwrap();
+            world.layers[layer]
+                .try_push_block(x, y, Block::Char(y as u32))
+                .unwrap();
+        }
+        // empty stacks and chunks aren't yielded
+        world.layers[0].try_get_mut(&chunk_key(9, 9)).unwrap();
+        world.layers[4]
+            .try_push_block(50, 50, Block::Color(0))
+            .unwrap();
+        world.layers[4].try_pop_block(50, 50).unwrap();
+        let mut found: Vec<_> = world
+            .iter_blocks()
+            .unwrap()
+            .map(|(layer, x, y, stack)| {
+                assert_eq!(stack, &[Block::Color(x as u32), Block::Char(y as u32)]);
+                (layer, x, y)
+            })
+            .collect();
+        found.sort_unstable();
+        let mut expected = cells.to_vec();
+        expected.sort_unstable();
+        assert_eq!(found, expected);
+        let mut layer_0: Vec<_> = world.layers[0]
+            .iter_blocks()
+            .unwrap()
+            .map(|(x, y, _)| (x, y))
+            .collect();
+        layer_0.sort_unstable();
+        assert_eq!(layer_0, [(-17, 33), (-1, -1), (0, 0), (15, -16)]);
+    }
+
+    #[test]
+    fn views_roundtrip() {
+        let view = View {
+            position: (-1234.5, 0.25),
+            zoom: 3.75,
+            layer: 17,
+        };
+        let mut buf = vec![];
+        view.save(&mut buf);
+        assert_eq!(View::load(&mut buf.clone().into_iter()), Some(view));
+        assert_eq!(View::load(&mut buf[..buf.len() - 1].iter().copied()), None);
+        let dir = temp_dir("view");
+        let mut world = sample_world();
+        world.save_to_dir(&dir).unwrap();
+        assert_eq!(
+            World::load_from_dir(&dir, None).unwrap().unwrap().view,
+            None
+        );
+        world.view = Some(view);
+        world.save_to_dir(&dir).unwrap();
+        assert_eq!(
+            World::load_from_dir(&dir, None).unwrap().unwrap().view,
+            Some(view)
+        );
+        // a damaged view file doesn't keep the world from loading
+        fs::write(dir.join("view"), b"SMKR").unwrap();
+        let loaded = World::load_from_dir(&dir, None).unwrap().unwrap();
+        assert_eq!(loaded.view, None);
+        world.view = None;
+        assert_same_world(&world, &loaded);
+        _ = fs::remove_dir_all(&dir);
+    }
+
+    #[test]
+    fn streaming_matches_buffering() {
+        fn streamed<T: SaveLoad>(v: &T) -> Vec<u8> {
+            let mut buf = vec![];
+            v.save(&mut buf);
+            let mut out = std::io::BufWriter::new(vec![]);
+            v.save_to(&mut out).unwrap();
+            let out = out.into_inner().unwrap();
+            assert_eq!(out, buf);
+            out
+        }
+        let world = sample_world();
+        for layer in [&world.layers[0], &world.layers[10], &world.layers[1]] {
+            let mut bytes = vec![];
+            layer.save_to(&mut bytes).unwrap();
+            let loaded = Layer::load_from(&bytes[..]).unwrap().unwrap();
+            assert_eq!(blocks_of(&loaded), blocks_of(layer));
+            let loaded = Layer::load(&mut bytes.into_iter()).unwrap();
+            assert_eq!(blocks_of(&loaded), blocks_of(layer));
+        }
+        type Queue = VecDeque<Vec<(u32, u8, u64, u8)>>;
+        let bytes = streamed(&world.signals_queue);
+        let loaded: Queue = SaveLoad::load_from(&bytes[..]).unwrap().unwrap();
+        assert_eq!(loaded, world.signals_queue);
+        // data which ends too early can't be parsed, read errors are returned
+        let short: Option<Queue> = SaveLoad::load_from(&bytes[..bytes.len() - 1]).unwrap();
+        assert!(short.is_none());
+        struct Failing;
+        impl Read for Failing {
+            fn read(&mut self, _: &mut [u8]) -> std::io::Result<usize> {
+                Err(std::io::Error::other("unplugged"))
+            }
+        }
+        assert!(Layer::load_from(Failing).is_err());
+    }
+
+    #[test]
+    fn incremental_saves_only_write_changed_layers() {
+        let dir = temp_dir("incremental");
+        let mut world = sample_world();
+        world.save_to_dir(&dir).unwrap();
+        assert!(world.layers.iter().all(|layer| !layer.is_dirty()));
+        let read = |name: &str| fs::read(dir.join(name)).ok();
+        let old_layer = read("layer_10").unwrap();
+        // a file which isn't rewritten stays missing
+        fs::remove_file(dir.join("layer_3")).unwrap();
+        fs::remove_file(dir.join("signals")).unwrap();
+        world.layers[10]
+            .try_push_block(7, 7, Block::Color(7))
+            .unwrap();
+        assert!(world.layers[10].is_dirty());
+        world.save_changed_to_dir(&dir).unwrap();
+        assert!(!world.layers[10].is_dirty());
+        assert_ne!(read("layer_10").unwrap(), old_layer);
+        assert_eq!(read("layer_3"), None);
+        assert!(read("signals").is_some());
+        // nothing changed since
+        fs::remove_file(dir.join("layer_10")).unwrap();
+        world.save_changed_to_dir(&dir).unwrap();
+        assert_eq!(read("layer_10"), None);
+        // a full save writes everything
+        world.save_to_dir(&dir).unwrap();
+        assert_same_world(&world, &World::load_from_dir(&dir, None).unwrap().unwrap());
+        _ = fs::remove_dir_all(&dir);
+    }
+
+    #[test]
+    fn merged_blocks_are_moved() {
+        let mut other = World::new_empty();
+        other.layers[0]
+            .try_push_block(0, 0, Block::Color(1))
+            .unwrap();
+        other.layers[0]
+            .try_push_block(0, 0, Block::Color(2))
+            .unwrap();
+        other.layers[0]
+            .try_push_block(-5, 3, Block::Clock(4, 0))
+            .unwrap();
+        other.layers[1]
+            .try_push_block(-20, 5, Block::Char('a' as u32))
+            .unwrap();
+        // would end up on a layer which doesn't exist
+        other.layers[30]
+            .try_push_block(1, 1, Block::Color(3))
+            .unwrap();
+        other.signals_mut(0).push((1, DIR_UP, 0, 0));
+        let mut world = World::new_empty();
+        world.layers[2]
+            .try_push_block(20, -5, Block::Not(DIR_UP))
+            .unwrap();
+        world.try_merge(&other, 20, -5, 2).unwrap();
+        let mut expected = vec![
+            (2, 15, -2, vec![Block::Clock(4, 0)]),
+            (
+                2,
+                20,
+                -5,
+                vec![Block::Not(DIR_UP), Block::Color(1), Block::Color(2)],
+            ),
+            (3, 0, 0, vec![Block::Char('a' as u32)]),
+        ];
+        expected.sort_by_key(|&(layer, x, y, _)| (layer, x, y));
+        assert_eq!(blocks(&world), expected);
+        assert!(world.signals_queue.iter().all(|signals| signals.is_empty()));
+        world.layers[2].update_clocks();
+        let (chunk, inner) = world.layers[2].get_where(15, -2);
+        assert!(world.layers[2].clocks.contains(&(chunk, inner)));
+        // moving layers the other way
+        let mut world = World::new_empty();
+        world.try_merge(&other, 0, 0, -1).unwrap();
+        assert_eq!(blocks(&world).len(), 2);
+        assert_eq!(
+            world.layers[0].top_block(-20, 5),
+            Some(&Block::Char('a' as u32))
+        );
+        assert_eq!(world.layers[29].top_block(1, 1), Some(&Block::Color(3)));
+    }
+
+    #[test]
+    fn single_chunks_can_be_read_and_rewritten() {
+        let dir = temp_dir("indexed");
+        let mut world = World::new_empty();
+        world.layers[0]
+            .try_push_block(-1, -1, Block::Color(1))
+            .unwrap();
+        world.layers[0]
+            .try_push_block(-1, -1, Block::Storage(5, 4, DIR_RIGHT))
+            .unwrap();
+        world.layers[0]
+            .try_push_block(20, 3, Block::Color(2))
+            .unwrap();
+        world.save_to_dir(&dir).unwrap();
+        let path = dir.join("layer_0");
+        let (key, inner) = world.layers[0].get_where(-1, -1);
+        let chunk = world.layers[0].get(&key).unwrap().clone();
+        assert_eq!(
+            Layer::load_chunk_at(&path, key).unwrap(),
+            Some(chunk.clone())
+        );
+        assert_eq!(Layer::load_chunk_at(&path, chunk_key(7, 7)).unwrap(), None);
+        // a chunk which is shorter than the saved one fits into its slot
+        let mut smaller = chunk.clone();
+        smaller[inner as usize].pop();
+        assert!(Layer::rewrite_chunk_at(&path, key, &smaller).unwrap());
+        assert_eq!(
+            Layer::load_chunk_at(&path, key).unwrap(),
+            Some(smaller.clone())
+        );
+        // the checksum was updated, so the whole file still loads
+        let loaded = World::load_from_dir(&dir, None).unwrap().unwrap();
+        assert_eq!(loaded.layers[0].get(&key), Some(&smaller));
+        assert_eq!(loaded.layers[0].top_block(20, 3), Some(&Block::Color(2)));
+        // the slot is still as long as the old chunk, but a longer one doesn't fit
+        assert!(Layer::rewrite_chunk_at(&path, key, &chunk).unwrap());
+        let saved = fs::read(&path).unwrap();
+        let (start, _) = find_indexed_chunk(&mut fs::File::open(&path).unwrap(), &path, key)
+            .unwrap()
+            .unwrap();
+        let mut larger = chunk.clone();
+        larger[inner as usize].push(Block::Color(3));
+        assert!(!Layer::rewrite_chunk_at(&path, key, &larger).unwrap());
+        assert!(!Layer::rewrite_chunk_at(&path, chunk_key(7, 7), &chunk).unwrap());
+        assert_eq!(fs::read(&path).unwrap(), saved);
+        // truncated
+        fs::write(&path, &saved[..saved.len() - 1]).unwrap();
+        assert!(matches!(
+            Layer::load_chunk_at(&path, key),
+            Err(LoadError::Corrupt { .. })
+        ));
+        // a flipped byte in the chunk's slot isn't noticed by reading it, but by the checksum when rewriting it
+        let mut damaged = saved.clone();
+        damaged[start as usize + 9] ^= 1;
+        fs::write(&path, &damaged).unwrap();
+        assert!(matches!(
+            Layer::rewrite_chunk_at(&path, key, &chunk),
+            Err(LoadError::Corrupt { .. })
+        ));
+        // the chunk's key, which has to match the index
+        damaged = saved.clone();
+        damaged[start as usize] ^= 1;
+        fs::write(&path, &damaged).unwrap();
+        assert!(matches!(
+            Layer::load_chunk_at(&path, key),
+            Err(LoadError::Corrupt { .. })
+        ));
+        // a newer version
+        damaged = saved.clone();
+        damaged[SAVE_MAGIC.len()..SAVE_MAGIC.len() + 4]
+            .copy_from_slice(&(SAVE_VERSION + 1).to_be_bytes());
+        fs::write(&path, &damaged).unwrap();
+        assert!(matches!(
+            Layer::load_chunk_at(&path, key),
+            Err(LoadError::UnsupportedVersion { version, .. }) if version == SAVE_VERSION + 1
+        ));
+        assert!(matches!(
+            Layer::rewrite_chunk_at(&path, key, &chunk),
+            Err(LoadError::UnsupportedVersion { .. })
+        ));
+        world
+            .save_to_dir_compressed(&dir, Compression::Gzip)
+            .unwrap();
+        assert!(matches!(
+            Layer::load_chunk_at(&path, key),
+            Err(LoadError::NotIndexed { .. })
+        ));
+        _ = fs::remove_dir_all(&dir);
+    }
+}